@@ -11,17 +11,17 @@ use http_router::*;
 
 #[bench]
 fn bench_router(b: &mut Bencher) {
-    let get_users = |_: ()| "get_users".to_string();
-    let post_users = |_: ()| "post_users".to_string();
-    let patch_users = |_: (), id: u32| format!("patch_users({})", id);
-    let delete_users = |_: (), id: u32| format!("delete_users({})", id);
-    let get_transactions = |_: (), id: u32| format!("get_transactions({})", id);
-    let post_transactions = |_: (), id: u32| format!("post_transactions({})", id);
+    let get_users = |_: &()| "get_users".to_string();
+    let post_users = |_: &()| "post_users".to_string();
+    let patch_users = |_: &(), id: u32| format!("patch_users({})", id);
+    let delete_users = |_: &(), id: u32| format!("delete_users({})", id);
+    let get_transactions = |_: &(), id: u32| format!("get_transactions({})", id);
+    let post_transactions = |_: &(), id: u32| format!("post_transactions({})", id);
     let patch_transactions =
-        |_: (), id: u32, hash: String| format!("patch_transactions({}, {})", id, hash);
+        |_: &(), id: u32, hash: String| format!("patch_transactions({}, {})", id, hash);
     let delete_transactions =
-        |_: (), id: u32, hash: String| format!("delete_transactions({}, {})", id, hash);
-    let fallback = |_: ()| "404".to_string();
+        |_: &(), id: u32, hash: String| format!("delete_transactions({}, {})", id, hash);
+    let fallback = |_: &()| "404".to_string();
 
     let router = router!(
         GET / => get_users,
@@ -84,3 +84,65 @@ fn bench_plain_regex(b: &mut Bencher) {
         }
     });
 }
+
+#[bench]
+fn bench_dynamic_router(b: &mut Bencher) {
+    let mut router: DynamicRouter<(), String> = DynamicRouter::new();
+    let handler = |_: &(), params: &Params| format!("{:?}", params.raw("user_id"));
+    for &(method, pattern) in [
+        (Method::GET, "/"),
+        (Method::GET, "/users"),
+        (Method::POST, "/users"),
+        (Method::PATCH, "/users/{user_id: u32}"),
+        (Method::DELETE, "/users/{user_id: u32}"),
+        (Method::GET, "/users/{user_id: u32}/transactions"),
+        (Method::POST, "/users/{user_id: u32}/transactions"),
+        (Method::PATCH, "/users/{user_id: u32}/transactions/{hash}"),
+        (Method::DELETE, "/users/{user_id: u32}/transactions/{hash}"),
+    ]
+    .iter()
+    {
+        router.add(method, pattern, handler).unwrap();
+    }
+    let test_cases = [
+        (Method::GET, "/users"),
+        (Method::PATCH, "/users/12"),
+        (Method::DELETE, "/users/534/transactions/0x234"),
+        (Method::GET, "/users/534/transactions/0x234/items"),
+        (Method::GET, "/u"),
+    ];
+
+    b.iter(|| {
+        let number = rand::random::<usize>() % test_cases.len();
+        let (method, path) = test_cases[number];
+        let _ = router.route(&(), method, path);
+    });
+}
+
+#[bench]
+fn bench_regex_cache_hit(b: &mut Bencher) {
+    let source =
+        r#"^/users/((?:[\w-]|%[0-9A-Fa-f]{2})+)/transactions/((?:[\w-]|%[0-9A-Fa-f]{2})+)$"#;
+    __http_router_create_regex(source);
+    b.iter(|| __http_router_create_regex(source));
+}
+
+#[bench]
+fn bench_pattern_captures(b: &mut Bencher) {
+    let pattern = RoutePattern::parse("/users/{user_id: u32}/transactions/{hash}").unwrap();
+    b.iter(|| {
+        pattern
+            .captures("/users/534/transactions/0x234")
+            .map(|captures| captures.len())
+    });
+}
+
+#[bench]
+fn bench_pattern_captures_inline(b: &mut Bencher) {
+    let pattern = RoutePattern::parse("/users/{user_id: u32}/transactions/{hash}").unwrap();
+    b.iter(|| {
+        pattern
+            .captures_inline::<2>("/users/534/transactions/0x234")
+            .map(|captures| captures.len())
+    });
+}
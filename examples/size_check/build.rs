@@ -0,0 +1,34 @@
+//! Generates a router of 200 routes, so that the binary size of both matchers can be compared
+
+use std::env;
+use std::fs;
+use std::path::Path;
+
+const ROUTES: usize = 200;
+
+fn main() {
+    let header = if env::var_os("CARGO_FEATURE_COMPACT").is_some() {
+        "compact;"
+    } else {
+        ""
+    };
+    let mut routes = String::new();
+    for i in 0..ROUTES {
+        let route = match i % 4 {
+            0 => format!("GET /r{}/items => list_items,", i),
+            1 => format!("GET /r{}/items/{{id: u32}} => get_item,", i),
+            2 => format!("POST /r{}/items/{{id: u32}}/tags/{{tag: String}} => tag_item,", i),
+            _ => format!("DELETE /r{}/items/{{id: u64}} => delete_item,", i),
+        };
+        routes.push_str(&route);
+        routes.push('\n');
+    }
+    let source = format!(
+        "pub fn route(context: &(), method: Method, path: &str) -> String {{\n\
+         let router = router!(\n{}\n{}\n_ => not_found,\n);\n\
+         router(context, method, path)\n}}\n",
+        header, routes
+    );
+    let out_dir = env::var("OUT_DIR").unwrap();
+    fs::write(Path::new(&out_dir).join("router.rs"), source).unwrap();
+}
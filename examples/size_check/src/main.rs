@@ -0,0 +1,39 @@
+//! Router of 200 routes to compare the binary size of the default and the `compact;` matcher:
+//!
+//! ```sh
+//! cargo build --release && ls -l target/release/size_check
+//! cargo build --release --features compact && ls -l target/release/size_check
+//! ```
+
+#[macro_use]
+extern crate http_router;
+
+use http_router::Method;
+use std::env;
+
+fn list_items(_: &&()) -> String {
+    "list".to_string()
+}
+
+fn get_item(_: &&(), id: u32) -> String {
+    format!("get({})", id)
+}
+
+fn tag_item(_: &&(), id: u32, tag: String) -> String {
+    format!("tag({}, {})", id, tag)
+}
+
+fn delete_item(_: &&(), id: u64) -> String {
+    format!("delete({})", id)
+}
+
+fn not_found(_: &&()) -> String {
+    "404".to_string()
+}
+
+include!(concat!(env!("OUT_DIR"), "/router.rs"));
+
+fn main() {
+    let path = env::args().nth(1).unwrap_or_else(|| "/r101/items/7".to_string());
+    println!("{}", route(&(), Method::GET, &path));
+}
@@ -0,0 +1,295 @@
+//! Checks over route tables, e.g. before a release
+
+use crate::method::Method;
+use crate::route_info::{RouteInfo, RouteMeta};
+use std::fmt;
+
+/// Rule checked by `coverage` and `coverage_meta`. Templates are compared as written.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[non_exhaustive]
+pub enum CoverageRule {
+    /// Every template with the first method also has the second, e.g. `HEAD` for each `GET`.
+    /// An `ANY` route of the template counts as having it.
+    RequirePair(Method, Method),
+    /// No route answers the method, e.g. `TRACE`. `ANY` routes answer every method, so they
+    /// break the rule too.
+    ForbidMethod(Method),
+    /// Every route declares the key with `[meta(...)]` rather than falling back to whatever
+    /// the app assumes for routes without it, e.g. `"auth"`. Only `coverage_meta` checks it,
+    /// `RouteInfo`s have no metadata.
+    RequireFallbackMetadata(&'static str),
+}
+
+/// Route breaking a `CoverageRule`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Violation {
+    pub rule: CoverageRule,
+    /// Method of the offending route, `None` for `ANY` routes and scopes
+    pub method: Option<Method>,
+    pub pattern: String,
+}
+
+impl fmt::Display for Violation {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let method = match self.method {
+            Some(method) => format!("{:?}", method),
+            None => "ANY".to_string(),
+        };
+        match self.rule {
+            CoverageRule::RequirePair(_, required) => write!(
+                f,
+                "route `{} {}` has no `{:?}` counterpart",
+                method, self.pattern, required
+            ),
+            CoverageRule::ForbidMethod(_) => write!(
+                f,
+                "route `{} {}` uses a forbidden method",
+                method, self.pattern
+            ),
+            CoverageRule::RequireFallbackMetadata(key) => write!(
+                f,
+                "route `{} {}` has no `{}` metadata",
+                method, self.pattern, key
+            ),
+        }
+    }
+}
+
+/// Route as seen by the rules
+struct Route<'a> {
+    method: Option<Method>,
+    pattern: &'a str,
+    // `None` for routes that can't have any
+    meta: Option<&'a [(&'a str, &'a str)]>,
+}
+
+/// Checks `routes` against each of `rules`, returning the violations by rule, then by route.
+///
+/// Panics on `CoverageRule::RequireFallbackMetadata`, since `RouteInfo`s carry no metadata.
+/// Check it with `coverage_meta` over the `meta` of a `RouteTable` instead.
+pub fn coverage(routes: &[RouteInfo], rules: &[CoverageRule]) -> Vec<Violation> {
+    if let Some(rule) = rules
+        .iter()
+        .find(|rule| matches!(rule, CoverageRule::RequireFallbackMetadata(_)))
+    {
+        panic!(
+            "{:?} needs route metadata, check it with `coverage_meta`",
+            rule
+        );
+    }
+    let routes: Vec<_> = routes
+        .iter()
+        .map(|route| Route {
+            method: Some(route.method),
+            pattern: &route.pattern,
+            meta: None,
+        })
+        .collect();
+    check(&routes, rules)
+}
+
+/// Checks the `meta` of a `RouteTable` against each of `rules` like `coverage`
+pub fn coverage_meta(routes: &[RouteMeta], rules: &[CoverageRule]) -> Vec<Violation> {
+    let routes: Vec<_> = routes
+        .iter()
+        .map(|route| Route {
+            method: route.method,
+            pattern: route.pattern,
+            meta: Some(route.meta),
+        })
+        .collect();
+    check(&routes, rules)
+}
+
+fn check(routes: &[Route], rules: &[CoverageRule]) -> Vec<Violation> {
+    // `ANY` routes answer every method
+    let has = |method: Method, pattern: &str| {
+        routes
+            .iter()
+            .any(|route| route.method.map_or(true, |m| m == method) && route.pattern == pattern)
+    };
+    let mut violations = Vec::new();
+    for &rule in rules {
+        for route in routes {
+            let violated = match rule {
+                CoverageRule::RequirePair(present, required) => {
+                    route.method == Some(present) && !has(required, route.pattern)
+                }
+                CoverageRule::ForbidMethod(method) => route.method.map_or(true, |m| m == method),
+                CoverageRule::RequireFallbackMetadata(key) => !route
+                    .meta
+                    .is_some_and(|meta| meta.iter().any(|&(name, _)| name == key)),
+            };
+            if violated {
+                violations.push(Violation {
+                    rule,
+                    method: route.method,
+                    pattern: route.pattern.to_string(),
+                });
+            }
+        }
+    }
+    violations
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn table(routes: &[(Method, &str)]) -> Vec<RouteInfo> {
+        routes
+            .iter()
+            .map(|&(method, pattern)| RouteInfo {
+                method,
+                pattern: pattern.to_string(),
+                params: Vec::new(),
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_passing_table() {
+        let routes = table(&[
+            (Method::GET, "/users"),
+            (Method::HEAD, "/users"),
+            (Method::POST, "/users"),
+            (Method::GET, "/users/{id}"),
+            (Method::HEAD, "/users/{id}"),
+            (Method::DELETE, "/users/{id}"),
+        ]);
+        let rules = [
+            CoverageRule::RequirePair(Method::GET, Method::HEAD),
+            CoverageRule::ForbidMethod(Method::TRACE),
+        ];
+        assert_eq!(coverage(&routes, &rules), vec![]);
+    }
+
+    #[test]
+    fn test_require_pair() {
+        let routes = table(&[
+            (Method::GET, "/users"),
+            (Method::HEAD, "/users"),
+            (Method::GET, "/users/{id}"),
+        ]);
+        let violations = coverage(
+            &routes,
+            &[CoverageRule::RequirePair(Method::GET, Method::HEAD)],
+        );
+        assert_eq!(
+            violations,
+            vec![Violation {
+                rule: CoverageRule::RequirePair(Method::GET, Method::HEAD),
+                method: Some(Method::GET),
+                pattern: "/users/{id}".to_string(),
+            }]
+        );
+        assert_eq!(
+            violations[0].to_string(),
+            "route `GET /users/{id}` has no `HEAD` counterpart"
+        );
+    }
+
+    #[test]
+    fn test_require_pair_any() {
+        let rule = CoverageRule::RequirePair(Method::GET, Method::HEAD);
+        let mut routes = vec![meta("/files/{path: ..}", &[])];
+        assert_eq!(coverage_meta(&routes, &[rule]).len(), 1);
+
+        // an `ANY` route of the same template answers `HEAD` too
+        routes.push(RouteMeta {
+            method: None,
+            ..meta("/files/{path: ..}", &[])
+        });
+        assert_eq!(coverage_meta(&routes, &[rule]), vec![]);
+    }
+
+    #[test]
+    fn test_forbid_method() {
+        let routes = table(&[(Method::GET, "/debug"), (Method::TRACE, "/debug")]);
+        let violations = coverage(&routes, &[CoverageRule::ForbidMethod(Method::TRACE)]);
+        assert_eq!(violations.len(), 1);
+        assert_eq!(
+            violations[0].to_string(),
+            "route `TRACE /debug` uses a forbidden method"
+        );
+    }
+
+    #[test]
+    fn test_forbid_method_any() {
+        let rule = CoverageRule::ForbidMethod(Method::TRACE);
+        let routes = [
+            meta("/users", &[]),
+            RouteMeta {
+                method: None,
+                ..meta("/files/{path: ..}", &[])
+            },
+        ];
+        // an `ANY` route answers `TRACE` too
+        let violations = coverage_meta(&routes, &[rule]);
+        assert_eq!(
+            violations,
+            vec![Violation {
+                rule,
+                method: None,
+                pattern: "/files/{path: ..}".to_string(),
+            }]
+        );
+        assert_eq!(
+            violations[0].to_string(),
+            "route `ANY /files/{path: ..}` uses a forbidden method"
+        );
+    }
+
+    fn meta(pattern: &'static str, meta: &'static [(&'static str, &'static str)]) -> RouteMeta {
+        RouteMeta {
+            method: Some(Method::GET),
+            pattern,
+            params: &[],
+            meta,
+        }
+    }
+
+    #[test]
+    fn test_require_fallback_metadata() {
+        let rule = CoverageRule::RequireFallbackMetadata("auth");
+        let passing = [
+            meta("/users", &[("auth", "user")]),
+            meta("/health", &[("cache", "none"), ("auth", "none")]),
+        ];
+        assert_eq!(coverage_meta(&passing, &[rule]), vec![]);
+
+        let mut failing = passing.to_vec();
+        failing.push(meta("/admin", &[("cache", "none")]));
+        failing.push(RouteMeta {
+            method: None,
+            ..meta("/files/{path: ..}", &[])
+        });
+        let violations = coverage_meta(&failing, &[rule]);
+        assert_eq!(
+            violations,
+            vec![
+                Violation {
+                    rule,
+                    method: Some(Method::GET),
+                    pattern: "/admin".to_string(),
+                },
+                Violation {
+                    rule,
+                    method: None,
+                    pattern: "/files/{path: ..}".to_string(),
+                },
+            ]
+        );
+        assert_eq!(
+            violations[1].to_string(),
+            "route `ANY /files/{path: ..}` has no `auth` metadata"
+        );
+    }
+
+    #[test]
+    #[should_panic(expected = "RequireFallbackMetadata(\"auth\") needs route metadata")]
+    fn test_require_fallback_metadata_without_meta() {
+        let routes = table(&[(Method::GET, "/users")]);
+        coverage(&routes, &[CoverageRule::RequireFallbackMetadata("auth")]);
+    }
+}
@@ -0,0 +1,182 @@
+use crate::error::PatternParseError;
+use crate::pattern::{ParamType, RoutePattern, Segment};
+use std::str::FromStr;
+
+/// A template for the authority of a request, i.e. `host` or `host:port` like the target of
+/// a `CONNECT` or a `Host` header, parsed at runtime, e.g. `{tenant}.example.com:{port: u16}`.
+///
+/// The host is matched like a `RoutePattern` with `.` separated segments, and the port as a
+/// single segment. A host made of one untyped or `String` param, like `{name}`, captures the
+/// whole host, dots included. Without a port in the template, the port of the authority is ignored.
+/// Hosts are compared as is, so lowercase them first.
+#[derive(Debug, Clone)]
+pub struct AuthorityPattern {
+    template: String,
+    host: RoutePattern,
+    port: Option<RoutePattern>,
+}
+
+impl AuthorityPattern {
+    /// Parses a template, e.g. `{name}:{port: u16}`. IPv6 hosts are written in brackets,
+    /// e.g. `[::1]:{port: u16}`.
+    pub fn parse(template: &str) -> Result<AuthorityPattern, PatternParseError> {
+        let (host, port) = match port_separator(template) {
+            Some(index) => (&template[..index], Some(&template[index + 1..])),
+            None => (template, None),
+        };
+        let mut host = RoutePattern::parse_with_separator(host, '.')?;
+        if let [Segment::Param(ref name)] | [Segment::TypedParam(ref name, ParamType::String)] =
+            *host.segments()
+        {
+            // a catch-all, with its `..` escaped as `.` separates the segments
+            host = RoutePattern::parse_with_separator(&format!(r"{{{}: \.\.}}", name), '.')?;
+        }
+        let port = match port {
+            Some("") => {
+                return Err(PatternParseError::EmptySegment {
+                    template: template.to_string(),
+                    position: template.len(),
+                })
+            }
+            Some(port) => Some(RoutePattern::parse_with_separator(port, '.')?),
+            None => None,
+        };
+        if let Some(ref port) = port {
+            let name = port
+                .segments()
+                .iter()
+                .filter_map(|s| s.param_name())
+                .find(|&name| {
+                    host.segments()
+                        .iter()
+                        .any(|segment| segment.param_name() == Some(name))
+                });
+            if let Some(name) = name {
+                return Err(PatternParseError::DuplicateParam {
+                    template: template.to_string(),
+                    name: name.to_string(),
+                    position: template.len() - port.template().len(),
+                });
+            }
+        }
+        Ok(AuthorityPattern {
+            template: template.to_string(),
+            host,
+            port,
+        })
+    }
+
+    /// The original template string
+    pub fn template(&self) -> &str {
+        &self.template
+    }
+
+    /// Pattern of the host part
+    pub fn host(&self) -> &RoutePattern {
+        &self.host
+    }
+
+    /// Pattern of the port part, if the template has one
+    pub fn port(&self) -> Option<&RoutePattern> {
+        self.port.as_ref()
+    }
+
+    /// Matches `authority` against the pattern, returning the captured params of the host,
+    /// then of the port, e.g. `[("name", "example.com"), ("port", "443")]` for
+    /// `example.com:443` and `{name}:{port: u16}`.
+    pub fn captures<'a>(&self, authority: &'a str) -> Option<Vec<(&str, &'a str)>> {
+        let (host, port) = match port_separator(authority) {
+            Some(index) => (&authority[..index], Some(&authority[index + 1..])),
+            None => (authority, None),
+        };
+        let mut captures = self.host.captures(host)?;
+        if let Some(ref pattern) = self.port {
+            captures.extend(pattern.captures(port?)?);
+        }
+        Some(captures)
+    }
+
+    pub fn is_match(&self, authority: &str) -> bool {
+        self.captures(authority).is_some()
+    }
+}
+
+impl FromStr for AuthorityPattern {
+    type Err = PatternParseError;
+
+    fn from_str(template: &str) -> Result<AuthorityPattern, PatternParseError> {
+        AuthorityPattern::parse(template)
+    }
+}
+
+/// Index of the `:` before the port, skipping those inside `{...}` params and `[...]`
+/// IPv6 addresses
+fn port_separator(authority: &str) -> Option<usize> {
+    let mut depth = 0usize;
+    let mut separator = None;
+    for (index, c) in authority.char_indices() {
+        match c {
+            '{' | '[' => depth += 1,
+            '}' | ']' => depth = depth.saturating_sub(1),
+            ':' if depth == 0 => separator = Some(index),
+            _ => {}
+        }
+    }
+    separator
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_authority_captures() {
+        let pattern = AuthorityPattern::parse("{name: String}:{port: u16}").unwrap();
+        assert_eq!(
+            pattern.captures("example.com:443"),
+            Some(vec![("name", "example.com"), ("port", "443")])
+        );
+        assert_eq!(pattern.captures("example.com"), None);
+        assert_eq!(pattern.captures("example.com:https"), None);
+        assert_eq!(pattern.captures("example.com:70000"), None);
+
+        let tenant = AuthorityPattern::parse("{tenant}.example.com").unwrap();
+        assert!(tenant.port().is_none());
+        assert_eq!(
+            tenant.captures("acme.example.com:8080"),
+            Some(vec![("tenant", "acme")])
+        );
+        assert_eq!(
+            tenant.captures("acme.example.com"),
+            Some(vec![("tenant", "acme")])
+        );
+        assert!(!tenant.is_match("example.com"));
+
+        let any_port = AuthorityPattern::parse("{host}").unwrap();
+        assert_eq!(
+            any_port.captures("example.com:80"),
+            Some(vec![("host", "example.com")])
+        );
+
+        let local = AuthorityPattern::parse("[::1]:{port: u16}").unwrap();
+        assert_eq!(local.captures("[::1]:8080"), Some(vec![("port", "8080")]));
+        assert!(!local.is_match("[::2]:8080"));
+    }
+
+    #[test]
+    fn test_authority_parse_errors() {
+        assert!(matches!(
+            AuthorityPattern::parse("example.com:"),
+            Err(PatternParseError::EmptySegment { .. })
+        ));
+        assert_eq!(
+            AuthorityPattern::parse("{name}:{name: u16}").unwrap_err(),
+            PatternParseError::DuplicateParam {
+                template: "{name}:{name: u16}".to_string(),
+                name: "name".to_string(),
+                position: 7,
+            }
+        );
+        assert!("{host}:{port: nope}".parse::<AuthorityPattern>().is_err());
+    }
+}
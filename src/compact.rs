@@ -0,0 +1,336 @@
+//! Matching loop of `compact;` routers. The macro only emits a table of the routes and the
+//! calls of their handlers, the matching is done here for all of them.
+
+use crate::method::Method;
+use crate::normalize::Normalize;
+use crate::{
+    __http_router_create_regex, __http_router_push_literal, __http_router_push_param_regex,
+    __http_router_push_segment, match_segments,
+};
+use regex::Regex;
+use std::borrow::Cow;
+use std::sync::OnceLock;
+
+/// Segment of a route in the table of a `compact;` router. The macro writes the segments
+/// of a route into one string, each a kind byte and the text up to a `\0`, e.g.
+/// `iusers\0p{id: u32}\0` for `/users/{id: u32}`, so that the table holds no pointer per
+/// segment.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Part<'s> {
+    /// `i`, literal of a single identifier, routes of only these are matched without a regex
+    Ident(&'s str),
+    /// `l`, literal of several tokens, like `well-known`
+    Literal(&'s str),
+    /// `p`, `{name: type}` as written
+    Param(&'s str),
+    /// `c`, `{name: ..}`
+    CatchAll,
+    /// `a`, `*` of `METHOD / *`
+    Any,
+    /// `e`, file extension of an `ext { ... }` route, with the dot
+    Extension(&'s str),
+}
+
+fn parts(encoded: &str) -> impl Iterator<Item = Part<'_>> {
+    encoded.split_terminator('\0').map(|part| {
+        let text = &part[1..];
+        match part.as_bytes()[0] {
+            b'i' => Part::Ident(text),
+            b'l' => Part::Literal(text),
+            b'p' => Part::Param(text),
+            b'c' => Part::CatchAll,
+            b'a' => Part::Any,
+            b'e' => Part::Extension(text),
+            kind => unreachable!("unknown route part kind `{}`", kind as char),
+        }
+    })
+}
+
+/// This is an implementation detail and *should not* be used directly!
+#[doc(hidden)]
+pub struct __HttpRouterTableRoute {
+    /// `None` for `ANY` routes and scopes
+    pub method: Option<Method>,
+    pub any: bool,
+    pub scope: bool,
+    pub catch_all: bool,
+    /// Has a `String` param, tried after the other routes with `specificity_order;`
+    pub loose: bool,
+    /// Segments, see `Part`
+    pub parts: &'static str,
+    /// Applies a `[normalize(...)]` attribute to the normalization of the router
+    pub normalize: Option<fn(Normalize) -> Normalize>,
+}
+
+/// This is an implementation detail and *should not* be used directly!
+///
+/// Regexes of the routes of a `compact;` router, compiled when first needed. Two per route,
+/// for case sensitive and insensitive matching.
+#[doc(hidden)]
+pub struct __HttpRouterRegexes(OnceLock<Box<[OnceLock<Regex>]>>);
+
+impl __HttpRouterRegexes {
+    #[allow(clippy::new_without_default)]
+    pub const fn new() -> Self {
+        __HttpRouterRegexes(OnceLock::new())
+    }
+
+    fn get(&self, routes: usize, index: usize, ignore_case: bool) -> &OnceLock<Regex> {
+        let regexes = self
+            .0
+            .get_or_init(|| (0..routes * 2).map(|_| OnceLock::new()).collect());
+        &regexes[index * 2 + ignore_case as usize]
+    }
+}
+
+/// This is an implementation detail and *should not* be used directly!
+///
+/// Route whose path matched, its params still have to be parsed
+#[doc(hidden)]
+pub struct __HttpRouterTableMatch<'a> {
+    pub index: usize,
+    path: Cow<'a, str>,
+    ranges: Vec<(usize, usize)>,
+    // start of the rest of the path after a scope
+    rest: usize,
+}
+
+impl<'a> __HttpRouterTableMatch<'a> {
+    /// Captured params in route order
+    pub fn params(&self) -> Vec<&str> {
+        self.ranges
+            .iter()
+            .map(|&(start, end)| &self.path[start..end])
+            .collect()
+    }
+
+    /// Rest of the path after a scope, `/` if nothing is left
+    pub fn rest(&self) -> &str {
+        match &self.path[self.rest..] {
+            "" => "/",
+            rest => rest,
+        }
+    }
+}
+
+/// Params of the route found by `RouteTable::match_pattern`, as matched, e.g. `"12"`
+/// for `{id: u32}`. Values aren't parsed into their types, and percent-encoded ones aren't
+/// decoded.
+pub struct RouteCaptures<'a>(__HttpRouterTableMatch<'a>);
+
+impl<'a> RouteCaptures<'a> {
+    #[doc(hidden)]
+    pub fn from_match(found: __HttpRouterTableMatch<'a>) -> (usize, Self) {
+        (found.index, RouteCaptures(found))
+    }
+
+    /// Value of the `index`th param of the route
+    pub fn get(&self, index: usize) -> Option<&str> {
+        let &(start, end) = self.0.ranges.get(index)?;
+        Some(&self.0.path[start..end])
+    }
+
+    /// Values of the params of the route, in the order of the pattern
+    pub fn iter(&self) -> impl Iterator<Item = &str> {
+        self.0
+            .ranges
+            .iter()
+            .map(move |&(start, end)| &self.0.path[start..end])
+    }
+
+    /// Number of params of the route
+    pub fn len(&self) -> usize {
+        self.0.ranges.len()
+    }
+
+    /// Whether the route has no params
+    pub fn is_empty(&self) -> bool {
+        self.0.ranges.is_empty()
+    }
+
+    /// Rest of the path after a scope, `/` if nothing is left
+    pub fn rest(&self) -> &str {
+        self.0.rest()
+    }
+}
+
+/// This is an implementation detail and *should not* be used directly!
+///
+/// Routes matching `method` and `path`, in the order `router!` tries them
+#[doc(hidden)]
+pub fn __http_router_table_matches<'a>(
+    routes: &'static [__HttpRouterTableRoute],
+    regexes: &'static __HttpRouterRegexes,
+    method: Method,
+    path: &'a str,
+    normalize: Normalize,
+    specificity_order: bool,
+) -> __HttpRouterTableMatches<'a> {
+    __HttpRouterTableMatches {
+        routes,
+        regexes,
+        method,
+        path,
+        normalized: normalize.path(path),
+        normalize,
+        specificity_order,
+        pass: 0,
+        index: 0,
+    }
+}
+
+/// This is an implementation detail and *should not* be used directly!
+#[doc(hidden)]
+pub struct __HttpRouterTableMatches<'a> {
+    routes: &'static [__HttpRouterTableRoute],
+    regexes: &'static __HttpRouterRegexes,
+    method: Method,
+    path: &'a str,
+    // `path` normalized for the routes without a `[normalize(...)]`, once for all of them
+    normalized: Cow<'a, str>,
+    normalize: Normalize,
+    specificity_order: bool,
+    pass: usize,
+    index: usize,
+}
+
+impl<'a> Iterator for __HttpRouterTableMatches<'a> {
+    type Item = __HttpRouterTableMatch<'a>;
+
+    fn next(&mut self) -> Option<__HttpRouterTableMatch<'a>> {
+        // catch-all routes are tried after all the others, and with `specificity_order;`
+        // `ANY` routes after the ones with a method, each with `String` params last
+        const PASSES: [(bool, bool, bool); 8] = [
+            (false, false, false),
+            (false, false, true),
+            (false, true, false),
+            (false, true, true),
+            (true, false, false),
+            (true, false, true),
+            (true, true, false),
+            (true, true, true),
+        ];
+        while let Some(&(catch_all, any, loose)) = PASSES.get(self.pass) {
+            while let Some(route) = self.routes.get(self.index) {
+                let index = self.index;
+                self.index += 1;
+                let in_pass = route.catch_all == catch_all
+                    && if self.specificity_order {
+                        route.any == any && route.loose == loose
+                    } else {
+                        !any && !loose
+                    };
+                if !in_pass {
+                    continue;
+                }
+                if let Some(found) = self.matches(index, route) {
+                    return Some(found);
+                }
+            }
+            self.pass += 1;
+            self.index = 0;
+        }
+        None
+    }
+}
+
+impl<'a> __HttpRouterTableMatches<'a> {
+    fn matches(
+        &self,
+        index: usize,
+        route: &__HttpRouterTableRoute,
+    ) -> Option<__HttpRouterTableMatch<'a>> {
+        if route.method.is_some_and(|method| method != self.method) {
+            return None;
+        }
+        let (normalize, own_path) = match route.normalize {
+            Some(normalize) => {
+                let normalize = normalize(self.normalize);
+                (normalize, Some(normalize.path(self.path)))
+            }
+            None => (self.normalize, None),
+        };
+        let path: &str = own_path.as_deref().unwrap_or(&self.normalized);
+        let ignore_case = normalize.ignore_case;
+        let any_case = |segment: &str| normalize.any_case(segment);
+        let idents = || {
+            parts(route.parts).map(|part| match part {
+                Part::Ident(ident) => ident,
+                _ => unreachable!("scopes and static routes only have identifiers"),
+            })
+        };
+        let found = |ranges, rest| {
+            Some(__HttpRouterTableMatch {
+                index,
+                path: own_path.clone().unwrap_or_else(|| self.normalized.clone()),
+                ranges,
+                rest,
+            })
+        };
+        if route.scope {
+            let rest = match_segments(path, idents(), any_case)?;
+            return found(Vec::new(), rest);
+        }
+        let is_static = parts(route.parts).all(|part| matches!(part, Part::Ident(_)));
+        if is_static {
+            let matched = if route.parts.is_empty() {
+                path == "/"
+            } else {
+                match_segments(path, idents(), any_case) == Some(path.len())
+            };
+            let len = path.len();
+            return if matched {
+                found(Vec::new(), len)
+            } else {
+                None
+            };
+        }
+        let regex = self
+            .regexes
+            .get(self.routes.len(), index, ignore_case)
+            .get_or_init(|| __http_router_create_regex(&route_regex(route.parts, &normalize)));
+        // `captures` allocates even if the path doesn't match
+        if !regex.is_match(path) {
+            return None;
+        }
+        let ranges = regex
+            .captures(path)?
+            .iter()
+            .skip(1)
+            .flatten()
+            .map(|capture| (capture.start(), capture.end()))
+            .collect();
+        let len = path.len();
+        found(ranges, len)
+    }
+}
+
+/// Same regex as `router!` builds for a route
+fn route_regex(encoded: &str, normalize: &Normalize) -> String {
+    let mut s = if normalize.ignore_case { "^(?i)" } else { "^" }.to_string();
+    for part in parts(encoded) {
+        match part {
+            Part::Ident(ident) => {
+                s.push('/');
+                __http_router_push_segment(&mut s, ident, normalize);
+            }
+            Part::Literal(literal) => {
+                s.push('/');
+                __http_router_push_segment(&mut s, literal, normalize);
+            }
+            Part::Param(param) => {
+                s.push('/');
+                __http_router_push_param_regex(&mut s, param);
+            }
+            Part::CatchAll => s.push_str("/(.+)"),
+            Part::Any => s.push_str("/.*"),
+            Part::Extension(extension) => __http_router_push_literal(&mut s, extension),
+        }
+    }
+    // handle home case
+    if s.ends_with('^') {
+        s.push('/')
+    }
+    s.push('$');
+    s
+}
@@ -0,0 +1,1862 @@
+use crate::error::{ParamError, RouteConflict, RouteError};
+use crate::method::Method;
+use crate::parsers;
+use crate::pattern::RoutePattern;
+use crate::route_info::RouteInfo;
+use crate::segments::{matched_segments, SegmentFilter, SegmentOffsets};
+use crate::stateful::StatefulRouter;
+#[cfg(feature = "trace")]
+use crate::trace::{CandidateTrace, TraceOutcome};
+use arc_swap::{ArcSwap, ArcSwapOption};
+use std::collections::HashMap;
+use std::ops;
+use std::str::FromStr;
+use std::sync::Arc;
+
+/// Params captured from a path by `DynamicRouter`, in the order of the route template
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Params {
+    entries: Vec<(String, String)>,
+    // raw values of `entries` matched by `route_bytes`, which may not be valid UTF-8
+    bytes: Vec<Vec<u8>>,
+    rest: Option<String>,
+    partial: Option<PartialMatch>,
+}
+
+/// Routes matching the longest start of a path that no route matched, passed to the fallback
+/// in diagnose mode, see `DynamicRouter::diagnose_mode`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PartialMatch {
+    /// Byte length of the matched start of the path, e.g. 6 for `/users` of `/users/abc`
+    pub matched_len: usize,
+    /// Routes matching that start, in the order they are tried, e.g. for "did you mean"
+    pub candidates: Vec<RouteInfo>,
+}
+
+impl Params {
+    /// Parses the param named `name` into `T`
+    pub fn get<T: FromStr>(&self, name: &str) -> Result<T, ParamError> {
+        let value = self
+            .raw(name)
+            .ok_or_else(|| ParamError::Missing(name.to_string()))?;
+        value.parse::<T>().map_err(|_| ParamError::Invalid {
+            name: name.to_string(),
+            value: value.to_string(),
+        })
+    }
+
+    /// Parses the param named `name` with the parser registered for `T` by
+    /// `register_param_parser`, for types without a `FromStr` impl. The value is invalid if
+    /// there is none.
+    pub fn get_registered<T: 'static>(&self, name: &str) -> Result<T, ParamError> {
+        let value = self
+            .raw(name)
+            .ok_or_else(|| ParamError::Missing(name.to_string()))?;
+        parsers::parse_registered(value).ok_or_else(|| ParamError::Invalid {
+            name: name.to_string(),
+            value: value.to_string(),
+        })
+    }
+
+    /// Raw string value of the param named `name`
+    pub fn raw(&self, name: &str) -> Option<&str> {
+        self.entries
+            .iter()
+            .find(|(n, _)| n == name)
+            .map(|(_, v)| v.as_str())
+    }
+
+    /// Raw bytes of the param named `name`. Unlike `raw`, they are exactly the bytes of the
+    /// path if it was matched by `DynamicRouter::route_bytes`.
+    pub fn raw_bytes(&self, name: &str) -> Option<&[u8]> {
+        let index = self.entries.iter().position(|(n, _)| n == name)?;
+        match self.bytes.get(index) {
+            Some(bytes) => Some(bytes),
+            None => Some(self.entries[index].1.as_bytes()),
+        }
+    }
+
+    /// Iterates over `(name, value)` pairs in template order
+    pub fn iter(&self) -> impl Iterator<Item = (&str, &str)> {
+        self.entries.iter().map(|(n, v)| (n.as_str(), v.as_str()))
+    }
+
+    /// Unmatched rest of the path in prefix match mode, see `DynamicRouter::prefix_match_mode`
+    pub fn rest(&self) -> Option<&str> {
+        self.rest.as_deref()
+    }
+
+    /// Partial match of the path for the fallback in diagnose mode, `None` for route
+    /// handlers and if no route matched a single segment
+    pub fn partial_match(&self) -> Option<&PartialMatch> {
+        self.partial.as_ref()
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /// The params as a JSON object of names to raw values in template order, e.g.
+    /// `{"id":"12","slug":"hello"}`, for debug endpoints echoing what the router captured
+    #[cfg(feature = "with_serde")]
+    pub fn to_json(&self) -> String {
+        serde_json::to_string(self).expect("Failed to serialize params")
+    }
+}
+
+/// Serializes as a map of param names to raw values in template order
+#[cfg(feature = "with_serde")]
+impl serde::Serialize for Params {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_map(self.iter())
+    }
+}
+
+/// Object-safe handler used by `DynamicRouter`.
+///
+/// Implemented for every `Fn(&C, &Params) -> R + Send + Sync`, so plugins can
+/// hand over plain closures as `Box<dyn DynHandler<C, R>>`.
+pub trait DynHandler<C, R>: Send + Sync {
+    fn call(&self, context: &C, params: &Params) -> R;
+}
+
+impl<C, R, F> DynHandler<C, R> for F
+where
+    F: Fn(&C, &Params) -> R + Send + Sync,
+{
+    fn call(&self, context: &C, params: &Params) -> R {
+        self(context, params)
+    }
+}
+
+/// Boxed handler as taken by `DynamicRouter`
+pub type BoxedHandler<C, R> = Box<dyn DynHandler<C, R>>;
+
+/// Plain function handler as taken by `DynamicRouter::from_routes`
+pub type HandlerFn<C, R> = fn(&C, &Params) -> R;
+
+/// Handler shared by the routes of `add_methods`
+struct SharedHandler<H>(Arc<H>);
+
+impl<C, R, H: DynHandler<C, R>> DynHandler<C, R> for SharedHandler<H> {
+    fn call(&self, context: &C, params: &Params) -> R {
+        self.0.call(context, params)
+    }
+}
+
+struct DynRoute<C, R> {
+    method: Method,
+    pattern: RoutePattern,
+    // shared with the copies of the routes made while requests still use them
+    handler: Arc<dyn DynHandler<C, R>>,
+    // shared with the callers of `route_for`
+    info: Arc<RouteInfo>,
+    // rejects most paths without running the regex
+    filter: Option<SegmentFilter>,
+}
+
+impl<C, R> DynRoute<C, R> {
+    fn new(method: Method, pattern: RoutePattern, handler: Box<dyn DynHandler<C, R>>) -> Self {
+        let info = Arc::new(RouteInfo::from_pattern(method, &pattern));
+        let filter = SegmentFilter::new(&pattern);
+        DynRoute {
+            method,
+            pattern,
+            handler: Arc::from(handler),
+            info,
+            filter,
+        }
+    }
+}
+
+impl<C, R> Clone for DynRoute<C, R> {
+    fn clone(&self) -> Self {
+        DynRoute {
+            method: self.method,
+            pattern: self.pattern.clone(),
+            handler: Arc::clone(&self.handler),
+            info: Arc::clone(&self.info),
+            filter: self.filter.clone(),
+        }
+    }
+}
+
+fn find_equivalent<C, R>(
+    routes: &[DynRoute<C, R>],
+    method: Method,
+    pattern: &RoutePattern,
+) -> Option<usize> {
+    routes
+        .iter()
+        .position(|route| route.method == method && route.pattern.is_equivalent(pattern))
+}
+
+fn check_conflict<C, R>(
+    routes: &[DynRoute<C, R>],
+    method: Method,
+    pattern: &RoutePattern,
+) -> Result<(), RouteError> {
+    match find_equivalent(routes, method, pattern) {
+        Some(index) => Err(RouteError::Conflict(RouteConflict {
+            existing: RouteInfo::clone(&routes[index].info),
+            new: RouteInfo::from_pattern(method, pattern),
+        })),
+        None => Ok(()),
+    }
+}
+
+/// Route of `DynamicRouter::reload`, with the template still to be parsed
+pub struct RouteDefinition<C, R> {
+    pub method: Method,
+    /// Template like `/users/{id}`, see `RoutePattern::parse`
+    pub pattern: String,
+    pub handler: BoxedHandler<C, R>,
+}
+
+impl<C, R> RouteDefinition<C, R> {
+    pub fn new<H>(method: Method, pattern: &str, handler: H) -> Self
+    where
+        H: DynHandler<C, R> + 'static,
+    {
+        RouteDefinition {
+            method,
+            pattern: pattern.to_string(),
+            handler: Box::new(handler),
+        }
+    }
+}
+
+/// Router over handlers registered at runtime, e.g. loaded from plugins.
+///
+/// Routes are tried in the order they were added, same as with `router!`, or by specificity
+/// in best match mode.
+pub struct DynamicRouter<C, R> {
+    // replaced as a whole by `reload` without blocking requests, which keep the routes they
+    // started with
+    routes: ArcSwap<Vec<DynRoute<C, R>>>,
+    fallback: ArcSwapOption<BoxedHandler<C, R>>,
+    prefix_match: bool,
+    diagnose: bool,
+    head_with_get: bool,
+    best_match: bool,
+}
+
+// order of the methods in `allowed_methods`
+const ALLOW_ORDER: [Method; 11] = [
+    Method::GET,
+    Method::HEAD,
+    Method::POST,
+    Method::PUT,
+    Method::PATCH,
+    Method::DELETE,
+    Method::CONNECT,
+    Method::TRACE,
+    Method::LOCK,
+    Method::UNLOCK,
+    Method::OPTIONS,
+];
+
+impl<C, R> Default for DynamicRouter<C, R> {
+    fn default() -> Self {
+        DynamicRouter {
+            routes: ArcSwap::from_pointee(Vec::new()),
+            fallback: ArcSwapOption::empty(),
+            prefix_match: false,
+            diagnose: false,
+            head_with_get: false,
+            best_match: false,
+        }
+    }
+}
+
+impl<C, R> DynamicRouter<C, R> {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Builds a router from a table of routes, e.g.
+    /// `&[(Method::GET, "/users/{id}", get_user)]` with `fn get_user(&C, &Params) -> R`.
+    ///
+    /// Fails on the first invalid or conflicting route, see `add`.
+    pub fn from_routes(routes: &[(Method, &str, HandlerFn<C, R>)]) -> Result<Self, RouteError>
+    where
+        C: 'static,
+        R: 'static,
+    {
+        let mut router = DynamicRouter::new();
+        for &(method, pattern, handler) in routes {
+            router.add(method, pattern, handler)?;
+        }
+        Ok(router)
+    }
+
+    /// Registers a boxed handler for `method` and a template like `/users/{id}`.
+    ///
+    /// Fails if a route with the same method and an equivalent template is already registered.
+    pub fn add_boxed(
+        &mut self,
+        method: Method,
+        pattern: &str,
+        handler: Box<dyn DynHandler<C, R>>,
+    ) -> Result<&mut Self, RouteError> {
+        let pattern = RoutePattern::parse(pattern)?;
+        self.push(method, pattern, handler)
+    }
+
+    /// Same as `add_boxed`, but boxes the handler for you
+    pub fn add<H>(
+        &mut self,
+        method: Method,
+        pattern: &str,
+        handler: H,
+    ) -> Result<&mut Self, RouteError>
+    where
+        H: DynHandler<C, R> + 'static,
+    {
+        self.add_boxed(method, pattern, Box::new(handler))
+    }
+
+    /// Same as `add`, but registers the route for each of `methods`, sharing the handler.
+    /// The template is parsed once.
+    ///
+    /// Fails without adding any route if one of them conflicts with a registered route, or
+    /// if a method is listed twice.
+    pub fn add_methods<H>(
+        &mut self,
+        methods: &[Method],
+        pattern: &str,
+        handler: H,
+    ) -> Result<&mut Self, RouteError>
+    where
+        H: DynHandler<C, R> + 'static,
+    {
+        let pattern = RoutePattern::parse(pattern)?;
+        self.update_routes(|routes| {
+            for (i, &method) in methods.iter().enumerate() {
+                if methods[..i].contains(&method) {
+                    let info = RouteInfo::from_pattern(method, &pattern);
+                    return Err(RouteError::Conflict(RouteConflict {
+                        existing: info.clone(),
+                        new: info,
+                    }));
+                }
+                check_conflict(routes, method, &pattern)?;
+            }
+            let handler = Arc::new(handler);
+            for &method in methods {
+                routes.push(DynRoute::new(
+                    method,
+                    pattern.clone(),
+                    Box::new(SharedHandler(handler.clone())),
+                ));
+            }
+            Ok(())
+        })?;
+        Ok(self)
+    }
+
+    /// Same as `add`, but replaces the handler of an equivalent route instead of failing.
+    /// The replaced route keeps its position.
+    pub fn add_or_replace<H>(
+        &mut self,
+        method: Method,
+        pattern: &str,
+        handler: H,
+    ) -> Result<&mut Self, RouteError>
+    where
+        H: DynHandler<C, R> + 'static,
+    {
+        let pattern = RoutePattern::parse(pattern)?;
+        self.update_routes(|routes| match find_equivalent(routes, method, &pattern) {
+            Some(index) => {
+                routes[index] = DynRoute::new(method, pattern, Box::new(handler));
+            }
+            None => {
+                routes.push(DynRoute::new(method, pattern, Box::new(handler)));
+            }
+        });
+        Ok(self)
+    }
+
+    /// Registers a handler for an OpenAPI path template, see `RoutePattern::from_openapi`.
+    ///
+    /// Params are available under their OpenAPI names.
+    pub fn add_openapi<H>(
+        &mut self,
+        method: Method,
+        template: &str,
+        handler: H,
+    ) -> Result<&mut Self, RouteError>
+    where
+        H: DynHandler<C, R> + 'static,
+    {
+        let pattern = RoutePattern::from_openapi(template)?;
+        self.push(method, pattern, Box::new(handler))
+    }
+
+    /// Registers a handler for a template whose segments are separated by `separator`, see
+    /// `RoutePattern::parse_with_separator`, e.g. `users\.v2/info.{id}` with `.`.
+    pub fn add_with_separator<H>(
+        &mut self,
+        method: Method,
+        template: &str,
+        separator: char,
+        handler: H,
+    ) -> Result<&mut Self, RouteError>
+    where
+        H: DynHandler<C, R> + 'static,
+    {
+        let pattern = RoutePattern::parse_with_separator(template, separator)?;
+        self.push(method, pattern, Box::new(handler))
+    }
+
+    fn check_conflict(&self, method: Method, pattern: &RoutePattern) -> Result<(), RouteError> {
+        check_conflict(&self.current_routes(), method, pattern)
+    }
+
+    fn push(
+        &mut self,
+        method: Method,
+        pattern: RoutePattern,
+        handler: Box<dyn DynHandler<C, R>>,
+    ) -> Result<&mut Self, RouteError> {
+        self.update_routes(|routes| {
+            check_conflict(routes, method, &pattern)?;
+            routes.push(DynRoute::new(method, pattern, handler));
+            Ok::<_, RouteError>(())
+        })?;
+        Ok(self)
+    }
+
+    /// Routes to try for one request
+    fn current_routes(&self) -> Arc<Vec<DynRoute<C, R>>> {
+        self.routes.load_full()
+    }
+
+    /// Changes the routes, in place unless requests still running share them, e.g. routes
+    /// returned by `route_for`
+    fn update_routes<T>(&mut self, update: impl FnOnce(&mut Vec<DynRoute<C, R>>) -> T) -> T {
+        let mut routes = self.routes.load_full();
+        let result = update(Arc::make_mut(&mut routes));
+        self.routes.store(routes);
+        result
+    }
+
+    /// Replaces all routes with `definitions`, e.g. reloaded from a config file, while the
+    /// router is in use. The routes are swapped atomically without blocking requests, and
+    /// requests already running finish with the previous routes.
+    ///
+    /// Fails with the errors of all invalid or conflicting definitions, keeping the
+    /// previous routes. The fallback and the modes are kept.
+    pub fn reload(&self, definitions: Vec<RouteDefinition<C, R>>) -> Result<(), Vec<RouteError>> {
+        let mut routes = Vec::with_capacity(definitions.len());
+        let mut errors = Vec::new();
+        for definition in definitions {
+            let added = RoutePattern::parse(&definition.pattern)
+                .map_err(RouteError::from)
+                .and_then(|pattern| {
+                    check_conflict(&routes, definition.method, &pattern)?;
+                    routes.push(DynRoute::new(
+                        definition.method,
+                        pattern,
+                        definition.handler,
+                    ));
+                    Ok(())
+                });
+            if let Err(error) = added {
+                errors.push(error);
+            }
+        }
+        if !errors.is_empty() {
+            return Err(errors);
+        }
+        self.routes.store(Arc::new(routes));
+        Ok(())
+    }
+
+    /// Appends the routes of `other`, which are tried after the routes of this router.
+    /// Its fallback is only kept if this router has none.
+    ///
+    /// Fails without adding any route if one of them conflicts with a registered route.
+    pub fn merge(&mut self, other: DynamicRouter<C, R>) -> Result<&mut Self, RouteError> {
+        let mut other_routes = other.routes.into_inner();
+        for route in other_routes.iter() {
+            self.check_conflict(route.method, &route.pattern)?;
+        }
+        self.update_routes(|routes| routes.append(Arc::make_mut(&mut other_routes)));
+        if self.fallback.load().is_none() {
+            self.fallback.store(other.fallback.into_inner());
+        }
+        Ok(self)
+    }
+
+    /// In prefix match mode, routes match paths that start with them, e.g. `/files` matches
+    /// `/files/a/b`, and handlers get the rest of the path as `Params::rest`, e.g. `/a/b`.
+    ///
+    /// Useful for mounting another router, which is called with the rest.
+    pub fn prefix_match_mode(&mut self, enabled: bool) -> &mut Self {
+        self.prefix_match = enabled;
+        self
+    }
+
+    /// In diagnose mode, the fallback gets the routes matching the most leading segments of
+    /// the path as `Params::partial_match`, e.g. `/users/{id: u32}` for `/users/abc`, to
+    /// answer with better error pages. Routes of all methods are considered.
+    ///
+    /// Finding them checks each route again, so only the fallback gets slower.
+    pub fn diagnose_mode(&mut self, enabled: bool) -> &mut Self {
+        self.diagnose = enabled;
+        self
+    }
+
+    /// In best match mode, routing (`route`, `route_bytes`, `route_traced`) and `route_for`
+    /// pick the matching route with the highest
+    /// `RoutePattern::specificity` instead of the first one, e.g. `/files/{id: u32}` over
+    /// `/files/{name}` for `/files/7`, whatever order they were added in. Of routes with the
+    /// same score, the first one added is picked.
+    ///
+    /// Every route is checked for each request, so routing gets slower.
+    pub fn best_match_mode(&mut self, enabled: bool) -> &mut Self {
+        self.best_match = enabled;
+        self
+    }
+
+    /// Lists `HEAD` in `allowed_methods` for paths with a `GET` route, since HTTP servers answer
+    /// `HEAD` like `GET` without the body
+    pub fn head_with_get(&mut self, enabled: bool) -> &mut Self {
+        self.head_with_get = enabled;
+        self
+    }
+
+    /// Turns the router into a `StatefulRouter`, whose handlers may also take `&S`
+    pub fn with_state<S>(self, state: Arc<S>) -> StatefulRouter<S, C, R> {
+        StatefulRouter::new(self, state)
+    }
+
+    /// Replaces the handler called when no route matches, returning the previous one.
+    ///
+    /// Can be called while the router is in use, e.g. to answer 503 during startup and
+    /// shutdown. Requests already running the previous fallback finish with it.
+    pub fn swap_fallback(
+        &self,
+        new_fallback: BoxedHandler<C, R>,
+    ) -> Option<Arc<BoxedHandler<C, R>>> {
+        self.fallback.swap(Some(Arc::new(new_fallback)))
+    }
+
+    /// Removes the fallback, so that `route` returns `None` again when no route matches
+    pub fn clear_fallback(&self) -> Option<Arc<BoxedHandler<C, R>>> {
+        self.fallback.swap(None)
+    }
+
+    /// Registered routes in the order they are tried
+    pub fn routes(&self) -> Vec<RouteInfo> {
+        self.current_routes()
+            .iter()
+            .map(|route| RouteInfo::clone(&route.info))
+            .collect()
+    }
+
+    /// Same as `routes`, grouped by method
+    pub fn routes_by_method(&self) -> HashMap<Method, Vec<RouteInfo>> {
+        let mut result: HashMap<Method, Vec<RouteInfo>> = HashMap::new();
+        for info in self.routes() {
+            result.entry(info.method).or_default().push(info);
+        }
+        result
+    }
+
+    /// Panics unless a route with exactly this method and template has been added.
+    ///
+    /// Compares the original template strings, e.g. `/users/{id}`, not paths.
+    #[cfg(any(test, feature = "test-support"))]
+    pub fn assert_route_exists(&self, method: Method, pattern: &str) {
+        let routes = self.current_routes();
+        let exists = routes
+            .iter()
+            .any(|route| route.method == method && route.pattern.template() == pattern);
+        if !exists {
+            let registered: Vec<String> = routes
+                .iter()
+                .map(|route| format!("{:?} {}", route.method, route.pattern.template()))
+                .collect();
+            panic!(
+                "route `{:?} {}` is not registered, registered routes: [{}]",
+                method,
+                pattern,
+                registered.join(", ")
+            );
+        }
+    }
+
+    /// Replaces the handler of a registered route with `mock`, e.g. one answering 500 to test
+    /// an error path, leaving the other routes as they are.
+    ///
+    /// Panics unless a route with this method and an equivalent template has been added.
+    #[cfg(any(test, feature = "test-support"))]
+    pub fn with_mock_handler(
+        mut self,
+        method: Method,
+        pattern: &str,
+        mock: BoxedHandler<C, R>,
+    ) -> Self {
+        let parsed = RoutePattern::parse(pattern)
+            .unwrap_or_else(|error| panic!("invalid route `{}`: {}", pattern, error));
+        self.update_routes(|routes| match find_equivalent(routes, method, &parsed) {
+            Some(index) => routes[index].handler = Arc::from(mock),
+            None => panic!("route `{:?} {}` is not registered", method, pattern),
+        });
+        self
+    }
+
+    /// Info of the route `route` would call the handler of, without calling it,
+    /// e.g. to log or rate limit by route pattern. `None` if no route matches.
+    ///
+    /// The info is shared with the router rather than copied, and stays valid after `reload`
+    /// replaces the route.
+    pub fn route_for(&self, method: Method, path: &str) -> Option<Arc<RouteInfo>> {
+        let offsets = SegmentOffsets::scan(path.as_bytes());
+        let routes = self.current_routes();
+        self.find(&routes, method, path, &offsets)
+            .map(|(route, _)| route.info.clone())
+    }
+
+    /// Methods `path` can be requested with, e.g. for the `Allow` header of answers to
+    /// `OPTIONS` or of 405s: those of the routes matching it and `OPTIONS`, and `HEAD` with
+    /// `GET` if enabled by `head_with_get`. Empty if no route matches the path.
+    pub fn allowed_methods(&self, path: &str) -> Vec<Method> {
+        let offsets = SegmentOffsets::scan(path.as_bytes());
+        let mut methods: Vec<Method> = self
+            .current_routes()
+            .iter()
+            .filter(|route| self.path_matches(route, path, &offsets))
+            .map(|route| route.method)
+            .collect();
+        if methods.is_empty() {
+            return methods;
+        }
+        if self.head_with_get && methods.contains(&Method::GET) {
+            methods.push(Method::HEAD);
+        }
+        methods.push(Method::OPTIONS);
+        ALLOW_ORDER
+            .iter()
+            .copied()
+            .filter(|method| methods.contains(method))
+            .collect()
+    }
+
+    /// `allowed_methods` as the value of an `Allow` header, e.g. `GET, HEAD, OPTIONS`
+    pub fn allow_header(&self, path: &str) -> Option<String> {
+        let methods = self.allowed_methods(path);
+        if methods.is_empty() {
+            return None;
+        }
+        let names: Vec<_> = methods
+            .iter()
+            .map(|method| String::from_utf8_lossy(method.as_bytes()))
+            .collect();
+        Some(names.join(", "))
+    }
+
+    /// Whether `path` matches the route, whatever its method
+    fn path_matches(&self, route: &DynRoute<C, R>, path: &str, offsets: &SegmentOffsets) -> bool {
+        if !self.has_segments(route, offsets) {
+            return false;
+        }
+        if self.prefix_match {
+            route.pattern.captures_prefix(path).is_some()
+        } else {
+            route.pattern.captures(path).is_some()
+        }
+    }
+
+    /// Captured params of `path`, and the rest of it in prefix match mode
+    #[allow(clippy::type_complexity)]
+    fn matches<'a>(
+        &self,
+        route: &'a DynRoute<C, R>,
+        method: Method,
+        path: &'a str,
+        offsets: &SegmentOffsets,
+    ) -> Option<(Vec<(&'a str, &'a str)>, Option<String>)> {
+        if !self.may_match(route, method, offsets) {
+            return None;
+        }
+        if self.prefix_match {
+            route
+                .pattern
+                .captures_prefix(path)
+                .map(|(captures, rest)| (captures, Some(rest.to_string())))
+        } else {
+            route
+                .pattern
+                .captures(path)
+                .map(|captures| (captures, None))
+        }
+    }
+
+    /// Whether the route has the method and the path has the segments it needs,
+    /// checked before running the route's regex
+    fn may_match(&self, route: &DynRoute<C, R>, method: Method, offsets: &SegmentOffsets) -> bool {
+        route.method == method && self.has_segments(route, offsets)
+    }
+
+    /// Whether the path has the segments the route needs
+    fn has_segments(&self, route: &DynRoute<C, R>, offsets: &SegmentOffsets) -> bool {
+        route
+            .filter
+            .as_ref()
+            .map_or(true, |filter| filter.accepts(offsets, self.prefix_match))
+    }
+
+    /// Route to call for the request with its captured params, the first matching one, or
+    /// the most specific one in best match mode
+    #[allow(clippy::type_complexity)]
+    fn find<'a>(
+        &self,
+        routes: &'a [DynRoute<C, R>],
+        method: Method,
+        path: &'a str,
+        offsets: &SegmentOffsets,
+    ) -> Option<(
+        &'a DynRoute<C, R>,
+        (Vec<(&'a str, &'a str)>, Option<String>),
+    )> {
+        self.pick(routes.iter().filter_map(|route| {
+            self.matches(route, method, path, offsets)
+                .map(|matched| (route, matched))
+        }))
+    }
+
+    /// First of the matching routes, or the most specific one in best match mode
+    fn pick<'a, M>(
+        &self,
+        mut matching: impl Iterator<Item = (&'a DynRoute<C, R>, M)>,
+    ) -> Option<(&'a DynRoute<C, R>, M)>
+    where
+        C: 'a,
+        R: 'a,
+    {
+        if !self.best_match {
+            return matching.next();
+        }
+        matching.fold(None, |best, (route, matched)| match best {
+            Some((best_route, _))
+                if best_route.pattern.specificity() >= route.pattern.specificity() =>
+            {
+                best
+            }
+            _ => Some((route, matched)),
+        })
+    }
+
+    /// Calls the first matching handler, or the fallback if no route matches.
+    /// Returns `None` if no route matches and there is no fallback.
+    pub fn route(&self, context: &C, method: Method, path: &str) -> Option<R> {
+        let offsets = SegmentOffsets::scan(path.as_bytes());
+        let routes = self.current_routes();
+        if let Some((route, (captures, rest))) = self.find(&routes, method, path, &offsets) {
+            let params = Params {
+                entries: captures
+                    .into_iter()
+                    .map(|(name, value)| (name.to_string(), value.to_string()))
+                    .collect(),
+                bytes: Vec::new(),
+                rest,
+                partial: None,
+            };
+            return Some(route.handler.call(context, &params));
+        }
+        self.call_fallback(context, &offsets)
+    }
+
+    /// Same as `route`, but also returns every route tried and how far it got, e.g. to find
+    /// out why a path reached the wrong handler. Routes after the one called aren't tried,
+    /// except in best match mode, and the fallback isn't listed.
+    #[cfg(feature = "trace")]
+    pub fn route_traced(
+        &self,
+        context: &C,
+        method: Method,
+        path: &str,
+    ) -> (Option<R>, Vec<CandidateTrace>) {
+        let offsets = SegmentOffsets::scan(path.as_bytes());
+        let routes = self.current_routes();
+        let mut trace = Vec::new();
+        let mut matching = Vec::new();
+        for route in routes.iter() {
+            let template = route.pattern.template().to_string();
+            if route.method != method {
+                trace.push(CandidateTrace {
+                    template,
+                    outcome: TraceOutcome::MethodRejected,
+                });
+                continue;
+            }
+            let matched = match self.matches(route, method, path, &offsets) {
+                Some(matched) => matched,
+                None => {
+                    let outcome = match route.pattern.rejected_param(path, self.prefix_match) {
+                        Some(name) => TraceOutcome::ParamRejected(name.to_string()),
+                        None => TraceOutcome::PatternRejected,
+                    };
+                    trace.push(CandidateTrace { template, outcome });
+                    continue;
+                }
+            };
+            trace.push(CandidateTrace {
+                template,
+                outcome: TraceOutcome::Outranked,
+            });
+            matching.push((route, (trace.len() - 1, matched)));
+            // in best match mode, the following routes may be more specific
+            if !self.best_match {
+                break;
+            }
+        }
+        let picked = self.pick(matching.into_iter());
+        let (route, (traced, (captures, rest))) = match picked {
+            Some(picked) => picked,
+            None => return (self.call_fallback(context, &offsets), trace),
+        };
+        trace[traced].outcome = TraceOutcome::Accepted;
+        let params = Params {
+            entries: captures
+                .into_iter()
+                .map(|(name, value)| (name.to_string(), value.to_string()))
+                .collect(),
+            bytes: Vec::new(),
+            rest,
+            partial: None,
+        };
+        (Some(route.handler.call(context, &params)), trace)
+    }
+
+    /// Same as `route`, but for paths that may not be valid UTF-8, e.g. in gateways that
+    /// must pass them on. Params may contain such bytes, see `Params::raw_bytes`. Their
+    /// `raw` values and the rest of the path have invalid bytes replaced by `U+FFFD`.
+    pub fn route_bytes(&self, context: &C, method: Method, path: &[u8]) -> Option<R> {
+        let offsets = SegmentOffsets::scan(path);
+        let routes = self.current_routes();
+        let matching = routes.iter().filter_map(|route| {
+            if !self.may_match(route, method, &offsets) {
+                return None;
+            }
+            let matched = if self.prefix_match {
+                route
+                    .pattern
+                    .captures_prefix_bytes(path)
+                    .map(|(captures, rest)| (captures, Some(String::from_utf8_lossy(rest))))
+            } else {
+                route
+                    .pattern
+                    .captures_bytes(path)
+                    .map(|captures| (captures, None))
+            };
+            matched.map(|matched| (route, matched))
+        });
+        if let Some((route, (captures, rest))) = self.pick(matching) {
+            let params = Params {
+                entries: captures
+                    .iter()
+                    .map(|&(name, value)| {
+                        (
+                            name.to_string(),
+                            String::from_utf8_lossy(value).into_owned(),
+                        )
+                    })
+                    .collect(),
+                bytes: captures.iter().map(|&(_, value)| value.to_vec()).collect(),
+                rest: rest.map(|rest| rest.into_owned()),
+                partial: None,
+            };
+            return Some(route.handler.call(context, &params));
+        }
+        self.call_fallback(context, &offsets)
+    }
+
+    /// Routes matching the most segments of the path, `None` if none matches one
+    fn partial_match(&self, offsets: &SegmentOffsets) -> Option<PartialMatch> {
+        let mut best = 0;
+        let mut candidates = Vec::new();
+        for route in self.current_routes().iter() {
+            let matched = matched_segments(&route.pattern, offsets);
+            if matched == 0 || matched < best {
+                continue;
+            }
+            if matched > best {
+                best = matched;
+                candidates.clear();
+            }
+            candidates.push(RouteInfo::clone(&route.info));
+        }
+        if candidates.is_empty() {
+            return None;
+        }
+        Some(PartialMatch {
+            matched_len: offsets.prefix_len(best),
+            candidates,
+        })
+    }
+
+    fn call_fallback(&self, context: &C, offsets: &SegmentOffsets) -> Option<R> {
+        // kept by this call, so the fallback may be swapped meanwhile
+        let fallback = self.fallback.load_full()?;
+        let params = Params {
+            partial: if self.diagnose {
+                self.partial_match(offsets)
+            } else {
+                None
+            },
+            ..Params::default()
+        };
+        Some(fallback.call(context, &params))
+    }
+}
+
+/// Merges two routers at startup, e.g. `api + admin + monitoring`, see `merge`.
+///
+/// Panics if the routers have conflicting routes. Note that with `std::ops::Add` imported,
+/// `router.add(..)` resolves to this, so call `DynamicRouter::add` explicitly there.
+impl<C, R> ops::Add for DynamicRouter<C, R> {
+    type Output = DynamicRouter<C, R>;
+
+    fn add(mut self, other: DynamicRouter<C, R>) -> DynamicRouter<C, R> {
+        self += other;
+        self
+    }
+}
+
+/// Appends the routes of another router, see `merge`.
+///
+/// Panics if the routers have conflicting routes.
+impl<C, R> ops::AddAssign for DynamicRouter<C, R> {
+    fn add_assign(&mut self, other: DynamicRouter<C, R>) {
+        if let Err(error) = self.merge(other) {
+            panic!("Failed to merge routers: {}", error);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::route_info::ParamInfo;
+
+    #[test]
+    fn test_boxed_handlers() {
+        let prefix = "user".to_string();
+        let users: Box<dyn DynHandler<(), String>> = Box::new(move |_: &(), params: &Params| {
+            format!("{}({})", prefix, params.get::<u32>("id").unwrap())
+        });
+        let health: Box<dyn DynHandler<(), String>> =
+            Box::new(|_: &(), _: &Params| "ok".to_string());
+        let mut router = DynamicRouter::new();
+        router
+            .add_boxed(Method::GET, "/users/{id}", users)
+            .unwrap()
+            .add_boxed(Method::GET, "/health", health)
+            .unwrap();
+        assert_eq!(
+            router.route(&(), Method::GET, "/users/12"),
+            Some("user(12)".to_string())
+        );
+        assert_eq!(
+            router.route(&(), Method::GET, "/health"),
+            Some("ok".to_string())
+        );
+        assert_eq!(router.route(&(), Method::POST, "/health"), None);
+        assert_eq!(router.route(&(), Method::GET, "/users"), None);
+    }
+
+    #[test]
+    fn test_custom_separator() {
+        let mut router = DynamicRouter::new();
+        router
+            .add_with_separator(
+                Method::GET,
+                r"users\.v2/info.{id: u32}",
+                '.',
+                |_: &(), params: &Params| format!("v2({})", params.get::<u32>("id").unwrap()),
+            )
+            .unwrap()
+            .add_with_separator(
+                Method::GET,
+                "users.{name}",
+                '.',
+                |_: &(), params: &Params| format!("users({})", params.raw("name").unwrap()),
+            )
+            .unwrap();
+        // the escaped dot is a literal of the first segment, not a separator
+        assert_eq!(
+            router.route(&(), Method::GET, "users.v2/info.12"),
+            Some("v2(12)".to_string())
+        );
+        assert_eq!(
+            router.route(&(), Method::GET, "users.v2"),
+            Some("users(v2)".to_string())
+        );
+        assert_eq!(router.route(&(), Method::GET, "usersXv2/info.12"), None);
+        assert_eq!(router.route(&(), Method::GET, "users.v2/info.abc"), None);
+        assert_eq!(router.route(&(), Method::GET, "users.v2/info"), None);
+    }
+
+    #[test]
+    fn test_from_routes() {
+        fn get_users(_: &(), _: &Params) -> String {
+            "users".to_string()
+        }
+        fn get_user(prefix: &&str, params: &Params) -> String {
+            format!("{}{}", prefix, params.get::<u32>("id").unwrap())
+        }
+        let router = DynamicRouter::from_routes(&[
+            (Method::GET, "/users", get_users as fn(&_, &_) -> _),
+            (Method::GET, "/users/{id: u32}", |_, params| {
+                format!("user({})", params.raw("id").unwrap())
+            }),
+        ])
+        .unwrap();
+        assert_eq!(
+            router.route(&(), Method::GET, "/users"),
+            Some("users".to_string())
+        );
+        assert_eq!(
+            router.route(&(), Method::GET, "/users/12"),
+            Some("user(12)".to_string())
+        );
+        assert_eq!(router.route(&(), Method::GET, "/posts"), None);
+
+        let router = DynamicRouter::from_routes(&[(Method::GET, "/users/{id}", get_user)]).unwrap();
+        assert_eq!(
+            router.route(&"user-", Method::GET, "/users/7"),
+            Some("user-7".to_string())
+        );
+
+        let conflict = DynamicRouter::<(), String>::from_routes(&[
+            (Method::GET, "/users", get_users),
+            (Method::GET, "/users", get_users),
+        ]);
+        assert!(matches!(conflict, Err(RouteError::Conflict(_))));
+    }
+
+    #[test]
+    fn test_swap_fallback() {
+        let mut router: DynamicRouter<(), String> = DynamicRouter::new();
+        router
+            .add(Method::GET, "/health", |_: &(), _: &Params| {
+                "ok".to_string()
+            })
+            .unwrap();
+        assert_eq!(router.route(&(), Method::GET, "/users"), None);
+
+        router.swap_fallback(Box::new(|_: &(), _: &Params| "503".to_string()));
+        assert_eq!(
+            router.route(&(), Method::GET, "/users"),
+            Some("503".to_string())
+        );
+        assert_eq!(
+            router.route(&(), Method::GET, "/health"),
+            Some("ok".to_string())
+        );
+
+        let previous = router.swap_fallback(Box::new(|_: &(), _: &Params| "404".to_string()));
+        assert_eq!(previous.unwrap().call(&(), &Params::default()), "503");
+        assert_eq!(
+            router.route(&(), Method::GET, "/users"),
+            Some("404".to_string())
+        );
+
+        router.clear_fallback();
+        assert_eq!(router.route(&(), Method::GET, "/users"), None);
+    }
+
+    #[test]
+    fn test_reload() {
+        let mut router: DynamicRouter<(), String> = DynamicRouter::new();
+        router
+            .add(Method::GET, "/old", |_: &(), _: &Params| "old".to_string())
+            .unwrap();
+        router.swap_fallback(Box::new(|_: &(), _: &Params| "404".to_string()));
+
+        router
+            .reload(vec![
+                RouteDefinition::new(
+                    Method::GET,
+                    "/users/{id: u32}",
+                    |_: &(), params: &Params| format!("user({})", params.get::<u32>("id").unwrap()),
+                ),
+                RouteDefinition::new(Method::POST, "/users", |_: &(), _: &Params| {
+                    "created".to_string()
+                }),
+            ])
+            .unwrap();
+        let route = |method, path| router.route(&(), method, path).unwrap();
+        assert_eq!(route(Method::GET, "/users/12"), "user(12)");
+        assert_eq!(route(Method::POST, "/users"), "created");
+        assert_eq!(route(Method::GET, "/old"), "404");
+
+        let errors = router
+            .reload(vec![
+                RouteDefinition::new(Method::GET, "/new", |_: &(), _: &Params| "new".to_string()),
+                RouteDefinition::new(Method::GET, "/users/{id", |_: &(), _: &Params| {
+                    "invalid".to_string()
+                }),
+                RouteDefinition::new(Method::GET, "/new", |_: &(), _: &Params| {
+                    "duplicate".to_string()
+                }),
+            ])
+            .unwrap_err();
+        assert_eq!(errors.len(), 2);
+        assert!(matches!(errors[0], RouteError::Pattern(_)));
+        assert!(matches!(errors[1], RouteError::Conflict(_)));
+        // nothing of a failed reload is installed
+        assert_eq!(route(Method::GET, "/users/12"), "user(12)");
+        assert_eq!(route(Method::GET, "/new"), "404");
+        assert_eq!(router.routes().len(), 2);
+
+        // routes can still be added after a reload
+        router
+            .add(Method::GET, "/health", |_: &(), _: &Params| {
+                "ok".to_string()
+            })
+            .unwrap();
+        assert_eq!(router.routes().len(), 3);
+    }
+
+    #[test]
+    fn test_reload_in_flight() {
+        let router: Arc<DynamicRouter<(), String>> = Arc::new(DynamicRouter::new());
+        let reload = Arc::downgrade(&router);
+        let version = "v1".to_string();
+        router
+            .reload(vec![RouteDefinition::new(
+                Method::GET,
+                "/version",
+                move |_: &(), _: &Params| {
+                    // reloads while this request is still running with the routes it started with
+                    if let Some(router) = reload.upgrade() {
+                        router
+                            .reload(vec![RouteDefinition::new(
+                                Method::GET,
+                                "/version",
+                                |_: &(), _: &Params| "v2".to_string(),
+                            )])
+                            .unwrap();
+                    }
+                    version.clone()
+                },
+            )])
+            .unwrap();
+        assert_eq!(
+            router.route(&(), Method::GET, "/version"),
+            Some("v1".to_string())
+        );
+        assert_eq!(
+            router.route(&(), Method::GET, "/version"),
+            Some("v2".to_string())
+        );
+    }
+
+    #[test]
+    fn test_reload_while_routing() {
+        let version = |version: &'static str| {
+            vec![RouteDefinition::new(
+                Method::GET,
+                "/version",
+                move |_: &(), _: &Params| version,
+            )]
+        };
+        let router: Arc<DynamicRouter<(), &'static str>> = Arc::new(DynamicRouter::new());
+        router.reload(version("v1")).unwrap();
+        let readers: Vec<_> = (0..4)
+            .map(|_| {
+                let router = router.clone();
+                std::thread::spawn(move || {
+                    for _ in 0..1000 {
+                        // never between two sets of routes
+                        let routed = router.route(&(), Method::GET, "/version");
+                        assert!(matches!(routed, Some("v1") | Some("v2")));
+                    }
+                })
+            })
+            .collect();
+        for i in 0..1000 {
+            router
+                .reload(version(if i % 2 == 0 { "v2" } else { "v1" }))
+                .unwrap();
+        }
+        for reader in readers {
+            reader.join().unwrap();
+        }
+    }
+
+    #[test]
+    fn test_swap_fallback_in_flight() {
+        let router: Arc<DynamicRouter<(), String>> = Arc::new(DynamicRouter::new());
+        let shutdown = Arc::downgrade(&router);
+        router.swap_fallback(Box::new(move |_: &(), _: &Params| {
+            // starts shutting down while this request is still running
+            if let Some(router) = shutdown.upgrade() {
+                router.swap_fallback(Box::new(|_: &(), _: &Params| "503".to_string()));
+            }
+            "404".to_string()
+        }));
+        assert_eq!(
+            router.route(&(), Method::GET, "/users"),
+            Some("404".to_string())
+        );
+        assert_eq!(
+            router.route(&(), Method::GET, "/users"),
+            Some("503".to_string())
+        );
+    }
+
+    #[test]
+    fn test_prefix_match_mode() {
+        let mut router = DynamicRouter::new();
+        router
+            .prefix_match_mode(true)
+            .add(Method::GET, "/files", |_: &(), params: &Params| {
+                format!("files({:?})", params.rest())
+            })
+            .unwrap()
+            .add(Method::GET, "/users/{id}", |_: &(), params: &Params| {
+                format!("user({}, {:?})", params.raw("id").unwrap(), params.rest())
+            })
+            .unwrap();
+        assert_eq!(
+            router.route(&(), Method::GET, "/files/anything/else"),
+            Some("files(Some(\"/anything/else\"))".to_string())
+        );
+        assert_eq!(
+            router.route(&(), Method::GET, "/files"),
+            Some("files(Some(\"\"))".to_string())
+        );
+        assert_eq!(
+            router.route(&(), Method::GET, "/users/12/posts"),
+            Some("user(12, Some(\"/posts\"))".to_string())
+        );
+        assert_eq!(router.route(&(), Method::GET, "/filesystem"), None);
+
+        router.prefix_match_mode(false);
+        assert_eq!(router.route(&(), Method::GET, "/files/anything"), None);
+        assert_eq!(
+            router.route(&(), Method::GET, "/files"),
+            Some("files(None)".to_string())
+        );
+    }
+
+    #[test]
+    fn test_best_match_mode() {
+        let mut router = DynamicRouter::new();
+        for &template in &["/files/{path: ..}", "/files/{name}", "/files/latest"] {
+            router
+                .add(Method::GET, template, move |_: &(), _: &Params| template)
+                .unwrap();
+        }
+        router
+            .add(Method::GET, "/files/{id: u32}", |_: &(), _: &Params| {
+                "/files/{id: u32}"
+            })
+            .unwrap();
+        assert_eq!(
+            router.route(&(), Method::GET, "/files/latest"),
+            Some("/files/{path: ..}")
+        );
+
+        router.best_match_mode(true);
+        assert_eq!(
+            router.route(&(), Method::GET, "/files/latest"),
+            Some("/files/latest")
+        );
+        assert_eq!(
+            router.route(&(), Method::GET, "/files/7"),
+            Some("/files/{id: u32}")
+        );
+        assert_eq!(
+            router.route(&(), Method::GET, "/files/readme"),
+            Some("/files/{name}")
+        );
+        assert_eq!(
+            router.route(&(), Method::GET, "/files/a/b"),
+            Some("/files/{path: ..}")
+        );
+        assert_eq!(
+            router.route_bytes(&(), Method::GET, b"/files/latest"),
+            Some("/files/latest")
+        );
+        assert_eq!(
+            router.route_bytes(&(), Method::GET, b"/files/7"),
+            Some("/files/{id: u32}")
+        );
+        assert_eq!(
+            router.route_for(Method::GET, "/files/7").unwrap().pattern,
+            "/files/{id: u32}"
+        );
+        assert_eq!(router.route(&(), Method::POST, "/files/7"), None);
+    }
+
+    #[test]
+    fn test_params_get() {
+        let mut router = DynamicRouter::new();
+        router
+            .add(Method::GET, "/users/{id}", |_: &(), params: &Params| {
+                (params.get::<u32>("id"), params.get::<u32>("user_id"))
+            })
+            .unwrap();
+        assert_eq!(
+            router.route(&(), Method::GET, "/users/12"),
+            Some((Ok(12), Err(ParamError::Missing("user_id".to_string()))))
+        );
+        assert_eq!(
+            router.route(&(), Method::GET, "/users/abc").unwrap().0,
+            Err(ParamError::Invalid {
+                name: "id".to_string(),
+                value: "abc".to_string()
+            })
+        );
+    }
+
+    #[test]
+    fn test_params_get_registered() {
+        use std::net::Shutdown;
+
+        crate::register_param_parser(|value: &str| match value {
+            "read" => Some(Shutdown::Read),
+            "write" => Some(Shutdown::Write),
+            _ => None,
+        });
+        let mut router = DynamicRouter::new();
+        router
+            .add(Method::POST, "/close/{how}", |_: &(), params: &Params| {
+                params.get_registered::<Shutdown>("how")
+            })
+            .unwrap();
+        assert_eq!(
+            router.route(&(), Method::POST, "/close/read"),
+            Some(Ok(Shutdown::Read))
+        );
+        assert_eq!(
+            router.route(&(), Method::POST, "/close/both"),
+            Some(Err(ParamError::Invalid {
+                name: "how".to_string(),
+                value: "both".to_string()
+            }))
+        );
+    }
+
+    #[test]
+    #[cfg(feature = "with_serde")]
+    fn test_to_json() {
+        let mut router = DynamicRouter::new();
+        router
+            .add(
+                Method::GET,
+                "/users/{id}/posts/{slug}",
+                |_: &(), params: &Params| params.to_json(),
+            )
+            .unwrap();
+        assert_eq!(
+            router.route(&(), Method::GET, "/users/12/posts/hello-world"),
+            Some(r#"{"id":"12","slug":"hello-world"}"#.to_string())
+        );
+        assert_eq!(Params::default().to_json(), "{}");
+    }
+
+    #[test]
+    fn test_assert_route_exists() {
+        let mut router: DynamicRouter<(), ()> = DynamicRouter::new();
+        router
+            .add(Method::GET, "/users/{id}", |_: &(), _: &Params| ())
+            .unwrap();
+        router.assert_route_exists(Method::GET, "/users/{id}");
+    }
+
+    #[test]
+    #[should_panic(expected = "route `POST /users/{id}` is not registered")]
+    fn test_assert_route_exists_panics() {
+        let mut router: DynamicRouter<(), ()> = DynamicRouter::new();
+        router
+            .add(Method::GET, "/users/{id}", |_: &(), _: &Params| ())
+            .unwrap();
+        router.assert_route_exists(Method::POST, "/users/{id}");
+    }
+
+    #[test]
+    fn test_with_mock_handler() {
+        let mut router: DynamicRouter<(), String> = DynamicRouter::new();
+        router
+            .add(Method::GET, "/users", |_: &(), _: &Params| {
+                "users".to_string()
+            })
+            .unwrap()
+            .add(Method::GET, "/users/{id}", |_: &(), params: &Params| {
+                format!("user({})", params.raw("id").unwrap())
+            })
+            .unwrap();
+        let router = router.with_mock_handler(
+            Method::GET,
+            "/users/{user_id}",
+            Box::new(|_: &(), _: &Params| "500".to_string()),
+        );
+        assert_eq!(router.route(&(), Method::GET, "/users/12").unwrap(), "500");
+        assert_eq!(router.route(&(), Method::GET, "/users").unwrap(), "users");
+        router.assert_route_exists(Method::GET, "/users/{id}");
+    }
+
+    #[test]
+    #[should_panic(expected = "route `POST /users` is not registered")]
+    fn test_with_mock_handler_panics() {
+        let mut router: DynamicRouter<(), ()> = DynamicRouter::new();
+        router
+            .add(Method::GET, "/users", |_: &(), _: &Params| ())
+            .unwrap();
+        router.with_mock_handler(Method::POST, "/users", Box::new(|_: &(), _: &Params| ()));
+    }
+
+    #[test]
+    fn test_diagnose_mode() {
+        let mut router: DynamicRouter<(), String> = DynamicRouter::new();
+        let handler = |_: &(), _: &Params| "found".to_string();
+        router
+            .add(Method::GET, "/users", handler)
+            .unwrap()
+            .add(Method::GET, "/users/{id: u32}", handler)
+            .unwrap()
+            .add(Method::DELETE, "/users/{id: u32}/posts", handler)
+            .unwrap()
+            .add(Method::GET, "/posts", handler)
+            .unwrap();
+        router.swap_fallback(Box::new(|_: &(), params: &Params| {
+            match params.partial_match() {
+                Some(partial) => format!(
+                    "404, did you mean {}? ({})",
+                    partial.candidates[0].pattern, partial.matched_len
+                ),
+                None => "404".to_string(),
+            }
+        }));
+        assert_eq!(router.route(&(), Method::GET, "/users/abc").unwrap(), "404");
+
+        router.diagnose_mode(true);
+        assert_eq!(
+            router.route(&(), Method::GET, "/users/abc").unwrap(),
+            "404, did you mean /users? (6)"
+        );
+        assert_eq!(
+            router
+                .route(&(), Method::GET, "/users/12/comments")
+                .unwrap(),
+            "404, did you mean /users/{id: u32}? (9)"
+        );
+        assert_eq!(router.route(&(), Method::GET, "/comments").unwrap(), "404");
+        assert_eq!(router.route(&(), Method::GET, "/users").unwrap(), "found");
+
+        let offsets = SegmentOffsets::scan(b"/users/12/comments");
+        let partial = router.partial_match(&offsets).unwrap();
+        let candidates: Vec<_> = partial
+            .candidates
+            .iter()
+            .map(|route| (route.method, route.pattern.as_str()))
+            .collect();
+        assert_eq!(
+            candidates,
+            vec![
+                (Method::GET, "/users/{id: u32}"),
+                (Method::DELETE, "/users/{id: u32}/posts"),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_routes_by_method() {
+        let mut router: DynamicRouter<(), ()> = DynamicRouter::new();
+        router
+            .add(Method::GET, "/users", |_: &(), _: &Params| ())
+            .unwrap()
+            .add(Method::POST, "/users", |_: &(), _: &Params| ())
+            .unwrap()
+            .add(Method::GET, "/users/{id}", |_: &(), _: &Params| ())
+            .unwrap();
+        let info = |method, pattern: &str, params: &[&str]| RouteInfo {
+            method,
+            pattern: pattern.to_string(),
+            params: params
+                .iter()
+                .map(|name| ParamInfo::of::<str>(name))
+                .collect(),
+        };
+        let routes = router.routes_by_method();
+        assert_eq!(routes.len(), 2);
+        assert_eq!(
+            routes[&Method::GET],
+            vec![
+                info(Method::GET, "/users", &[]),
+                info(Method::GET, "/users/{id}", &["id"])
+            ]
+        );
+        assert_eq!(
+            routes[&Method::POST],
+            vec![info(Method::POST, "/users", &[])]
+        );
+    }
+
+    #[test]
+    fn test_add_methods() {
+        let mut router = DynamicRouter::new();
+        router
+            .add_methods(
+                &[Method::GET, Method::POST],
+                "/users",
+                |_: &(), _: &Params| "users",
+            )
+            .unwrap();
+        router.swap_fallback(Box::new(|_: &(), _: &Params| "404"));
+        assert_eq!(router.route(&(), Method::GET, "/users"), Some("users"));
+        assert_eq!(router.route(&(), Method::POST, "/users"), Some("users"));
+        assert_eq!(router.route(&(), Method::DELETE, "/users"), Some("404"));
+        assert_eq!(router.routes().len(), 2);
+
+        let result = router.add_methods(
+            &[Method::PUT, Method::POST],
+            "/users",
+            |_: &(), _: &Params| "users",
+        );
+        assert!(matches!(result, Err(RouteError::Conflict(_))));
+        assert_eq!(router.route(&(), Method::PUT, "/users"), Some("404"));
+
+        // a method listed twice doesn't add the route for the first one either
+        let result = router.add_methods(
+            &[Method::GET, Method::GET],
+            "/posts",
+            |_: &(), _: &Params| "posts",
+        );
+        assert!(matches!(result, Err(RouteError::Conflict(_))));
+        assert_eq!(router.route(&(), Method::GET, "/posts"), Some("404"));
+        assert_eq!(router.routes().len(), 2);
+    }
+
+    #[test]
+    fn test_merge() {
+        let handler = |name: &'static str| move |_: &(), _: &Params| name;
+        let mut api = DynamicRouter::new();
+        api.add(Method::GET, "/users", handler("users")).unwrap();
+        let mut admin = DynamicRouter::new();
+        admin.add(Method::GET, "/admin", handler("admin")).unwrap();
+        admin.swap_fallback(Box::new(handler("admin 404")));
+        let mut monitoring = DynamicRouter::new();
+        monitoring
+            .add(Method::GET, "/health", handler("health"))
+            .unwrap();
+
+        let mut router = api + admin + monitoring;
+        assert_eq!(router.route(&(), Method::GET, "/users"), Some("users"));
+        assert_eq!(router.route(&(), Method::GET, "/admin"), Some("admin"));
+        assert_eq!(router.route(&(), Method::GET, "/health"), Some("health"));
+        assert_eq!(router.route(&(), Method::GET, "/posts"), Some("admin 404"));
+
+        let mut metrics = DynamicRouter::new();
+        metrics
+            .add(Method::GET, "/metrics", handler("metrics"))
+            .unwrap();
+        router += metrics;
+        assert_eq!(router.route(&(), Method::GET, "/metrics"), Some("metrics"));
+        assert_eq!(router.routes().len(), 4);
+    }
+
+    #[test]
+    #[should_panic(expected = "conflicts with `GET /users`")]
+    fn test_merge_conflict() {
+        let mut api = DynamicRouter::new();
+        api.add(Method::GET, "/users", |_: &(), _: &Params| ())
+            .unwrap();
+        let mut other = DynamicRouter::new();
+        other
+            .add(Method::GET, "/users", |_: &(), _: &Params| ())
+            .unwrap();
+        let _ = api + other;
+    }
+
+    #[test]
+    fn test_route_bytes() {
+        let mut router = DynamicRouter::new();
+        router
+            .add(Method::GET, "/files/{name}", |_: &(), params: &Params| {
+                (
+                    params.raw("name").unwrap().to_string(),
+                    params.raw_bytes("name").unwrap().to_vec(),
+                )
+            })
+            .unwrap();
+        router.swap_fallback(Box::new(|_: &(), _: &Params| (String::new(), Vec::new())));
+        assert_eq!(
+            router.route_bytes(&(), Method::GET, b"/files/caf\xE9"),
+            Some(("caf\u{FFFD}".to_string(), b"caf\xE9".to_vec()))
+        );
+        assert_eq!(
+            router.route(&(), Method::GET, "/files/cafe"),
+            Some(("cafe".to_string(), b"cafe".to_vec()))
+        );
+        assert_eq!(
+            router.route_bytes(&(), Method::GET, b"/posts/\xE9"),
+            Some((String::new(), Vec::new()))
+        );
+    }
+
+    #[test]
+    fn test_route_for() {
+        let mut router: DynamicRouter<(), ()> = DynamicRouter::new();
+        router
+            .add(Method::GET, "/users/{id: u32}", |_: &(), _: &Params| {
+                panic!("handler called")
+            })
+            .unwrap()
+            .add(Method::GET, "/users/{name}", |_: &(), _: &Params| {
+                panic!("handler called")
+            })
+            .unwrap();
+        let info = router.route_for(Method::GET, "/users/12").unwrap();
+        assert_eq!(info.pattern, "/users/{id: u32}");
+        assert_eq!(info.params, vec![ParamInfo::of::<u32>("id")]);
+        // lookups share the info of the route instead of copying it
+        let again = router.route_for(Method::GET, "/users/7").unwrap();
+        assert!(Arc::ptr_eq(&info, &again));
+        assert_eq!(
+            router
+                .route_for(Method::GET, "/users/alice")
+                .unwrap()
+                .pattern,
+            "/users/{name}"
+        );
+        assert_eq!(router.route_for(Method::POST, "/users/12"), None);
+        assert_eq!(router.route_for(Method::GET, "/posts"), None);
+    }
+
+    #[test]
+    #[cfg(feature = "trace")]
+    fn test_route_traced() {
+        use crate::trace::{CandidateTrace, TraceOutcome};
+
+        let mut router = DynamicRouter::new();
+        router
+            .add(Method::GET, "/users/{id: u32}", |_: &(), _: &Params| {
+                "user_by_id"
+            })
+            .unwrap()
+            .add(Method::POST, "/users/{name}", |_: &(), _: &Params| {
+                "create_user"
+            })
+            .unwrap()
+            .add(Method::GET, "/users/{name}/posts", |_: &(), _: &Params| {
+                "posts"
+            })
+            .unwrap()
+            .add(Method::GET, "/users/{name}", |_: &(), _: &Params| {
+                "user_by_name"
+            })
+            .unwrap()
+            .add(Method::GET, "/{any}/{name}", |_: &(), _: &Params| "any")
+            .unwrap();
+        router.swap_fallback(Box::new(|_: &(), _: &Params| "404"));
+        let trace = |template: &str, outcome| CandidateTrace {
+            template: template.to_string(),
+            outcome,
+        };
+        assert_eq!(
+            router.route_traced(&(), Method::GET, "/users/abc"),
+            (
+                Some("user_by_name"),
+                vec![
+                    trace(
+                        "/users/{id: u32}",
+                        TraceOutcome::ParamRejected("id".to_string())
+                    ),
+                    trace("/users/{name}", TraceOutcome::MethodRejected),
+                    trace("/users/{name}/posts", TraceOutcome::PatternRejected),
+                    trace("/users/{name}", TraceOutcome::Accepted),
+                ]
+            )
+        );
+        assert_eq!(
+            router.route_traced(&(), Method::GET, "/users/12"),
+            (
+                Some("user_by_id"),
+                vec![trace("/users/{id: u32}", TraceOutcome::Accepted)]
+            )
+        );
+        let (result, traced) = router.route_traced(&(), Method::POST, "/posts");
+        assert_eq!(result, Some("404"));
+        assert_eq!(traced.len(), 5);
+        assert!(traced
+            .iter()
+            .all(|candidate| candidate.outcome != TraceOutcome::Accepted));
+
+        // in best match mode every route is tried, and the most specific one is called
+        router.best_match_mode(true);
+        let (result, traced) = router.route_traced(&(), Method::GET, "/users/abc");
+        assert_eq!(result, Some("user_by_name"));
+        assert_eq!(
+            traced[3..],
+            [
+                trace("/users/{name}", TraceOutcome::Accepted),
+                trace("/{any}/{name}", TraceOutcome::Outranked),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_allowed_methods() {
+        let mut router = DynamicRouter::new();
+        router
+            .add(Method::GET, "/users", |_: &(), _: &Params| ())
+            .unwrap()
+            .add(Method::GET, "/users/{id: u32}", |_: &(), _: &Params| ())
+            .unwrap()
+            .add(Method::DELETE, "/users/{id}", |_: &(), _: &Params| ())
+            .unwrap()
+            .add(Method::PUT, "/users/{id: u32}", |_: &(), _: &Params| ())
+            .unwrap();
+        assert_eq!(
+            router.allow_header("/users").as_deref(),
+            Some("GET, OPTIONS")
+        );
+        assert_eq!(router.allow_header("/posts"), None);
+        assert_eq!(router.allowed_methods("/posts"), []);
+
+        router.head_with_get(true);
+        assert_eq!(
+            router.allow_header("/users").as_deref(),
+            Some("GET, HEAD, OPTIONS")
+        );
+        assert_eq!(
+            router.allowed_methods("/users/12"),
+            [
+                Method::GET,
+                Method::HEAD,
+                Method::PUT,
+                Method::DELETE,
+                Method::OPTIONS
+            ]
+        );
+        assert_eq!(
+            router.allow_header("/users/me").as_deref(),
+            Some("DELETE, OPTIONS")
+        );
+    }
+
+    #[test]
+    fn test_openapi() {
+        let params = |_: &(), params: &Params| {
+            params
+                .iter()
+                .map(|(name, value)| format!("{}={}", name, value))
+                .collect::<Vec<_>>()
+                .join(",")
+        };
+        let mut router = DynamicRouter::new();
+        for &(method, template) in &[
+            (Method::GET, "/pets"),
+            (Method::GET, "/pets/{petId}"),
+            (Method::DELETE, "/pets/{petId}"),
+            (Method::GET, "/stores/{store-id}/orders/{order.id}"),
+        ] {
+            router.add_openapi(method, template, params).unwrap();
+        }
+        assert_eq!(
+            router.route(&(), Method::GET, "/pets"),
+            Some("".to_string())
+        );
+        assert_eq!(
+            router.route(&(), Method::DELETE, "/pets/12"),
+            Some("petId=12".to_string())
+        );
+        assert_eq!(
+            router.route(&(), Method::GET, "/stores/main/orders/7"),
+            Some("store-id=main,order.id=7".to_string())
+        );
+        assert_eq!(router.route(&(), Method::POST, "/pets"), None);
+        assert!(router
+            .add_openapi(Method::GET, "/reports/{id}.pdf", params)
+            .is_err());
+    }
+
+    #[test]
+    fn test_conflicts() {
+        let mut router = DynamicRouter::new();
+        router
+            .add(Method::GET, "/users/{id}", |_: &(), _: &Params| "id")
+            .unwrap()
+            .add(Method::GET, "/users/{id}/posts", |_: &(), _: &Params| {
+                "posts"
+            })
+            .unwrap()
+            .add(Method::POST, "/users/{id}", |_: &(), _: &Params| "post")
+            .unwrap()
+            .add(Method::GET, "/users/me", |_: &(), _: &Params| "me")
+            .unwrap();
+        let result = router
+            .add(Method::GET, "/users/{user_id}", |_: &(), _: &Params| {
+                "user_id"
+            })
+            .map(|_| ());
+        assert_eq!(
+            result,
+            Err(RouteError::Conflict(RouteConflict {
+                existing: RouteInfo {
+                    method: Method::GET,
+                    pattern: "/users/{id}".to_string(),
+                    params: vec![ParamInfo::of::<str>("id")],
+                },
+                new: RouteInfo {
+                    method: Method::GET,
+                    pattern: "/users/{user_id}".to_string(),
+                    params: vec![ParamInfo::of::<str>("user_id")],
+                },
+            }))
+        );
+        assert_eq!(router.routes().len(), 4);
+        assert_eq!(router.route(&(), Method::GET, "/users/1"), Some("id"));
+    }
+
+    #[test]
+    fn test_add_or_replace() {
+        let mut router = DynamicRouter::new();
+        router
+            .add(Method::GET, "/users/{id}", |_: &(), _: &Params| "old")
+            .unwrap()
+            .add(Method::GET, "/users/{id}/posts", |_: &(), _: &Params| {
+                "posts"
+            })
+            .unwrap()
+            .add_or_replace(Method::GET, "/users/{user_id}", |_: &(), _: &Params| "new")
+            .unwrap()
+            .add_or_replace(Method::POST, "/users", |_: &(), _: &Params| "post")
+            .unwrap();
+        assert_eq!(router.route(&(), Method::GET, "/users/1"), Some("new"));
+        assert_eq!(router.route(&(), Method::POST, "/users"), Some("post"));
+        let patterns: Vec<String> = router.routes().into_iter().map(|r| r.pattern).collect();
+        assert_eq!(
+            patterns,
+            vec!["/users/{user_id}", "/users/{id}/posts", "/users"]
+        );
+    }
+
+    #[test]
+    fn test_invalid_pattern() {
+        let mut router: DynamicRouter<(), ()> = DynamicRouter::new();
+        assert!(router
+            .add(Method::GET, "users", |_: &(), _: &Params| ())
+            .is_err());
+        assert!(router
+            .add(Method::GET, "/users/{id", |_: &(), _: &Params| ())
+            .is_err());
+        assert!(router
+            .add(Method::GET, "/users//posts", |_: &(), _: &Params| ())
+            .is_err());
+    }
+}
@@ -0,0 +1,466 @@
+//! Errors of the fallible operations of this crate
+
+use crate::route_info::RouteInfo;
+use std::error::Error;
+use std::fmt;
+
+/// Error returned when a route template cannot be parsed.
+///
+/// Positions are byte offsets into the template.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum PatternParseError {
+    /// Template doesn't start with `/`
+    MissingLeadingSlash { template: String },
+    /// Template has an empty segment, e.g. `/users//posts`
+    EmptySegment { template: String, position: usize },
+    /// Param segment is not of the form `{name}`
+    InvalidParam {
+        template: String,
+        segment: String,
+        position: usize,
+    },
+    /// Segment starts with `{` but has no `}`
+    UnclosedParam { template: String, position: usize },
+    /// Type of a `{name: type}` param isn't one of `ParamType`
+    UnknownParamType {
+        template: String,
+        type_name: String,
+        position: usize,
+    },
+    /// Two params have the same name
+    DuplicateParam {
+        template: String,
+        name: String,
+        position: usize,
+    },
+}
+
+/// Former name of `PatternParseError`
+pub type PatternError = PatternParseError;
+
+impl fmt::Display for PatternParseError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            PatternParseError::MissingLeadingSlash { ref template } => {
+                write!(f, "route `{}` must start with `/`", template)
+            }
+            PatternParseError::EmptySegment {
+                ref template,
+                position,
+            } => write!(
+                f,
+                "route `{}` has an empty segment at byte {}",
+                template, position
+            ),
+            PatternParseError::InvalidParam {
+                ref template,
+                ref segment,
+                position,
+            } => write!(
+                f,
+                "route `{}` has an invalid param segment `{}` at byte {}",
+                template, segment, position
+            ),
+            PatternParseError::UnclosedParam {
+                ref template,
+                position,
+            } => write!(
+                f,
+                "route `{}` has an unclosed `{{` at byte {}",
+                template, position
+            ),
+            PatternParseError::UnknownParamType {
+                ref template,
+                ref type_name,
+                position,
+            } => write!(
+                f,
+                "route `{}` has a param of unknown type `{}` at byte {}",
+                template, type_name, position
+            ),
+            PatternParseError::DuplicateParam {
+                ref template,
+                ref name,
+                position,
+            } => write!(
+                f,
+                "route `{}` has a duplicate param `{}` at byte {}",
+                template, name, position
+            ),
+        }
+    }
+}
+
+impl Error for PatternParseError {}
+
+/// Error returned when a path cannot be built from a template, see `RoutePattern::render` and
+/// `RouteTable::render`
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum RenderError {
+    /// No value was given for the param
+    MissingParam { template: String, name: String },
+    /// The value is empty or contains a `/`, so it wouldn't match the param
+    InvalidValue {
+        template: String,
+        name: String,
+        value: String,
+    },
+    /// The template can't be rendered, like those of file extension routes
+    Unsupported { template: String },
+}
+
+impl fmt::Display for RenderError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            RenderError::MissingParam {
+                ref template,
+                ref name,
+            } => write!(f, "missing param `{}` for route `{}`", name, template),
+            RenderError::InvalidValue {
+                ref template,
+                ref name,
+                ref value,
+            } => write!(
+                f,
+                "invalid value `{}` for param `{}` of route `{}`",
+                value, name, template
+            ),
+            RenderError::Unsupported { ref template } => {
+                write!(f, "route `{}` can't be rendered", template)
+            }
+        }
+    }
+}
+
+impl Error for RenderError {}
+
+/// Error returned by `Params::get`
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum ParamError {
+    /// The route has no param with this name
+    Missing(String),
+    /// The param is present, but couldn't be parsed into the requested type
+    Invalid { name: String, value: String },
+}
+
+impl fmt::Display for ParamError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            ParamError::Missing(ref name) => write!(f, "missing param `{}`", name),
+            ParamError::Invalid {
+                ref name,
+                ref value,
+            } => write!(f, "invalid value `{}` for param `{}`", value, name),
+        }
+    }
+}
+
+impl Error for ParamError {}
+
+/// Error returned by `Query::required` and `Query::optional`
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum QueryError {
+    /// A required query param is missing, or a param couldn't be parsed into its type, which
+    /// should be answered with 400 Bad Request
+    BadParam { name: String },
+}
+
+impl fmt::Display for QueryError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            QueryError::BadParam { ref name } => {
+                write!(f, "missing or invalid query param `{}`", name)
+            }
+        }
+    }
+}
+
+impl Error for QueryError {}
+
+/// Two routes with the same method and equivalent patterns, see `RoutePattern::is_equivalent`
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct RouteConflict {
+    pub existing: RouteInfo,
+    pub new: RouteInfo,
+}
+
+impl fmt::Display for RouteConflict {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "route `{:?} {}` conflicts with `{:?} {}`",
+            self.new.method, self.new.pattern, self.existing.method, self.existing.pattern
+        )
+    }
+}
+
+impl Error for RouteConflict {}
+
+/// Error returned when a route can't be added to `DynamicRouter`
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum RouteError {
+    /// The template couldn't be parsed
+    Pattern(PatternParseError),
+    /// An equivalent route is already registered, use `add_or_replace` to replace it
+    Conflict(RouteConflict),
+}
+
+impl fmt::Display for RouteError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            RouteError::Pattern(ref error) => error.fmt(f),
+            RouteError::Conflict(ref conflict) => conflict.fmt(f),
+        }
+    }
+}
+
+// The message is the one of the wrapped error, so it isn't a source of its own
+impl Error for RouteError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match *self {
+            RouteError::Pattern(ref error) => error.source(),
+            RouteError::Conflict(ref conflict) => conflict.source(),
+        }
+    }
+}
+
+impl From<PatternParseError> for RouteError {
+    fn from(error: PatternParseError) -> Self {
+        RouteError::Pattern(error)
+    }
+}
+
+impl From<RouteConflict> for RouteError {
+    fn from(conflict: RouteConflict) -> Self {
+        RouteError::Conflict(conflict)
+    }
+}
+
+/// Error returned by `escape::decode_segment` for a `%` not followed by two hex digits, or when
+/// the decoded bytes aren't valid UTF-8
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct DecodeError {
+    pub segment: String,
+}
+
+impl fmt::Display for DecodeError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "segment `{}` has an invalid escape or isn't valid UTF-8 once decoded",
+            self.segment
+        )
+    }
+}
+
+impl Error for DecodeError {}
+
+/// Error returned by `import_manifest`
+#[cfg(feature = "with_serde")]
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum ImportError {
+    /// Not a manifest, e.g. invalid JSON or a route without a pattern
+    Malformed(String),
+    /// The manifest has a version of the format this crate can't read
+    UnsupportedVersion(u64),
+    /// The pattern of a route couldn't be parsed
+    Pattern(PatternParseError),
+}
+
+#[cfg(feature = "with_serde")]
+impl fmt::Display for ImportError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            ImportError::Malformed(ref message) => write!(f, "malformed manifest: {}", message),
+            ImportError::UnsupportedVersion(version) => {
+                write!(f, "unsupported manifest version {}", version)
+            }
+            ImportError::Pattern(_) => write!(f, "invalid route pattern in manifest"),
+        }
+    }
+}
+
+#[cfg(feature = "with_serde")]
+impl Error for ImportError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match *self {
+            ImportError::Pattern(ref error) => Some(error),
+            _ => None,
+        }
+    }
+}
+
+#[cfg(feature = "with_serde")]
+impl From<PatternParseError> for ImportError {
+    fn from(error: PatternParseError) -> Self {
+        ImportError::Pattern(error)
+    }
+}
+
+/// Error returned for a method that `Method` has no variant for, e.g. WebDAV's `PROPFIND`
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct MethodParseError {
+    pub method: String,
+}
+
+impl fmt::Display for MethodParseError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "unsupported http method `{}`", self.method)
+    }
+}
+
+impl Error for MethodParseError {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::method::Method;
+    use crate::pattern::RoutePattern;
+
+    fn assert_error<E: Error + Send + Sync + 'static>(_: &E) {}
+
+    #[test]
+    fn test_display() {
+        let cases: Vec<(Box<dyn Error + Send + Sync>, &str)> = vec![
+            (
+                Box::new(RoutePattern::parse("users").unwrap_err()),
+                "route `users` must start with `/`",
+            ),
+            (
+                Box::new(RoutePattern::parse("/users//posts").unwrap_err()),
+                "route `/users//posts` has an empty segment at byte 7",
+            ),
+            (
+                Box::new(RoutePattern::parse("/users/{id").unwrap_err()),
+                "route `/users/{id` has an unclosed `{` at byte 7",
+            ),
+            (
+                Box::new(RoutePattern::parse("/users/id}").unwrap_err()),
+                "route `/users/id}` has an invalid param segment `id}` at byte 7",
+            ),
+            (
+                Box::new(RoutePattern::parse("/users/{id: uint}").unwrap_err()),
+                "route `/users/{id: uint}` has a param of unknown type `uint` at byte 7",
+            ),
+            (
+                Box::new(RoutePattern::parse("/{id}/{id}").unwrap_err()),
+                "route `/{id}/{id}` has a duplicate param `id` at byte 6",
+            ),
+            (
+                Box::new(
+                    RoutePattern::parse("/users/{id}")
+                        .unwrap()
+                        .render(&[])
+                        .unwrap_err(),
+                ),
+                "missing param `id` for route `/users/{id}`",
+            ),
+            (
+                Box::new(RenderError::Unsupported {
+                    template: "/reports/{id: u32}.json".to_string(),
+                }),
+                "route `/reports/{id: u32}.json` can't be rendered",
+            ),
+            (
+                Box::new(ParamError::Invalid {
+                    name: "id".to_string(),
+                    value: "abc".to_string(),
+                }),
+                "invalid value `abc` for param `id`",
+            ),
+            (
+                Box::new(QueryError::BadParam {
+                    name: "q".to_string(),
+                }),
+                "missing or invalid query param `q`",
+            ),
+            (
+                Box::new(RouteError::from(RouteConflict {
+                    existing: RouteInfo::from_pattern(
+                        Method::GET,
+                        &RoutePattern::parse("/users/{id}").unwrap(),
+                    ),
+                    new: RouteInfo::from_pattern(
+                        Method::GET,
+                        &RoutePattern::parse("/users/{user_id}").unwrap(),
+                    ),
+                })),
+                "route `GET /users/{user_id}` conflicts with `GET /users/{id}`",
+            ),
+            (
+                Box::new("PROPFIND".parse::<Method>().unwrap_err()),
+                "unsupported http method `PROPFIND`",
+            ),
+        ];
+        for (error, expected) in cases {
+            assert_eq!(error.to_string(), expected);
+        }
+    }
+
+    // Each error of the chain once, like `anyhow` prints it with `{:#}`
+    fn format_chain(error: &dyn Error) -> String {
+        let mut chain = error.to_string();
+        let mut source = error.source();
+        while let Some(error) = source {
+            chain.push_str(": ");
+            chain.push_str(&error.to_string());
+            source = error.source();
+        }
+        chain
+    }
+
+    #[test]
+    fn test_chain() {
+        let error = RouteError::from(RoutePattern::parse("users").unwrap_err());
+        assert_eq!(format_chain(&error), "route `users` must start with `/`");
+        let error = RouteError::from(RouteConflict {
+            existing: RouteInfo::from_pattern(
+                Method::GET,
+                &RoutePattern::parse("/users/{id}").unwrap(),
+            ),
+            new: RouteInfo::from_pattern(
+                Method::GET,
+                &RoutePattern::parse("/users/{user_id}").unwrap(),
+            ),
+        });
+        assert_eq!(
+            format_chain(&error),
+            "route `GET /users/{user_id}` conflicts with `GET /users/{id}`"
+        );
+        #[cfg(feature = "with_serde")]
+        assert_eq!(
+            format_chain(&ImportError::from(
+                RoutePattern::parse("users").unwrap_err()
+            )),
+            "invalid route pattern in manifest: route `users` must start with `/`"
+        );
+    }
+
+    #[test]
+    fn test_send_sync() {
+        let error = RoutePattern::parse("users").unwrap_err();
+        assert_error(&error);
+        assert_error(&RouteError::from(error));
+        assert_error(&ParamError::Missing("id".to_string()));
+        assert_error(&QueryError::BadParam {
+            name: "q".to_string(),
+        });
+        assert_error(&"PROPFIND".parse::<Method>().unwrap_err());
+        assert_error(&crate::escape::decode_segment("%FF").unwrap_err());
+        assert_error(
+            &RoutePattern::parse("/{id}")
+                .unwrap()
+                .render(&[("id", "a/b")])
+                .unwrap_err(),
+        );
+    }
+}
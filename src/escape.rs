@@ -0,0 +1,134 @@
+//! Percent-encoding of path segments, deciding like the router does which characters are
+//! escaped, so that handlers and adapters agree with the routing
+
+use crate::error::DecodeError;
+use std::borrow::Cow;
+
+/// Decodes the `%XX` escapes of a segment, like the router does for params before parsing
+/// them, e.g. `jörg` for `j%C3%B6rg`.
+///
+/// Fails like the router, which then treats the param as not matching, if a `%` isn't
+/// followed by two hex digits or if the decoded bytes aren't valid UTF-8.
+pub fn decode_segment(segment: &str) -> Result<Cow<'_, str>, DecodeError> {
+    if !segment.contains('%') {
+        return Ok(Cow::Borrowed(segment));
+    }
+    let error = || DecodeError {
+        segment: segment.to_string(),
+    };
+    let bytes = segment.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        if bytes[i] != b'%' {
+            decoded.push(bytes[i]);
+            i += 1;
+            continue;
+        }
+        let byte = match bytes.get(i + 1..i + 3) {
+            Some(&[high, low]) => hex_value(high).zip(hex_value(low)),
+            _ => None,
+        };
+        let (high, low) = byte.ok_or_else(error)?;
+        decoded.push(high << 4 | low);
+        i += 3;
+    }
+    String::from_utf8(decoded)
+        .map(Cow::Owned)
+        .map_err(|_| error())
+}
+
+/// Escapes a value as `%XX` so that it is a single segment captured back as is by a param,
+/// e.g. `a%20b%2Fc` for `a b/c` or `a%2Eb` for `a.b`.
+///
+/// Only ASCII letters, digits, `_` and `-` are left alone, the characters params match
+/// without escapes. `decode_segment` gives back the value.
+pub fn encode_segment(value: &str) -> Cow<'_, str> {
+    if value.bytes().all(is_unescaped) {
+        return Cow::Borrowed(value);
+    }
+    let mut encoded = String::with_capacity(value.len() * 3);
+    for byte in value.bytes() {
+        if is_unescaped(byte) {
+            encoded.push(byte as char);
+        } else {
+            encoded.push('%');
+            encoded.push(char::from(HEX_DIGITS[usize::from(byte >> 4)]));
+            encoded.push(char::from(HEX_DIGITS[usize::from(byte & 0xF)]));
+        }
+    }
+    Cow::Owned(encoded)
+}
+
+const HEX_DIGITS: &[u8; 16] = b"0123456789ABCDEF";
+
+fn hex_value(digit: u8) -> Option<u8> {
+    char::from(digit).to_digit(16).map(|value| value as u8)
+}
+
+/// Whether `encode_segment` leaves the byte alone
+fn is_unescaped(byte: u8) -> bool {
+    byte.is_ascii_alphanumeric() || byte == b'_' || byte == b'-'
+}
+
+/// Segments and their decoded values, `None` if decoding fails. Checked against both
+/// `decode_segment` and the params of `router!`, so that they can't diverge.
+#[cfg(test)]
+pub(crate) const DECODE_CASES: &[(&str, Option<&str>)] = &[
+    ("plain", Some("plain")),
+    ("a%20b", Some("a b")),
+    ("%40me", Some("@me")),
+    ("j%C3%B6rg", Some("jörg")),
+    ("j%c3%b6rg", Some("jörg")),
+    ("100%25", Some("100%")),
+    ("a%2Fb", Some("a/b")),
+    ("a%2Eb", Some("a.b")),
+    ("a%zz", None),
+    ("a%2", None),
+    ("%", None),
+    ("a%FFb", None),
+    ("%C3", None),
+];
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_decode_segment() {
+        for &(segment, expected) in DECODE_CASES {
+            assert_eq!(
+                decode_segment(segment).ok().as_deref(),
+                expected,
+                "{}",
+                segment
+            );
+        }
+        assert!(matches!(decode_segment("plain"), Ok(Cow::Borrowed(_))));
+        assert_eq!(
+            decode_segment("a%FFb"),
+            Err(DecodeError {
+                segment: "a%FFb".to_string()
+            })
+        );
+    }
+
+    #[test]
+    fn test_encode_segment() {
+        assert!(matches!(encode_segment("user_1-a"), Cow::Borrowed(_)));
+        assert_eq!(encode_segment("a b/c"), "a%20b%2Fc");
+        assert_eq!(
+            encode_segment("~user:1@host;v=2.0"),
+            "%7Euser%3A1%40host%3Bv%3D2%2E0"
+        );
+        assert_eq!(encode_segment("100%"), "100%25");
+        assert_eq!(encode_segment("jörg?#"), "j%C3%B6rg%3F%23");
+        for &(_, decoded) in DECODE_CASES {
+            if let Some(decoded) = decoded {
+                let encoded = encode_segment(decoded);
+                assert!(crate::is_valid_url_segment(&encoded), "{}", encoded);
+                assert_eq!(decode_segment(&encoded).unwrap(), decoded);
+            }
+        }
+    }
+}
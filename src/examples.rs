@@ -0,0 +1,109 @@
+//! Example requests generated from route tables, e.g. for onboarding docs and smoke tests
+
+use crate::method::Method;
+use crate::route_info::RouteInfo;
+
+/// One curl command line per route, in route order, with params replaced by placeholders
+/// of their type: `1` for integers, `1.5` for floats, `true` for `bool` and `example`
+/// for anything else.
+///
+/// Routes with a catch-all (`{name: ..}`) are annotated with a comment line instead,
+/// since no placeholder shows what they match.
+pub fn curl(routes: &[RouteInfo], base_url: &str) -> Vec<String> {
+    let base_url = base_url.trim_end_matches('/');
+    routes
+        .iter()
+        .map(|route| match example_path(route) {
+            Some(path) => {
+                let method = match route.method {
+                    // `-X HEAD` would wait for a body that never comes
+                    Method::HEAD => "-I".to_string(),
+                    method => format!("-X {:?}", method),
+                };
+                format!("curl {} '{}{}'", method, base_url, path)
+            }
+            None => format!(
+                "# {:?} {} has a catch-all, no example",
+                route.method, route.pattern
+            ),
+        })
+        .collect()
+}
+
+/// Path of the route with placeholders, `None` for catch-all routes
+fn example_path(route: &RouteInfo) -> Option<String> {
+    let mut path = String::new();
+    let mut rest = route.pattern.as_str();
+    while let Some(start) = rest.find('{') {
+        let end = match rest[start..].find('}') {
+            Some(end) => start + end,
+            None => break,
+        };
+        let mut param = rest[start + 1..end].splitn(2, ':');
+        let name = param.next().unwrap_or("").trim();
+        if param.next().map(str::trim) == Some("..") {
+            return None;
+        }
+        let type_name = route
+            .params
+            .iter()
+            .find(|param| param.name == name)
+            .map_or("str", |param| param.type_name);
+        path.push_str(&rest[..start]);
+        path.push_str(placeholder(type_name));
+        rest = &rest[end + 1..];
+    }
+    path.push_str(rest);
+    Some(path)
+}
+
+fn placeholder(type_name: &str) -> &'static str {
+    match type_name.rsplit("::").next().unwrap_or(type_name) {
+        "u8" | "u16" | "u32" | "u64" | "u128" | "usize" | "i8" | "i16" | "i32" | "i64" | "i128"
+        | "isize" => "1",
+        "f32" | "f64" => "1.5",
+        "bool" => "true",
+        _ => "example",
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::dynamic::{DynamicRouter, Params};
+    use crate::route_info::ParamInfo;
+
+    #[test]
+    fn test_curl() {
+        let mut router: DynamicRouter<(), ()> = DynamicRouter::new();
+        let handler = |_: &(), _: &Params| ();
+        router
+            .add(Method::GET, "/", handler)
+            .unwrap()
+            .add(Method::HEAD, "/users", handler)
+            .unwrap()
+            .add(Method::PATCH, "/users/{id: u32}/posts/{slug}", handler)
+            .unwrap()
+            .add(Method::PUT, "/prices/{amount: f64}", handler)
+            .unwrap()
+            .add(Method::GET, "/files/{path: ..}", handler)
+            .unwrap();
+        let mut routes = router.routes();
+        routes.push(RouteInfo {
+            method: Method::DELETE,
+            pattern: "/flags/{enabled}".to_string(),
+            params: vec![ParamInfo::of::<bool>("enabled")],
+        });
+        assert_eq!(
+            curl(&routes, "http://localhost:8080/"),
+            vec![
+                "curl -X GET 'http://localhost:8080/'",
+                "curl -I 'http://localhost:8080/users'",
+                "curl -X PATCH 'http://localhost:8080/users/1/posts/example'",
+                "curl -X PUT 'http://localhost:8080/prices/1.5'",
+                "# GET /files/{path: ..} has a catch-all, no example",
+                "curl -X DELETE 'http://localhost:8080/flags/true'",
+            ]
+        );
+    }
+}
@@ -0,0 +1,63 @@
+use std::hash::Hasher;
+
+/// The hash function of rustc (`FxHash`), much faster than SipHash for short keys but not
+/// resistant to collisions chosen by an attacker. Keys of the regex cache are the sources
+/// of the routes' regexes, so they are trusted.
+#[derive(Debug, Default, Clone, Copy)]
+pub(crate) struct FxHasher {
+    hash: u64,
+}
+
+const SEED: u64 = 0x51_7c_c1_b7_27_22_0a_95;
+
+impl FxHasher {
+    fn add_to_hash(&mut self, word: u64) {
+        self.hash = (self.hash.rotate_left(5) ^ word).wrapping_mul(SEED);
+    }
+}
+
+impl Hasher for FxHasher {
+    fn write(&mut self, bytes: &[u8]) {
+        let mut chunks = bytes.chunks_exact(8);
+        for chunk in &mut chunks {
+            let mut word = [0; 8];
+            word.copy_from_slice(chunk);
+            self.add_to_hash(u64::from_le_bytes(word));
+        }
+        for &byte in chunks.remainder() {
+            self.add_to_hash(u64::from(byte));
+        }
+    }
+
+    fn write_u8(&mut self, byte: u8) {
+        self.add_to_hash(u64::from(byte));
+    }
+
+    fn write_usize(&mut self, value: usize) {
+        self.add_to_hash(value as u64);
+    }
+
+    fn finish(&self) -> u64 {
+        self.hash
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::hash::{BuildHasher, BuildHasherDefault};
+
+    #[test]
+    fn test_fx_hasher() {
+        let build = BuildHasherDefault::<FxHasher>::default();
+        let hash = |s: &str| {
+            let mut hasher = build.build_hasher();
+            hasher.write(s.as_bytes());
+            hasher.finish()
+        };
+        assert_eq!(hash("^/users/(\\d+)$"), hash("^/users/(\\d+)$"));
+        assert_ne!(hash("^/users/(\\d+)$"), hash("^/users/(\\d+)/$"));
+        assert_ne!(hash("^/a$"), hash("^/b$"));
+        assert_eq!(FxHasher::default().finish(), 0);
+    }
+}
@@ -3,7 +3,7 @@
 //! ### Key features:
 //! - Very expressive routes with fully typed parameters
 //! - Can be used with any http lib
-//! - Few dependencies (only `regex` and `lazy_static`)
+//! - Few dependencies (only `regex`, `regex-syntax`, `memchr` and `arc-swap`)
 //!
 //! ### Getting started (for Hyper >= 0.12)
 //!
@@ -14,12 +14,15 @@
 //! http_router = "0.1"
 //! ```
 //!
-//! In your lib.rs or main.rs:
+//! The minimum supported Rust version is 1.70.
+//!
+//! In the modules using it:
 //! ```rust
-//! #[macro_use]
-//! extern crate http_router;
+//! use http_router::router;
 //! ```
 //!
+//! Crates on the 2015 edition use `#[macro_use] extern crate http_router;` instead.
+//!
 //! In your struct than implements Hyper `Service`:
 //!
 //! ```rust
@@ -113,461 +116,5439 @@
 //!
 //! `method` is a param of type Method defined in `http_router` lib. It is one of `GET`, `POST`, etc.
 //!
+//! For tide, surf and other `http-types` based libs, enable the `with_http_types` feature to convert their `Method` with `Method::try_from`. Methods not listed in `Method`, like WebDAV's `PROPFIND`, give a `MethodParseError`.
+//!
 //! `path` is a `&str` which is the current route for a request.
 //!
 //! Once you define these 3 params, you can use the `router!` macro for routing.
 //!
+//! ### Routes registered at runtime
+//!
+//! When handlers are not known at compile time (e.g. they are loaded from plugins), use `DynamicRouter`.
+//! Handlers are boxed `DynHandler`s that receive the context and the captured `Params`:
+//!
+//! ```rust
+//! let mut router = DynamicRouter::new();
+//! router.add(Method::GET, "/users/{id}", |context: &Context, params: &Params| {
+//!     let id: u32 = params.get("id")?;
+//!     ...
+//! })?;
+//! router.route(&ctx, req.method.into(), path)
+//! ```
+//!
+//! `router.with_state(Arc::new(db))` makes a `StatefulRouter`, whose `add_with_state` handlers
+//! also take `&Db` after the context.
+//!
+//! Routes are tried in the order they were added. With `router.best_match_mode(true)`, the
+//! most specific matching route is called instead, scored by its literal segments and then the
+//! types of its params, e.g. `/files/{id: u32}` over `/files/{name}` for `/files/7`.
+//!
+//! `AuthorityPattern` matches the authority of a request instead of its path, e.g. `example.com:443`
+//! against `{name}:{port: u16}` for a gateway routing `CONNECT` targets.
+//!
+//! With the `with_serde` feature, `export_manifest` writes the routes of a `RouteTable` as
+//! JSON, and `import_manifest` reads them back as `RoutePattern`s matching the same paths, e.g.
+//! for an edge proxy rejecting unknown paths early.
+//!
+//! `router.reload(definitions)` replaces all routes at once while the router is in use, e.g. when a
+//! config file changes, and keeps the previous routes if any `RouteDefinition` is invalid.
+//!
 //! ### Benchmarks
 //!
 //! Right now the router with 10 routes takes approx 50 microseconds per route
 //!
 
-extern crate regex;
-#[macro_use]
-extern crate lazy_static;
+#![cfg_attr(all(test, feature = "nightly"), feature(test))]
+
+extern crate arc_swap;
+#[cfg(feature = "with_http_types")]
+extern crate http_types;
 #[cfg(feature = "with_hyper")]
 extern crate hyper;
+extern crate memchr;
+extern crate regex;
+extern crate regex_syntax;
+#[cfg(feature = "with_serde")]
+extern crate serde;
+#[cfg(feature = "with_serde")]
+extern crate serde_json;
+#[cfg(feature = "with_uuid")]
+extern crate uuid;
 
+pub mod audit;
+mod authority;
+mod compact;
+mod dynamic;
+pub mod error;
+pub mod escape;
+pub mod examples;
+mod extensions;
+#[cfg(feature = "fx_hash")]
+mod fx_hash;
+#[cfg(feature = "with_serde")]
+mod manifest;
+mod memo;
 mod method;
+mod normalize;
+mod parsers;
+mod pattern;
+#[cfg(feature = "profiling")]
+mod profiling;
+mod query;
+mod render;
+mod route_info;
+mod segments;
+mod stateful;
+mod table;
+#[cfg(feature = "trace")]
+mod trace;
+mod unmatched;
+mod wrap;
 
-pub use self::method::Method;
+pub use self::authority::AuthorityPattern;
+pub use self::compact::RouteCaptures;
+#[doc(hidden)]
+pub use self::compact::{
+    __HttpRouterRegexes, __HttpRouterTableMatch, __HttpRouterTableMatches, __HttpRouterTableRoute,
+    __http_router_table_matches,
+};
+pub use self::dynamic::{
+    BoxedHandler, DynHandler, DynamicRouter, HandlerFn, Params, PartialMatch, RouteDefinition,
+};
+#[cfg(feature = "with_serde")]
+pub use self::error::ImportError;
+pub use self::error::{
+    DecodeError, MethodParseError, ParamError, PatternError, PatternParseError, QueryError,
+    RenderError, RouteConflict, RouteError,
+};
+pub use self::extensions::Extensions;
+#[cfg(feature = "with_serde")]
+pub use self::manifest::{export_manifest, import_manifest, MANIFEST_VERSION};
+#[doc(hidden)]
+pub use self::memo::__http_router_memo_key;
+pub use self::memo::{MemoryCache, RouteCache};
+pub use self::method::{IntoMethod, Method};
+pub use self::normalize::{__HttpRouterNormalizedPaths, MatrixParams, Normalize};
+pub use self::parsers::register_param_parser;
+pub use self::pattern::{param_names, InlineCaptures, ParamType, RoutePattern, Segment};
+#[cfg(feature = "profiling")]
+pub use self::profiling::bench_route;
+pub use self::query::Query;
+#[doc(hidden)]
+pub use self::render::__HttpRouterTemplate;
+pub use self::route_info::{ParamInfo, RouteAliases, RouteInfo, RouteMeta, StaticRouteInfo};
+pub use self::stateful::StatefulRouter;
+pub use self::table::RouteTable;
+#[cfg(feature = "trace")]
+pub use self::trace::{CandidateTrace, TraceOutcome};
+pub use self::unmatched::UnmatchedPaths;
+pub use self::wrap::{
+    record_unmatched, shared_router, strip_prefixes, with_context_factory, with_locale,
+    SharedRouter,
+};
+use std::cell::Cell;
 use std::collections::HashMap;
-use std::sync::{Arc, Mutex};
+use std::future::{self, Future, Ready};
+use std::marker::PhantomData;
+use std::str::FromStr;
+use std::sync::{Mutex, OnceLock};
+use std::time::{Duration, Instant};
 
-lazy_static! {
-    static ref REGEXES: Arc<Mutex<HashMap<String, regex::Regex>>> =
-        { Arc::new(Mutex::new(HashMap::new())) };
-}
+/// Regexes of all routers by their source, compiled once per process
+#[cfg_attr(feature = "no_shared_cache", allow(dead_code))]
+static REGEXES: OnceLock<Mutex<HashMap<String, regex::Regex, RegexHasher>>> = OnceLock::new();
+
+/// Hasher of the regex cache, SipHash unless the `fx_hash` feature is enabled
+#[cfg(not(feature = "fx_hash"))]
+type RegexHasher = std::collections::hash_map::RandomState;
+
+#[cfg(feature = "fx_hash")]
+type RegexHasher = std::hash::BuildHasherDefault<fx_hash::FxHasher>;
+
+/// This is an implementation detail and *should not* be used directly!
+#[doc(hidden)]
+pub type __HttpRouterRegex = regex::Regex;
+
+/// This is an implementation detail and *should not* be used directly!
+#[doc(hidden)]
+pub type __HttpRouterCaptureLocations = regex::CaptureLocations;
 
 /// This is an implementation detail and *should not* be called directly!
 #[doc(hidden)]
 pub fn __http_router_create_regex(s: &str) -> regex::Regex {
-    let mut _result: Option<regex::Regex> = None;
-    {
-        let regexes = REGEXES.lock().expect("Failed to obtain mutex lock");
-        _result = regexes.get(s).cloned();
-    };
-    _result.unwrap_or_else(|| {
-        let re = regex::Regex::new(s).unwrap();
-        let mut regexes = REGEXES.lock().expect("Failed to obtain mutex lock");
-        regexes.insert(s.to_string(), re.clone());
-        re
-    })
+    compile_regex(s).unwrap()
 }
 
-/// This macro returns a closure that takes 3 params. See crate doc for more details.
-///
-/// ### Limitations:
-/// - Home route is optional and should come first
-/// - Fallback route (`_`) is required and should come last
-///
-/// ### Performace
-/// Macro routers itself has almost no cost, so you can call it
-/// everywhere as many times as you like. The closure that it returns
-/// have some cost (approx 50 microseconds per one call).
+/// This is an implementation detail and *should not* be called directly!
 ///
-/// ### Thread safety
-/// The closure returned by this macro is thread-safe.
-#[macro_export]
-macro_rules! router {
-    // convert params from string
-    (@parse_type $value:expr, $ty:ty) => {{
-        let maybe_val = $value.parse::<$ty>();
-        if maybe_val.is_err() { return None };
-        maybe_val.unwrap()
-    }};
+/// Panics if the regex of `route` doesn't compile. Compiled regexes are cached, so
+/// validating a router again is cheap, unless the `no_shared_cache` feature is enabled.
+#[doc(hidden)]
+pub fn __http_router_validate_regex(s: &str, route: &str) {
+    if let Err(error) = compile_regex(s) {
+        panic!("invalid regex `{}` of route `{}`: {}", s, route, error);
+    }
+}
 
-    // call handler with params
-    (@call_pure $context:expr, $handler:ident, $params:expr, $({$id:ident : $ty:ty : $idx:expr}),*) => {{
-        $handler(&$context, $({
-            let value = $params[$idx];
-            router!(@parse_type value, $ty)
-        }),*)
-    }};
+/// Routes keep their regexes in statics of their own, so without the shared cache each
+/// route's regex is still compiled only once
+#[cfg(feature = "no_shared_cache")]
+fn compile_regex(s: &str) -> Result<regex::Regex, regex::Error> {
+    regex::Regex::new(s)
+}
 
-    // Extract params from route, 0 params case
-    (@call, $context:expr, $handler:ident, $params:expr, $($p:ident)*) => {{
-        $handler(&$context)
-    }};
+#[cfg(not(feature = "no_shared_cache"))]
+fn compile_regex(s: &str) -> Result<regex::Regex, regex::Error> {
+    let cache = REGEXES.get_or_init(Default::default);
+    let cached = cache
+        .lock()
+        .unwrap_or_else(std::sync::PoisonError::into_inner)
+        .get(s)
+        .cloned();
+    match cached {
+        Some(re) => Ok(re),
+        None => {
+            let re = regex::Regex::new(s)?;
+            // entries are inserted whole, so a panic while the lock was held left none half done
+            let mut regexes = cache
+                .lock()
+                .unwrap_or_else(std::sync::PoisonError::into_inner);
+            regexes.insert(s.to_string(), re.clone());
+            Ok(re)
+        }
+    }
+}
 
-    // Extract params from route, 1 params case
-    (@call, $context:expr, $handler:ident, $params:expr, $($p:ident)* {$id1:ident : $ty1:ty} $($p1:ident)*) => {{
-        router!(@call_pure $context, $handler, $params, {$id1 : $ty1 : 0})
-    }};
+/// This is an implementation detail and *should not* be used directly!
+///
+/// Times a call of a router with a `timing(hook);` header: matching up to the call of the
+/// handler that answers, and that handler.
+#[doc(hidden)]
+pub struct __HttpRouterTimer {
+    start: Instant,
+    handler_start: Cell<Option<Instant>>,
+    template: Cell<&'static str>,
+}
 
-    // Extract params from route, 2 params case
-    (@call, $context:expr, $handler:ident, $params:expr, $($p:ident)* {$id1:ident : $ty1:ty} $($p1:ident)* {$id2:ident : $ty2:ty} $($p2:ident)*) => {{
-        router!(@call_pure $context, $handler, $params, {$id1 : $ty1 : 0}, {$id2 : $ty2 : 1})
-    }};
+impl __HttpRouterTimer {
+    pub fn start() -> Self {
+        __HttpRouterTimer {
+            start: Instant::now(),
+            handler_start: Cell::new(None),
+            template: Cell::new("<fallback>"),
+        }
+    }
 
-    // Extract params from route, 3 params case
-    (@call, $context:expr, $handler:ident, $params:expr, $($p:ident)* {$id1:ident : $ty1:ty} $($p1:ident)* {$id2:ident : $ty2:ty} $($p2:ident)* {$id3:ident : $ty3:ty} $($p3:ident)*) => {{
-        router!(@call_pure $context, $handler, $params, {$id1 : $ty1 : 0}, {$id2 : $ty2 : 1}, {$id3 : $ty3 : 2})
-    }};
+    /// Called right before a handler, the last one called answers unless it declines
+    pub fn handler_started(&self) {
+        self.handler_start.set(Some(Instant::now()));
+    }
 
-    // Extract params from route, 4 params case
-    (@call, $context:expr, $handler:ident, $params:expr, $($p:ident)* {$id1:ident : $ty1:ty} $($p1:ident)* {$id2:ident : $ty2:ty} $($p2:ident)* {$id3:ident : $ty3:ty} $($p3:ident)* {$id4:ident : $ty4:ty} $($p4:ident)*) => {{
-        router!(@call_pure $context, $handler, $params, {$id1 : $ty1 : 0}, {$id2 : $ty2 : 1}, {$id3 : $ty3 : 2}, {$id4 : $ty4 : 3})
-    }};
+    /// Template of the route that answered
+    pub fn route(&self, template: &'static str) {
+        self.template.set(template);
+    }
 
-    // Extract params from route, 5 params case
-    (@call, $context:expr, $handler:ident, $params:expr, $($p:ident)* {$id1:ident : $ty1:ty} $($p1:ident)* {$id2:ident : $ty2:ty} $($p2:ident)* {$id3:ident : $ty3:ty} $($p3:ident)* {$id4:ident : $ty4:ty} $($p4:ident)* {$id5:ident : $ty5:ty} $($p5:ident)*) => {{
-        router!(@call_pure $context, $handler, $params, {$id1 : $ty1 : 0}, {$id2 : $ty2 : 1}, {$id3 : $ty3 : 2}, {$id4 : $ty4 : 3}, {$id5 : $ty5 : 4})
-    }};
+    /// Calls `hook` with the template and the nanoseconds of both phases. Without a handler
+    /// call, e.g. for a failed guard, all of it counts as matching.
+    pub fn finish<F: Fn(&'static str, u64, u64)>(&self, hook: &F) {
+        let end = Instant::now();
+        let handler_start = self.handler_start.get().unwrap_or(end);
+        let nanos = |duration: Duration| duration.as_nanos() as u64;
+        hook(
+            self.template.get(),
+            nanos(handler_start - self.start),
+            nanos(end - handler_start),
+        );
+    }
+}
 
-    // Extract params from route, 6 params case
-    (@call, $context:expr, $handler:ident, $params:expr, $($p:ident)* {$id1:ident : $ty1:ty} $($p1:ident)* {$id2:ident : $ty2:ty} $($p2:ident)* {$id3:ident : $ty3:ty} $($p3:ident)* {$id4:ident : $ty4:ty} $($p4:ident)* {$id5:ident : $ty5:ty} $($p5:ident)* {$id6:ident : $ty6:ty} $($p6:ident)*) => {{
-        router!(@call_pure $context, $handler, $params, {$id1 : $ty1 : 0}, {$id2 : $ty2 : 1}, {$id3 : $ty3 : 2}, {$id4 : $ty4 : 3}, {$id5 : $ty5 : 4}, {$id6 : $ty6 : 5})
-    }};
+/// This is an implementation detail and *should not* be called directly!
+///
+/// Message of a panic caught by a `catch_panics = handler;` router, given to the handler.
+#[doc(hidden)]
+pub fn __http_router_panic_message(panic: &(dyn std::any::Any + Send)) -> &str {
+    if let Some(message) = panic.downcast_ref::<&str>() {
+        message
+    } else if let Some(message) = panic.downcast_ref::<String>() {
+        message
+    } else {
+        "Box<dyn Any>"
+    }
+}
 
-    // Extract params from route, 7 params case
-    (@call, $context:expr, $handler:ident, $params:expr, $($p:ident)* {$id1:ident : $ty1:ty} $($p1:ident)* {$id2:ident : $ty2:ty} $($p2:ident)* {$id3:ident : $ty3:ty} $($p3:ident)* {$id4:ident : $ty4:ty} $($p4:ident)* {$id5:ident : $ty5:ty} $($p5:ident)* {$id6:ident : $ty6:ty} $($p6:ident)* {$id7:ident : $ty7:ty} $($p7:ident)*) => {{
-        router!(@call_pure $context, $handler, $params, {$id1 : $ty1 : 0}, {$id2 : $ty2 : 1}, {$id3 : $ty3 : 2}, {$id4 : $ty4 : 3}, {$id5 : $ty5 : 4}, {$id6 : $ty6 : 5}, {$id6 : $ty6 : 6})
-    }};
+/// This is an implementation detail and *should not* be called directly!
+///
+/// Parses a `{name: type registered}` param with the parser of `register_param_parser`, `None`
+/// if it fails or if no parser is registered for the type.
+#[doc(hidden)]
+pub fn __http_router_parse_registered<T: 'static>(value: &str) -> Option<T> {
+    parsers::parse_registered(&escape::decode_segment(value).ok()?)
+}
 
-    // Test a particular route for match and forward to @call if there is match
-    (@one_route_with_method $context:expr, $method:expr, $path:expr, $default:expr, $expected_method: expr, $handler:ident, $($path_segment:tt)*) => {{
-        if $method != $expected_method { return None };
-        let mut s = "^".to_string();
-        $(
-            s.push('/');
-            let path_segment = stringify!($path_segment);
-            if path_segment.starts_with('{') {
-                s.push_str(r#"([\w-]+)"#);
-            } else {
-                s.push_str(path_segment);
-            }
-        )*
-        // handle home case
-        if s.len() == 1 { s.push('/') }
-        s.push('$');
-        let re = $crate::__http_router_create_regex(&s);
-        if let Some(captures) = re.captures($path) {
-            let _matches: Vec<&str> = captures.iter().skip(1).filter(|x| x.is_some()).map(|x| x.unwrap().as_str()).collect();
-            Some(router!(@call, $context, $handler, _matches, $($path_segment)*))
-        } else {
-            None
-        }
-    }};
+/// This is an implementation detail and *should not* be called directly!
+///
+/// Evaluates a route condition, giving the closure a concrete context type to infer from.
+#[doc(hidden)]
+pub fn __http_router_check<C, F: FnOnce(&C) -> bool>(context: &C, check: F) -> bool {
+    check(context)
+}
 
-    // Transform GET token to Method::GET
-    (@one_route $context:expr, $method:expr, $path:expr, $default:expr, GET, $handler:ident, $($path_segment:tt)*) => {
-        router!(@one_route_with_method $context, $method, $path, $default, $crate::Method::GET, $handler, $($path_segment)*)
-    };
+/// This is an implementation detail and *should not* be called directly!
+///
+/// Checks that `path` consists of exactly `segments`, e.g. `/users/me` for `["users", "me"]`.
+#[doc(hidden)]
+pub fn __http_router_match_static(path: &str, segments: &[&str], normalize: &Normalize) -> bool {
+    // handle home case
+    if segments.is_empty() {
+        return path == "/";
+    }
+    match_segments(path, segments.iter().copied(), |segment| {
+        normalize.any_case(segment)
+    }) == Some(path.len())
+}
 
-    // Transform POST token to Method::POST
-    (@one_route $context:expr, $method:expr, $path:expr, $default:expr, POST, $handler:ident, $($path_segment:tt)*) => {
-        router!(@one_route_with_method $context, $method, $path, $default, $crate::Method::POST, $handler, $($path_segment)*)
-    };
-    // Transform PUT token to Method::PUT
-    (@one_route $context:expr, $method:expr, $path:expr, $default:expr, PUT, $handler:ident, $($path_segment:tt)*) => {
-        router!(@one_route_with_method $context, $method, $path, $default, $crate::Method::PUT, $handler, $($path_segment)*)
-    };
-    // Transform PATCH token to Method::PATCH
-    (@one_route $context:expr, $method:expr, $path:expr, $default:expr, PATCH, $handler:ident, $($path_segment:tt)*) => {
-        router!(@one_route_with_method $context, $method, $path, $default, $crate::Method::PATCH, $handler, $($path_segment)*)
-    };
-    // Transform DELETE token to Method::DELETE
-    (@one_route $context:expr, $method:expr, $path:expr, $default:expr, DELETE, $handler:ident, $($path_segment:tt)*) => {
-        router!(@one_route_with_method $context, $method, $path, $default, $crate::Method::DELETE, $handler, $($path_segment)*)
-    };
-    // Transform OPTIONS token to Method::OPTIONS
-    (@one_route $context:expr, $method:expr, $path:expr, $default:expr, OPTIONS, $handler:ident, $($path_segment:tt)*) => {
-        router!(@one_route_with_method $context, $method, $path, $default, $crate::Method::OPTIONS, $handler, $($path_segment)*)
-    };
+/// This is an implementation detail and *should not* be called directly!
+///
+/// Rest of `path` after the `segments` of a scope, e.g. `/users` for `/admin/users` and
+/// `["admin"]`, or `/` if nothing is left.
+#[doc(hidden)]
+pub fn __http_router_strip_scope<'a>(
+    path: &'a str,
+    segments: &[&str],
+    normalize: &Normalize,
+) -> Option<&'a str> {
+    let any_case = |segment: &str| normalize.any_case(segment);
+    let rest = &path[match_segments(path, segments.iter().copied(), any_case)?..];
+    Some(if rest.is_empty() { "/" } else { rest })
+}
 
-    // Transform HEAD token to Method::HEAD
-    (@one_route $context:expr, $method:expr, $path:expr, $default:expr, HEAD, $handler:ident, $($path_segment:tt)*) => {
-        router!(@one_route_with_method $context, $method, $path, $default, $crate::Method::HEAD, $handler, $($path_segment)*)
-    };
+/// Rest of `path` after `prefix`, like a `scope` would pass it to its router, e.g.
+/// `Some("/users")` for `/api/users` and `/api`, for dispatching on it by hand.
+///
+/// The prefix only matches whole segments, so `/apiv2` gives `None`, and a path equal to the
+/// prefix gives `Some("/")`.
+pub fn strip_prefix_route<'a>(path: &'a str, prefix: &str) -> Option<&'a str> {
+    let segments: Vec<&str> = prefix.split('/').filter(|s| !s.is_empty()).collect();
+    __http_router_strip_scope(path, &segments, &Normalize::default())
+}
 
-    // Transform TRACE token to Method::TRACE
-    (@one_route $context:expr, $method:expr, $path:expr, $default:expr, TRACE, $handler:ident, $($path_segment:tt)*) => {
-        router!(@one_route_with_method $context, $method, $path, $default, $crate::Method::TRACE, $handler, $($path_segment)*)
-    };
+/// This is an implementation detail and *should not* be called directly!
+///
+/// Checks whether the first segment of `path` is one of the `reserved` words.
+#[doc(hidden)]
+pub fn __http_router_is_reserved(path: &str, reserved: &[&str], ignore_case: bool) -> bool {
+    reserved
+        .iter()
+        .any(|word| match_segments(path, Some(*word), |_| ignore_case).is_some())
+}
 
-    // Transform CONNECT token to Method::CONNECT
-    (@one_route $context:expr, $method:expr, $path:expr, $default:expr, CONNECT, $handler:ident, $($path_segment:tt)*) => {
-        router!(@one_route_with_method $context, $method, $path, $default, $crate::Method::CONNECT, $handler, $($path_segment)*)
-    };
+/// Byte length of the `segments` at the start of `path`, if they are there as whole segments.
+/// Segments for which `any_case` is true match ignoring ASCII case.
+pub(crate) fn match_segments<'s, I, F>(path: &str, segments: I, any_case: F) -> Option<usize>
+where
+    I: IntoIterator<Item = &'s str>,
+    F: Fn(&str) -> bool,
+{
+    let bytes = path.as_bytes();
+    let mut pos = 0;
+    for segment in segments {
+        if bytes.get(pos) != Some(&b'/') {
+            return None;
+        }
+        pos += 1;
+        let end = memchr::memchr(b'/', &bytes[pos..]).map_or(bytes.len(), |i| pos + i);
+        let matches = if any_case(segment) {
+            bytes[pos..end].eq_ignore_ascii_case(segment.as_bytes())
+        } else {
+            &bytes[pos..end] == segment.as_bytes()
+        };
+        if !matches {
+            return None;
+        }
+        pos = end;
+    }
+    Some(pos)
+}
 
-    // Entry pattern
-    ($($method_token:ident $(/$path_segment:tt)+ => $handler:ident,)* _ => $default:ident $(,)*) => {{
-        move |context, method: $crate::Method, path: &str| {
-            let mut result = None;
-            $(
-                if result.is_none() {
-                    // we use closure here so that we could make early return from macros inside of it
-                    let closure = || {
-                        router!(@one_route context, method, path, $default, $method_token, $handler, $($path_segment)*)
-                    };
-                    result = closure();
-                }
-            )*
-            result.unwrap_or_else(|| $default(&context))
+/// Whether `segment` only has characters allowed in a URL path segment by RFC 3986, i.e.
+/// letters, digits, `-._~!$&'()*+,;=:@` and `%XX` escapes. `router!` checks its literal
+/// segments with this at compile time.
+pub const fn is_valid_url_segment(segment: &str) -> bool {
+    let bytes = segment.as_bytes();
+    if bytes.is_empty() {
+        return false;
+    }
+    let mut i = 0;
+    while i < bytes.len() {
+        match bytes[i] {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' => {}
+            b'-' | b'.' | b'_' | b'~' => {}
+            b'!' | b'$' | b'&' | b'\'' | b'(' | b')' | b'*' | b'+' | b',' | b';' | b'=' => {}
+            b':' | b'@' => {}
+            b'%' if i + 2 < bytes.len()
+                && bytes[i + 1].is_ascii_hexdigit()
+                && bytes[i + 2].is_ascii_hexdigit() =>
+            {
+                i += 2;
+            }
+            _ => return false,
         }
-    }};
+        i += 1;
+    }
+    true
+}
 
-    // Entry pattern - with home first
-    ($home_method_token:ident / => $home_handler:ident, $($method_token:ident $(/$path_segment:tt)+ => $handler:ident,)* _ => $default:ident $(,)*) => {{
-        move |context, method: $crate::Method, path: &str| {
-            let closure = || {
-                router!(@one_route context, method, path, $default, $home_method_token, $home_handler,)
-            };
-            let mut result = closure();
-            $(
-                if result.is_none() {
-                    // we use closure here so that we could make early return from macros inside of it
-                    let closure = || {
-                        router!(@one_route context, method, path, $default, $method_token, $handler, $($path_segment)*)
-                    };
-                    result = closure();
-                }
-            )*
-            result.unwrap_or_else(|| $default(&context))
+/// This is an implementation detail and *should not* be called directly!
+///
+/// Whether two routes have the same `(name, type)` params in the same order, checked at compile
+/// time for the aliases of a route.
+#[doc(hidden)]
+pub const fn __http_router_same_params(a: &[(&str, &str)], b: &[(&str, &str)]) -> bool {
+    const fn same(a: &str, b: &str) -> bool {
+        let (a, b) = (a.as_bytes(), b.as_bytes());
+        if a.len() != b.len() {
+            return false;
         }
-    }};
+        let mut i = 0;
+        while i < a.len() {
+            if a[i] != b[i] {
+                return false;
+            }
+            i += 1;
+        }
+        true
+    }
+    if a.len() != b.len() {
+        return false;
+    }
+    let mut i = 0;
+    while i < a.len() {
+        if !same(a[i].0, b[i].0) || !same(a[i].1, b[i].1) {
+            return false;
+        }
+        i += 1;
+    }
+    true
+}
 
-    // Entry pattern - default only
-    (_ => $default:ident $(,)*) => {
-        |context, _method: $crate::Method, _path: &str| {
-            $default(&context)
+/// This is an implementation detail and *should not* be called directly!
+///
+/// Pushes the regex for a literal segment of several tokens, like `index.html`.
+#[doc(hidden)]
+pub fn __http_router_push_literal(s: &mut String, literal: &str) {
+    s.push_str(&regex::escape(literal));
+}
+
+/// This is an implementation detail and *should not* be called directly!
+///
+/// Pushes the regex for a literal segment, matching in any case if it is one of the
+/// `lowercase_segments` of `normalize`.
+#[doc(hidden)]
+pub fn __http_router_push_segment(s: &mut String, literal: &str, normalize: &Normalize) {
+    if !normalize.ignore_case && normalize.any_case(literal) {
+        s.push_str("(?i:");
+        __http_router_push_literal(s, literal);
+        s.push(')');
+    } else {
+        __http_router_push_literal(s, literal);
+    }
+}
+
+/// This is an implementation detail and *should not* be called directly!
+///
+/// Pushes the regex for a `{name: type}` segment. `[T; N]` params capture `N` segments at once,
+/// `{name: type len 3..=40}` params only values of 3 to 40 characters, `{name: type no_ext}`
+/// params only values without a `.`.
+#[doc(hidden)]
+pub fn __http_router_push_param_regex(s: &mut String, segment: &str) {
+    if let Some((min, max)) = param_len(segment) {
+        s.push_str(r#"([\w-]{"#);
+        s.push_str(&min.to_string());
+        if max != Some(min) {
+            s.push(',');
+        }
+        if let Some(max) = max.filter(|&max| max != min) {
+            s.push_str(&max.to_string());
+        }
+        s.push_str("})");
+        return;
+    }
+    if segment
+        .trim_end_matches('}')
+        .trim_end()
+        .ends_with(" no_ext")
+    {
+        s.push_str(NO_EXTENSION_REGEX);
+        return;
+    }
+    #[cfg(feature = "with_uuid")]
+    {
+        if is_uuid_param(segment) {
+            s.push_str(UUID_REGEX);
+            return;
         }
     }
+    let array_len = segment
+        .find('[')
+        .and_then(|start| segment[start..].find(';').map(|semi| start + semi + 1))
+        .and_then(|start| {
+            segment[start..]
+                .find(']')
+                .map(|end| &segment[start..start + end])
+        })
+        .and_then(|len| len.trim().parse::<usize>().ok());
+    match array_len {
+        Some(len) if len > 0 => {
+            s.push_str("([^/]+");
+            for _ in 1..len {
+                s.push_str("/[^/]+");
+            }
+            s.push(')');
+        }
+        // percent-encoded bytes are decoded after matching
+        _ => s.push_str(r#"((?:[\w-]|%[0-9A-Fa-f]{2})+)"#),
+    }
 }
 
-#[cfg(test)]
-mod tests {
-    extern crate rand;
+/// Length range of a `{name: type len ...}` segment, e.g. `(3, Some(40))` for `len 3..=40`
+fn param_len(segment: &str) -> Option<(usize, Option<usize>)> {
+    let start = segment.find(" len ")? + " len ".len();
+    let range: String = segment[start..]
+        .chars()
+        .filter(|c| !c.is_whitespace() && *c != '}')
+        .collect();
+    let parse = |bound: &str| bound.parse::<usize>().ok();
+    if let Some(dots) = range.find("..") {
+        let (min, max) = (&range[..dots], &range[dots + 2..]);
+        // a param never captures an empty segment
+        let min = if min.is_empty() { 1 } else { parse(min)? };
+        let max = if let Some(max) = max.strip_prefix('=') {
+            Some(parse(max)?)
+        } else if max.is_empty() {
+            None
+        } else {
+            Some(parse(max)?.checked_sub(1)?)
+        };
+        Some((min, max))
+    } else {
+        let len = parse(&range)?;
+        Some((len, Some(len)))
+    }
+}
 
-    // use self::test::Bencher;
-    use super::*;
-    use std::thread;
+/// Param without a `.`, not even escaped as `%2E`, so that it can't end with a file extension
+const NO_EXTENSION_REGEX: &str = r#"((?:[\w-]|%(?:[013-9A-Fa-f][0-9A-Fa-f]|2[0-9A-Da-df]))+)"#;
 
-    const NUMBER_OF_THREADS_FOR_REAL_LIFE_TEST: usize = 4;
-    const NUMBER_OF_TESTS_FOR_REAL_LIFE_TEST: usize = 3000;
+/// Hyphenated UUIDs, the only form `Uuid` params match
+#[cfg(feature = "with_uuid")]
+const UUID_REGEX: &str =
+    "([0-9a-fA-F]{8}-[0-9a-fA-F]{4}-[0-9a-fA-F]{4}-[0-9a-fA-F]{4}-[0-9a-fA-F]{12})";
 
-    #[test]
-    fn test_real_life() {
-        let get_users = |_: &()| "get_users".to_string();
-        let post_users = |_: &()| "post_users".to_string();
-        let patch_users = |_: &(), id: u32| format!("patch_users({})", id);
-        let delete_users = |_: &(), id: u32| format!("delete_users({})", id);
-        let get_transactions = |_: &(), id: u32| format!("get_transactions({})", id);
-        let post_transactions = |_: &(), id: u32| format!("post_transactions({})", id);
-        let patch_transactions =
-            |_: &(), id: u32, hash: String| format!("patch_transactions({}, {})", id, hash);
-        let delete_transactions =
-            |_: &(), id: u32, hash: String| format!("delete_transactions({}, {})", id, hash);
-        let fallback = |_: &()| "404".to_string();
+/// Whether a `{name: type}` segment is a `Uuid` or `uuid::Uuid` param, by the name of its type
+/// only, so aliases of `Uuid` aren't recognized
+#[cfg(feature = "with_uuid")]
+fn is_uuid_param(segment: &str) -> bool {
+    let ty: String = segment
+        .split_once(':')
+        .map_or("", |(_, ty)| ty)
+        .chars()
+        .filter(|c| !c.is_whitespace() && *c != '}')
+        .collect();
+    matches!(ty.as_str(), "Uuid" | "uuid::Uuid" | "::uuid::Uuid")
+}
 
-        let router = router!(
-            GET / => get_users,
-            GET /users => get_users,
-            POST /users => post_users,
-            PATCH /users/{user_id: u32} => patch_users,
-            DELETE /users/{user_id: u32} => delete_users,
-            GET /users/{user_id: u32}/transactions => get_transactions,
-            POST /users/{user_id: u32}/transactions => post_transactions,
-            PATCH /users/{user_id: u32}/transactions/{hash: String} => patch_transactions,
-            DELETE /users/{user_id: u32}/transactions/{hash: String} => delete_transactions,
-            _ => fallback,
-        );
-        let test_cases = [
-            (Method::GET, "/", "get_users"),
-            (Method::GET, "/users", "get_users"),
-            (Method::POST, "/users", "post_users"),
-            (Method::PATCH, "/users/12", "patch_users(12)"),
-            (Method::DELETE, "/users/132134", "delete_users(132134)"),
-            (
-                Method::GET,
-                "/users/534/transactions",
-                "get_transactions(534)",
-            ),
-            (
-                Method::POST,
-                "/users/534/transactions",
-                "post_transactions(534)",
-            ),
-            (
-                Method::PATCH,
-                "/users/534/transactions/0x234",
-                "patch_transactions(534, 0x234)",
-            ),
-            (
-                Method::DELETE,
-                "/users/534/transactions/0x234",
-                "delete_transactions(534, 0x234)",
-            ),
-            (Method::DELETE, "/users/5d34/transactions/0x234", "404"),
-            (Method::POST, "/users/534/transactions/0x234", "404"),
-            (Method::GET, "/u", "404"),
-            (Method::POST, "/", "404"),
-        ];
-        for test_case in test_cases.into_iter() {
-            let (method, path, expected) = test_case.clone();
-            assert_eq!(router((), method.clone(), path), expected.to_string());
-        }
-
-        let mut threads: Vec<thread::JoinHandle<_>> = Vec::new();
-        for _ in 0..NUMBER_OF_THREADS_FOR_REAL_LIFE_TEST {
-            let handle = thread::spawn(move || {
-                for _ in 0..NUMBER_OF_TESTS_FOR_REAL_LIFE_TEST {
-                    let number = rand::random::<usize>() % test_cases.len();
-                    let test_case = test_cases[number];
-                    let (method, path, expected) = test_case;
-                    assert_eq!(router((), method.clone(), path), expected.to_string());
-                }
-            });
-            threads.push(handle);
-        }
-        for thread in threads {
-            let _ = thread.join();
-        }
+/// This is an implementation detail and *should not* be used directly!
+///
+/// Picks how a captured value is parsed based on the param type (see `__HttpRouterParseArray`)
+#[doc(hidden)]
+pub struct __HttpRouterParser<T>(pub PhantomData<T>);
+
+/// This is an implementation detail and *should not* be used directly!
+#[doc(hidden)]
+pub trait __HttpRouterParseValue<T> {
+    fn parse_param(&self, value: &str) -> Option<T>;
+}
+
+impl<T: FromStr> __HttpRouterParseValue<T> for __HttpRouterParser<T> {
+    fn parse_param(&self, value: &str) -> Option<T> {
+        escape::decode_segment(value).ok()?.parse::<T>().ok()
     }
+}
 
-    #[allow(unused_mut)]
-    #[test]
-    fn test_home() {
-        let get_home = |_: &()| "get_home";
-        let unreachable = |_: &()| unreachable!();
-        let router = router!(
-            GET / => get_home,
-            _ => unreachable
-        );
-        assert_eq!(router((), Method::GET, "/"), "get_home");
+/// This is an implementation detail and *should not* be used directly!
+///
+/// Implemented for `&__HttpRouterParser<[T; N]>`, so that method resolution picks it
+/// over `__HttpRouterParseValue` for array params.
+#[doc(hidden)]
+pub trait __HttpRouterParseArray<T> {
+    fn parse_param(&self, value: &str) -> Option<T>;
+}
+
+impl<T: FromStr, const N: usize> __HttpRouterParseArray<[T; N]> for &__HttpRouterParser<[T; N]> {
+    fn parse_param(&self, value: &str) -> Option<[T; N]> {
+        let mut parts = value.split('/');
+        let items: [Option<T>; N] = std::array::from_fn(|_| {
+            parts
+                .next()
+                .and_then(|part| escape::decode_segment(part).ok()?.parse().ok())
+        });
+        if parts.next().is_some() || items.iter().any(Option::is_none) {
+            return None;
+        }
+        Some(items.map(|item| item.unwrap()))
     }
+}
 
-    #[test]
-    fn test_fallback() {
-        let home = |_: &()| "home";
-        let users = |_: &()| "users";
-        let fallback = |_: &()| "fallback";
-        let router = router!(
-            GET / => home,
-            POST /users => users,
-            _ => fallback
-        );
-        assert_eq!(router((), Method::GET, "/"), "home");
-        assert_eq!(router((), Method::POST, "/users"), "users");
-        assert_eq!(router((), Method::GET, "/users"), "fallback");
-        assert_eq!(router((), Method::GET, "/us"), "fallback");
-        assert_eq!(router((), Method::PATCH, "/"), "fallback");
+/// This is an implementation detail and *should not* be used directly!
+///
+/// Value returned by a handler of an `async_router!`, awaited if it is a future
+/// (see `__HttpRouterAwaitValue`)
+#[doc(hidden)]
+pub struct __HttpRouterHandlerOutput<T>(Cell<Option<T>>);
+
+impl<T> __HttpRouterHandlerOutput<T> {
+    pub fn new(value: T) -> Self {
+        __HttpRouterHandlerOutput(Cell::new(Some(value)))
     }
 
-    #[test]
-    fn test_verbs() {
-        let get_test = |_: &()| Method::GET;
-        let post_test = |_: &()| Method::POST;
-        let put_test = |_: &()| Method::PUT;
-        let patch_test = |_: &()| Method::PATCH;
-        let delete_test = |_: &()| Method::DELETE;
-        let connect_test = |_: &()| Method::CONNECT;
-        let options_test = |_: &()| Method::OPTIONS;
-        let trace_test = |_: &()| Method::TRACE;
-        let head_test = |_: &()| Method::HEAD;
-        let panic_test = |_: &()| unreachable!();
-        let router = router!(
-            GET /users => get_test,
-            POST /users => post_test,
-            PUT /users => put_test,
-            PATCH /users => patch_test,
-            DELETE /users => delete_test,
-            OPTIONS /users => options_test,
-            CONNECT /users => connect_test,
-            TRACE /users => trace_test,
-            HEAD /users => head_test,
-            _ => panic_test
-        );
-
-        assert_eq!(router((), Method::GET, "/users"), Method::GET);
-        assert_eq!(router((), Method::POST, "/users"), Method::POST);
-        assert_eq!(router((), Method::PUT, "/users"), Method::PUT);
-        assert_eq!(router((), Method::PATCH, "/users"), Method::PATCH);
-        assert_eq!(router((), Method::DELETE, "/users"), Method::DELETE);
-        assert_eq!(router((), Method::OPTIONS, "/users"), Method::OPTIONS);
-        assert_eq!(router((), Method::TRACE, "/users"), Method::TRACE);
-        assert_eq!(router((), Method::CONNECT, "/users"), Method::CONNECT);
-        assert_eq!(router((), Method::HEAD, "/users"), Method::HEAD);
+    fn take(&self) -> T {
+        self.0.take().expect("handler output was already taken")
     }
+}
 
-    #[test]
-    fn test_params_number() {
-        let zero = |_: &()| String::new();
-        let one = |_: &(), p1: String| format!("{}", &p1);
-        let two = |_: &(), p1: String, p2: String| format!("{}{}", &p1, &p2);
-        let three = |_: &(), p1: String, p2: String, p3: String| format!("{}{}{}", &p1, &p2, &p3);
-        let four = |_: &(), p1: String, p2: String, p3: String, p4: String| {
-            format!("{}{}{}{}", &p1, &p2, &p3, &p4)
+/// This is an implementation detail and *should not* be used directly!
+#[doc(hidden)]
+pub trait __HttpRouterAwaitFuture {
+    type Future: Future;
+    fn route_future(&self) -> Self::Future;
+}
+
+impl<F: Future> __HttpRouterAwaitFuture for __HttpRouterHandlerOutput<F> {
+    type Future = F;
+    fn route_future(&self) -> F {
+        self.take()
+    }
+}
+
+/// This is an implementation detail and *should not* be used directly!
+///
+/// Implemented for `&__HttpRouterHandlerOutput<T>`, so that method resolution only picks it
+/// when the value isn't a future.
+#[doc(hidden)]
+pub trait __HttpRouterAwaitValue {
+    type Future: Future;
+    fn route_future(&self) -> Self::Future;
+}
+
+impl<T> __HttpRouterAwaitValue for &__HttpRouterHandlerOutput<T> {
+    type Future = Ready<T>;
+    fn route_future(&self) -> Ready<T> {
+        future::ready(self.take())
+    }
+}
+
+/// This macro returns a closure that takes 3 params. See crate doc for more details.
+///
+/// ### Limitations:
+/// - Fallback route (`_`) is required and should come last
+/// - Home route is optional and should come first
+/// - Plain `METHOD /path => handler` routes after the last other route (home route, aliases,
+///   `ext`, `pick`, scopes, or segments of several tokens like `/well-known`) are parsed in
+///   one step. Each route before it is one level of macro recursion, and each segment of
+///   several tokens one more, so routers with more than ~100 of those need a higher
+///   `#![recursion_limit]`.
+/// - Literal segments may only have URL path characters (see `is_valid_url_segment`), others
+///   like `?` or `#` fail to compile
+///
+/// ### Any method
+/// `ANY` routes match every method. Like other routes, they are tried in declaration order,
+/// so an `ANY` route shadows the routes below it with the same path. With a
+/// `specificity_order;` header, routes with a method are tried before `ANY` routes instead:
+///
+/// ```rust
+/// let router = router!(
+///     specificity_order;
+///     ANY /users => method_not_allowed,
+///     GET /users => get_users,
+///     _ => not_found,
+/// );
+/// ```
+///
+/// `String` params also match numbers, so with `specificity_order;` routes with a `String`
+/// param are tried after the others too, and `/posts/123` goes to the id route below in
+/// either order, while `/posts/123abc` goes to the slug route. Without it, the first of the
+/// two routes takes numbers:
+///
+/// ```rust
+/// let router = router!(
+///     specificity_order;
+///     GET /posts/{slug: String} => get_post_by_slug,
+///     GET /posts/{id: u32} => get_post_by_id,
+///     _ => not_found,
+/// );
+/// ```
+///
+/// ### Fallback detection
+/// Prefix routes with `matched;` to get a closure returning `(bool, T)`, where `false` means
+/// the value was produced by the fallback:
+///
+/// ```rust
+/// let router = router!(matched; GET /users => get_users, _ => not_found);
+/// let (matched, response) = router(ctx, method, path);
+/// ```
+///
+/// ### Fallthrough
+/// A handler followed by `?` returns `Option<T>`. Returning `None` declines the request,
+/// and the following routes are tried as if this one didn't match:
+///
+/// ```rust
+/// let router = router!(
+///     GET /{page_slug: String} => cms_page?,
+///     GET /{file: String} => static_file,
+///     _ => not_found,
+/// );
+/// ```
+///
+/// ### Conditions
+/// A route can be toggled at runtime with `if` and a closure over the context. The closure is
+/// called after params are parsed, so it can use them too. If it returns `false`, the route
+/// doesn't match:
+///
+/// ```rust
+/// let router = router!(
+///     GET /beta/reports => beta_reports if |ctx| ctx.flags.beta_reports,
+///     GET /users/{id: u32} => get_user if |ctx| ctx.can_view(id),
+///     _ => not_found,
+/// );
+/// ```
+///
+/// The condition has to be a closure, `if ctx.flags.beta_reports` doesn't compile: variables
+/// bound by a macro aren't visible to the expressions passed to it, so the closure is what
+/// names the context. Params are bound from the route's tokens, so they are in scope without
+/// it, e.g. `if |_| id > 0`.
+///
+/// Predicates named with `when` are conditions too, e.g. functions over the context shared
+/// by several routes. A route can have any number of them before its `guard` and `if`, and only
+/// matches if all of them return `true`, checked in order:
+///
+/// ```rust
+/// fn has_body(ctx: &Context) -> bool { ... }
+/// fn is_json(ctx: &Context) -> bool { ... }
+///
+/// let router = router!(
+///     POST /orders => create_order when has_body when is_json,
+///     POST /orders => unsupported_media_type,
+///     _ => not_found,
+/// );
+/// ```
+///
+/// ### Guards
+/// A `guard` is called with the context like a condition, but returns `Result<(), T>`.
+/// `Err(response)` answers the request with `response` right away, without calling the handler
+/// or trying later routes and the fallback. It runs after the `when` and `if` conditions:
+///
+/// ```rust
+/// fn auth_check(ctx: &Context) -> Result<(), Response> { ... }
+///
+/// let router = router!(
+///     GET /admin/{path: ..} => admin_panel guard auth_check,
+///     GET /admin/{path: ..} => admin_legacy guard auth_check if |ctx| ctx.flags.legacy,
+///     _ => not_found,
+/// );
+/// ```
+///
+/// ### Normalization
+/// A `normalize(...)` header sets `Normalize` options for all routes, and a `[normalize(...)]`
+/// attribute after a route's path overrides them for this route only:
+///
+/// ```rust
+/// let router = router!(
+///     normalize(ignore_case = true, trailing_slash = true);
+///     GET /users => get_users,
+///     GET /tokens/{token: String} [normalize(ignore_case = false)] => get_token,
+///     _ => not_found,
+/// );
+/// ```
+///
+/// Whether a route accepts repeated slashes like `/a//b` is set the same way, e.g.
+/// `[normalize(merge_slashes = false)]` on a route that must reject them in a router with
+/// `normalize(merge_slashes = true);`. Only routes with a catch-all match empty segments
+/// without merging them.
+///
+/// `strip_matrix_params = MatrixParams::LastSegment` ignores a `;...` suffix of the last
+/// segment, e.g. `;jsessionid=ABC123` appended by legacy Java clients, and `AllSegments` those
+/// of every segment. `Normalize::matrix_params` returns the stripped text.
+///
+/// `lowercase_segments = &["exports", "csv"]` matches these literal segments in any case, e.g.
+/// `/Exports/CSV` for `GET /exports/csv`, while other literals stay case-sensitive. Params keep
+/// their case, even when their value is one of the listed segments.
+///
+/// ### Route groups
+/// `scope { ... }` groups routes without adding a prefix to their paths, e.g. to keep routes
+/// sharing a concern together. The routes of the group are tried in place, like routes
+/// written without it, so the routes after the group are still tried when none of the group
+/// matches:
+///
+/// ```rust
+/// let router = router!(
+///     GET / => home,
+///     scope {
+///         GET /users => get_users,
+///         POST /users => post_users,
+///     },
+///     GET /about => about,
+///     _ => not_found,
+/// );
+/// ```
+///
+/// ### Memoization
+/// A `GET` route marked `[memoize(cache)]` looks the request up in `cache`, a `RouteCache`,
+/// after its params, conditions and guard passed, and stores the handler's result there,
+/// keyed by method and path like `GET /reports/2020`. Mark only routes whose results only
+/// depend on the path. `MemoryCache` keeps a bounded number of results in memory:
+///
+/// ```rust
+/// let reports = Arc::new(MemoryCache::new(64));
+/// let router = router!(
+///     GET /reports/{year: u32} [memoize(reports)] => build_report,
+///     _ => not_found,
+/// );
+/// ```
+///
+/// Marking a route with another method fails to compile. The cache is moved into the router,
+/// so share it by reference or `Arc`.
+///
+/// ### Timing
+/// With a `timing(hook);` header, the router calls `hook(template, match_ns, handler_ns)`
+/// after each call, e.g. to report routing overhead apart from handler time. `template` is
+/// the route that answered, like `/users/{id: u32}`, or `"<fallback>"`. Matching lasts until
+/// the handler is called, so it includes parsing params and checking conditions. Without the
+/// header no timing code is generated:
+///
+/// ```rust
+/// fn record_timing(template: &'static str, match_ns: u64, handler_ns: u64) { ... }
+///
+/// let router = router!(timing(record_timing); GET /users => get_users, _ => not_found);
+/// ```
+///
+/// With async handlers, the handler time is the time to create the future.
+///
+/// ### Wrapping handlers
+/// With a `wrap = hook;` header, the router calls `hook(template, timeout_ms, call)` instead
+/// of the handler of the route that matched, `call` being a closure calling the handler.
+/// `timeout_ms` is the `Option<u64>` of a `[timeout_ms = 5000]` route attribute, e.g. for the
+/// hook to enforce it, and `template` the route like for `timing(hook);`. The fallback and
+/// mounted routers aren't wrapped:
+///
+/// ```rust
+/// fn with_timeout(template: &'static str, timeout_ms: Option<u64>, call: impl FnOnce() -> Response) -> Response { ... }
+///
+/// let router = router!(
+///     wrap = with_timeout;
+///     GET /reports/{id: u64} [timeout_ms = 5000] => build_report,
+///     _ => not_found,
+/// );
+/// ```
+///
+/// With async handlers, `call` returns the handler's future, which the hook may wrap.
+///
+/// ### Panics
+/// With a `catch_panics = recover;` header, a panic of a handler, condition, guard, the fallback
+/// or the timing hook is caught with `std::panic::catch_unwind`, and the router returns
+/// `recover(&context, message)` instead, `message` being the panic's `&str` or `String`. The
+/// context is asserted unwind safe, so state it shares through `RefCell`s or the like may be
+/// left half updated. Panics aborting the process aren't caught, and async routers don't
+/// support the header:
+///
+/// ```rust
+/// fn internal_error(context: &Context, message: &str) -> Response { ... }
+///
+/// let router = router!(catch_panics = internal_error; GET /users => get_users, _ => not_found);
+/// ```
+///
+/// `matched;` routers report the recovered value as matched.
+///
+/// ### Async handlers
+/// With an `async;` header, handler futures are boxed, so that `async fn` handlers
+/// (each returning its own future type) can be mixed. The closure then returns
+/// `Pin<Box<dyn Future<Output = T> + '_>>`. Pass the context by reference, so that the
+/// futures can borrow it:
+///
+/// ```rust
+/// async fn get_users(context: &Context) -> Response { ... }
+///
+/// let router = router!(async; GET /users => get_users, _ => not_found);
+/// let response = router(&ctx, method, path).await;
+/// ```
+///
+/// With `async move;` instead, handlers take the context by value, e.g. an `Arc<Context>`
+/// cloned for each call, so that their futures own it and can outlive the call. The
+/// closure then returns `Pin<Box<dyn Future<Output = T> + Send>>`, ready for `tokio::spawn`:
+///
+/// ```rust
+/// async fn get_users(context: Arc<Context>) -> Response { ... }
+///
+/// let router = router!(async move; GET /users => get_users, _ => not_found);
+/// let response = tokio::spawn(router(ctx.clone(), method, path)).await;
+/// ```
+///
+/// To mix sync handlers in, use `async_router!` instead.
+///
+/// ### Extensions
+/// With an `extensions;` header, the closure takes `&Extensions` after the context, and
+/// handlers (including the fallback) get it after the context too. Middleware fills it
+/// with request-scoped values before calling the router:
+///
+/// ```rust
+/// fn get_profile(context: &Context, extensions: &Extensions) -> Response { ... }
+///
+/// let router = router!(extensions; GET /profile => get_profile, _ => not_found);
+/// let mut extensions = Extensions::new();
+/// extensions.insert(authenticate(&request));
+/// let response = router(ctx, &extensions, method, path);
+/// ```
+///
+/// ### Per-request values
+/// With a `request;` header, the closure takes one more argument after the context (and the
+/// extensions), e.g. the request's body and headers, and moves it into the handler that runs,
+/// so it doesn't have to be `Clone`. Handlers (including the fallback) get it after the context:
+///
+/// ```rust
+/// fn get_user(context: &Context, request: Request, id: u32) -> Response { ... }
+/// fn not_found(context: &Context, request: Request) -> Response { ... }
+///
+/// let router = router!(request; GET /users/{id: u32} => get_user, _ => not_found);
+/// let response = router(ctx, request, method, path);
+/// ```
+///
+/// Fallthrough routes (`handler?`) fail to compile with a `request;` header, since the value
+/// moved into a declining handler couldn't be passed to the following routes.
+///
+/// A route marked `[no_body(predicate => bad_request)]` checks the value with
+/// `predicate: fn(&Request) -> bool` after its params, conditions and guard passed. If it
+/// fails, `bad_request` answers instead of the handler, getting the context (and the
+/// extensions) and the value like a route without params:
+///
+/// ```rust
+/// fn has_no_body(request: &Request) -> bool { request.body.is_empty() }
+/// fn bad_request(context: &Context, request: Request) -> Response { ... }
+///
+/// let router = router!(
+///     request;
+///     GET /users/{id: u32} [no_body(has_no_body => bad_request)] => get_user,
+///     _ => not_found,
+/// );
+/// ```
+///
+/// A route takes several attributes one after another, e.g.
+/// `GET /reports/{year: u32} [memoize(reports)] [no_body(has_no_body => bad_request)] => build_report`.
+/// A rejected value is answered before the cache is looked up.
+///
+/// ### Scopes
+/// `scope /prefix (map_ctx = f) => inner,` mounts another router under a prefix. `f` maps
+/// `&Context` to `Option<InnerContext>`: with `Some`, `inner` is called with the inner context
+/// by value and the rest of the path, so its fallback answers paths it doesn't know. With
+/// `None` the scope doesn't match, e.g. for an auth gate, and the following routes are tried:
+///
+/// ```rust
+/// fn to_admin_ctx(context: &AppContext) -> Option<AdminContext> { ... }
+///
+/// let admin_router = router!(GET /users => list_users, _ => admin_not_found);
+/// let router = router!(
+///     scope /admin (map_ctx = to_admin_ctx) => admin_router,
+///     _ => not_found,
+/// );
+/// ```
+///
+/// The prefix only matches whole segments. Both `/admin` and `/admin/` call `inner` with `/`,
+/// so they reach its `GET /` route, or its fallback if it has none, while `/adminx` isn't in
+/// the scope and falls through to the following routes.
+///
+/// Outside of a router, `strip_prefix_route(path, "/admin")` gives the rest of the path the
+/// same way, e.g. `Some("/users")` for `/admin/users`.
+///
+/// ### File extensions
+/// `ext { ... }` instead of a handler picks the handler by the extension of the last segment,
+/// sharing the rest of the route. Paths without one of the listed extensions don't match:
+///
+/// ```rust
+/// let router = router!(
+///     GET /reports/{id: u32} => ext { "json" => json_report, "xml" => xml_report },
+///     _ => not_found,
+/// );
+/// ```
+///
+/// Params never match a raw `.`, but they match an escaped one like `about%2Ejson`, which is
+/// decoded to `about.json`. A `no_ext` marker after the type rejects those too, so that a
+/// value can't end with an extension meant for other routes:
+///
+/// ```rust
+/// let router = router!(
+///     GET /page/{name: String no_ext} => page,
+///     GET /page/{name: String} => ext { "json" => json_page },
+///     _ => not_found,
+/// );
+/// ```
+///
+/// ### Handler pickers
+/// `pick(picker => handlers...)` instead of a handler calls `picker(&context) -> usize` once
+/// the route matched, and then the handler at that index, e.g. for A/B tests. Indices past
+/// the end pick the last handler. The handlers take the same params, and return the same type,
+/// so in `async;` routers they can't be `async fn`s of their own:
+///
+/// ```rust
+/// fn variant(context: &Context) -> usize { (context.user_id % 2) as usize }
+///
+/// let router = router!(GET /home => pick(variant => home_a, home_b), _ => not_found);
+/// ```
+///
+/// ### Route aliases
+/// Templates separated by `|` match the same route, e.g. to keep serving an old spelling. The
+/// first one is canonical, the others are aliases sharing its handler, conditions, guard and
+/// attributes. Aliases must have the same params as the canonical template, with the same names
+/// and types, or the router doesn't compile:
+///
+/// ```rust
+/// let router = router!(
+///     GET /organizations | /organisations => list_orgs,
+///     GET /organizations/{id: u32} | /orgs/{id: u32} => get_org,
+///     _ => not_found,
+/// );
+/// ```
+///
+/// Aliases are routes of their own in the static route table, so `route_count` counts them,
+/// see `aliases` and `render` there to link to the canonical template.
+///
+/// ### Catch-all
+/// A `{name: ..}` segment captures the rest of the path, slashes included, as a `String`.
+/// Routes with a catch-all are tried after all the other routes, wherever they are declared,
+/// so that more specific routes win:
+///
+/// ```rust
+/// let router = router!(
+///     GET /files/{path: ..} => static_file,
+///     GET /files/index.html => index,
+///     _ => not_found,
+/// );
+/// ```
+///
+/// ### Reserved paths
+/// With a `reserved("admin", ...);` header, paths whose first segment is reserved skip
+/// routes starting with a param or catch-all, e.g. so that a `/{slug}` CMS route can't answer
+/// `/admin`. Routes starting with a literal segment still match them, wherever they are
+/// declared. Unmatched reserved paths go to the fallback, or to the handler given after `=>`:
+///
+/// ```rust
+/// let router = router!(
+///     reserved("admin", "login", "static") => reserved_path;
+///     GET /{slug: String} => cms_page,
+///     GET /login => login_page,
+///     _ => not_found,
+/// );
+/// ```
+///
+/// The reserved handler takes the same arguments as the fallback.
+///
+/// ### All paths of a method
+/// `METHOD / *` matches every path with the method, e.g. to log all `DELETE` requests before
+/// the fallback. Like catch-all routes, it is tried after the other routes. Write it with a
+/// space, since `/*` starts a comment:
+///
+/// ```rust
+/// let router = router!(
+///     GET /users => get_users,
+///     DELETE / * => log_delete,
+///     _ => not_found,
+/// );
+/// ```
+///
+/// ### Length constraints
+/// A param can be limited to a number of characters with `len` and a range, e.g.
+/// `GET /posts/{slug: String len 3..=40}`. Values of other lengths don't match the route,
+/// so the following routes are tried. The type has to be a single identifier like `String`.
+/// A range that can't match like `len 5..=2`, or that isn't a range of integers, fails to
+/// compile. In debug builds, routes whose regex doesn't compile panic as soon as the router
+/// is created.
+///
+/// ### Param validators
+/// `{name: type if validator}` checks a param with `validator(value, &context) -> bool`
+/// before it is parsed, e.g. a signed URL token against an HMAC key in the context. The value
+/// is passed as matched, still percent-encoded. If the validator returns `false`, the route
+/// doesn't match and the following routes are tried:
+///
+/// ```rust
+/// fn valid_signature(token: &str, context: &Context) -> bool { ... }
+///
+/// let router = router!(
+///     GET /download/{token: String if valid_signature} => download,
+///     _ => not_found,
+/// );
+/// ```
+///
+/// ### Custom param types
+/// Params are parsed with `FromStr`, so a type without a `FromStr` impl fails to compile. For
+/// one of another crate, register a parser once with `register_param_parser`, before
+/// routing, and mark the param `registered` to parse it with that parser. Until one is
+/// registered, such routes don't match. `Params::get_registered` uses the parsers too:
+///
+/// ```rust
+/// register_param_parser(|value: &str| NaiveDate::parse_from_str(value, "%Y%m%d").ok());
+///
+/// let router = router!(
+///     GET /reports/{day: NaiveDate registered} => daily_report,
+///     _ => not_found,
+/// );
+/// ```
+///
+/// ### Query params
+/// Routes only match the path, so a route doesn't decline when a query param it needs is
+/// missing. Handlers read them with `Query`, whose `required` fails with
+/// `QueryError::BadParam` naming the param, to answer 400 Bad Request instead:
+///
+/// ```rust
+/// fn search(context: &Context, request: Request) -> Response {
+///     match Query::new(request.query()).required::<String>("q") {
+///         Ok(q) => ...,
+///         Err(QueryError::BadParam { name }) => bad_request(format!("missing `{}`", name)),
+///     }
+/// }
+///
+/// let router = router!(request; GET /search => search, _ => not_found);
+/// ```
+///
+/// ### Percent-encoding
+/// Routes are matched against the raw path, so a literal like `/users/@me` only matches a raw
+/// `@`, not `%40`. Captured params are percent-decoded before they are parsed, e.g.
+/// `/users/j%C3%B6rg` gives `"jörg"`, and values that don't decode to UTF-8 don't match.
+/// Params with a length constraint don't accept escapes.
+///
+/// `escape::decode_segment` decodes like the router, and `escape::encode_segment` escapes a
+/// value so that a param without a length constraint captures it back as is, e.g. when
+/// building links. A `%` not followed by two hex digits doesn't match, not even in a
+/// `{name: ..}` catch-all.
+///
+/// ### Signed integer params
+/// Params match word characters, hyphens and escapes before they are parsed, so for `i8` or
+/// `i16` params `-1` matches and parses, while `--1`, `1a` and overflowing values like `-129`
+/// for `i8` match but don't parse, and the following routes are tried. A raw `+1` doesn't
+/// match, but the escaped `%2B1` does and parses as `1`, like `i8::from_str("+1")`.
+///
+/// ### Array params
+/// A param of type `[T; N]` captures exactly `N` segments, e.g. `GET /point/{coords: [f64; 2]}`
+/// matches `/point/1.5/2.5`, but not `/point/1.5`.
+///
+/// ### UUID params
+/// With the `with_uuid` feature, `Uuid` params like `{item_id: Uuid}` only match hyphenated
+/// UUIDs in either case, e.g. `550e8400-e29b-41d4-a716-446655440000` or
+/// `550E8400-E29B-41D4-A716-446655440000`, and are parsed with `Uuid::from_str`. Other segments
+/// of word characters and hyphens fall through to the following routes.
+///
+/// The param is recognized by its type as written, `Uuid`, `uuid::Uuid` or `::uuid::Uuid`,
+/// since the macro can't resolve names. Under an alias like `use uuid::Uuid as Id;` or a
+/// re-export, `{item_id: Id}` matches any segment of word characters and hyphens like other
+/// params, so that e.g. unhyphenated UUIDs parse too.
+///
+/// ### Static route table
+/// With a `table;` header, the macro returns a `RouteTable` instead of the closure. It derefs
+/// to the router, and lists the routes the router was built from in declaration order, e.g. to
+/// size per-route metrics:
+///
+/// ```rust
+/// let api = router!(table; GET /users => get_users, GET /users/{id: u32} => get_user, _ => not_found);
+///
+/// let hits: Vec<AtomicU64> = (0..api.route_count()).map(|_| AtomicU64::new(0)).collect();
+/// let response = api(ctx, method, path);
+/// ```
+///
+/// `routes` gives the `StaticRouteInfo` of each route, and `meta` its `RouteMeta`, with the
+/// types of its params as written, e.g. `("id", "u32")`, to generate client SDKs. A route
+/// marked `[meta(auth = "admin")]` lists `("auth", "admin")` in its `meta`, e.g. for
+/// `audit::coverage_meta`. `timeouts_ms` gives the `[timeout_ms = ...]` of each route.
+///
+/// `match_pattern(method, path)` finds the first route whose method and pattern match, by its
+/// index in `routes`, and its params as matched, e.g. for hosts that call handlers through a
+/// function table of their own. It only looks at patterns: conditions, guards, `reserved(...)`
+/// and param types aren't checked, so the route it finds may be one the router declines, and
+/// checking them is left to the caller.
+///
+/// `aliases` gives the canonical template and aliases of each route, and `render(index, params)`
+/// renders the canonical template of a route, e.g. to redirect from the alias `match_pattern`
+/// found. Templates are parsed on the first call, and those of `ext { ... }` give
+/// `RenderError::Unsupported`:
+///
+/// ```rust
+/// let (index, captures) = api.match_pattern(method, path)?;
+/// if api.aliases[index].alias {
+///     return redirect(api.render(index, &[("id", captures.get(0)?)])?);
+/// }
+/// ```
+///
+/// With `table(predicates);` instead, `is_route` has a `handler(method, path) -> bool` method
+/// per handler, telling whether `match_pattern` finds one of its routes, e.g. for tests and
+/// guards. Like with `match_pattern`, params aren't parsed, and a handler may only be listed
+/// once:
+///
+/// ```rust
+/// let api = router!(table(predicates); PUT /users/{id: u32} => put_users, _ => not_found);
+///
+/// assert!(api.is_route.put_users(Method::PUT, "/users/5"));
+/// ```
+///
+/// ### Compact routers
+/// With a `compact;` header, the macro only emits a static table of the routes and the
+/// handler calls, and the routes are matched by a loop in this crate. The closure behaves
+/// the same, but routers with hundreds of routes compile to less code. See
+/// `examples/size_check` to compare the binary sizes:
+///
+/// ```rust
+/// let router = router!(compact; GET /users => get_users, GET /users/{id: u32} => get_user, _ => not_found);
+/// ```
+///
+/// ### Performace
+/// Macro routers itself has almost no cost, so you can call it
+/// everywhere as many times as you like. The closure that it returns
+/// have some cost (approx 50 microseconds per one call).
+/// Requests to routes without params don't allocate, once the regexes of the routes
+/// tried before them are compiled on the first requests.
+///
+/// Regexes are compiled through a cache shared by all routers of the process. If routers
+/// are created once, or on single-threaded targets like wasm, the `no_shared_cache` feature
+/// leaves it out. Each route still compiles its regex once, but debug builds then compile
+/// them again to validate every router created. The `fx_hash` feature looks regexes up in
+/// the cache with FxHash instead of SipHash, which is faster for these trusted keys.
+///
+/// ### Thread safety
+/// The closure returned by this macro is thread-safe.
+///
+/// It only captures the handlers, hooks and caches it is given, so it is `Clone` (and `Copy`)
+/// when all of them are, e.g. functions and closures capturing `Arc`s, and each task can get
+/// its own copy. Otherwise `shared_router` wraps it into a `SharedRouter`, an
+/// `Arc<dyn Fn(C, Method, &str) -> R + Send + Sync>` that is cheap to clone:
+///
+/// ```rust
+/// let router = shared_router(router!(GET /users => list_users, _ => not_found));
+/// for connection in connections {
+///     let router = router.clone();
+///     thread::spawn(move || serve(connection, router));
+/// }
+/// ```
+#[macro_export]
+macro_rules! router {
+    // Internal rules start with `@`, the expansion runs through them in this order:
+    //
+    // 1. The entry pattern (last rule) starts @header, which consumes the header lines
+    //    (`matched;`, `async;`, `normalize(...);`, ...) into its state: the mode picking the
+    //    closure (`[plain]`, `[matched]`, `[async]`, `[async_move]`, `[boxed]`), the
+    //    router's normalization, the `extensions;`/`request;` params with the
+    //    `timing(...);` hook, `reserved(...);` words, `catch_panics = ...;` handler,
+    //    `wrap = ...;` hook and `table;` flag, and the order (`[specificity_order]` or
+    //    `[specificity_order, compact]`).
+    // 2. @parse (with @path and @extensions) turns each route into a tuple
+    //    `(METHOD [segments] handler [fallthrough] [conditions] [guard] [attributes])`,
+    //    and hands the tuples to @closure at the fallback. With `table;`, @table puts the
+    //    closure in a `RouteTable` along with statics describing the routes.
+    // 3. @closure builds the closure of the mode, which calls @find with the context, the
+    //    handler args, the method and the path.
+    // 4. @find tries the routes in order, each through @one_route with the path normalized
+    //    for the route, and returns `Some` of the first handler value, or `None` to call the
+    //    fallback. Compact routers match the paths in the crate and only call @table_call for
+    //    the route that matched.
+    // 5. @one_route turns the method token into a `Method` and calls @one_route_with_method,
+    //    which matches the path and returns `None` if it doesn't match.
+    // 6. @call_params drops the literal segments and passes the params to @call, which
+    //    numbers them (up to 7) and calls @call_pure to parse them with @parse_type, check
+    //    the conditions and guard with @check and call the handler.
+
+    // convert params from string
+    (@parse_type $value:expr, $ty:ty) => {{
+        #[allow(unused_imports)]
+        use $crate::{__HttpRouterParseArray, __HttpRouterParseValue};
+        let parser = $crate::__HttpRouterParser::<$ty>(::std::marker::PhantomData);
+        (&&parser).parse_param($value)?
+    }};
+
+    // Value of a param, checked by the validator of `{name: type if validator}` before it is
+    // parsed, or parsed by the parser registered for `{name: type registered}`
+    (@param_value $context:expr, $value:expr, $ty:ty) => {{
+        let value = $value;
+        $crate::router!(@parse_type value, $ty)
+    }};
+    (@param_value $context:expr, $value:expr, $ty:ty, [registered]) => {
+        $crate::__http_router_parse_registered::<$ty>($value)?
+    };
+    (@param_value $context:expr, $value:expr, $ty:ty, $valid:ident) => {{
+        let value = $value;
+        if !$valid(value, &$context) {
+            return None;
+        }
+        $crate::router!(@parse_type value, $ty)
+    }};
+
+    // Conditions and guard of the route, checked after params are parsed.
+    // A failed guard leaves its response in `$guarded` for @find.
+    (@check $context:expr, ([$($cond:expr;)*] [$($guard:ident)?] $guarded:ident $timer:tt $memo:tt $no_body:tt $wrap:tt)) => {
+        $(if !$crate::__http_router_check(&$context, $cond) { return None };)*
+        $(if let Err(response) = $guard(&$context) {
+            $guarded.set(Some(response));
+            return None;
+        })?
+        $crate::router!(@no_body $no_body);
+        $crate::router!(@memo_get $memo);
+        $crate::router!(@timing_handler $timer);
+    };
+
+    // With `[memoize(cache)]`, `(GET [[memoize(cache)] ...] memo)` looks the request up in the cache
+    // once the route matched, and stores the handler's result after the call
+    (@memo_request (GET [[memoize($cache:expr)] $($attr:tt)*] $memo:ident $request:tt), $method:expr, $path:expr) => {
+        let $memo = ($method, $path);
+    };
+    (@memo_request ($method_token:ident [$other:tt $($attr:tt)*] $memo:ident $request:tt), $method:expr, $path:expr) => {
+        $crate::router!(@memo_request ($method_token [$($attr)*] $memo $request), $method, $path)
+    };
+    (@memo_request $memo:tt, $method:expr, $path:expr) => {};
+    (@memo_get (GET [[memoize($cache:expr)] $($attr:tt)*] $memo:ident $request:tt)) => {
+        let $memo = $crate::__http_router_memo_key($memo.0, $memo.1);
+        if let Some(value) = $crate::RouteCache::get(&$cache, &$memo) {
+            return Some(value);
+        }
+    };
+    (@memo_get ($method_token:ident [[memoize($cache:expr)] $($attr:tt)*] $memo:ident $request:tt)) => {
+        compile_error!("`[memoize(...)]` only applies to GET routes");
+    };
+    (@memo_get ($method_token:ident [$other:tt $($attr:tt)*] $memo:ident $request:tt)) => {
+        $crate::router!(@memo_get ($method_token [$($attr)*] $memo $request))
+    };
+    (@memo_get $memo:tt) => {};
+    (@memo_put ($conds:tt $guard:tt $guarded:ident $timer:tt (GET [[memoize($cache:expr)] $($attr:tt)*] $memo:ident $request:tt) $no_body:tt $wrap:tt), $value:expr) => {{
+        let value = $value;
+        $crate::RouteCache::put(&$cache, &$memo, &value);
+        value
+    }};
+    (@memo_put ($conds:tt $guard:tt $guarded:ident $timer:tt ($method_token:ident [$other:tt $($attr:tt)*] $memo:ident $request:tt) $no_body:tt $wrap:tt), $value:expr) => {
+        $crate::router!(@memo_put ($conds $guard $guarded $timer ($method_token [$($attr)*] $memo $request) $no_body $wrap), $value)
+    };
+    (@memo_put $cond:tt, $value:expr) => { $value };
+
+    // With a `wrap = hook;` header, the handler call goes through `hook(template, timeout_ms, call)`
+    (@wrap ($conds:tt $guard:tt $guarded:ident $timer:tt ($method_token:ident $attrs:tt $memo:ident $request:tt) $no_body:tt ([$hook:expr] [$($path_segment:tt)*])), $value:expr) => {
+        $hook($crate::router!(@template $($path_segment)*), $crate::router!(@timeout_ms $attrs), || $value)
+    };
+    (@wrap $cond:tt, $value:expr) => { $value };
+
+    // With `[no_body(predicate => bad_request)]`, `([[no_body(...)] ...] rejected [request])` checks
+    // the request value once the route matched, and a value failing the predicate is answered
+    // by `bad_request` instead of the handler
+    (@no_body_request ([[no_body($($check:tt)*)] $($attr:tt)*] $rejected:ident [$request:ident])) => {
+        let $rejected = ::std::cell::Cell::new(false);
+    };
+    (@no_body_request ([[no_body($($check:tt)*)] $($attr:tt)*] $rejected:ident [])) => {
+        compile_error!("`[no_body(...)]` needs a `request;` header");
+    };
+    (@no_body_request ([$other:tt $($attr:tt)*] $rejected:ident $request:tt)) => {
+        $crate::router!(@no_body_request ([$($attr)*] $rejected $request))
+    };
+    (@no_body_request $no_body:tt) => {};
+    (@no_body ([[no_body($predicate:expr => $bad_request:ident)] $($attr:tt)*] $rejected:ident [$request:ident])) => {
+        let value = $request.take();
+        let passes = value.as_ref().map_or(true, |value| $predicate(value));
+        $request.set(value);
+        if !passes {
+            $rejected.set(true);
+            return None;
+        }
+    };
+    (@no_body ([$other:tt $($attr:tt)*] $rejected:ident $request:tt)) => {
+        $crate::router!(@no_body ([$($attr)*] $rejected $request))
+    };
+    (@no_body $no_body:tt) => {};
+    (@no_body_response [$mode:ident] ([[no_body($predicate:expr => $bad_request:ident)] $($attr:tt)*] $rejected:ident $request:tt), $result:ident, [$($args:tt)*]) => {
+        if $rejected.get() {
+            $result = Some($crate::router!(@box_future [$mode] $bad_request($($args)*)));
+        }
+    };
+    (@no_body_response [$mode:ident] ([$other:tt $($attr:tt)*] $rejected:ident $request:tt), $result:ident, $args:tt) => {
+        $crate::router!(@no_body_response [$mode] ([$($attr)*] $rejected $request), $result, $args)
+    };
+    (@no_body_response [$mode:ident] $no_body:tt, $result:ident, $args:tt) => {};
+
+    // With a `timing(hook);` header, `[timer hook]` marks the end of matching when the handler
+    // of a route is called, and @find records the template of the route that answered
+    (@timing_handler []) => {};
+    (@timing_handler [$timer:ident $hook:expr]) => {
+        $timer.handler_started();
+    };
+    // With a `reserved(...);` header, whether the first segment of the path is reserved
+    (@reserved [[$($word:literal),+] $handler:tt], $normalize:expr, $path:expr) => {
+        $crate::__http_router_is_reserved(&$normalize.path($path), &[$($word),+], $normalize.ignore_case)
+    };
+    (@reserved [], $normalize:expr, $path:expr) => { false };
+
+    // Value of the block, or of the `catch_panics = handler;` handler if it panics. Catching
+    // the panic leaves the context borrowed by the block as is, so it is asserted unwind safe.
+    (@catch_panics $mode:tt [] $context:expr, $body:block) => { $body };
+    (@catch_panics [$mode:ident] [$recover:expr] $context:expr, $body:block) => {
+        match ::std::panic::catch_unwind(::std::panic::AssertUnwindSafe(|| $body)) {
+            Ok(value) => value,
+            Err(panic) => $crate::router!(@recovered [$mode] $recover($context, $crate::__http_router_panic_message(&*panic))),
+        }
+    };
+    (@recovered [plain] $value:expr) => { $value };
+    // the route matched if a handler panicked, the fallback panicking is reported as matched too
+    (@recovered [matched] $value:expr) => { (true, $value) };
+
+    // `catch_panics = handler;` needs the handler's value, async routers return it as a future
+    (@sync_only []) => {};
+    (@sync_only [$recover:expr]) => {
+        compile_error!("catch_panics = handler; is only supported by sync routers")
+    };
+
+    // Fallback call, or the handler of a `reserved(...) => handler;` header for reserved paths
+    (@fallback [$mode:ident] [$words:tt [$handler:ident]], $reserved:ident, $default:ident($($arg:expr),*)) => {
+        if $reserved {
+            $crate::router!(@box_future [$mode] $handler($($arg),*))
+        } else {
+            $crate::router!(@box_future [$mode] $default($($arg),*))
+        }
+    };
+    (@fallback [$mode:ident] $state:tt, $reserved:ident, $default:ident($($arg:expr),*)) => {{
+        let _ = $reserved;
+        $crate::router!(@box_future [$mode] $default($($arg),*))
+    }};
+
+    // Whether the route starts with a param or catch-all, so that reserved paths skip it
+    (@starts_with_param {$($param:tt)*} $($rest:tt)*) => { true };
+    (@starts_with_param * $($rest:tt)*) => { true };
+    (@starts_with_param $($path_segment:tt)*) => { false };
+
+    (@timing_route [], $result:ident, $($path_segment:tt)*) => {};
+    (@timing_route [$timer:ident $hook:expr], $result:ident, $($path_segment:tt)*) => {
+        if $result.is_some() {
+            $timer.route($crate::router!(@template $($path_segment)*));
+        }
+    };
+
+    // call handler with params
+    (@call_pure $context:expr, [$($args:tt)*], $cond:tt, $handler:tt, $params:expr, $({$id:ident : $ty:ty : $idx:expr $(; $valid:tt)?}),*) => {{
+        // params are bound by name for the condition, so they may shadow the handler
+        let handler = $crate::router!(@handler_ref $handler);
+        $(
+            let $id: $ty = $crate::router!(@param_value $context, $params[$idx], $ty $(, $valid)?);
+        )*
+        $crate::router!(@check $context, $cond);
+        $crate::router!(@memo_put $cond, $crate::router!(@wrap $cond, $crate::router!(@invoke $context, handler, $handler, ($($args)*, $($id),*))))
+    }};
+
+    // Reference to the handler of a route, or to the picker and handlers of `pick(...)`
+    (@handler_ref [pick $picker:ident $($choice:ident)+]) => {
+        (&$picker, $crate::router!(@pick_list $($choice)+))
+    };
+    (@handler_ref $handler:ident) => { &$handler };
+    (@pick_list $choice:ident $($more:ident)*) => { (&$choice, $crate::router!(@pick_list $($more)*)) };
+    (@pick_list) => { () };
+
+    // Call the handler, or the one the picker of `pick(...)` chose
+    (@invoke $context:expr, $handler_ref:ident, [pick $picker:ident $($choice:ident)+], $call_args:tt) => {{
+        let choice: usize = ($handler_ref.0)(&$context);
+        $crate::router!(@pick_call choice, 0, $handler_ref.1, $call_args, $($choice)+)
+    }};
+    (@invoke $context:expr, $handler_ref:ident, $handler:ident, $call_args:tt) => { $handler_ref $call_args };
+
+    // Handlers of `pick(...)` by index, indices past the end choosing the last one
+    (@pick_call $choice:ident, $index:expr, $handlers:expr, $call_args:tt, $last:ident) => { ($handlers.0) $call_args };
+    (@pick_call $choice:ident, $index:expr, $handlers:expr, $call_args:tt, $first:ident $($more:ident)+) => {
+        if $choice == $index {
+            ($handlers.0) $call_args
+        } else {
+            $crate::router!(@pick_call $choice, $index + 1, $handlers.1, $call_args, $($more)+)
+        }
+    };
+
+    // Extract params from route, 0 params case
+    (@call, $context:expr, [$($args:tt)*], $cond:tt, $handler:tt, $params:expr, $($p:ident)*) => {{
+        $crate::router!(@check $context, $cond);
+        let handler = $crate::router!(@handler_ref $handler);
+        $crate::router!(@memo_put $cond, $crate::router!(@wrap $cond, $crate::router!(@invoke $context, handler, $handler, ($($args)*))))
+    }};
+
+    // Extract params from route, 1 params case
+    (@call, $context:expr, $args:tt, $cond:tt, $handler:tt, $params:expr, $($p:ident)* {$id1:ident : $ty1:ty $(; $valid1:tt)?} $($p1:ident)*) => {{
+        $crate::router!(@call_pure $context, $args, $cond, $handler, $params, {$id1 : $ty1 : 0 $(; $valid1)?})
+    }};
+
+    // Extract params from route, 2 params case
+    (@call, $context:expr, $args:tt, $cond:tt, $handler:tt, $params:expr, $($p:ident)* {$id1:ident : $ty1:ty $(; $valid1:tt)?} $($p1:ident)* {$id2:ident : $ty2:ty $(; $valid2:tt)?} $($p2:ident)*) => {{
+        $crate::router!(@call_pure $context, $args, $cond, $handler, $params, {$id1 : $ty1 : 0 $(; $valid1)?}, {$id2 : $ty2 : 1 $(; $valid2)?})
+    }};
+
+    // Extract params from route, 3 params case
+    (@call, $context:expr, $args:tt, $cond:tt, $handler:tt, $params:expr, $($p:ident)* {$id1:ident : $ty1:ty $(; $valid1:tt)?} $($p1:ident)* {$id2:ident : $ty2:ty $(; $valid2:tt)?} $($p2:ident)* {$id3:ident : $ty3:ty $(; $valid3:tt)?} $($p3:ident)*) => {{
+        $crate::router!(@call_pure $context, $args, $cond, $handler, $params, {$id1 : $ty1 : 0 $(; $valid1)?}, {$id2 : $ty2 : 1 $(; $valid2)?}, {$id3 : $ty3 : 2 $(; $valid3)?})
+    }};
+
+    // Extract params from route, 4 params case
+    (@call, $context:expr, $args:tt, $cond:tt, $handler:tt, $params:expr, $($p:ident)* {$id1:ident : $ty1:ty $(; $valid1:tt)?} $($p1:ident)* {$id2:ident : $ty2:ty $(; $valid2:tt)?} $($p2:ident)* {$id3:ident : $ty3:ty $(; $valid3:tt)?} $($p3:ident)* {$id4:ident : $ty4:ty $(; $valid4:tt)?} $($p4:ident)*) => {{
+        $crate::router!(@call_pure $context, $args, $cond, $handler, $params, {$id1 : $ty1 : 0 $(; $valid1)?}, {$id2 : $ty2 : 1 $(; $valid2)?}, {$id3 : $ty3 : 2 $(; $valid3)?}, {$id4 : $ty4 : 3 $(; $valid4)?})
+    }};
+
+    // Extract params from route, 5 params case
+    (@call, $context:expr, $args:tt, $cond:tt, $handler:tt, $params:expr, $($p:ident)* {$id1:ident : $ty1:ty $(; $valid1:tt)?} $($p1:ident)* {$id2:ident : $ty2:ty $(; $valid2:tt)?} $($p2:ident)* {$id3:ident : $ty3:ty $(; $valid3:tt)?} $($p3:ident)* {$id4:ident : $ty4:ty $(; $valid4:tt)?} $($p4:ident)* {$id5:ident : $ty5:ty $(; $valid5:tt)?} $($p5:ident)*) => {{
+        $crate::router!(@call_pure $context, $args, $cond, $handler, $params, {$id1 : $ty1 : 0 $(; $valid1)?}, {$id2 : $ty2 : 1 $(; $valid2)?}, {$id3 : $ty3 : 2 $(; $valid3)?}, {$id4 : $ty4 : 3 $(; $valid4)?}, {$id5 : $ty5 : 4 $(; $valid5)?})
+    }};
+
+    // Extract params from route, 6 params case
+    (@call, $context:expr, $args:tt, $cond:tt, $handler:tt, $params:expr, $($p:ident)* {$id1:ident : $ty1:ty $(; $valid1:tt)?} $($p1:ident)* {$id2:ident : $ty2:ty $(; $valid2:tt)?} $($p2:ident)* {$id3:ident : $ty3:ty $(; $valid3:tt)?} $($p3:ident)* {$id4:ident : $ty4:ty $(; $valid4:tt)?} $($p4:ident)* {$id5:ident : $ty5:ty $(; $valid5:tt)?} $($p5:ident)* {$id6:ident : $ty6:ty $(; $valid6:tt)?} $($p6:ident)*) => {{
+        $crate::router!(@call_pure $context, $args, $cond, $handler, $params, {$id1 : $ty1 : 0 $(; $valid1)?}, {$id2 : $ty2 : 1 $(; $valid2)?}, {$id3 : $ty3 : 2 $(; $valid3)?}, {$id4 : $ty4 : 3 $(; $valid4)?}, {$id5 : $ty5 : 4 $(; $valid5)?}, {$id6 : $ty6 : 5 $(; $valid6)?})
+    }};
+
+    // Extract params from route, 7 params case
+    (@call, $context:expr, $args:tt, $cond:tt, $handler:tt, $params:expr, $($p:ident)* {$id1:ident : $ty1:ty $(; $valid1:tt)?} $($p1:ident)* {$id2:ident : $ty2:ty $(; $valid2:tt)?} $($p2:ident)* {$id3:ident : $ty3:ty $(; $valid3:tt)?} $($p3:ident)* {$id4:ident : $ty4:ty $(; $valid4:tt)?} $($p4:ident)* {$id5:ident : $ty5:ty $(; $valid5:tt)?} $($p5:ident)* {$id6:ident : $ty6:ty $(; $valid6:tt)?} $($p6:ident)* {$id7:ident : $ty7:ty $(; $valid7:tt)?} $($p7:ident)*) => {{
+        $crate::router!(@call_pure $context, $args, $cond, $handler, $params, {$id1 : $ty1 : 0 $(; $valid1)?}, {$id2 : $ty2 : 1 $(; $valid2)?}, {$id3 : $ty3 : 2 $(; $valid3)?}, {$id4 : $ty4 : 3 $(; $valid4)?}, {$id5 : $ty5 : 4 $(; $valid5)?}, {$id6 : $ty6 : 5 $(; $valid6)?}, {$id7 : $ty7 : 6 $(; $valid7)?})
+    }};
+
+    // Drop literal segments, so that only `{name: type}` params are passed to @call.
+    // Length constraints and `no_ext` are checked by the regex and dropped too, catch-alls
+    // are strings. Validators of `{name: type if validator}` and the `registered` marker are
+    // kept for @call_pure.
+    (@call_params $context:expr, $args:tt, $cond:tt, $handler:tt, $params:expr, [$($param:tt)*] {$id:ident : ..} $($rest:tt)*) => {
+        $crate::router!(@call_params $context, $args, $cond, $handler, $params, [$($param)* {$id : String}] $($rest)*)
+    };
+    (@call_params $context:expr, $args:tt, $cond:tt, $handler:tt, $params:expr, [$($param:tt)*] {$id:ident : $ty:ident len $($range:tt)*} $($rest:tt)*) => {
+        $crate::router!(@call_params $context, $args, $cond, $handler, $params, [$($param)* {$id : $ty}] $($rest)*)
+    };
+    (@call_params $context:expr, $args:tt, $cond:tt, $handler:tt, $params:expr, [$($param:tt)*] {$id:ident : $ty:ident if $valid:ident} $($rest:tt)*) => {
+        $crate::router!(@call_params $context, $args, $cond, $handler, $params, [$($param)* {$id : $ty ; $valid}] $($rest)*)
+    };
+    (@call_params $context:expr, $args:tt, $cond:tt, $handler:tt, $params:expr, [$($param:tt)*] {$id:ident : $ty:ident registered} $($rest:tt)*) => {
+        $crate::router!(@call_params $context, $args, $cond, $handler, $params, [$($param)* {$id : $ty ; [registered]}] $($rest)*)
+    };
+    (@call_params $context:expr, $args:tt, $cond:tt, $handler:tt, $params:expr, [$($param:tt)*] {$id:ident : $ty:ident no_ext} $($rest:tt)*) => {
+        $crate::router!(@call_params $context, $args, $cond, $handler, $params, [$($param)* {$id : $ty}] $($rest)*)
+    };
+    (@call_params $context:expr, $args:tt, $cond:tt, $handler:tt, $params:expr, [$($param:tt)*] {$($p:tt)*} $($rest:tt)*) => {
+        $crate::router!(@call_params $context, $args, $cond, $handler, $params, [$($param)* {$($p)*}] $($rest)*)
+    };
+    (@call_params $context:expr, $args:tt, $cond:tt, $handler:tt, $params:expr, [$($param:tt)*] $literal:tt $($rest:tt)*) => {
+        $crate::router!(@call_params $context, $args, $cond, $handler, $params, [$($param)*] $($rest)*)
+    };
+    (@call_params $context:expr, $args:tt, $cond:tt, $handler:tt, $params:expr, [$($param:tt)*]) => {
+        $crate::router!(@call, $context, $args, $cond, $handler, $params, $($param)*)
+    };
+
+    // Regex of one segment. Hyphenated literals like `well-known` come as `[well - known]`,
+    // the file extension of `ext { ... }` routes as `(. "json")` after the last segment.
+    (@push_segment $s:ident, $normalize:ident, (. $extension:literal)) => {
+        $crate::__http_router_push_literal(&mut $s, concat!(".", $extension))
+    };
+    (@push_segment $s:ident, $normalize:ident, *) => {
+        $s.push_str("/.*")
+    };
+    (@push_segment $s:ident, $normalize:ident, {$name:ident : ..}) => {
+        $s.push_str("/(.+)")
+    };
+    (@push_segment $s:ident, $normalize:ident, {$($param:tt)*}) => {{
+        $s.push('/');
+        $crate::__http_router_push_param_regex(&mut $s, stringify!({$($param)*}))
+    }};
+    (@push_segment $s:ident, $normalize:ident, [$($part:tt)*]) => {{
+        $s.push('/');
+        $crate::__http_router_push_segment(&mut $s, concat!($(stringify!($part)),*), &$normalize)
+    }};
+    (@push_segment $s:ident, $normalize:ident, $literal:tt) => {{
+        $s.push('/');
+        $crate::__http_router_push_segment(&mut $s, stringify!($literal), &$normalize)
+    }};
+
+    // Regex of a route's path
+    (@route_regex $normalize:expr, $($path_segment:tt)*) => {{
+        let normalize: $crate::Normalize = $normalize;
+        let mut s = if normalize.ignore_case { "^(?i)" } else { "^" }.to_string();
+        $(
+            $crate::router!(@push_segment s, normalize, $path_segment);
+        )*
+        // handle home case
+        if s.ends_with('^') { s.push('/') }
+        s.push('$');
+        s
+    }};
+
+    // Fails to compile if a literal segment has characters that can't be in a URL path,
+    // e.g. `?format` in `GET /users ?format => ...`
+    (@check_segment (. $extension:literal)) => {
+        $crate::router!(@check_segment_str concat!(".", $extension));
+    };
+    (@check_segment *) => {};
+    (@check_segment {$id:ident : $ty:ident len $($range:tt)*}) => {
+        $crate::router!(@check_len [$($range)*]);
+    };
+    (@check_segment {$($param:tt)*}) => {};
+    (@check_segment [$($part:tt)*]) => {
+        $crate::router!(@check_segment_str concat!($(stringify!($part)),*));
+    };
+    (@check_segment $literal:tt) => {
+        $crate::router!(@check_segment_str stringify!($literal));
+    };
+
+    // Aliases must have the params of the canonical route, which the handler is called with
+    (@check_alias [$(fallthrough)? alias [$($canonical:tt)*] $aliases:tt] [$($path_segment:tt)*]) => {
+        const _: () = assert!(
+            $crate::__http_router_same_params(
+                $crate::router!(@param_meta [] $($canonical)*),
+                $crate::router!(@param_meta [] $($path_segment)*),
+            ),
+            "Aliases of a route in router! must have the same params as the route, with the same names and types"
+        );
+    };
+    (@check_alias $kind:tt $path:tt) => {};
+
+    (@check_segment_str $segment:expr) => {
+        const _: () = assert!(
+            $crate::is_valid_url_segment($segment),
+            concat!("Invalid path segment `", $segment, "` in router!, only URL path characters are allowed")
+        );
+    };
+
+    // Range of a `{name: type len ...}` param, checked at compile time so that no profile
+    // drops a constraint that can't match
+    (@check_len [$len:literal]) => {
+        $crate::router!(@check_len_bounds $len, $len, concat!("len ", stringify!($len)));
+    };
+    (@check_len [$min:literal ..= $max:literal]) => {
+        $crate::router!(@check_len_bounds $min, $max, concat!("len ", stringify!($min), "..=", stringify!($max)));
+    };
+    (@check_len [..= $max:literal]) => {
+        $crate::router!(@check_len_bounds 1, $max, concat!("len ..=", stringify!($max)));
+    };
+    (@check_len [$min:literal .. $max:literal]) => {
+        $crate::router!(@check_len_bounds $min, $max - 1, concat!("len ", stringify!($min), "..", stringify!($max)));
+    };
+    (@check_len [.. $max:literal]) => {
+        $crate::router!(@check_len_bounds 1, $max - 1, concat!("len ..", stringify!($max)));
+    };
+    (@check_len [$min:literal ..]) => {
+        $crate::router!(@check_len_bounds $min, usize::MAX, concat!("len ", stringify!($min), ".."));
+    };
+    (@check_len [$($range:tt)*]) => {
+        compile_error!(concat!("Invalid `len ", stringify!($($range)*), "` in router!, expected `len N`, `len A..=B`, `len A..B`, `len A..`, `len ..=B` or `len ..B`"));
+    };
+    (@check_len_bounds $min:expr, $max:expr, $range:expr) => {
+        const _: () = {
+            let (min, max): (usize, usize) = ($min, $max);
+            assert!(min <= max && max > 0, concat!("`", $range, "` in router! can't match any value"));
+        };
+    };
+
+    // Compiles the regexes of all routes in debug builds, so that an invalid one panics
+    // when the router is created rather than on the first request reaching it
+    (@validate $normalize:tt, $(($method_token:ident [$($path_segment:tt)*] $handler:tt $kind:tt $cond:tt $guard:tt $attrs:tt))*) => {
+        $($($crate::router!(@check_segment $path_segment);)*)*
+        $($crate::router!(@check_alias $kind [$($path_segment)*]);)*
+        $($crate::router!(@check_attrs [router] $attrs);)*
+        #[cfg(debug_assertions)]
+        {
+            let _normalize = $crate::router!(@route_normalize $crate::Normalize::default(), $normalize);
+            $(
+                $crate::__http_router_validate_regex(
+                    &$crate::router!(@route_regex $crate::router!(@route_normalize _normalize, $attrs), $($path_segment)*),
+                    concat!(stringify!($method_token), " ", $crate::router!(@template $($path_segment)*)),
+                );
+            )*
+        }
+    };
+
+    // Test a route without params, comparing segments directly instead of using a regex
+    (@one_route_with_method $context:expr, [$($args:tt)*], $method:expr, $path:expr, $default:expr, $cond:tt, $normalize:expr, $expected_method: expr, $handler:tt, $($path_segment:ident)*) => {{
+        if $method != $expected_method { return None };
+        let path = $path;
+        let matched = if $normalize.ignore_case || !$normalize.lowercase_segments.is_empty() {
+            $crate::__http_router_match_static(path, &[$(stringify!($path_segment)),*], &$normalize)
+        } else {
+            // the whole route is a literal string, e.g. "/api/auth/login"
+            path == $crate::router!(@template $($path_segment)*)
+        };
+        if matched {
+            $crate::router!(@check $context, $cond);
+            let handler = $crate::router!(@handler_ref $handler);
+            Some($crate::router!(@memo_put $cond, $crate::router!(@wrap $cond, $crate::router!(@invoke $context, handler, $handler, ($($args)*)))))
+        } else {
+            None
+        }
+    }};
+
+    // Test a particular route for match and forward to @call if there is match
+    (@one_route_with_method $context:expr, $args:tt, $method:expr, $path:expr, $default:expr, $cond:tt, $normalize:expr, $expected_method: expr, $handler:tt, $($path_segment:tt)*) => {{
+        if $method != $expected_method { return None };
+        let path = $path;
+        // compiled once per route and case mode, so that a request doesn't allocate until a route matches
+        static REGEXES: [::std::sync::OnceLock<$crate::__HttpRouterRegex>; 2] = [::std::sync::OnceLock::new(), ::std::sync::OnceLock::new()];
+        let re = REGEXES[$normalize.ignore_case as usize]
+            .get_or_init(|| $crate::__http_router_create_regex(&$crate::router!(@route_regex $normalize, $($path_segment)*)));
+        // capture locations are reused by each thread, since allocating them for every request
+        // would allocate even when the path doesn't match
+        ::std::thread_local! {
+            static LOCATIONS: ::std::cell::Cell<Option<$crate::__HttpRouterCaptureLocations>> = ::std::cell::Cell::new(None);
+        }
+        let mut locations = LOCATIONS.with(|locations| locations.take()).unwrap_or_else(|| re.capture_locations());
+        let matched = re.captures_read(&mut locations, path).is_some();
+        let _matches: Vec<&str> = if matched {
+            (1..locations.len()).filter_map(|i| locations.get(i)).map(|(start, end)| &path[start..end]).collect()
+        } else {
+            Vec::new()
+        };
+        LOCATIONS.with(|cell| cell.set(Some(locations)));
+        if matched {
+            Some($crate::router!(@call_params $context, $args, $cond, $handler, _matches, [] $($path_segment)*))
+        } else {
+            None
+        }
+    }};
+
+    // Mounted router, called with the rest of the path and the context mapped by `map_ctx`
+    (@one_route $context:expr, [$first:expr $(, $arg:expr)*], $method:expr, $path:expr, $default:expr, ([$map_ctx:expr] $guard:tt $guarded:ident $timer:tt $memo:tt $no_body:tt $wrap:tt), $normalize:expr, scope, $router:ident, $($path_segment:ident)*) => {{
+        let rest = $crate::__http_router_strip_scope($path, &[$(stringify!($path_segment)),*], &$normalize)?;
+        let inner_context = $map_ctx(&$context)?;
+        $crate::router!(@timing_handler $timer);
+        Some($router(inner_context, $($arg,)* $method, rest))
+    }};
+
+    // `ANY` matches every method
+    (@one_route $context:expr, $args:tt, $method:expr, $path:expr, $default:expr, $cond:tt, $normalize:expr, ANY, $handler:tt, $($path_segment:tt)*) => {
+        $crate::router!(@one_route_with_method $context, $args, $method, $path, $default, $cond, $normalize, $method, $handler, $($path_segment)*)
+    };
+
+    // Transform GET token to Method::GET
+    (@one_route $context:expr, $args:tt, $method:expr, $path:expr, $default:expr, $cond:tt, $normalize:expr, GET, $handler:tt, $($path_segment:tt)*) => {
+        $crate::router!(@one_route_with_method $context, $args, $method, $path, $default, $cond, $normalize, $crate::Method::GET, $handler, $($path_segment)*)
+    };
+
+    // Transform POST token to Method::POST
+    (@one_route $context:expr, $args:tt, $method:expr, $path:expr, $default:expr, $cond:tt, $normalize:expr, POST, $handler:tt, $($path_segment:tt)*) => {
+        $crate::router!(@one_route_with_method $context, $args, $method, $path, $default, $cond, $normalize, $crate::Method::POST, $handler, $($path_segment)*)
+    };
+    // Transform PUT token to Method::PUT
+    (@one_route $context:expr, $args:tt, $method:expr, $path:expr, $default:expr, $cond:tt, $normalize:expr, PUT, $handler:tt, $($path_segment:tt)*) => {
+        $crate::router!(@one_route_with_method $context, $args, $method, $path, $default, $cond, $normalize, $crate::Method::PUT, $handler, $($path_segment)*)
+    };
+    // Transform PATCH token to Method::PATCH
+    (@one_route $context:expr, $args:tt, $method:expr, $path:expr, $default:expr, $cond:tt, $normalize:expr, PATCH, $handler:tt, $($path_segment:tt)*) => {
+        $crate::router!(@one_route_with_method $context, $args, $method, $path, $default, $cond, $normalize, $crate::Method::PATCH, $handler, $($path_segment)*)
+    };
+    // Transform DELETE token to Method::DELETE
+    (@one_route $context:expr, $args:tt, $method:expr, $path:expr, $default:expr, $cond:tt, $normalize:expr, DELETE, $handler:tt, $($path_segment:tt)*) => {
+        $crate::router!(@one_route_with_method $context, $args, $method, $path, $default, $cond, $normalize, $crate::Method::DELETE, $handler, $($path_segment)*)
+    };
+    // Transform OPTIONS token to Method::OPTIONS
+    (@one_route $context:expr, $args:tt, $method:expr, $path:expr, $default:expr, $cond:tt, $normalize:expr, OPTIONS, $handler:tt, $($path_segment:tt)*) => {
+        $crate::router!(@one_route_with_method $context, $args, $method, $path, $default, $cond, $normalize, $crate::Method::OPTIONS, $handler, $($path_segment)*)
+    };
+
+    // Transform HEAD token to Method::HEAD
+    (@one_route $context:expr, $args:tt, $method:expr, $path:expr, $default:expr, $cond:tt, $normalize:expr, HEAD, $handler:tt, $($path_segment:tt)*) => {
+        $crate::router!(@one_route_with_method $context, $args, $method, $path, $default, $cond, $normalize, $crate::Method::HEAD, $handler, $($path_segment)*)
+    };
+
+    // Transform TRACE token to Method::TRACE
+    (@one_route $context:expr, $args:tt, $method:expr, $path:expr, $default:expr, $cond:tt, $normalize:expr, TRACE, $handler:tt, $($path_segment:tt)*) => {
+        $crate::router!(@one_route_with_method $context, $args, $method, $path, $default, $cond, $normalize, $crate::Method::TRACE, $handler, $($path_segment)*)
+    };
+
+    // Transform CONNECT token to Method::CONNECT
+    (@one_route $context:expr, $args:tt, $method:expr, $path:expr, $default:expr, $cond:tt, $normalize:expr, CONNECT, $handler:tt, $($path_segment:tt)*) => {
+        $crate::router!(@one_route_with_method $context, $args, $method, $path, $default, $cond, $normalize, $crate::Method::CONNECT, $handler, $($path_segment)*)
+    };
+
+    // Transform LOCK token to Method::LOCK
+    (@one_route $context:expr, $args:tt, $method:expr, $path:expr, $default:expr, $cond:tt, $normalize:expr, LOCK, $handler:tt, $($path_segment:tt)*) => {
+        $crate::router!(@one_route_with_method $context, $args, $method, $path, $default, $cond, $normalize, $crate::Method::LOCK, $handler, $($path_segment)*)
+    };
+
+    // Transform UNLOCK token to Method::UNLOCK
+    (@one_route $context:expr, $args:tt, $method:expr, $path:expr, $default:expr, $cond:tt, $normalize:expr, UNLOCK, $handler:tt, $($path_segment:tt)*) => {
+        $crate::router!(@one_route_with_method $context, $args, $method, $path, $default, $cond, $normalize, $crate::Method::UNLOCK, $handler, $($path_segment)*)
+    };
+
+    // `[normalize(...)]` overrides of a route applied to the router's normalization, `None` without them
+    (@normalize_override $normalize:expr, []) => { None };
+    (@normalize_override $normalize:expr, [[normalize($($options:tt)*)] $($attr:tt)*]) => {
+        Some($crate::router!(@route_normalize $normalize, [[normalize($($options)*)] $($attr)*]))
+    };
+    (@normalize_override $normalize:expr, [$other:tt $($attr:tt)*]) => {
+        $crate::router!(@normalize_override $normalize, [$($attr)*])
+    };
+
+    // Normalization of the router with `[normalize(...)]` overrides of the route applied
+    (@route_normalize $normalize:expr, []) => { $normalize };
+    (@route_normalize $normalize:expr, [[normalize($($field:ident = $value:expr),* $(,)*)] $($attr:tt)*]) => {{
+        #[allow(unused_mut)]
+        let mut normalize: $crate::Normalize = $normalize;
+        $(normalize.$field = $value;)*
+        $crate::router!(@route_normalize normalize, [$($attr)*])
+    }};
+    (@route_normalize $normalize:expr, [$other:tt $($attr:tt)*]) => {
+        $crate::router!(@route_normalize $normalize, [$($attr)*])
+    };
+
+    // Value of a route that matched. Fallthrough handlers return `Option`, `None` meaning they declined
+    (@resolve [], $value:expr) => { $value };
+    (@resolve [fallthrough], $value:expr) => { $value.and_then(|resolved| resolved) };
+    (@resolve [fallthrough $($alias:tt)+], $value:expr) => { $value.and_then(|resolved| resolved) };
+    (@resolve [$($alias:tt)+], $value:expr) => { $value };
+
+    // Response of a failed guard as a handler value
+    (@guard_response [async] $response:expr) => { ::std::future::ready($response) };
+    (@guard_response [async_move] $response:expr) => { ::std::future::ready($response) };
+    (@guard_response [$mode:ident] $response:expr) => { $response };
+
+    // Boxes handler futures, so that all routes of an `async;` router return the same type
+    (@box_future [async] $value:expr) => {{
+        use ::std::future::Future;
+        Box::pin($value) as ::std::pin::Pin<Box<dyn Future<Output = _> + '_>>
+    }};
+    (@box_future [async_move] $value:expr) => {{
+        use ::std::future::Future;
+        Box::pin($value) as ::std::pin::Pin<Box<dyn Future<Output = _> + Send>>
+    }};
+    (@box_future [boxed] $value:expr) => {{
+        use ::std::future::Future;
+        #[allow(unused_imports)]
+        use $crate::{__HttpRouterAwaitFuture, __HttpRouterAwaitValue};
+        // handlers may return a future or a plain value, which an `async` block couldn't
+        // `.await`, so autoref picks the future itself or a ready one with the value
+        let output = $crate::__HttpRouterHandlerOutput::new($value);
+        Box::pin((&output).route_future()) as ::std::pin::Pin<Box<dyn Future<Output = _> + '_>>
+    }};
+    (@box_future [$mode:ident] $value:expr) => { $value };
+
+    // Try routes of a `compact;` router: the crate matches them against a table, and only the
+    // handler calls of the routes are generated, picked by the index of the route that matched
+    (@find [$mode:ident] $timer:tt $wrap:tt $reserved:tt $request:tt [$specificity_order:expr, compact] $context:expr, $args:tt, $method:expr, $path:expr, $normalize:expr, $default:ident, $(($method_token:ident [$($path_segment:tt)*] $handler:tt $kind:tt $cond:tt [$($guard:ident)?] $attrs:tt))*) => {{
+        static REGEXES: $crate::__HttpRouterRegexes = $crate::__HttpRouterRegexes::new();
+        static ROUTES: &[$crate::__HttpRouterTableRoute] = $crate::router!(@table_routes $(($method_token [$($path_segment)*] $attrs))*);
+        $($crate::router!(@no_request_fallthrough $request $kind);)*
+        let mut result = None;
+        for found in $crate::__http_router_table_matches(ROUTES, &REGEXES, $method, $path, $normalize, $specificity_order) {
+            // unused if no route has params
+            #[allow(unused_variables)]
+            let params = found.params();
+            let mut index = 0;
+            $(
+                if index == found.index && !($reserved && $crate::router!(@starts_with_param $($path_segment)*)) {
+                    $(let guarded = ::std::cell::Cell::new(None); let _ = &$guard;)?
+                    $crate::router!(@memo_request ($method_token $attrs memo $request), $method, $path);
+                    $crate::router!(@no_body_request ($attrs rejected $request));
+                    // we use closure here so that we could make early return from macros inside of it
+                    let closure = || {
+                        $crate::router!(@table_call $context, $args, $method, found, params, ($cond [$($guard)?] guarded $timer ($method_token $attrs memo $request) ($attrs rejected $request) ($wrap [$($path_segment)*])), $method_token, $handler, $($path_segment)*)
+                    };
+                    result = $crate::router!(@resolve $kind, closure()).map(|value| $crate::router!(@box_future [$mode] value));
+                    // a failed guard answers the request, later routes and the fallback are skipped
+                    $(let _ = &$guard; if let Some(response) = guarded.take() {
+                        result = Some($crate::router!(@box_future [$mode] $crate::router!(@guard_response [$mode] response)));
+                    })?
+                    $crate::router!(@no_body_response [$mode] ($attrs rejected $request), result, $args);
+                    $crate::router!(@timing_route $timer, result, $($path_segment)*);
+                }
+                index += 1;
+            )*
+            let _ = index;
+            if result.is_some() {
+                break;
+            }
+        }
+        result
+    }};
+
+    // Routes in the table of a `compact;` router, or of `match_pattern` of a `table;` router
+    (@table_routes $(($method_token:ident [$($path_segment:tt)*] $attrs:tt))*) => {
+        &[$(
+            $crate::__HttpRouterTableRoute {
+                method: $crate::router!(@static_method $method_token),
+                any: $crate::router!(@is_any $method_token),
+                scope: $crate::router!(@is_scope $method_token),
+                catch_all: $crate::router!(@is_catch_all $($path_segment)*),
+                loose: $crate::router!(@is_loose $($path_segment)*),
+                parts: concat!($($crate::router!(@table_part $path_segment)),*),
+                normalize: $crate::router!(@table_normalize $attrs),
+            },
+        )*]
+    };
+
+    // Handler call of a route of a `compact;` router whose path matched
+    (@table_call $context:expr, [$first:expr $(, $arg:expr)*], $method:expr, $found:ident, $params:ident, ([$map_ctx:expr] $guard:tt $guarded:ident $timer:tt $memo:tt $no_body:tt $wrap:tt), scope, $router:ident, $($path_segment:ident)*) => {{
+        let inner_context = $map_ctx(&$context)?;
+        $crate::router!(@timing_handler $timer);
+        Some($router(inner_context, $($arg,)* $method, $found.rest()))
+    }};
+    (@table_call $context:expr, $args:tt, $method:expr, $found:ident, $params:ident, $cond:tt, $method_token:ident, $handler:tt, $($path_segment:tt)*) => {
+        Some($crate::router!(@call_params $context, $args, $cond, $handler, $params, [] $($path_segment)*))
+    };
+
+    // Per-route normalization of a `compact;` router, applied to the router's
+    (@table_normalize []) => { None };
+    (@table_normalize [[normalize($($options:tt)*)] $($attr:tt)*]) => {
+        Some(|normalize: $crate::Normalize| $crate::router!(@route_normalize normalize, [[normalize($($options)*)] $($attr)*]))
+    };
+    (@table_normalize [$other:tt $($attr:tt)*]) => {
+        $crate::router!(@table_normalize [$($attr)*])
+    };
+
+    // Whether the route is a mounted router
+    (@is_scope scope) => { true };
+    (@is_scope $method_token:ident) => { false };
+
+    // Segment in the table of a `compact;` router, a kind byte and the segment up to a `\0`
+    (@table_part (. $extension:literal)) => { concat!("e.", $extension, "\0") };
+    (@table_part *) => { "a\0" };
+    (@table_part {$name:ident : ..}) => { "c\0" };
+    (@table_part {$($param:tt)*}) => { concat!("p", stringify!({$($param)*}), "\0") };
+    (@table_part [$($part:tt)*]) => { concat!("l", $(stringify!($part),)* "\0") };
+    (@table_part $literal:ident) => { concat!("i", stringify!($literal), "\0") };
+
+    // Try routes one by one, `None` if none of them matched
+    (@find [$mode:ident] $timer:tt $wrap:tt $reserved:tt $request:tt [$specificity_order:expr] $context:expr, $args:tt, $method:expr, $path:expr, $normalize:expr, $default:ident, $(($method_token:ident [$($path_segment:tt)*] $handler:tt $kind:tt $cond:tt [$($guard:ident)?] $attrs:tt))*) => {{
+        $($crate::router!(@no_request_fallthrough $request $kind);)*
+        let mut result = None;
+        // catch-all routes are tried after all the others, and with `specificity_order;`
+        // `ANY` routes after the ones with a method, each with `String` params last
+        let passes = [
+            (false, false, false), (false, false, true), (false, true, false), (false, true, true),
+            (true, false, false), (true, false, true), (true, true, false), (true, true, true),
+        ];
+        // paths are normalized once per distinct normalization rather than by each route tried
+        let paths = $crate::__HttpRouterNormalizedPaths::new($path, $normalize, [$($crate::router!(@normalize_override $normalize, $attrs)),*]);
+        for &(catch_all, any, loose) in passes.iter() {
+            let mut index = 0;
+            $(
+                if result.is_none()
+                    && $crate::router!(@is_catch_all $($path_segment)*) == catch_all
+                    && (if $specificity_order {
+                        $crate::router!(@is_any $method_token) == any && $crate::router!(@is_loose $($path_segment)*) == loose
+                    } else {
+                        !any && !loose
+                    })
+                    && !($reserved && $crate::router!(@starts_with_param $($path_segment)*))
+                {
+                    $(let guarded = ::std::cell::Cell::new(None); let _ = &$guard;)?
+                    $crate::router!(@memo_request ($method_token $attrs memo $request), $method, $path);
+                    $crate::router!(@no_body_request ($attrs rejected $request));
+                    // we use closure here so that we could make early return from macros inside of it
+                    let (normalize, path) = paths.get(index);
+                    let closure = || {
+                        $crate::router!(@one_route $context, $args, $method, path, $default, ($cond [$($guard)?] guarded $timer ($method_token $attrs memo $request) ($attrs rejected $request) ($wrap [$($path_segment)*])), normalize, $method_token, $handler, $($path_segment)*)
+                    };
+                    result = $crate::router!(@resolve $kind, closure()).map(|value| $crate::router!(@box_future [$mode] value));
+                    // a failed guard answers the request, later routes and the fallback are skipped
+                    $(let _ = &$guard; if let Some(response) = guarded.take() {
+                        result = Some($crate::router!(@box_future [$mode] $crate::router!(@guard_response [$mode] response)));
+                    })?
+                    $crate::router!(@no_body_response [$mode] ($attrs rejected $request), result, $args);
+                    $crate::router!(@timing_route $timer, result, $($path_segment)*);
+                }
+                index += 1;
+            )*
+            let _ = index;
+        }
+        result
+    }};
+
+    // Whether the route matches all methods
+    (@is_any ANY) => { true };
+    (@is_any $method_token:ident) => { false };
+
+    // Whether the route has a `{name: ..}` segment or is a `/ *` route
+    (@is_catch_all {$name:ident : ..} $($rest:tt)*) => { true };
+    (@is_catch_all * $($rest:tt)*) => { true };
+    (@is_catch_all $segment:tt $($rest:tt)*) => { $crate::router!(@is_catch_all $($rest)*) };
+    (@is_catch_all) => { false };
+
+    // Whether the route has a `String` param, which also matches numbers
+    (@is_loose {$name:ident : String $($constraint:tt)*} $($rest:tt)*) => { true };
+    (@is_loose $segment:tt $($rest:tt)*) => { $crate::router!(@is_loose $($rest)*) };
+    (@is_loose) => { false };
+
+    // The per-request value of a `request;` router, moved into the one handler that runs
+    (@take $request:ident) => {
+        $request.take().expect("request was already moved into a handler")
+    };
+    // A fallthrough handler would take the value and couldn't hand it to the next route
+    (@no_request_fallthrough [$request:ident] [fallthrough $($alias:tt)*]) => {
+        compile_error!("fallthrough routes (`handler?`) aren't supported with a `request;` header")
+    };
+    (@no_request_fallthrough $request:tt $kind:tt) => {};
+
+    // Router in a `RouteTable` along with its routes
+    (@table [count] $normalize:tt $order:tt $router:tt, $(($method_token:ident $($route:tt)*))*) => {
+        <[&str]>::len(&[$(stringify!($method_token)),*])
+    };
+    (@table [table $($predicates:ident)?] $normalize:tt $order:tt ($router:expr), $(($method_token:ident [$($path_segment:tt)*] $handler:tt $kind:tt $cond:tt $guard:tt $attrs:tt))*) => {{
+        static ROUTES: &[$crate::StaticRouteInfo] = &[$(
+            $crate::StaticRouteInfo {
+                method: $crate::router!(@static_method $method_token),
+                pattern: $crate::router!(@template $($path_segment)*),
+                params: $crate::router!(@param_names [] $($path_segment)*),
+            },
+        )*];
+        static ROUTE_META: &[$crate::RouteMeta] = &[$(
+            $crate::RouteMeta {
+                method: $crate::router!(@static_method $method_token),
+                pattern: $crate::router!(@template $($path_segment)*),
+                params: $crate::router!(@param_meta [] $($path_segment)*),
+                meta: $crate::router!(@route_meta $attrs),
+            },
+        )*];
+        static ROUTE_TIMEOUTS_MS: &[Option<u64>] = &[$($crate::router!(@timeout_ms $attrs)),*];
+        static ROUTE_ALIASES: &[$crate::RouteAliases] = &[$($crate::router!(@route_aliases $kind [$($path_segment)*])),*];
+        // Patterns only, see `RouteTable::match_pattern`
+        fn match_pattern(method: $crate::Method, path: &str) -> Option<(usize, $crate::RouteCaptures<'_>)> {
+            static REGEXES: $crate::__HttpRouterRegexes = $crate::__HttpRouterRegexes::new();
+            static TABLE: &[$crate::__HttpRouterTableRoute] = $crate::router!(@table_routes $(($method_token [$($path_segment)*] $attrs))*);
+            let normalize = $crate::router!(@route_normalize $crate::Normalize::default(), $normalize);
+            $crate::__http_router_table_matches(TABLE, &REGEXES, method, path, normalize, $crate::router!(@specificity_order $order))
+                .next()
+                .map($crate::RouteCaptures::from_match)
+        }
+        $crate::RouteTable::new(
+            $router,
+            ROUTES,
+            ROUTE_META,
+            ROUTE_TIMEOUTS_MS,
+            ROUTE_ALIASES,
+            match_pattern,
+            $crate::router!(@is_route [$($predicates)?] $(($handler $kind))*),
+        )
+    }};
+
+    // `is_route` of a `RouteTable`, with `table(predicates);` a value with a method per handler
+    (@is_route [] $($route:tt)*) => { () };
+    (@is_route [predicates] $(($handler:tt $kind:tt))*) => {{
+        /// Whether the router would call a handler for a request, e.g.
+        /// `is_route.get_user(Method::GET, "/users/12")`
+        struct IsRoute;
+
+        // handler of each route, empty for `pick(...)` routes
+        static HANDLERS: &[&str] = &[$($crate::router!(@handler_name $handler)),*];
+
+        #[allow(dead_code)]
+        impl IsRoute {
+            $($crate::router!(@predicate $handler $kind);)*
+        }
+
+        IsRoute
+    }};
+
+    (@handler_name $handler:ident) => { stringify!($handler) };
+    (@handler_name $handler:tt) => { "" };
+
+    // `fn handler(&self, method, path) -> bool` of `is_route`, aliases share the one of their route
+    (@predicate $handler:ident [$(fallthrough)? alias $($aliases:tt)*]) => {};
+    (@predicate $handler:ident $kind:tt) => {
+        pub fn $handler(&self, method: $crate::Method, path: &str) -> bool {
+            match_pattern(method, path)
+                .map_or(false, |(index, _)| HANDLERS[index] == stringify!($handler))
+        }
+    };
+    (@predicate $handler:tt $kind:tt) => {};
+
+    // `RouteAliases` of a route, given the `[alias [canonical] [aliases]]` or `[aliased [aliases]]`
+    // of its kind
+    (@route_aliases [$(fallthrough)? alias [$($canonical:tt)*] [$([$($alias:tt)*])+]] $path:tt) => {
+        $crate::RouteAliases {
+            canonical: $crate::router!(@template $($canonical)*),
+            aliases: &[$($crate::router!(@template $($alias)*)),+],
+            alias: true,
+        }
+    };
+    (@route_aliases [$(fallthrough)? aliased [$([$($alias:tt)*])+]] [$($path_segment:tt)*]) => {
+        $crate::RouteAliases {
+            canonical: $crate::router!(@template $($path_segment)*),
+            aliases: &[$($crate::router!(@template $($alias)*)),+],
+            alias: false,
+        }
+    };
+    (@route_aliases $kind:tt [$($path_segment:tt)*]) => {
+        $crate::RouteAliases {
+            canonical: $crate::router!(@template $($path_segment)*),
+            aliases: &[],
+            alias: false,
+        }
+    };
+
+    (@timeout_ms [[timeout_ms = $timeout_ms:expr] $($attr:tt)*]) => { Some($timeout_ms) };
+    (@timeout_ms [$other:tt $($attr:tt)*]) => { $crate::router!(@timeout_ms [$($attr)*]) };
+    (@timeout_ms []) => { None };
+
+    // Attributes of a route are known ones
+    (@check_attrs $mode:tt []) => {};
+    (@check_attrs $mode:tt [[memoize($($args:tt)*)] $($attr:tt)*]) => { $crate::router!(@check_attrs $mode [$($attr)*]); };
+    (@check_attrs $mode:tt [[no_body($($args:tt)*)] $($attr:tt)*]) => { $crate::router!(@check_attrs $mode [$($attr)*]); };
+    (@check_attrs $mode:tt [[normalize($($args:tt)*)] $($attr:tt)*]) => { $crate::router!(@check_attrs $mode [$($attr)*]); };
+    (@check_attrs $mode:tt [[meta($($args:tt)*)] $($attr:tt)*]) => { $crate::router!(@check_attrs $mode [$($attr)*]); };
+    (@check_attrs $mode:tt [[timeout_ms = $timeout_ms:expr] $($attr:tt)*]) => { $crate::router!(@check_attrs $mode [$($attr)*]); };
+    (@check_attrs $mode:tt [$other:tt $($attr:tt)*]) => {
+        compile_error!("Unknown route attribute, expected `[memoize(...)]`, `[no_body(...)]`, `[normalize(...)]`, `[meta(...)]` or `[timeout_ms = ...]`");
+    };
+
+    // `[meta(key = "value", ...)]` of a route as `(key, value)` pairs
+    (@route_meta [[meta($($key:ident = $value:literal),* $(,)*)] $($attr:tt)*]) => { &[$((stringify!($key), $value)),*] };
+    (@route_meta [$other:tt $($attr:tt)*]) => { $crate::router!(@route_meta [$($attr)*]) };
+    (@route_meta []) => { &[] };
+
+    // Whether a `specificity_order;` header was given
+    (@specificity_order [$specificity_order:expr $(, compact)?]) => { $specificity_order };
+
+    // `Option<Method>` of a route in the table, `None` for all methods
+    (@static_method ANY) => { None };
+    (@static_method scope) => { None };
+    (@static_method $method_token:ident) => { Some($crate::Method::$method_token) };
+
+    // Route as written, e.g. `/users/{id: u32}`
+    (@template) => { "/" };
+    (@template $($path_segment:tt)+) => { concat!($($crate::router!(@template_segment $path_segment)),+) };
+    (@template_segment (. $extension:literal)) => { concat!(".", $extension) };
+    (@template_segment [$($part:tt)*]) => { concat!("/", $(stringify!($part)),*) };
+    (@template_segment {$id:ident : $($param:tt)*}) => {
+        concat!("/{", stringify!($id), ": ", $crate::router!(@tokens [] [""] $($param)*), "}")
+    };
+    (@template_segment $path_segment:tt) => { concat!("/", stringify!($path_segment)) };
+
+    // Tokens spaced like rustfmt would, e.g. `String len 3..=40` or `[f64; 2]`, since the spacing
+    // of `stringify!` changes between compiler versions. `$space` goes before the next token.
+    (@tokens [$($out:expr),*] $space:tt) => { concat!("" $(, $out)*) };
+    (@tokens [$($out:expr),*] $space:tt :: $($rest:tt)*) => { $crate::router!(@tokens [$($out,)* "::"] [""] $($rest)*) };
+    (@tokens [$($out:expr),*] [$($space:literal)?] $start:literal ..= $($rest:tt)*) => {
+        $crate::router!(@tokens [$($out,)* $($space,)? stringify!($start), "..="] [""] $($rest)*)
+    };
+    (@tokens [$($out:expr),*] [$($space:literal)?] $start:literal .. $($rest:tt)*) => {
+        $crate::router!(@tokens [$($out,)* $($space,)? stringify!($start), ".."] [""] $($rest)*)
+    };
+    (@tokens [$($out:expr),*] [$($space:literal)?] ..= $($rest:tt)*) => { $crate::router!(@tokens [$($out,)* $($space,)? "..="] [""] $($rest)*) };
+    (@tokens [$($out:expr),*] [$($space:literal)?] .. $($rest:tt)*) => { $crate::router!(@tokens [$($out,)* $($space,)? ".."] [""] $($rest)*) };
+    (@tokens [$($out:expr),*] $space:tt < $($rest:tt)*) => { $crate::router!(@tokens [$($out,)* "<"] [""] $($rest)*) };
+    (@tokens [$($out:expr),*] $space:tt > $($rest:tt)*) => { $crate::router!(@tokens [$($out,)* ">"] [" "] $($rest)*) };
+    (@tokens [$($out:expr),*] $space:tt >> $($rest:tt)*) => { $crate::router!(@tokens [$($out,)* ">>"] [" "] $($rest)*) };
+    (@tokens [$($out:expr),*] $space:tt , $($rest:tt)*) => { $crate::router!(@tokens [$($out,)* ","] [" "] $($rest)*) };
+    (@tokens [$($out:expr),*] $space:tt ; $($rest:tt)*) => { $crate::router!(@tokens [$($out,)* ";"] [" "] $($rest)*) };
+    (@tokens [$($out:expr),*] [$($space:literal)?] [$($inner:tt)*] $($rest:tt)*) => {
+        $crate::router!(@tokens [$($out,)* $($space,)? "[", $crate::router!(@tokens [] [""] $($inner)*), "]"] [" "] $($rest)*)
+    };
+    (@tokens [$($out:expr),*] [$($space:literal)?] $token:tt $($rest:tt)*) => {
+        $crate::router!(@tokens [$($out,)* $($space,)? stringify!($token)] [" "] $($rest)*)
+    };
+
+    // `(name, type)` of the params of a route, e.g. `("id", "u32")`, collected segment by segment
+    (@param_meta [$($meta:tt)*]) => { &[$($meta),*] };
+    (@param_meta [$($meta:tt)*] {$id:ident : ..} $($rest:tt)*) => {
+        $crate::router!(@param_meta [$($meta)* (stringify!($id), "String")] $($rest)*)
+    };
+    (@param_meta [$($meta:tt)*] {$id:ident : $ty:ident len $($range:tt)*} $($rest:tt)*) => {
+        $crate::router!(@param_meta [$($meta)* (stringify!($id), stringify!($ty))] $($rest)*)
+    };
+    (@param_meta [$($meta:tt)*] {$id:ident : $ty:ident if $valid:ident} $($rest:tt)*) => {
+        $crate::router!(@param_meta [$($meta)* (stringify!($id), stringify!($ty))] $($rest)*)
+    };
+    (@param_meta [$($meta:tt)*] {$id:ident : $ty:ident registered} $($rest:tt)*) => {
+        $crate::router!(@param_meta [$($meta)* (stringify!($id), stringify!($ty))] $($rest)*)
+    };
+    (@param_meta [$($meta:tt)*] {$id:ident : $ty:ident no_ext} $($rest:tt)*) => {
+        $crate::router!(@param_meta [$($meta)* (stringify!($id), stringify!($ty))] $($rest)*)
+    };
+    (@param_meta [$($meta:tt)*] {$id:ident : $($ty:tt)*} $($rest:tt)*) => {
+        $crate::router!(@param_meta [$($meta)* (stringify!($id), $crate::router!(@tokens [] [""] $($ty)*))] $($rest)*)
+    };
+    (@param_meta $meta:tt $path_segment:tt $($rest:tt)*) => {
+        $crate::router!(@param_meta $meta $($rest)*)
+    };
+
+    // Names of the params of a route, collected segment by segment
+    (@param_names [$($name:ident)*]) => { &[$(stringify!($name)),*] };
+    (@param_names [$($name:ident)*] {$id:ident : $($ty:tt)*} $($rest:tt)*) => {
+        $crate::router!(@param_names [$($name)* $id] $($rest)*)
+    };
+    (@param_names $names:tt $path_segment:tt $($rest:tt)*) => {
+        $crate::router!(@param_names $names $($rest)*)
+    };
+
+    // Closure returning the handler's value
+    (@closure [plain] $normalize:tt [[$($ext:ident)?] [$($request:ident)?] [$($timing:expr)?] $reserved:tt [$($recover:expr)?] [$($wrap:expr)?] $table:tt] $order:tt $default:ident, $($routes:tt)*) => {{
+        $crate::router!(@validate $normalize, $($routes)*);
+        #[allow(clippy::redundant_closure_call)]
+        let router = move |context, $($ext: &$crate::Extensions,)? $($request,)? method: $crate::Method, path: &str| {
+            $(let $request = ::std::cell::Cell::new(Some($request));)?
+            // lets route conditions infer the context type from the fallback
+            let _ = || $default(&context $(, $ext)? $(, $crate::router!(@take $request))?);
+            $crate::router!(@catch_panics [plain] [$($recover)?] &context, {
+                $(let timer = $crate::__HttpRouterTimer::start(); let _ = &$timing;)?
+                let normalize = $crate::router!(@route_normalize $crate::Normalize::default(), $normalize);
+                let reserved = $crate::router!(@reserved $reserved, normalize, path);
+                let value = $crate::router!(@find [plain] [$(timer $timing)?] [$($wrap)?] reserved [$($request)?] $order context, [&context $(, $ext)? $(, $crate::router!(@take $request))?], method, path, normalize, $default, $($routes)*).unwrap_or_else(|| {
+                    $(timer.handler_started(); let _ = &$timing;)?
+                    $crate::router!(@fallback [plain] $reserved, reserved, $default(&context $(, $ext)? $(, $crate::router!(@take $request))?))
+                });
+                $(timer.finish(&$timing);)?
+                value
+            })
+        };
+        router
+    }};
+
+    // Closure returning `(matched, value)`, `matched` is `false` if the value came from the fallback
+    (@closure [matched] $normalize:tt [[$($ext:ident)?] [$($request:ident)?] [$($timing:expr)?] $reserved:tt [$($recover:expr)?] [$($wrap:expr)?] $table:tt] $order:tt $default:ident, $($routes:tt)*) => {{
+        $crate::router!(@validate $normalize, $($routes)*);
+        #[allow(clippy::redundant_closure_call)]
+        let router = move |context, $($ext: &$crate::Extensions,)? $($request,)? method: $crate::Method, path: &str| {
+            $(let $request = ::std::cell::Cell::new(Some($request));)?
+            let _ = || $default(&context $(, $ext)? $(, $crate::router!(@take $request))?);
+            $crate::router!(@catch_panics [matched] [$($recover)?] &context, {
+                $(let timer = $crate::__HttpRouterTimer::start(); let _ = &$timing;)?
+                let normalize = $crate::router!(@route_normalize $crate::Normalize::default(), $normalize);
+                let reserved = $crate::router!(@reserved $reserved, normalize, path);
+                let value = match $crate::router!(@find [matched] [$(timer $timing)?] [$($wrap)?] reserved [$($request)?] $order context, [&context $(, $ext)? $(, $crate::router!(@take $request))?], method, path, normalize, $default, $($routes)*) {
+                    Some(result) => (true, result),
+                    None => {
+                        $(timer.handler_started(); let _ = &$timing;)?
+                        (false, $crate::router!(@fallback [plain] $reserved, reserved, $default(&context $(, $ext)? $(, $crate::router!(@take $request))?)))
+                    }
+                };
+                $(timer.finish(&$timing);)?
+                value
+            })
+        };
+        router
+    }};
+
+    // Closure returning `Pin<Box<dyn Future>>` of the handler's future
+    (@closure [async] $normalize:tt [[$($ext:ident)?] [$($request:ident)?] [$($timing:expr)?] $reserved:tt $recover:tt [$($wrap:expr)?] $table:tt] $order:tt $default:ident, $($routes:tt)*) => {{
+        $crate::router!(@sync_only $recover);
+        $crate::router!(@validate $normalize, $($routes)*);
+        #[allow(clippy::redundant_closure_call)]
+        let router = move |context, $($ext: &$crate::Extensions,)? $($request,)? method: $crate::Method, path: &str| {
+            $(let $request = ::std::cell::Cell::new(Some($request));)?
+            // the context is a reference here, handlers get it reborrowed for its whole lifetime
+            let _ = || $default(context $(, $ext)? $(, $crate::router!(@take $request))?);
+            $(let timer = $crate::__HttpRouterTimer::start(); let _ = &$timing;)?
+            let normalize = $crate::router!(@route_normalize $crate::Normalize::default(), $normalize);
+            let reserved = $crate::router!(@reserved $reserved, normalize, path);
+            let future = match $crate::router!(@find [async] [$(timer $timing)?] [$($wrap)?] reserved [$($request)?] $order context, [&context $(, $ext)? $(, $crate::router!(@take $request))?], method, path, normalize, $default, $($routes)*) {
+                Some(future) => future,
+                None => {
+                    $(timer.handler_started(); let _ = &$timing;)?
+                    $crate::router!(@fallback [async] $reserved, reserved, $default(context $(, $ext)? $(, $crate::router!(@take $request))?))
+                }
+            };
+            $(timer.finish(&$timing);)?
+            future
+        };
+        router
+    }};
+
+    // Closure returning `Pin<Box<dyn Future>>` of the handler's value, awaited if it is a future
+    (@closure [boxed] $normalize:tt [[$($ext:ident)?] [$($request:ident)?] [$($timing:expr)?] $reserved:tt $recover:tt [$($wrap:expr)?] $table:tt] $order:tt $default:ident, $($routes:tt)*) => {{
+        $crate::router!(@sync_only $recover);
+        $crate::router!(@validate $normalize, $($routes)*);
+        #[allow(clippy::redundant_closure_call)]
+        let router = move |context, $($ext: &$crate::Extensions,)? $($request,)? method: $crate::Method, path: &str| {
+            $(let $request = ::std::cell::Cell::new(Some($request));)?
+            let _ = || $default(context $(, $ext)? $(, $crate::router!(@take $request))?);
+            $(let timer = $crate::__HttpRouterTimer::start(); let _ = &$timing;)?
+            let normalize = $crate::router!(@route_normalize $crate::Normalize::default(), $normalize);
+            let reserved = $crate::router!(@reserved $reserved, normalize, path);
+            let future = match $crate::router!(@find [boxed] [$(timer $timing)?] [$($wrap)?] reserved [$($request)?] $order context, [&context $(, $ext)? $(, $crate::router!(@take $request))?], method, path, normalize, $default, $($routes)*) {
+                Some(future) => future,
+                None => {
+                    $(timer.handler_started(); let _ = &$timing;)?
+                    $crate::router!(@fallback [boxed] $reserved, reserved, $default(context $(, $ext)? $(, $crate::router!(@take $request))?))
+                }
+            };
+            $(timer.finish(&$timing);)?
+            future
+        };
+        router
+    }};
+
+    // Closure returning `Pin<Box<dyn Future + Send>>`, handlers get their own clone of the context
+    (@closure [async_move] $normalize:tt [[$($ext:ident)?] [$($request:ident)?] [$($timing:expr)?] $reserved:tt $recover:tt [$($wrap:expr)?] $table:tt] $order:tt $default:ident, $($routes:tt)*) => {{
+        $crate::router!(@sync_only $recover);
+        $crate::router!(@validate $normalize, $($routes)*);
+        #[allow(clippy::redundant_closure_call)]
+        let router = move |context, $($ext: &$crate::Extensions,)? $($request,)? method: $crate::Method, path: &str| {
+            $(let $request = ::std::cell::Cell::new(Some($request));)?
+            let _ = || $default(Clone::clone(&context) $(, $ext)? $(, $crate::router!(@take $request))?);
+            $(let timer = $crate::__HttpRouterTimer::start(); let _ = &$timing;)?
+            let normalize = $crate::router!(@route_normalize $crate::Normalize::default(), $normalize);
+            let reserved = $crate::router!(@reserved $reserved, normalize, path);
+            let future = match $crate::router!(@find [async_move] [$(timer $timing)?] [$($wrap)?] reserved [$($request)?] $order context, [Clone::clone(&context) $(, $ext)? $(, $crate::router!(@take $request))?], method, path, normalize, $default, $($routes)*) {
+                Some(future) => future,
+                None => {
+                    $(timer.handler_started(); let _ = &$timing;)?
+                    $crate::router!(@fallback [async_move] $reserved, reserved, $default(context $(, $ext)? $(, $crate::router!(@take $request))?))
+                }
+            };
+            $(timer.finish(&$timing);)?
+            future
+        };
+        router
+    }};
+
+    // Table of the routes around the router, or only their number for `const_assert_route_count!`
+    (@parse [$mode:ident] $normalize:tt [$ext:tt $request:tt $timing:tt $reserved:tt $recover:tt $wrap:tt [$($table:ident)+]] $order:tt [$($routes:tt)*] _ => $default:ident $(,)*) => {
+        $crate::router!(@table [$($table)+] $normalize $order ($crate::router!(@parse [$mode] $normalize [$ext $request $timing $reserved $recover $wrap []] $order [$($routes)*] _ => $default)), $($routes)*)
+    };
+
+    // Default only
+    (@parse [plain] $normalize:tt [[$($ext:ident)?] [$($request:ident)?] [$($timing:expr)?] $reserved:tt [$($recover:expr)?] $wrap:tt $table:tt] $order:tt [] _ => $default:ident $(,)*) => {
+        |context, $($ext: &$crate::Extensions,)? $($request,)? _method: $crate::Method, _path: &str| {
+            $crate::router!(@catch_panics [plain] [$($recover)?] &context, {
+                let reserved = $crate::router!(@reserved $reserved, $crate::router!(@route_normalize $crate::Normalize::default(), $normalize), _path);
+                $(let timer = $crate::__HttpRouterTimer::start(); timer.handler_started(); let _ = &$timing;)?
+                let value = $crate::router!(@fallback [plain] $reserved, reserved, $default(&context $(, $ext)? $(, $request)?));
+                $(timer.finish(&$timing);)?
+                value
+            })
+        }
+    };
+
+    // Default only, reporting no match
+    (@parse [matched] $normalize:tt [[$($ext:ident)?] [$($request:ident)?] [$($timing:expr)?] $reserved:tt [$($recover:expr)?] $wrap:tt $table:tt] $order:tt [] _ => $default:ident $(,)*) => {
+        |context, $($ext: &$crate::Extensions,)? $($request,)? _method: $crate::Method, _path: &str| {
+            $crate::router!(@catch_panics [matched] [$($recover)?] &context, {
+                let reserved = $crate::router!(@reserved $reserved, $crate::router!(@route_normalize $crate::Normalize::default(), $normalize), _path);
+                $(let timer = $crate::__HttpRouterTimer::start(); timer.handler_started(); let _ = &$timing;)?
+                let value = (false, $crate::router!(@fallback [plain] $reserved, reserved, $default(&context $(, $ext)? $(, $request)?)));
+                $(timer.finish(&$timing);)?
+                value
+            })
+        }
+    };
+
+    // Default only, boxing the future
+    (@parse [async] $normalize:tt [[$($ext:ident)?] [$($request:ident)?] [$($timing:expr)?] $reserved:tt $recover:tt $wrap:tt $table:tt] $order:tt [] _ => $default:ident $(,)*) => {{
+        $crate::router!(@sync_only $recover);
+        |context, $($ext: &$crate::Extensions,)? $($request,)? _method: $crate::Method, _path: &str| {
+            let reserved = $crate::router!(@reserved $reserved, $crate::router!(@route_normalize $crate::Normalize::default(), $normalize), _path);
+            $(let timer = $crate::__HttpRouterTimer::start(); timer.handler_started(); let _ = &$timing;)?
+            let value = $crate::router!(@fallback [async] $reserved, reserved, $default(context $(, $ext)? $(, $request)?));
+            $(timer.finish(&$timing);)?
+            value
+        }
+    }};
+
+    // Default only, boxing the future
+    (@parse [async_move] $normalize:tt [[$($ext:ident)?] [$($request:ident)?] [$($timing:expr)?] $reserved:tt $recover:tt $wrap:tt $table:tt] $order:tt [] _ => $default:ident $(,)*) => {{
+        $crate::router!(@sync_only $recover);
+        |context, $($ext: &$crate::Extensions,)? $($request,)? _method: $crate::Method, _path: &str| {
+            let reserved = $crate::router!(@reserved $reserved, $crate::router!(@route_normalize $crate::Normalize::default(), $normalize), _path);
+            $(let timer = $crate::__HttpRouterTimer::start(); timer.handler_started(); let _ = &$timing;)?
+            let value = $crate::router!(@fallback [async_move] $reserved, reserved, $default(context $(, $ext)? $(, $request)?));
+            $(timer.finish(&$timing);)?
+            value
+        }
+    }};
+
+    // Default only, boxing the value
+    (@parse [boxed] $normalize:tt [[$($ext:ident)?] [$($request:ident)?] [$($timing:expr)?] $reserved:tt $recover:tt $wrap:tt $table:tt] $order:tt [] _ => $default:ident $(,)*) => {{
+        $crate::router!(@sync_only $recover);
+        |context, $($ext: &$crate::Extensions,)? $($request,)? _method: $crate::Method, _path: &str| {
+            let reserved = $crate::router!(@reserved $reserved, $crate::router!(@route_normalize $crate::Normalize::default(), $normalize), _path);
+            $(let timer = $crate::__HttpRouterTimer::start(); timer.handler_started(); let _ = &$timing;)?
+            let value = $crate::router!(@fallback [boxed] $reserved, reserved, $default(context $(, $ext)? $(, $request)?));
+            $(timer.finish(&$timing);)?
+            value
+        }
+    }};
+
+    // Fallback ends the route list
+    (@parse [$mode:ident] $normalize:tt $ext:tt $order:tt [$($routes:tt)*] _ => $default:ident $(,)*) => {
+        $crate::router!(@closure [$mode] $normalize $ext $order $default, $($routes)*)
+    };
+
+    // Plain routes up to the fallback, all in one step
+    (@parse [$mode:ident] $normalize:tt $ext:tt $order:tt [$($routes:tt)*] $($method_token:ident $(/$path_segment:tt)+ $([$($attr:tt)*])* => $handler:ident $(? $([$never:tt])*)? $(when $when:ident)* $(guard $guard:ident)? $(if $cond:expr)?,)+ _ => $default:ident $(,)*) => {
+        $crate::router!(@parse [$mode] $normalize $ext $order [$($routes)* $(($method_token [$($path_segment)*] $handler [$(fallthrough $($never)*)?] [$(&$when;)* $($cond;)?] [$($guard)?] [$([$($attr)*])*]))+] _ => $default)
+    };
+
+    // Home route. The route is normalized to `(METHOD [segments] handler [fallthrough] [conditions] [guard] [attributes])`,
+    // the `[$never]` repetition is never written and only makes `?` transcribable.
+    (@parse [$mode:ident] $normalize:tt $ext:tt $order:tt [$($routes:tt)*] $method_token:ident / $([$($attr:tt)*])* => $handler:ident $(? $([$never:tt])*)? $(when $when:ident)* $(guard $guard:ident)? $(if $cond:expr)?, $($rest:tt)*) => {
+        $crate::router!(@parse [$mode] $normalize $ext $order [$($routes)* ($method_token [] $handler [$(fallthrough $($never)*)?] [$(&$when;)* $($cond;)?] [$($guard)?] [$([$($attr)*])*])] $($rest)*)
+    };
+
+    // Home route with a picker, like the route arm below
+    (@parse [$mode:ident] $normalize:tt $ext:tt $order:tt [$($routes:tt)*] $method_token:ident / $([$($attr:tt)*])* => pick($picker:ident => $($choice:ident),+ $(,)*) $(when $when:ident)* $(guard $guard:ident)? $(if $cond:expr)?, $($rest:tt)*) => {
+        $crate::router!(@parse [$mode] $normalize $ext $order [$($routes)* ($method_token [] [pick $picker $($choice)+] [] [$(&$when;)* $($cond;)?] [$($guard)?] [$([$($attr)*])*])] $($rest)*)
+    };
+
+    // Router mounted under a prefix
+    (@parse [$mode:ident] $normalize:tt $ext:tt $order:tt [$($routes:tt)*] scope $(/$path_segment:ident)+ (map_ctx = $map_ctx:expr) => $router:ident, $($rest:tt)*) => {
+        $crate::router!(@parse [$mode] $normalize $ext $order [$($routes)* (scope [$($path_segment)*] $router [] [$map_ctx] [] [])] $($rest)*)
+    };
+
+    // Route whose handler is chosen by a picker on each request, e.g. for A/B tests
+    (@parse [$mode:ident] $normalize:tt $ext:tt $order:tt [$($routes:tt)*] $method_token:ident $(/$path_segment:tt)+ $([$($attr:tt)*])* => pick($picker:ident => $($choice:ident),+ $(,)*) $(when $when:ident)* $(guard $guard:ident)? $(if $cond:expr)?, $($rest:tt)*) => {
+        $crate::router!(@parse [$mode] $normalize $ext $order [$($routes)* ($method_token [$($path_segment)*] [pick $picker $($choice)+] [] [$(&$when;)* $($cond;)?] [$($guard)?] [$([$($attr)*])*])] $($rest)*)
+    };
+
+    // Route picking the handler by the file extension, e.g. `/reports/12.json`
+    (@parse [$mode:ident] $normalize:tt $ext:tt $order:tt [$($routes:tt)*] $method_token:ident $(/$path_segment:tt)+ $([$($attr:tt)*])* => ext { $($extension:literal => $handler:ident),+ $(,)* } $(when $when:ident)* $(if $cond:expr)?, $($rest:tt)*) => {
+        $crate::router!(@extensions [$mode] $normalize $ext $order [$($routes)*] ($method_token [$($path_segment)*] [$(&$when;)* $($cond;)?] [$([$($attr)*])*]) [$($extension => $handler),+] $($rest)*)
+    };
+
+    // Route with aliases, e.g. `GET /organizations | /organisations`, all calling the handler
+    (@parse [$mode:ident] $normalize:tt $ext:tt $order:tt [$($routes:tt)*] $method_token:ident $(/$path_segment:tt)+ $(| $(/$alias_segment:tt)+)+ $([$($attr:tt)*])* => $handler:ident $(? $([$never:tt])*)? $(when $when:ident)* $(guard $guard:ident)? $(if $cond:expr)?, $($rest:tt)*) => {
+        $crate::router!(@aliases [$mode] $normalize $ext $order [$($routes)* ($method_token [$($path_segment)*] $handler [$(fallthrough $($never)*)? aliased [$([$($alias_segment)*])+]] [$(&$when;)* $($cond;)?] [$($guard)?] [$([$($attr)*])*])] ($method_token [$($path_segment)*] $handler [$(fallthrough $($never)*)?] [$(&$when;)* $($cond;)?] [$($guard)?] [$([$($attr)*])*]) [$([$($alias_segment)*])+] [$([$($alias_segment)*])+] $($rest)*)
+    };
+
+    // Route
+    (@parse [$mode:ident] $normalize:tt $ext:tt $order:tt [$($routes:tt)*] $method_token:ident $(/$path_segment:tt)+ $([$($attr:tt)*])* => $handler:ident $(? $([$never:tt])*)? $(when $when:ident)* $(guard $guard:ident)? $(if $cond:expr)?, $($rest:tt)*) => {
+        $crate::router!(@parse [$mode] $normalize $ext $order [$($routes)* ($method_token [$($path_segment)*] $handler [$(fallthrough $($never)*)?] [$(&$when;)* $($cond;)?] [$($guard)?] [$([$($attr)*])*])] $($rest)*)
+    };
+
+    // Route with segments of several tokens, like `/well-known`, collected token by token
+    (@parse [$mode:ident] $normalize:tt $ext:tt $order:tt [$($routes:tt)*] $method_token:ident / $($rest:tt)*) => {
+        $crate::router!(@path [$mode] $normalize $ext $order [$($routes)*] $method_token [] [] $($rest)*)
+    };
+
+    // Group of routes without a prefix, tried in place of the group
+    (@parse [$mode:ident] $normalize:tt $ext:tt $order:tt [$($routes:tt)*] scope { $($group:tt)* }, $($rest:tt)*) => {
+        $crate::router!(@parse [$mode] $normalize $ext $order [$($routes)*] $($group)* , $($rest)*)
+    };
+
+    // Comma left after the last route of a group or an empty one
+    (@parse [$mode:ident] $normalize:tt $ext:tt $order:tt [$($routes:tt)*] , $($rest:tt)*) => {
+        $crate::router!(@parse [$mode] $normalize $ext $order [$($routes)*] $($rest)*)
+    };
+
+    // Anything else is a syntax error
+    (@parse [$mode:ident] $normalize:tt $ext:tt $order:tt [$($routes:tt)*] $($rest:tt)*) => {
+        compile_error!("Invalid router! syntax, see the crate docs for the supported routes")
+    };
+
+    // One route per alias of a route, after the canonical one
+    (@aliases $mode:tt $normalize:tt $ext:tt $order:tt [$($routes:tt)*] ($method_token:ident $canonical:tt $handler:tt [$($kind:ident)?] $cond:tt $guard:tt $attrs:tt) $aliases:tt [$alias:tt $($more:tt)*] $($rest:tt)*) => {
+        $crate::router!(@aliases $mode $normalize $ext $order [$($routes)* ($method_token $alias $handler [$($kind)? alias $canonical $aliases] $cond $guard $attrs)] ($method_token $canonical $handler [$($kind)?] $cond $guard $attrs) $aliases [$($more)*] $($rest)*)
+    };
+    (@aliases [$mode:ident] $normalize:tt $ext:tt $order:tt [$($routes:tt)*] $route:tt $aliases:tt [] $($rest:tt)*) => {
+        $crate::router!(@parse [$mode] $normalize $ext $order [$($routes)*] $($rest)*)
+    };
+
+    // One route per extension of an `ext { ... }` route
+    (@extensions $mode:tt $normalize:tt $ext:tt $order:tt [$($routes:tt)*] ($method_token:ident [$($path_segment:tt)*] $cond:tt $attrs:tt) [$extension:literal => $handler:ident $(, $more:literal => $more_handler:ident)*] $($rest:tt)*) => {
+        $crate::router!(@extensions $mode $normalize $ext $order [$($routes)* ($method_token [$($path_segment)* (. $extension)] $handler [] $cond [] $attrs)] ($method_token [$($path_segment)*] $cond $attrs) [$($more => $more_handler),*] $($rest)*)
+    };
+    (@extensions [$mode:ident] $normalize:tt $ext:tt $order:tt [$($routes:tt)*] $route:tt [] $($rest:tt)*) => {
+        $crate::router!(@parse [$mode] $normalize $ext $order [$($routes)*] $($rest)*)
+    };
+
+    // Path is done, continue like the route arm of @parse
+    (@path [$mode:ident] $normalize:tt $ext:tt $order:tt [$($routes:tt)*] $method_token:ident [$($done:tt)*] [] @end $([$($attr:tt)*])* => $handler:ident $(? $([$never:tt])*)? $(when $when:ident)* $(guard $guard:ident)? $(if $cond:expr)?, $($rest:tt)*) => {
+        $crate::router!(@parse [$mode] $normalize $ext $order [$($routes)* ($method_token [$($done)*] $handler [$(fallthrough $($never)*)?] [$(&$when;)* $($cond;)?] [$($guard)?] [$([$($attr)*])*])] $($rest)*)
+    };
+
+    (@path [$mode:ident] $normalize:tt $ext:tt $order:tt [$($routes:tt)*] $method_token:ident [$($done:tt)*] [] @end $([$($attr:tt)*])* => pick($picker:ident => $($choice:ident),+ $(,)*) $(when $when:ident)* $(guard $guard:ident)? $(if $cond:expr)?, $($rest:tt)*) => {
+        $crate::router!(@parse [$mode] $normalize $ext $order [$($routes)* ($method_token [$($done)*] [pick $picker $($choice)+] [] [$(&$when;)* $($cond;)?] [$($guard)?] [$([$($attr)*])*])] $($rest)*)
+    };
+
+    // End of a segment. Single idents and params are kept as is, other segments become `[tokens]`
+    (@path $mode:tt $normalize:tt $ext:tt $order:tt $routes:tt $method_token:ident [$($done:tt)*] [$segment:ident] / $($rest:tt)*) => {
+        $crate::router!(@path $mode $normalize $ext $order $routes $method_token [$($done)* $segment] [] $($rest)*)
+    };
+    (@path $mode:tt $normalize:tt $ext:tt $order:tt $routes:tt $method_token:ident [$($done:tt)*] [{$($param:tt)*}] / $($rest:tt)*) => {
+        $crate::router!(@path $mode $normalize $ext $order $routes $method_token [$($done)* {$($param)*}] [] $($rest)*)
+    };
+    (@path $mode:tt $normalize:tt $ext:tt $order:tt $routes:tt $method_token:ident [$($done:tt)*] [$($segment:tt)+] / $($rest:tt)*) => {
+        $crate::router!(@path $mode $normalize $ext $order $routes $method_token [$($done)* [$($segment)+]] [] $($rest)*)
+    };
+
+    // End of the path, ending the last segment
+    (@path $mode:tt $normalize:tt $ext:tt $order:tt $routes:tt $method_token:ident $done:tt [$($segment:tt)+] $([$($attr:tt)*])+ => $($rest:tt)*) => {
+        $crate::router!(@path $mode $normalize $ext $order $routes $method_token $done [$($segment)+] / @end $([$($attr)*])+ => $($rest)*)
+    };
+    (@path $mode:tt $normalize:tt $ext:tt $order:tt $routes:tt $method_token:ident $done:tt [$($segment:tt)+] => $($rest:tt)*) => {
+        $crate::router!(@path $mode $normalize $ext $order $routes $method_token $done [$($segment)+] / @end => $($rest)*)
+    };
+
+    // Hyphenated tokens, like `well-known`, ending a segment in one step
+    (@path $mode:tt $normalize:tt $ext:tt $order:tt $routes:tt $method_token:ident [$($done:tt)*] [$($segment:tt)*] $first:tt $(- $more:tt)+ / $($rest:tt)*) => {
+        $crate::router!(@path $mode $normalize $ext $order $routes $method_token [$($done)* [$($segment)* $first $(- $more)+]] [] $($rest)*)
+    };
+    (@path $mode:tt $normalize:tt $ext:tt $order:tt $routes:tt $method_token:ident [$($done:tt)*] [$($segment:tt)*] $first:tt $(- $more:tt)+ $([$($attr:tt)*])* => $($rest:tt)*) => {
+        $crate::router!(@path $mode $normalize $ext $order $routes $method_token [$($done)* [$($segment)* $first $(- $more)+]] [] @end $([$($attr)*])* => $($rest)*)
+    };
+
+    (@path $mode:tt $normalize:tt $ext:tt $order:tt $routes:tt $method_token:ident $done:tt [$($segment:tt)*] $token:tt $($rest:tt)*) => {
+        $crate::router!(@path $mode $normalize $ext $order $routes $method_token $done [$($segment)* $token] $($rest)*)
+    };
+
+    // Report whether a route matched along with the value
+    (@header [$mode:ident] $normalize:tt $ext:tt $order:tt matched; $($rest:tt)*) => {
+        $crate::router!(@header [matched] $normalize $ext $order $($rest)*)
+    };
+
+    // Box handler futures, cloning the context into each of them
+    (@header [$mode:ident] $normalize:tt $ext:tt $order:tt async move; $($rest:tt)*) => {
+        $crate::router!(@header [async_move] $normalize $ext $order $($rest)*)
+    };
+
+    // Box handler futures
+    (@header [$mode:ident] $normalize:tt $ext:tt $order:tt async; $($rest:tt)*) => {
+        $crate::router!(@header [async] $normalize $ext $order $($rest)*)
+    };
+
+    // Try routes with a method before `ANY` routes
+    (@header [$mode:ident] $normalize:tt $ext:tt [$specificity_order:expr $(, $compact:ident)?] specificity_order; $($rest:tt)*) => {
+        $crate::router!(@header [$mode] $normalize $ext [true $(, $compact)?] $($rest)*)
+    };
+
+    // Match routes against a table in the crate instead of generating code for each route
+    (@header [$mode:ident] $normalize:tt $ext:tt [$specificity_order:expr $(, $compact:ident)?] compact; $($rest:tt)*) => {
+        $crate::router!(@header [$mode] $normalize $ext [$specificity_order, compact] $($rest)*)
+    };
+
+    // Return the router along with its routes in a `RouteTable`
+    (@header [$mode:ident] $normalize:tt [$ext:tt $request:tt $timing:tt $reserved:tt $recover:tt $wrap:tt $table:tt] $order:tt table; $($rest:tt)*) => {
+        $crate::router!(@header [$mode] $normalize [$ext $request $timing $reserved $recover $wrap [table]] $order $($rest)*)
+    };
+
+    // Same, along with a predicate per handler
+    (@header [$mode:ident] $normalize:tt [$ext:tt $request:tt $timing:tt $reserved:tt $recover:tt $wrap:tt $table:tt] $order:tt table(predicates); $($rest:tt)*) => {
+        $crate::router!(@header [$mode] $normalize [$ext $request $timing $reserved $recover $wrap [table predicates]] $order $($rest)*)
+    };
+
+    // Pass request extensions to handlers after the context
+    (@header [$mode:ident] $normalize:tt [$ext:tt $request:tt $timing:tt $reserved:tt $recover:tt $wrap:tt $table:tt] $order:tt extensions; $($rest:tt)*) => {
+        $crate::router!(@header [$mode] $normalize [[extensions] $request $timing $reserved $recover $wrap $table] $order $($rest)*)
+    };
+
+    // Pass a per-request value to handlers by value after the context
+    (@header [$mode:ident] $normalize:tt [$ext:tt $request:tt $timing:tt $reserved:tt $recover:tt $wrap:tt $table:tt] $order:tt request; $($rest:tt)*) => {
+        $crate::router!(@header [$mode] $normalize [$ext [request] $timing $reserved $recover $wrap $table] $order $($rest)*)
+    };
+
+    // Call the handlers through a hook, along with their template and timeout
+    (@header [$mode:ident] $normalize:tt [$ext:tt $request:tt $timing:tt $reserved:tt $recover:tt $wrap:tt $table:tt] $order:tt wrap = $hook:expr; $($rest:tt)*) => {
+        $crate::router!(@header [$mode] $normalize [$ext $request $timing $reserved $recover [$hook] $table] $order $($rest)*)
+    };
+
+    // Report the matching and handler time of each call to a hook
+    (@header [$mode:ident] $normalize:tt [$ext:tt $request:tt $timing:tt $reserved:tt $recover:tt $wrap:tt $table:tt] $order:tt timing($hook:expr); $($rest:tt)*) => {
+        $crate::router!(@header [$mode] $normalize [$ext $request [$hook] $reserved $recover $wrap $table] $order $($rest)*)
+    };
+
+    // Answer requests whose handler panics with a recovery handler
+    (@header [$mode:ident] $normalize:tt [$ext:tt $request:tt $timing:tt $reserved:tt $recover:tt $wrap:tt $table:tt] $order:tt catch_panics = $handler:ident; $($rest:tt)*) => {
+        $crate::router!(@header [$mode] $normalize [$ext $request $timing $reserved [$handler] $wrap $table] $order $($rest)*)
+    };
+
+    // First segments that routes starting with a param or catch-all don't match
+    (@header [$mode:ident] $normalize:tt [$ext:tt $request:tt $timing:tt $reserved:tt $recover:tt $wrap:tt $table:tt] $order:tt reserved($($word:literal),+ $(,)?) $(=> $handler:ident)?; $($rest:tt)*) => {
+        $crate::router!(@header [$mode] $normalize [$ext $request $timing [[$($word),+] [$($handler)?]] $recover $wrap $table] $order $($rest)*)
+    };
+
+    // Normalization for all routes
+    (@header [$mode:ident] $normalize:tt $ext:tt $order:tt normalize($($options:tt)*); $($rest:tt)*) => {
+        $crate::router!(@header [$mode] [[normalize($($options)*)]] $ext $order $($rest)*)
+    };
+
+    // No more header lines, parse the routes
+    (@header [$mode:ident] $normalize:tt $ext:tt $order:tt $($rest:tt)*) => {
+        $crate::router!(@parse [$mode] $normalize $ext $order [] $($rest)*)
+    };
+
+    // Entry pattern
+    ($($rest:tt)*) => {
+        $crate::router!(@header [plain] [] [[] [] [] [] [] [] []] [false] $($rest)*)
+    };
+}
+
+/// Like `router!`, but handlers may be sync or async: each handler's value is boxed as a
+/// future, the value itself if it is one, or a ready future of it otherwise. The closure returns
+/// `Pin<Box<dyn Future<Output = T> + '_>>` and takes the context by reference, like with an
+/// `async;` header:
+///
+/// ```rust
+/// fn get_users(context: &Context) -> Response { ... }
+/// async fn get_user(context: &Context, id: u32) -> Response { ... }
+///
+/// let router = async_router!(GET /users => get_users, GET /users/{id: u32} => get_user, _ => not_found);
+/// let response = router(&ctx, method, path).await;
+/// ```
+///
+/// Sync handlers returning a future, e.g. a boxed one, are treated as async handlers.
+#[macro_export]
+macro_rules! async_router {
+    ($($rest:tt)*) => {
+        $crate::router!(@header [boxed] [] [[] [] [] [] [] [] []] [false] $($rest)*)
+    };
+}
+
+/// Calls a router with a method that converts with `IntoMethod`, e.g. a name or a `Method`:
+///
+/// ```rust
+/// let response = router_call!(router, ctx, "GET", "/users");
+/// ```
+///
+/// Arguments between the context and the method, like the extensions, are passed on as is.
+/// Panics if the method doesn't convert, call `into_method()` first to answer it instead.
+#[macro_export]
+macro_rules! router_call {
+    (@call $router:expr, [$($arg:expr,)*] $method:expr, $path:expr $(,)?) => {
+        $router(
+            $($arg,)*
+            $crate::IntoMethod::into_method($method).unwrap_or_else(|error| panic!("{}", error)),
+            $path,
+        )
+    };
+    (@call $router:expr, [$($arg:expr,)*] $next:expr, $($rest:expr),+ $(,)?) => {
+        $crate::router_call!(@call $router, [$($arg,)* $next,] $($rest),+)
+    };
+    ($router:expr, $context:expr, $($rest:expr),+ $(,)?) => {
+        $crate::router_call!(@call $router, [$context,] $($rest),+)
+    };
+}
+
+/// Fails to compile if the routes of a `router!` body, written in braces, are more than
+/// `max`, e.g. to keep a large application's router from growing unnoticed:
+///
+/// ```rust
+/// const_assert_route_count!({
+///     GET /users => get_users,
+///     GET /users/{id: u32} => get_user,
+///     _ => not_found,
+/// }, 50);
+/// ```
+///
+/// The fallback isn't counted, and each extension of an `ext { ... }` route counts as a route.
+/// Use it in item position, e.g. next to the function creating the router.
+#[macro_export]
+macro_rules! const_assert_route_count {
+    ({ $($routes:tt)* }, $max:expr) => {
+        const _: () = assert!(
+            $crate::router!(@header [plain] [] [[] [] [] [] [] [] [count]] [false] $($routes)*) <= $max,
+            concat!("the router has more than ", stringify!($max), " routes")
+        );
+    };
+}
+
+#[cfg(test)]
+// the first tests were written before these lints and are kept as they were
+#[allow(
+    array_into_iter,
+    clippy::clone_on_copy,
+    clippy::into_iter_on_ref,
+    clippy::useless_format
+)]
+mod tests {
+    extern crate rand;
+
+    // use self::test::Bencher;
+    use super::*;
+    use std::cell::Cell;
+    use std::future::{self, Future, Ready};
+    use std::pin::Pin;
+    use std::sync::Arc;
+    use std::task::{Context as TaskContext, Poll, Wake, Waker};
+    use std::thread;
+
+    const NUMBER_OF_THREADS_FOR_REAL_LIFE_TEST: usize = 4;
+    const NUMBER_OF_TESTS_FOR_REAL_LIFE_TEST: usize = 3000;
+
+    // Defines each test twice, `plain` calling `router!` as written and `compact` with a
+    // `compact;` header added, so that both kinds of routers pass the same tests
+    macro_rules! router_tests {
+        ($(#[$attr:meta])* fn $name:ident() $body:block) => {
+            router_tests!(@dollar [$] $(#[$attr])* fn $name() $body);
+        };
+        (@dollar [$d:tt] $(#[$attr:meta])* fn $name:ident() $body:block) => {
+            mod $name {
+                #[allow(unused_imports)]
+                use super::*;
+
+                #[test]
+                $(#[$attr])*
+                fn plain() $body
+
+                #[test]
+                $(#[$attr])*
+                fn compact() {
+                    #[allow(unused_macros)]
+                    macro_rules! router {
+                        ($d($d tokens:tt)*) => { $crate::router!(compact; $d($d tokens)*) };
+                    }
+                    $body
+                }
+            }
+        };
+    }
+
+    router_tests! {
+        fn test_real_life() {
+            let get_users = |_: &()| "get_users".to_string();
+            let post_users = |_: &()| "post_users".to_string();
+            let patch_users = |_: &(), id: u32| format!("patch_users({})", id);
+            let delete_users = |_: &(), id: u32| format!("delete_users({})", id);
+            let get_transactions = |_: &(), id: u32| format!("get_transactions({})", id);
+            let post_transactions = |_: &(), id: u32| format!("post_transactions({})", id);
+            let patch_transactions =
+                |_: &(), id: u32, hash: String| format!("patch_transactions({}, {})", id, hash);
+            let delete_transactions =
+                |_: &(), id: u32, hash: String| format!("delete_transactions({}, {})", id, hash);
+            let fallback = |_: &()| "404".to_string();
+
+            let router = router!(
+                GET / => get_users,
+                GET /users => get_users,
+                POST /users => post_users,
+                PATCH /users/{user_id: u32} => patch_users,
+                DELETE /users/{user_id: u32} => delete_users,
+                GET /users/{user_id: u32}/transactions => get_transactions,
+                POST /users/{user_id: u32}/transactions => post_transactions,
+                PATCH /users/{user_id: u32}/transactions/{hash: String} => patch_transactions,
+                DELETE /users/{user_id: u32}/transactions/{hash: String} => delete_transactions,
+                _ => fallback,
+            );
+            let test_cases = [
+                (Method::GET, "/", "get_users"),
+                (Method::GET, "/users", "get_users"),
+                (Method::POST, "/users", "post_users"),
+                (Method::PATCH, "/users/12", "patch_users(12)"),
+                (Method::DELETE, "/users/132134", "delete_users(132134)"),
+                (
+                    Method::GET,
+                    "/users/534/transactions",
+                    "get_transactions(534)",
+                ),
+                (
+                    Method::POST,
+                    "/users/534/transactions",
+                    "post_transactions(534)",
+                ),
+                (
+                    Method::PATCH,
+                    "/users/534/transactions/0x234",
+                    "patch_transactions(534, 0x234)",
+                ),
+                (
+                    Method::DELETE,
+                    "/users/534/transactions/0x234",
+                    "delete_transactions(534, 0x234)",
+                ),
+                (Method::DELETE, "/users/5d34/transactions/0x234", "404"),
+                (Method::POST, "/users/534/transactions/0x234", "404"),
+                (Method::GET, "/u", "404"),
+                (Method::POST, "/", "404"),
+            ];
+            for test_case in test_cases.into_iter() {
+                let (method, path, expected) = test_case.clone();
+                assert_eq!(router((), method.clone(), path), expected.to_string());
+            }
+
+            let mut threads: Vec<thread::JoinHandle<_>> = Vec::new();
+            for _ in 0..NUMBER_OF_THREADS_FOR_REAL_LIFE_TEST {
+                let handle = thread::spawn(move || {
+                    for _ in 0..NUMBER_OF_TESTS_FOR_REAL_LIFE_TEST {
+                        let number = rand::random::<usize>() % test_cases.len();
+                        let test_case = test_cases[number];
+                        let (method, path, expected) = test_case;
+                        assert_eq!(router((), method.clone(), path), expected.to_string());
+                    }
+                });
+                threads.push(handle);
+            }
+            for thread in threads {
+                let _ = thread.join();
+            }
+        }
+    }
+
+    router_tests! {
+        fn test_clone_router() {
+            use std::sync::atomic::{AtomicUsize, Ordering};
+            use std::sync::Arc;
+
+            let hits = Arc::new(AtomicUsize::new(0));
+            let counter = hits.clone();
+            let get_user = move |_: &(), id: u32| {
+                counter.fetch_add(1, Ordering::SeqCst);
+                format!("user {}", id)
+            };
+            let fallback = |_: &()| "404".to_string();
+            let router = router!(GET /users/{id: u32} => get_user, _ => fallback);
+
+            let copy = router.clone();
+            let handle = thread::spawn(move || copy((), Method::GET, "/users/1"));
+            assert_eq!(router((), Method::GET, "/users/2"), "user 2");
+            assert_eq!(handle.join().unwrap(), "user 1");
+            assert_eq!(hits.load(Ordering::SeqCst), 2);
+        }
+    }
+
+    router_tests! {
+        #[allow(unused_mut)]
+        fn test_home() {
+            let get_home = |_: &()| "get_home";
+            let unreachable = |_: &()| unreachable!();
+            let router = router!(
+                GET / => get_home,
+                _ => unreachable
+            );
+            assert_eq!(router((), Method::GET, "/"), "get_home");
+        }
+    }
+
+    #[test]
+    fn test_static_table() {
+        let home = |_: &()| "home".to_string();
+        let get_user = |_: &(), id: u32| format!("get_user({})", id);
+        let well_known = |_: &(), name: String| format!("well_known({})", name);
+        let json_report = |_: &(), id: u32| format!("json_report({})", id);
+        let xml_report = |_: &(), id: u32| format!("xml_report({})", id);
+        let fallback = |_: &()| "404".to_string();
+        let api = router!(
+            table;
+            GET / => home,
+            GET /users/{id: u32} => get_user,
+            POST /.well-known/{name: String}/index.html => well_known,
+            ANY /reports/{id: u32} => ext { "json" => json_report, "xml" => xml_report },
+            _ => fallback,
+        );
+        assert_eq!(api.route_count(), 5);
+        assert_eq!(
+            api.routes[1],
+            StaticRouteInfo {
+                method: Some(Method::GET),
+                pattern: "/users/{id: u32}",
+                params: &["id"],
+            }
+        );
+        let patterns: Vec<_> = api.routes.iter().map(|route| route.pattern).collect();
+        assert_eq!(
+            patterns,
+            vec![
+                "/",
+                "/users/{id: u32}",
+                "/.well-known/{name: String}/index.html",
+                "/reports/{id: u32}.json",
+                "/reports/{id: u32}.xml"
+            ]
+        );
+        assert_eq!(api.routes[3].method, None);
+
+        assert_eq!(
+            api.meta[1],
+            RouteMeta {
+                method: Some(Method::GET),
+                pattern: "/users/{id: u32}",
+                params: &[("id", "u32")],
+                meta: &[],
+            }
+        );
+        assert_eq!(api.meta[2].params, &[("name", "String")]);
+        assert_eq!(api.meta[0].params, &[]);
+        assert_eq!(api.meta.len(), api.route_count());
+
+        // the table derefs to the router it describes
+        assert_eq!(api((), Method::GET, "/users/12"), "get_user(12)");
+        assert_eq!(api((), Method::PUT, "/reports/3.xml"), "xml_report(3)");
+        assert_eq!(api((), Method::GET, "/posts"), "404");
+        let router = api.into_router();
+        assert_eq!(router((), Method::GET, "/"), "home");
+
+        let get_post = |_: &(), slug: String, _: [f64; 2]| format!("get_post({})", slug);
+        let static_file = |_: &(), path: String| format!("static_file({})", path);
+        let page = |_: &(), name: String| format!("page({})", name);
+        let meta_table = router!(
+            table;
+            GET /posts/{slug: String len 3..=40}/{coords: [f64; 2]} => get_post,
+            GET /files/{path: ..} [meta(auth = "none", cache = "public")] => static_file,
+            GET /page/{name: String no_ext} [meta(auth = "user")] => page,
+            _ => fallback,
+        );
+        assert_eq!(
+            meta_table.meta[0].params,
+            &[("slug", "String"), ("coords", "[f64; 2]")]
+        );
+        assert_eq!(meta_table.meta[1].params, &[("path", "String")]);
+        assert_eq!(meta_table.meta[2].params, &[("name", "String")]);
+        assert_eq!(meta_table.meta[0].meta, &[]);
+        assert_eq!(
+            meta_table.meta[1].meta,
+            &[("auth", "none"), ("cache", "public")]
+        );
+        assert_eq!(meta_table.meta[2].meta, &[("auth", "user")]);
+        let violations = audit::coverage_meta(
+            meta_table.meta,
+            &[audit::CoverageRule::RequireFallbackMetadata("auth")],
+        );
+        let patterns: Vec<_> = violations.iter().map(|v| v.pattern.as_str()).collect();
+        assert_eq!(
+            patterns,
+            vec!["/posts/{slug: String len 3..=40}/{coords: [f64; 2]}"]
+        );
+
+        // templates are spelled the same whatever the spacing in the source
+        fn small(n: &str, _: &()) -> bool {
+            n.len() == 1
+        }
+        let a = |_: &(), id: u32, _: String| format!("a({})", id);
+        let b = |_: &(), _: [u8; 2], n: u8| format!("b({})", n);
+        let template_table = router!(
+            table;
+            GET /a/{id : u32 len ..= 9}/{name:std::string::String} => a,
+            GET /b/{pair: [ u8 ;2 ]}/{n: u8 if small} => b,
+            _ => fallback,
+        );
+        assert_eq!(
+            template_table.routes[0].pattern,
+            "/a/{id: u32 len ..=9}/{name: std::string::String}"
+        );
+        assert_eq!(
+            template_table.routes[1].pattern,
+            "/b/{pair: [u8; 2]}/{n: u8 if small}"
+        );
+        assert_eq!(
+            template_table.meta[1].params,
+            &[("pair", "[u8; 2]"), ("n", "u8")]
+        );
+        assert_eq!(template_table((), Method::GET, "/b/1/2/3"), "b(3)");
+
+        let mut hits = vec![0u32; meta_table.route_count()];
+        for path in &["/files/a.css", "/page/about", "/files/b.css"] {
+            let (index, _) = meta_table.match_pattern(Method::GET, path).unwrap();
+            hits[index] += 1;
+        }
+        assert_eq!(hits, [0, 2, 1]);
+    }
+
+    const_assert_route_count!({
+        async;
+        GET /users => get_users,
+        GET /users/{id: u32} => get_user,
+        ANY /reports/{id: u32} => ext { "json" => json_report, "xml" => xml_report },
+        _ => not_found,
+    }, 4);
+
+    #[cfg(feature = "with_serde")]
+    #[test]
+    fn test_import_manifest() {
+        // answering with the index of the route
+        let home = |_: &()| Some(0);
+        let get_user = |_: &(), _: u32| Some(1);
+        let get_post = |_: &(), _: u32, _: String| Some(2);
+        let tag = |_: &(), _: String| Some(3);
+        let page = |_: &(), _: String| Some(4);
+        let well_known = |_: &(), _: String| Some(5);
+        let static_file = |_: &(), _: String| Some(6);
+        let fallback = |_: &()| None;
+        let api = router!(
+            table;
+            GET / => home,
+            GET /users/{id: u32} => get_user,
+            GET /users/{id: u32}/posts/{slug: String} => get_post,
+            GET /tags/{name: String len 3..=8} => tag,
+            GET /pages/{name: String no_ext} => page,
+            GET /.well-known/{name: String}/index.html => well_known,
+            GET /files/{path: ..} => static_file,
+            _ => fallback,
+        );
+        let patterns = import_manifest(&export_manifest(api.routes)).unwrap();
+        assert_eq!(patterns.len(), api.route_count());
+
+        let paths = [
+            "/",
+            "/users/12",
+            "/users/1%32",
+            "/users/+1",
+            "/users/abc",
+            "/users/99999999999",
+            "/users/12/posts/hello-world",
+            "/users/12/posts/a.b",
+            "/users/12/posts/j%C3%B6rg",
+            "/users/12/posts/a%FFb",
+            "/tags/ab",
+            "/tags/abc",
+            "/tags/abcdefghi",
+            "/tags/a%20b",
+            "/pages/about",
+            "/pages/about.json",
+            "/pages/about%2Ejson",
+            "/.well-known/acme/index.html",
+            "/.well-known/a.b/index.html",
+            "/files/a/b.css",
+            "/files",
+            "/users/",
+            "//users/12",
+            "/nope",
+        ];
+        for path in &paths {
+            let imported = patterns
+                .iter()
+                .position(|pattern| pattern.captures(path).is_some());
+            assert_eq!(imported, api((), Method::GET, path), "{}", path);
+        }
+        assert_eq!(
+            patterns[2].captures("/users/1%32/posts/j%C3%B6rg"),
+            Some(vec![("id", "1%32"), ("slug", "j%C3%B6rg")])
+        );
+    }
+
+    #[test]
+    fn test_route_timeouts() {
+        // middleware receiving the timeout of the route that matched along with its template
+        fn with_timeout<F: FnOnce() -> String>(
+            template: &'static str,
+            timeout_ms: Option<u64>,
+            call: F,
+        ) -> String {
+            format!("{} {:?} {}", template, timeout_ms, call())
+        }
+        let build_report = |_: &(), id: u64| format!("build_report({})", id);
+        let list_reports = |_: &()| "list_reports".to_string();
+        let export_report = |_: &(), id: u64| format!("export_report({})", id);
+        let fallback = |_: &()| "404".to_string();
+        let api = router!(
+            table;
+            wrap = with_timeout;
+            GET /reports/{id: u64} [timeout_ms = 5000] => build_report,
+            GET /reports => list_reports,
+            POST /reports/{id: u64}/export [timeout_ms = 60_000] => export_report,
+            _ => fallback,
+        );
+        assert_eq!(api.timeouts_ms, &[Some(5000), None, Some(60_000)]);
+        assert_eq!(
+            api((), Method::GET, "/reports/7"),
+            "/reports/{id: u64} Some(5000) build_report(7)"
+        );
+        assert_eq!(
+            api((), Method::POST, "/reports/7/export"),
+            "/reports/{id: u64}/export Some(60000) export_report(7)"
+        );
+        assert_eq!(
+            api((), Method::GET, "/reports"),
+            "/reports None list_reports"
+        );
+        // the fallback isn't wrapped
+        assert_eq!(api((), Method::POST, "/reports/7"), "404");
+        assert_eq!(api((), Method::GET, "/missing"), "404");
+    }
+
+    #[test]
+    fn test_route_aliases() {
+        let list_orgs = |_: &()| "list_orgs".to_string();
+        let list_members = |_: &(), id: u32| format!("list_members({})", id);
+        let list_users = |_: &()| "list_users".to_string();
+        let json_report = |_: &(), id: u32| format!("json_report({})", id);
+        let fallback = |_: &()| "404".to_string();
+        let api = router!(
+            table(predicates);
+            GET /organizations | /organisations => list_orgs,
+            GET /organizations/{id: u32}/members | /organisations/{id: u32}/members | /orgs/{id: u32}/members [timeout_ms = 500] => list_members,
+            GET /users => list_users,
+            GET /reports/{id: u32} => ext { "json" => json_report },
+            _ => fallback,
+        );
+        assert_eq!(api((), Method::GET, "/organizations"), "list_orgs");
+        assert_eq!(api((), Method::GET, "/organisations"), "list_orgs");
+        assert_eq!(api((), Method::POST, "/organisations"), "404");
+        for path in &[
+            "/organizations/3/members",
+            "/organisations/3/members",
+            "/orgs/3/members",
+        ] {
+            assert_eq!(api((), Method::GET, path), "list_members(3)");
+        }
+        assert_eq!(api((), Method::GET, "/orgs/x/members"), "404");
+
+        assert_eq!(api.route_count(), 7);
+        assert_eq!(
+            api.aliases[0],
+            RouteAliases {
+                canonical: "/organizations",
+                aliases: &["/organisations"],
+                alias: false,
+            }
+        );
+        assert_eq!(api.routes[1].pattern, "/organisations");
+        assert!(api.aliases[1].alias);
+        assert_eq!(
+            api.aliases[4],
+            RouteAliases {
+                canonical: "/organizations/{id: u32}/members",
+                aliases: &[
+                    "/organisations/{id: u32}/members",
+                    "/orgs/{id: u32}/members"
+                ],
+                alias: true,
+            }
+        );
+        assert_eq!(
+            api.aliases[5],
+            RouteAliases {
+                canonical: "/users",
+                aliases: &[],
+                alias: false,
+            }
+        );
+        assert_eq!(api.timeouts_ms[4], Some(500));
+
+        // links to a route found through an alias use the canonical template
+        let (index, captures) = api.match_pattern(Method::GET, "/orgs/7/members").unwrap();
+        assert_eq!(index, 4);
+        assert_eq!(
+            api.render(index, &[("id", captures.get(0).unwrap())])
+                .unwrap(),
+            "/organizations/7/members"
+        );
+        assert_eq!(api.render(1, &[]).unwrap(), "/organizations");
+        assert_eq!(
+            api.render(6, &[("id", "7")]),
+            Err(RenderError::Unsupported {
+                template: api.aliases[6].canonical.to_string()
+            })
+        );
+        assert!(api.is_route.list_orgs(Method::GET, "/organisations"));
+        assert!(api.is_route.list_members(Method::GET, "/orgs/7/members"));
+    }
+
+    #[test]
+    fn test_same_params() {
+        assert!(__http_router_same_params(
+            &[("id", "u32")],
+            &[("id", "u32")]
+        ));
+        assert!(__http_router_same_params(&[], &[]));
+        assert!(!__http_router_same_params(
+            &[("id", "u32")],
+            &[("id", "u64")]
+        ));
+        assert!(!__http_router_same_params(
+            &[("id", "u32")],
+            &[("org", "u32")]
+        ));
+        assert!(!__http_router_same_params(
+            &[("id", "u32")],
+            &[("id", "u32"), ("page", "u32")]
+        ));
+    }
+
+    #[test]
+    fn test_route_predicates() {
+        let get_users = |_: &()| "get_users";
+        let put_users = |_: &(), _: u32| "put_users";
+        let well_known = |_: &(), _: String| "well_known";
+        let variant = |_: &()| 0;
+        let home_a = |_: &()| "home_a";
+        let home_b = |_: &()| "home_b";
+        let json_report = |_: &(), _: u32| "json_report";
+        let xml_report = |_: &(), _: u32| "xml_report";
+        let fallback = |_: &()| "404";
+        let api = router!(
+            table(predicates);
+            GET /users => get_users,
+            PUT /users/{id: u32} => put_users,
+            GET /.well-known/{name: String}/index.html => well_known,
+            GET /home => pick(variant => home_a, home_b),
+            ANY /reports/{id: u32} => ext { "json" => json_report, "xml" => xml_report },
+            _ => fallback,
+        );
+        let is_route = &api.is_route;
+
+        assert!(is_route.put_users(Method::PUT, "/users/5"));
+        assert!(!is_route.put_users(Method::GET, "/users/5"));
+        assert!(!is_route.put_users(Method::PUT, "/users"));
+        assert!(is_route.get_users(Method::GET, "/users"));
+        assert!(!is_route.get_users(Method::GET, "/users/5"));
+        assert!(is_route.well_known(Method::GET, "/.well-known/acme/index.html"));
+        assert!(is_route.json_report(Method::POST, "/reports/3.json"));
+        assert!(!is_route.json_report(Method::POST, "/reports/3.xml"));
+        assert!(is_route.xml_report(Method::POST, "/reports/3.xml"));
+        assert_eq!(api.route_count(), 6);
+        assert_eq!(api((), Method::GET, "/home"), "home_a");
+    }
+
+    #[test]
+    fn test_match_pattern() {
+        let home = |_: &()| ();
+        let get_user = |_: &(), _: u32| ();
+        let well_known = |_: &(), _: String| ();
+        let json_report = |_: &(), _: u32| ();
+        let xml_report = |_: &(), _: u32| ();
+        let fallback = |_: &()| ();
+        let api = router!(
+            table;
+            GET / => home,
+            GET /users/{id: u32} => get_user,
+            POST /.well-known/{name: String}/index.html => well_known,
+            ANY /reports/{id: u32} => ext { "json" => json_report, "xml" => xml_report },
+            _ => fallback,
+        );
+        let pattern = |method, path| {
+            api.match_pattern(method, path)
+                .map(|(index, _)| api.routes[index].pattern)
+        };
+        assert_eq!(pattern(Method::GET, "/"), Some("/"));
+        assert_eq!(pattern(Method::GET, "/users/12"), Some("/users/{id: u32}"));
+        assert_eq!(pattern(Method::POST, "/users/12"), None);
+        assert_eq!(
+            pattern(Method::DELETE, "/reports/3.xml"),
+            Some("/reports/{id: u32}.xml")
+        );
+        assert_eq!(pattern(Method::GET, "/posts"), None);
+
+        let (index, captures) = api
+            .match_pattern(Method::POST, "/.well-known/acme/index.html")
+            .unwrap();
+        assert_eq!(index, 2);
+        assert_eq!(api.routes[index].params, &["name"]);
+        assert_eq!(captures.get(0), Some("acme"));
+        assert_eq!(captures.get(1), None);
+        assert_eq!(captures.len(), 1);
+
+        let static_file = |_: &(), _: String| ();
+        let page = |_: &(), _: String| ();
+        let meta_table = router!(
+            table;
+            GET /files/{path: ..} => static_file,
+            GET /page/{name: String no_ext} => page,
+            _ => fallback,
+        );
+        let (index, captures) = meta_table
+            .match_pattern(Method::GET, "/files/a/b.css")
+            .unwrap();
+        assert_eq!(meta_table.meta[index].pattern, "/files/{path: ..}");
+        assert_eq!(captures.iter().collect::<Vec<_>>(), ["a/b.css"]);
+        let (index, _) = meta_table
+            .match_pattern(Method::GET, "/page/about")
+            .unwrap();
+        assert_eq!(index, 1);
+
+        // only patterns are matched, the router declines both
+        let get_draft = |_: &(), _: u32| "get_draft";
+        let not_found = |_: &()| "404";
+        let guarded_table =
+            router!(table; GET /drafts/{id: u32} => get_draft if |_: &()| false, _ => not_found);
+        let (index, captures) = guarded_table
+            .match_pattern(Method::GET, "/drafts/7")
+            .unwrap();
+        assert_eq!((index, captures.get(0)), (0, Some("7")));
+        assert_eq!(guarded_table((), Method::GET, "/drafts/7"), "404");
+        let (_, captures) = guarded_table
+            .match_pattern(Method::GET, "/drafts/99999999999")
+            .unwrap();
+        assert_eq!(captures.get(0), Some("99999999999"));
+        assert_eq!(guarded_table((), Method::GET, "/drafts/99999999999"), "404");
+    }
+
+    #[test]
+    fn test_match_pattern_index() {
+        // handlers answer with the index of their route
+        let list = |_: &()| Some(0);
+        let get = |_: &(), _: u8| Some(1);
+        let by_name = |_: &(), _: String| Some(2);
+        let json = |_: &(), _: u8| Some(3);
+        let xml = |_: &(), _: u8| Some(4);
+        let admin = |_: &()| Some(5);
+        let fallback = |_: &()| None;
+        let api = router!(
+            table;
+            GET /items => list,
+            GET /items/{id: u8} => get,
+            GET /items/{name: String} => by_name,
+            GET /items/{id: u8}/export => ext { "json" => json, "xml" => xml },
+            GET /admin => admin if |_: &()| false,
+            _ => fallback,
+        );
+        let index = |path| api.match_pattern(Method::GET, path).map(|(index, _)| index);
+        for path in &["/items", "/items/7", "/items/7/export.xml", "/nope"] {
+            assert_eq!(index(path), api((), Method::GET, path), "{}", path);
+        }
+        assert_eq!(api.routes[3].pattern, "/items/{id: u8}/export.json");
+        // patterns only: the router parses the param and checks the condition
+        assert_eq!(index("/items/300"), Some(1));
+        assert_eq!(api((), Method::GET, "/items/300"), Some(2));
+        assert_eq!(index("/items/abc"), Some(1));
+        assert_eq!(api((), Method::GET, "/items/abc"), Some(2));
+        assert_eq!(index("/admin"), Some(5));
+        assert_eq!(api((), Method::GET, "/admin"), None);
+    }
+
+    router_tests! {
+        fn test_all_paths_of_method() {
+            let get_users = |_: &()| "users".to_string();
+            let get_user = |_: &(), id: u32| format!("user({})", id);
+            let all_gets = |_: &()| "all gets".to_string();
+            let not_found = |_: &()| "404".to_string();
+            let router = router!(
+                GET / * => all_gets,
+                GET /users => get_users,
+                GET /users/{id: u32} => get_user,
+                _ => not_found,
+            );
+            assert_eq!(router((), Method::GET, "/users"), "users");
+            assert_eq!(router((), Method::GET, "/users/12"), "user(12)");
+            assert_eq!(router((), Method::GET, "/users/abc"), "all gets");
+            assert_eq!(router((), Method::GET, "/"), "all gets");
+            assert_eq!(router((), Method::GET, "/a/b/c.html"), "all gets");
+            assert_eq!(router((), Method::DELETE, "/users"), "404");
+        }
+    }
+
+    router_tests! {
+        fn test_literal_routes() {
+            let health = |_: &()| "health";
+            let login = |_: &()| "login";
+            let fallback = |_: &()| "404";
+            let router = router!(
+                GET /health => health,
+                POST /api/auth/login => login,
+                _ => fallback,
+            );
+            assert_eq!(router((), Method::GET, "/health"), "health");
+            assert_eq!(router((), Method::GET, "/healthz"), "404");
+            assert_eq!(router((), Method::GET, "/health/"), "404");
+            assert_eq!(router((), Method::POST, "/api/auth/login"), "login");
+            assert_eq!(router((), Method::POST, "/api/auth"), "404");
+
+            let router = router!(
+                normalize(ignore_case = true, trailing_slash = true);
+                POST /api/auth/login => login,
+                _ => fallback,
+            );
+            assert_eq!(router((), Method::POST, "/API/Auth/login/"), "login");
+        }
+    }
+
+    router_tests! {
+        fn test_percent_encoding() {
+            let me = |_: &()| "me".to_string();
+            let user = |_: &(), name: String| format!("user({})", name);
+            let list = |_: &()| "list".to_string();
+            let tags = |_: &()| "tags".to_string();
+            let files = |_: &(), path: String| format!("files({})", path);
+            let fallback = |_: &()| "404".to_string();
+            let router = router!(
+                GET /users/@me => me,
+                GET /users/{name: String} => user,
+                GET /ns/users:list => list,
+                GET /tags/red,green => tags,
+                GET /files/{path: ..} => files,
+                _ => fallback,
+            );
+            assert_eq!(router((), Method::GET, "/users/@me"), "me");
+            assert_eq!(router((), Method::GET, "/users/%40me"), "user(@me)");
+            assert_eq!(router((), Method::GET, "/users/j%C3%B6rg"), "user(jörg)");
+            assert_eq!(router((), Method::GET, "/users/a%FFb"), "404");
+            assert_eq!(router((), Method::GET, "/users/a%zz"), "404");
+            assert_eq!(router((), Method::GET, "/ns/users:list"), "list");
+            assert_eq!(router((), Method::GET, "/ns/users%3Alist"), "404");
+            assert_eq!(router((), Method::GET, "/tags/red,green"), "tags");
+            assert_eq!(router((), Method::GET, "/tags/red%2Cgreen"), "404");
+            assert_eq!(router((), Method::GET, "/files/a%20b/c"), "files(a b/c)");
+
+            // params and catch-alls decode their values like `decode_segment` does
+            for &(segment, decoded) in escape::DECODE_CASES {
+                let expected =
+                    decoded.map_or("404".to_string(), |decoded| format!("user({})", decoded));
+                assert_eq!(
+                    router((), Method::GET, &format!("/users/{}", segment)),
+                    expected
+                );
+                let expected =
+                    decoded.map_or("404".to_string(), |decoded| format!("files({})", decoded));
+                assert_eq!(
+                    router((), Method::GET, &format!("/files/{}", segment)),
+                    expected
+                );
+            }
+
+            // and `encode_segment` escapes values so that a param captures them back
+            for &value in &[
+                "a.b",
+                "user@host",
+                "v=2",
+                "~me",
+                "a b/c",
+                "jörg",
+                "100%",
+                "x;y:z",
+            ] {
+                assert_eq!(
+                    router(
+                        (),
+                        Method::GET,
+                        &format!("/users/{}", escape::encode_segment(value))
+                    ),
+                    format!("user({})", value)
+                );
+            }
+        }
+    }
+
+    router_tests! {
+        fn test_fallback_only() {
+            let fallback_only = |_: &()| "fallback_only";
+            let router = router!(_ => fallback_only);
+            assert_eq!(router((), Method::GET, "/"), "fallback_only");
+            assert_eq!(router((), Method::POST, "/users"), "fallback_only");
+
+            let router = router!(_ => fallback_only,);
+            assert_eq!(router((), Method::GET, "/"), "fallback_only");
+        }
+    }
+
+    router_tests! {
+        fn test_single_route() {
+            let home = |_: &()| "home";
+            let health = |_: &()| "health";
+            let not_found = |_: &()| "not_found";
+            let router = router!(GET / => home, _ => not_found);
+            assert_eq!(router((), Method::GET, "/"), "home");
+            assert_eq!(router((), Method::GET, "/health"), "not_found");
+
+            let router = router!(GET /health => health, _ => not_found);
+            assert_eq!(router((), Method::GET, "/health"), "health");
+            assert_eq!(router((), Method::GET, "/"), "not_found");
+            assert_eq!(router((), Method::POST, "/health"), "not_found");
+        }
+    }
+
+    router_tests! {
+        fn test_fallback() {
+            let home = |_: &()| "home";
+            let users = |_: &()| "users";
+            let fallback = |_: &()| "fallback";
+            let router = router!(
+                GET / => home,
+                POST /users => users,
+                _ => fallback
+            );
+            assert_eq!(router((), Method::GET, "/"), "home");
+            assert_eq!(router((), Method::POST, "/users"), "users");
+            assert_eq!(router((), Method::GET, "/users"), "fallback");
+            assert_eq!(router((), Method::GET, "/us"), "fallback");
+            assert_eq!(router((), Method::PATCH, "/"), "fallback");
+        }
+    }
+
+    router_tests! {
+        fn test_verbs() {
+            let get_test = |_: &()| Method::GET;
+            let post_test = |_: &()| Method::POST;
+            let put_test = |_: &()| Method::PUT;
+            let patch_test = |_: &()| Method::PATCH;
+            let delete_test = |_: &()| Method::DELETE;
+            let connect_test = |_: &()| Method::CONNECT;
+            let options_test = |_: &()| Method::OPTIONS;
+            let trace_test = |_: &()| Method::TRACE;
+            let head_test = |_: &()| Method::HEAD;
+            let lock_test = |_: &()| Method::LOCK;
+            let unlock_test = |_: &()| Method::UNLOCK;
+            let panic_test = |_: &()| unreachable!();
+            let router = router!(
+                GET /users => get_test,
+                POST /users => post_test,
+                PUT /users => put_test,
+                PATCH /users => patch_test,
+                DELETE /users => delete_test,
+                OPTIONS /users => options_test,
+                CONNECT /users => connect_test,
+                TRACE /users => trace_test,
+                HEAD /users => head_test,
+                LOCK /users => lock_test,
+                UNLOCK /users => unlock_test,
+                _ => panic_test
+            );
+
+            assert_eq!(router((), Method::GET, "/users"), Method::GET);
+            assert_eq!(router((), Method::POST, "/users"), Method::POST);
+            assert_eq!(router((), Method::PUT, "/users"), Method::PUT);
+            assert_eq!(router((), Method::PATCH, "/users"), Method::PATCH);
+            assert_eq!(router((), Method::DELETE, "/users"), Method::DELETE);
+            assert_eq!(router((), Method::OPTIONS, "/users"), Method::OPTIONS);
+            assert_eq!(router((), Method::TRACE, "/users"), Method::TRACE);
+            assert_eq!(router((), Method::CONNECT, "/users"), Method::CONNECT);
+            assert_eq!(router((), Method::HEAD, "/users"), Method::HEAD);
+            assert_eq!(router((), Method::LOCK, "/users"), Method::LOCK);
+            assert_eq!(router((), Method::UNLOCK, "/users"), Method::UNLOCK);
+            assert_eq!("UNLOCK".parse(), Ok(Method::UNLOCK));
+        }
+    }
+
+    router_tests! {
+        fn test_any_method() {
+            let any = |_: &()| "any".to_string();
+            let any_user = |_: &(), id: u32| format!("any({})", id);
+            let get_users = |_: &()| "get_users".to_string();
+            let get_user = |_: &(), id: u32| format!("get_user({})", id);
+            let fallback = |_: &()| "404".to_string();
+            let router = router!(
+                ANY /users => any,
+                GET /users => get_users,
+                _ => fallback,
+            );
+            assert_eq!(router((), Method::GET, "/users"), "any");
+            assert_eq!(router((), Method::DELETE, "/users"), "any");
+
+            let router = router!(
+                specificity_order;
+                ANY /users => any,
+                ANY /users/{id: u32} => any_user,
+                GET /users => get_users,
+                GET /users/{id: u32} => get_user,
+                _ => fallback,
+            );
+            assert_eq!(router((), Method::GET, "/users"), "get_users");
+            assert_eq!(router((), Method::GET, "/users/12"), "get_user(12)");
+            assert_eq!(router((), Method::POST, "/users"), "any");
+            assert_eq!(router((), Method::PATCH, "/users/12"), "any(12)");
+            assert_eq!(router((), Method::GET, "/posts"), "404");
+        }
+    }
+
+    #[test]
+    fn test_numeric_before_slug() {
+        let by_id = |_: &(), id: u32| format!("by_id({})", id);
+        let by_slug = |_: &(), slug: String| format!("by_slug({})", slug);
+        let fallback = |_: &()| "404".to_string();
+
+        // in declaration order, the slug route takes numbers too
+        let router = router!(
+            GET /posts/{slug: String} => by_slug,
+            GET /posts/{id: u32} => by_id,
+            _ => fallback,
+        );
+        assert_eq!(router((), Method::GET, "/posts/123"), "by_slug(123)");
+
+        let slug_first = router!(
+            specificity_order;
+            GET /posts/{slug: String} => by_slug,
+            GET /posts/{id: u32} => by_id,
+            _ => fallback,
+        );
+        let id_first = router!(
+            specificity_order;
+            GET /posts/{id: u32} => by_id,
+            GET /posts/{slug: String} => by_slug,
+            _ => fallback,
+        );
+        let compact = router!(
+            compact;
+            specificity_order;
+            GET /posts/{slug: String} => by_slug,
+            GET /posts/{id: u32} => by_id,
+            _ => fallback,
+        );
+        fn check(router: &dyn Fn((), Method, &str) -> String) {
+            assert_eq!(router((), Method::GET, "/posts/123"), "by_id(123)");
+            assert_eq!(router((), Method::GET, "/posts/123abc"), "by_slug(123abc)");
+            assert_eq!(
+                router((), Method::GET, "/posts/how-to-rust"),
+                "by_slug(how-to-rust)"
+            );
+        }
+        check(&slug_first);
+        check(&id_first);
+        check(&compact);
+    }
+
+    router_tests! {
+        fn test_static_routes() {
+            let health = |_: &()| "health";
+            let login = |_: &()| "login";
+            let fallback = |_: &()| "404";
+            let router = router!(
+                GET /health => health,
+                POST /api/auth/login => login,
+                _ => fallback,
+            );
+            assert_eq!(router((), Method::GET, "/health"), "health");
+            assert_eq!(router((), Method::POST, "/api/auth/login"), "login");
+            assert_eq!(router((), Method::POST, "/api/auth"), "404");
+            assert_eq!(router((), Method::POST, "/api/auth/login/"), "404");
+            assert_eq!(router((), Method::POST, "/api/auth/login/me"), "404");
+            assert_eq!(router((), Method::POST, "//api/auth/login"), "404");
+            assert_eq!(router((), Method::POST, "api/auth/login"), "404");
+            assert_eq!(router((), Method::GET, "/healthz"), "404");
+        }
+    }
+
+    router_tests! {
+        fn test_fallthrough() {
+            let page = |_: &(), slug: String| {
+                if slug == "about" {
+                    Some(format!("page({})", slug))
+                } else {
+                    None
+                }
+            };
+            let file = |_: &(), name: String| {
+                if name.starts_with("static-") {
+                    Some(format!("file({})", name))
+                } else {
+                    None
+                }
+            };
+            let fallback = |_: &()| "404".to_string();
+            let router = router!(
+                GET /{slug: String} => page?,
+                GET /{name: String} => file?,
+                _ => fallback,
+            );
+            assert_eq!(router((), Method::GET, "/about"), "page(about)");
+            assert_eq!(router((), Method::GET, "/static-logo"), "file(static-logo)");
+            assert_eq!(router((), Method::GET, "/contacts"), "404");
+        }
+    }
+
+    #[test]
+    fn test_scope_group() {
+        let home = |_: &()| "home".to_string();
+        let get_users = |_: &()| "get_users".to_string();
+        let post_users = |_: &()| "post_users".to_string();
+        let user = |_: &(), id: u32| format!("user({})", id);
+        let about = |_: &()| "about".to_string();
+        let fallback = |_: &()| "404".to_string();
+        let router = router!(
+            GET / => home,
+            scope {
+                GET /users => get_users,
+                POST /users => post_users,
+                scope { GET /users/{id: u32} => user }
+            },
+            scope {},
+            GET /about => about,
+            _ => fallback,
+        );
+        assert_eq!(router((), Method::GET, "/"), "home");
+        assert_eq!(router((), Method::GET, "/users"), "get_users");
+        assert_eq!(router((), Method::POST, "/users"), "post_users");
+        assert_eq!(router((), Method::GET, "/users/12"), "user(12)");
+        // routes after the group are still tried
+        assert_eq!(router((), Method::GET, "/about"), "about");
+        assert_eq!(router((), Method::GET, "//users"), "404");
+        assert_eq!(router((), Method::DELETE, "/users"), "404");
+    }
+
+    struct Flags<'a> {
+        beta_reports: bool,
+        checks: &'a Cell<u32>,
+    }
+
+    router_tests! {
+        fn test_conditions() {
+            let beta_reports = |_: &Flags| "beta_reports".to_string();
+            let user = |_: &Flags, id: u32| format!("user({})", id);
+            let fallback = |_: &Flags| "404".to_string();
+            // response and number of times the condition of `/users/{id}` ran
+            let router = |beta_reports_enabled: bool, method: Method, path: &str| {
+                let checks = Cell::new(0);
+                let flags = Flags {
+                    beta_reports: beta_reports_enabled,
+                    checks: &checks,
+                };
+                let router = router!(
+                    GET /beta/reports => beta_reports if |ctx| ctx.beta_reports,
+                    GET /users/{id: u32} => user if |ctx| {
+                        ctx.checks.set(ctx.checks.get() + 1);
+                        id < 100
+                    },
+                    _ => fallback,
+                );
+                let response = router(flags, method, path);
+                (response, checks.get())
+            };
+            assert_eq!(
+                router(true, Method::GET, "/beta/reports"),
+                ("beta_reports".to_string(), 0)
+            );
+            assert_eq!(
+                router(false, Method::GET, "/beta/reports"),
+                ("404".to_string(), 0)
+            );
+            assert_eq!(
+                router(false, Method::GET, "/users/12"),
+                ("user(12)".to_string(), 1)
+            );
+            assert_eq!(
+                router(false, Method::GET, "/users/120"),
+                ("404".to_string(), 1)
+            );
+            // not run when the path doesn't match
+            assert_eq!(
+                router(false, Method::GET, "/users/x"),
+                ("404".to_string(), 0)
+            );
+        }
+    }
+
+    router_tests! {
+        fn test_guards() {
+            #[derive(Clone, Copy)]
+            struct Ctx {
+                token: Option<&'static str>,
+                beta: bool,
+            }
+            let auth_check = |ctx: &Ctx| match ctx.token {
+                Some(_) => Ok(()),
+                None => Err("401".to_string()),
+            };
+            let admin = |ctx: &Ctx, path: String| format!("admin({}, {})", ctx.token.unwrap(), path);
+            let beta = |_: &Ctx| "beta".to_string();
+            let public = |_: &Ctx, path: String| format!("public({})", path);
+            let fallback = |_: &Ctx| unreachable!();
+            let router = router!(
+                GET /admin/beta => beta guard auth_check if |ctx| ctx.beta,
+                GET /admin/{path: ..} => admin guard auth_check,
+                GET /{path: ..} => public,
+                _ => fallback,
+            );
+            let anonymous = Ctx {
+                token: None,
+                beta: false,
+            };
+            let user = Ctx {
+                token: Some("alice"),
+                beta: true,
+            };
+            assert_eq!(router(anonymous, Method::GET, "/admin/users"), "401");
+            assert_eq!(router(anonymous, Method::GET, "/about"), "public(about)");
+            assert_eq!(
+                router(user, Method::GET, "/admin/users"),
+                "admin(alice, users)"
+            );
+            assert_eq!(router(user, Method::GET, "/admin/beta"), "beta");
+            // the condition fails first, so the route falls through instead of answering 401
+            assert_eq!(router(anonymous, Method::GET, "/admin/beta"), "401");
+        }
+    }
+
+    router_tests! {
+        fn test_when_predicates() {
+            struct Request {
+                content_length: u64,
+                content_type: &'static str,
+            }
+            let json = "application/json".to_string();
+            let has_body = |req: &Request| req.content_length > 0;
+            let is_json = move |req: &Request| req.content_type == json;
+            let create_order = |_: &Request| "create_order".to_string();
+            let upload = |_: &Request, id: u32| format!("upload({})", id);
+            let unsupported = |_: &Request| "415".to_string();
+            let fallback = |_: &Request| "404".to_string();
+            let router = router!(
+                POST /orders => create_order when has_body when is_json,
+                POST /orders/{id: u32} => upload when has_body if |_| id > 0,
+                POST /orders => unsupported,
+                _ => fallback,
+            );
+            let request = |content_length, content_type| Request {
+                content_length,
+                content_type,
+            };
+            let post = |request, path| router(request, Method::POST, path);
+            assert_eq!(
+                post(request(12, "application/json"), "/orders"),
+                "create_order"
+            );
+            // one of the two predicates fails, so the route falls through
+            assert_eq!(post(request(0, "application/json"), "/orders"), "415");
+            assert_eq!(post(request(12, "text/plain"), "/orders"), "415");
+            assert_eq!(post(request(12, "text/plain"), "/orders/3"), "upload(3)");
+            assert_eq!(post(request(12, "text/plain"), "/orders/0"), "404");
+            assert_eq!(post(request(0, "text/plain"), "/orders/3"), "404");
+        }
+    }
+
+    router_tests! {
+        fn test_condition_order() {
+            let checks = Cell::new(0);
+            let user = |_: &&Cell<u32>, id: u32| id;
+            let fallback = |_: &&Cell<u32>| 0;
+            let router = router!(
+                GET /users/{id: u32} => user if |checks| {
+                    checks.set(checks.get() + 1);
+                    id > 1
+                },
+                _ => fallback,
+            );
+            // pattern doesn't match
+            assert_eq!(router(&checks, Method::GET, "/posts/12"), 0);
+            assert_eq!(checks.get(), 0);
+            // param doesn't parse
+            assert_eq!(router(&checks, Method::GET, "/users/abc"), 0);
+            assert_eq!(checks.get(), 0);
+            // condition sees the parsed param
+            assert_eq!(router(&checks, Method::GET, "/users/1"), 0);
+            assert_eq!(checks.get(), 1);
+            assert_eq!(router(&checks, Method::GET, "/users/12"), 12);
+            assert_eq!(checks.get(), 2);
+        }
+    }
+
+    router_tests! {
+        fn test_normalize() {
+            let users = |_: &(), id: u32| format!("users({})", id);
+            let token = |_: &(), token: String| format!("token({})", token);
+            let fallback = |_: &()| "404".to_string();
+            let router = router!(
+                normalize(ignore_case = true, trailing_slash = true, merge_slashes = true);
+                GET /Users/{id: u32} => users,
+                GET /Token/{token: String} [normalize(ignore_case = false)] => token,
+                _ => fallback,
+            );
+            assert_eq!(router((), Method::GET, "/users/12"), "users(12)");
+            assert_eq!(router((), Method::GET, "/USERS//12/"), "users(12)");
+            assert_eq!(router((), Method::GET, "/Token/AbC/"), "token(AbC)");
+            assert_eq!(router((), Method::GET, "/token/AbC"), "404");
+            assert_eq!(router((), Method::GET, "/TOKEN/AbC"), "404");
+        }
+    }
+
+    #[test]
+    fn test_route_merge_slashes() {
+        let lenient = |_: &()| "lenient".to_string();
+        let strict = |_: &()| "strict".to_string();
+        let fallback = |_: &()| "404".to_string();
+        let router = router!(
+            GET /a/b [normalize(merge_slashes = true)] => lenient,
+            GET /c/d => strict,
+            _ => fallback,
+        );
+        assert_eq!(router((), Method::GET, "/a//b"), "lenient");
+        assert_eq!(router((), Method::GET, "/c//d"), "404");
+        assert_eq!(router((), Method::GET, "/c/d"), "strict");
+
+        let router = router!(
+            compact;
+            normalize(merge_slashes = true);
+            GET /a/b => lenient,
+            GET /c/d [normalize(merge_slashes = false)] => strict,
+            _ => fallback,
+        );
+        assert_eq!(router((), Method::GET, "/a//b"), "lenient");
+        assert_eq!(router((), Method::GET, "/c//d"), "404");
+        assert_eq!(router((), Method::GET, "//c/d"), "404");
+    }
+
+    router_tests! {
+        fn test_signed_params() {
+            let small = |_: &(), n: i8| format!("i8({})", n);
+            let medium = |_: &(), n: i16| format!("i16({})", n);
+            let other = |_: &(), value: String| format!("other({})", value);
+            let fallback = |_: &()| "404".to_string();
+            let router = router!(
+                GET /n/{n: i8} => small,
+                GET /n/{n: i16} => medium,
+                GET /n/{value: String} => other,
+                _ => fallback,
+            );
+            let get = |path| router((), Method::GET, path);
+            assert_eq!(get("/n/-1"), "i8(-1)");
+            assert_eq!(get("/n/-128"), "i8(-128)");
+            // overflows i8, falls through to i16
+            assert_eq!(get("/n/-129"), "i16(-129)");
+            assert_eq!(get("/n/-32769"), "other(-32769)");
+            assert_eq!(get("/n/--1"), "other(--1)");
+            assert_eq!(get("/n/1a"), "other(1a)");
+            assert_eq!(get("/n/-"), "other(-)");
+            // `+` isn't matched raw, but escaped it is and parses like `i8::from_str`
+            assert_eq!(get("/n/+1"), "404");
+            assert_eq!(get("/n/%2B1"), "i8(1)");
+        }
+    }
+
+    router_tests! {
+        fn test_registered_param_parser() {
+            use std::ops::{Range, RangeInclusive};
+
+            // types of another crate without `FromStr`, parsed from e.g. `3-7`
+            type Pages = Range<u32>;
+            type Span = RangeInclusive<u32>;
+
+            let get_pages = |_: &(), pages: Pages| format!("pages({:?})", pages);
+            let get_span = |_: &(), span: Span| format!("span({:?})", span);
+            let get_page = |_: &(), page: u32| format!("page({})", page);
+            let fallback = |_: &()| "404".to_string();
+            let router = router!(
+                GET /pages/{pages: Pages registered} => get_pages,
+                GET /spans/{span: Span registered} => get_span,
+                GET /page/{page: u32} => get_page,
+                _ => fallback,
+            );
+            // nothing registered for `Span`, so its route never matches
+            assert_eq!(router((), Method::GET, "/spans/3-7"), "404");
+
+            register_param_parser(|value: &str| {
+                let (start, end) = value.split_once('-')?;
+                Some(start.parse::<u32>().ok()?..end.parse().ok()?)
+            });
+            assert_eq!(router((), Method::GET, "/pages/3-7"), "pages(3..7)");
+            assert_eq!(router((), Method::GET, "/pages/3%2D7"), "pages(3..7)");
+            assert_eq!(router((), Method::GET, "/pages/3_7"), "404");
+            assert_eq!(router((), Method::GET, "/page/3"), "page(3)");
+        }
+    }
+
+    #[test]
+    fn test_lowercase_segments() {
+        let exports = |_: &()| "exports".to_string();
+        let export = |_: &(), name: String| format!("export({})", name);
+        let report = |_: &(), name: String| format!("report({})", name);
+        let fallback = |_: &()| "404".to_string();
+        let router = router!(
+            normalize(lowercase_segments = &["exports", "csv", "json"]);
+            GET /exports/csv => exports,
+            GET /exports/csv/{name: String} => export,
+            GET /reports/{name: String} => report,
+            _ => fallback,
+        );
+        assert_eq!(router((), Method::GET, "/exports/csv"), "exports");
+        assert_eq!(router((), Method::GET, "/Exports/CSV"), "exports");
+        assert_eq!(
+            router((), Method::GET, "/EXPORTS/Csv/MyReport"),
+            "export(MyReport)"
+        );
+        assert_eq!(router((), Method::GET, "/Reports/MyReport"), "404");
+        assert_eq!(
+            router((), Method::GET, "/reports/MyReport"),
+            "report(MyReport)"
+        );
+        // params keep their case, even when their value is a listed segment
+        assert_eq!(router((), Method::GET, "/reports/CSV"), "report(CSV)");
+        assert_eq!(router((), Method::GET, "/exports/Csv/JSON"), "export(JSON)");
+
+        let compact = router!(
+            compact;
+            normalize(lowercase_segments = &["exports", "csv", "json"]);
+            GET /exports/csv => exports,
+            GET /exports/csv/{name: String} => export,
+            GET /reports/{name: String} => report,
+            _ => fallback,
+        );
+        assert_eq!(compact((), Method::GET, "/Exports/CSV"), "exports");
+        assert_eq!(
+            compact((), Method::GET, "/EXPORTS/csv/Json"),
+            "export(Json)"
+        );
+        assert_eq!(compact((), Method::GET, "/reports/CSV"), "report(CSV)");
+        assert_eq!(compact((), Method::GET, "/Reports/x"), "404");
+    }
+
+    #[test]
+    fn test_strip_matrix_params() {
+        let user = |_: &(), id: u32| format!("user({})", id);
+        let file = |_: &(), path: String| format!("file({})", path);
+        let named = |_: &(), name: String| format!("named({})", name);
+        let fallback = |_: &()| "404".to_string();
+        let router = router!(
+            normalize(strip_matrix_params = MatrixParams::LastSegment);
+            GET /users/{id: u32} => user,
+            GET /names/{name: String} => named,
+            GET /files/{path: ..} [normalize(strip_matrix_params = MatrixParams::Keep)] => file,
+            _ => fallback,
+        );
+        let get = |path| router((), Method::GET, path);
+        assert_eq!(get("/users/42;jsessionid=ABC123"), "user(42)");
+        assert_eq!(get("/users/42"), "user(42)");
+        // an escaped `;` is part of the param, and decoded for the handler
+        assert_eq!(get("/names/a%3Bb;jsessionid=ABC123"), "named(a;b)");
+        // kept with the option off
+        assert_eq!(get("/files/a;b/c;d"), "file(a;b/c;d)");
+
+        let router = router!(
+            compact;
+            normalize(strip_matrix_params = MatrixParams::AllSegments);
+            GET /users/{id: u32}/posts => user,
+            _ => fallback,
+        );
+        assert_eq!(
+            router((), Method::GET, "/users;v=2/42;x=1/posts;jsessionid=A"),
+            "user(42)"
+        );
+        assert_eq!(router((), Method::GET, "/users/42%3B/posts"), "404");
+    }
+
+    struct Greeting<'a> {
+        name: &'a str,
+        id: u32,
+    }
+
+    impl<'a> Future for Greeting<'a> {
+        type Output = String;
+
+        fn poll(self: Pin<&mut Self>, _: &mut TaskContext) -> Poll<String> {
+            Poll::Ready(format!("{}({})", self.name, self.id))
+        }
+    }
+
+    // `Waker::noop` needs a newer Rust than the crate
+    struct NoopWaker;
+
+    impl Wake for NoopWaker {
+        fn wake(self: Arc<Self>) {}
+    }
+
+    fn block_on<F: Future>(future: F) -> F::Output {
+        let mut future = Box::pin(future);
+        let waker = Waker::from(Arc::new(NoopWaker));
+        match future.as_mut().poll(&mut TaskContext::from_waker(&waker)) {
+            Poll::Ready(value) => value,
+            Poll::Pending => panic!("test futures are always ready"),
+        }
+    }
+
+    /// `block_on` for futures yielding before they are ready, like `Lookup`
+    fn block_on_yielding<F: Future>(future: F) -> F::Output {
+        let mut future = Box::pin(future);
+        let waker = Waker::from(Arc::new(NoopWaker));
+        // they are ready after a few polls, so there is no need to wait for wakeups
+        loop {
+            let poll = future.as_mut().poll(&mut TaskContext::from_waker(&waker));
+            if let Poll::Ready(value) = poll {
+                return value;
+            }
+        }
+    }
+
+    /// Reads the name from the context only after yielding once, like an `async fn`
+    /// using its context after an `.await`
+    struct Lookup {
+        context: Arc<String>,
+        id: u32,
+        yielded: bool,
+    }
+
+    impl Future for Lookup {
+        type Output = String;
+
+        fn poll(mut self: Pin<&mut Self>, _: &mut TaskContext) -> Poll<String> {
+            if !self.yielded {
+                self.yielded = true;
+                return Poll::Pending;
+            }
+            Poll::Ready(format!("{}({})", self.context, self.id))
+        }
+    }
+
+    router_tests! {
+        fn test_async() {
+            fn get_user(name: &String, id: u32) -> Greeting<'_> {
+                Greeting { name, id }
+            }
+            fn get_users(_: &String) -> Ready<String> {
+                future::ready("users".to_string())
+            }
+            fn not_found(_: &String) -> Ready<String> {
+                future::ready("404".to_string())
+            }
+            let name = "user".to_string();
+            let router = router!(
+                async;
+                GET /users => get_users,
+                GET /users/{id: u32} => get_user,
+                _ => not_found,
+            );
+            assert_eq!(block_on(router(&name, Method::GET, "/users")), "users");
+            assert_eq!(
+                block_on(router(&name, Method::GET, "/users/12")),
+                "user(12)"
+            );
+            assert_eq!(block_on(router(&name, Method::GET, "/posts")), "404");
+        }
+    }
+
+    router_tests! {
+        fn test_async_guard() {
+            fn get_user(name: &String, id: u32) -> Greeting<'_> {
+                Greeting { name, id }
+            }
+            fn not_found(_: &String) -> Ready<String> {
+                future::ready("404".to_string())
+            }
+            fn members_only(name: &str) -> Result<(), String> {
+                if name.is_empty() {
+                    Err("401".to_string())
+                } else {
+                    Ok(())
+                }
+            }
+            let router = router!(
+                async;
+                GET /users/{id: u32} => get_user guard members_only,
+                _ => not_found,
+            );
+            let name = "user".to_string();
+            assert_eq!(
+                block_on(router(&name, Method::GET, "/users/12")),
+                "user(12)"
+            );
+            // the guard's response is a ready future, like the handler values
+            let anonymous = String::new();
+            assert_eq!(
+                block_on(router(&anonymous, Method::GET, "/users/12")),
+                "401"
+            );
+            assert_eq!(block_on(router(&anonymous, Method::GET, "/posts")), "404");
+        }
+    }
+
+    router_tests! {
+        fn test_async_move() {
+            fn get_user(context: Arc<String>, id: u32) -> Lookup {
+                Lookup {
+                    context,
+                    id,
+                    yielded: false,
+                }
+            }
+            fn not_found(_: Arc<String>) -> Ready<String> {
+                future::ready("404".to_string())
+            }
+            let router = router!(
+                async move;
+                GET /users/{id: u32} => get_user if |ctx| !ctx.is_empty(),
+                _ => not_found,
+            );
+            let context = Arc::new("user".to_string());
+            let user = router(context.clone(), Method::GET, "/users/12");
+            let missing = router(context.clone(), Method::GET, "/posts");
+            // the futures own their clones of the context
+            drop(context);
+            assert_eq!(
+                thread::spawn(move || block_on_yielding(user)).join().unwrap(),
+                "user(12)"
+            );
+            assert_eq!(block_on(missing), "404");
+
+            let router = router!(async move; _ => not_found);
+            assert_eq!(
+                block_on(router(Arc::new(String::new()), Method::GET, "/")),
+                "404"
+            );
+        }
+    }
+
+    router_tests! {
+        fn test_async_router() {
+            fn get_users(_: &String) -> String {
+                "users".to_string()
+            }
+            fn get_user(context: &str, id: u32) -> Lookup {
+                Lookup {
+                    context: Arc::new(context.to_string()),
+                    id,
+                    yielded: false,
+                }
+            }
+            fn get_post(name: &String, id: u32) -> Greeting<'_> {
+                Greeting { name, id }
+            }
+            fn not_found(_: &String) -> String {
+                "404".to_string()
+            }
+            let name = "user".to_string();
+            let router = async_router!(
+                GET /users => get_users,
+                GET /users/{id: u32} => get_user,
+                GET /posts/{id: u32} => get_post,
+                _ => not_found,
+            );
+            assert_eq!(block_on(router(&name, Method::GET, "/users")), "users");
+            assert_eq!(
+                block_on_yielding(router(&name, Method::GET, "/users/12")),
+                "user(12)"
+            );
+            assert_eq!(block_on(router(&name, Method::GET, "/posts/3")), "user(3)");
+            assert_eq!(block_on(router(&name, Method::GET, "/posts")), "404");
+
+            fn not_found_async(_: &String) -> Ready<String> {
+                future::ready("404".to_string())
+            }
+            let router = async_router!(_ => not_found_async);
+            assert_eq!(block_on(router(&name, Method::GET, "/")), "404");
+        }
+    }
+
+    router_tests! {
+        fn test_matched() {
+            let users = |_: &(), id: u32| format!("users({})", id);
+            let fallback = |_: &()| "404".to_string();
+            let router = router!(
+                matched;
+                GET /users/{id: u32} => users,
+                _ => fallback,
+            );
+            assert_eq!(
+                router((), Method::GET, "/users/12"),
+                (true, "users(12)".to_string())
+            );
+            assert_eq!(
+                router((), Method::GET, "/users/abc"),
+                (false, "404".to_string())
+            );
+            assert_eq!(
+                router((), Method::POST, "/users/12"),
+                (false, "404".to_string())
+            );
+
+            let router = router!(matched; _ => fallback);
+            assert_eq!(router((), Method::GET, "/"), (false, "404".to_string()));
+        }
+    }
+
+    #[test]
+    fn test_strip_prefix_route() {
+        assert_eq!(strip_prefix_route("/api/users", "/api"), Some("/users"));
+        assert_eq!(strip_prefix_route("/other", "/api"), None);
+        assert_eq!(strip_prefix_route("/apiv2/users", "/api"), None);
+        assert_eq!(strip_prefix_route("/api", "/api"), Some("/"));
+        assert_eq!(strip_prefix_route("/api/", "/api/"), Some("/"));
+        assert_eq!(
+            strip_prefix_route("/api/v1/users/7", "/api/v1"),
+            Some("/users/7")
+        );
+        assert_eq!(strip_prefix_route("/users", "/"), Some("/users"));
+    }
+
+    router_tests! {
+        fn test_scope() {
+            struct AppContext {
+                user: &'static str,
+            }
+            struct AdminContext {
+                admin: &'static str,
+            }
+
+            fn to_admin_ctx(context: &AppContext) -> Option<AdminContext> {
+                if context.user.starts_with("admin") {
+                    Some(AdminContext {
+                        admin: context.user,
+                    })
+                } else {
+                    None
+                }
+            }
+
+            let list_users = |context: &AdminContext| format!("list_users({})", context.admin);
+            let get_user =
+                |context: &AdminContext, id: u32| format!("get_user({}, {})", context.admin, id);
+            let admin_fallback = |context: &AdminContext| format!("admin_404({})", context.admin);
+            let admin_router = router!(
+                GET / => list_users,
+                GET /users/{id: u32} => get_user,
+                _ => admin_fallback,
+            );
+
+            let home = |_: &AppContext| "home".to_string();
+            let fallback = |context: &AppContext| format!("404({})", context.user);
+            let router = router!(
+                GET / => home,
+                scope /admin (map_ctx = to_admin_ctx) => admin_router,
+                _ => fallback,
+            );
+            let admin = || AppContext { user: "admin1" };
+            let guest = || AppContext { user: "guest" };
+            assert_eq!(router(admin(), Method::GET, "/admin"), "list_users(admin1)");
+            assert_eq!(
+                router(admin(), Method::GET, "/admin/users/7"),
+                "get_user(admin1, 7)"
+            );
+            assert_eq!(
+                router(admin(), Method::GET, "/admin/unknown"),
+                "admin_404(admin1)"
+            );
+            assert_eq!(
+                router(admin(), Method::GET, "/administrator"),
+                "404(admin1)"
+            );
+            assert_eq!(router(guest(), Method::GET, "/admin/users/7"), "404(guest)");
+            assert_eq!(router(guest(), Method::GET, "/"), "home");
+        }
+    }
+
+    router_tests! {
+        fn test_scope_root() {
+            let some = |_: &()| Some(());
+            let admin_home = |_: &()| "admin_home".to_string();
+            let admin_users = |_: &()| "admin_users".to_string();
+            let admin_fallback = |_: &()| "admin_404".to_string();
+            let fallback = |_: &()| "404".to_string();
+
+            // the scope root is the home route of the inner router, with or without the slash
+            let with_home =
+                router!(GET / => admin_home, GET /users => admin_users, _ => admin_fallback);
+            let router = router!(scope /admin (map_ctx = some) => with_home, _ => fallback);
+            assert_eq!(router((), Method::GET, "/admin"), "admin_home");
+            assert_eq!(router((), Method::GET, "/admin/"), "admin_home");
+            assert_eq!(router((), Method::GET, "/adminx"), "404");
+            assert_eq!(router((), Method::POST, "/admin"), "admin_404");
+
+            // without one, the root is still inside the scope and reaches its fallback
+            let without_home = router!(GET /users => admin_users, _ => admin_fallback);
+            let router = router!(scope /admin (map_ctx = some) => without_home, _ => fallback);
+            assert_eq!(router((), Method::GET, "/admin"), "admin_404");
+            assert_eq!(router((), Method::GET, "/admin/"), "admin_404");
+            assert_eq!(router((), Method::GET, "/adminx"), "404");
+            assert_eq!(router((), Method::GET, "/admin/users"), "admin_users");
+
+            assert_eq!(strip_prefix_route("/admin", "/admin"), Some("/"));
+            assert_eq!(strip_prefix_route("/admin/", "/admin"), Some("/"));
+            assert_eq!(strip_prefix_route("/adminx", "/admin"), None);
+        }
+    }
+
+    router_tests! {
+        fn test_required_query() {
+            let search = |_: &(), query: &str| {
+                Query::new(query)
+                    .required::<String>("q")
+                    .map(|q| format!("search {}", q))
+            };
+            let fallback = |_: &(), _: &str| Ok("404".to_string());
+
+            let router = router!(request; GET /search => search, _ => fallback);
+            assert_eq!(
+                router((), "q=rust", Method::GET, "/search"),
+                Ok("search rust".to_string())
+            );
+            assert_eq!(
+                router((), "", Method::GET, "/search"),
+                Err(QueryError::BadParam {
+                    name: "q".to_string()
+                })
+            );
+            assert_eq!(
+                router((), "", Method::GET, "/searches"),
+                Ok("404".to_string())
+            );
+        }
+    }
+
+    router_tests! {
+        fn test_extensions() {
+            #[derive(Debug)]
+            struct User(String);
+
+            let profile = |_: &(), extensions: &Extensions| match extensions.get::<User>() {
+                Some(user) => format!("profile({})", user.0),
+                None => "401".to_string(),
+            };
+            let post = |_: &(), extensions: &Extensions, id: u32| {
+                format!(
+                    "post({}, {:?})",
+                    id,
+                    extensions.get::<User>().map(|user| &user.0)
+                )
+            };
+            let fallback = |_: &(), _: &Extensions| "404".to_string();
+            let router = router!(
+                extensions;
+                GET /profile => profile,
+                GET /posts/{id: u32} => post,
+                _ => fallback,
+            );
+            let middleware = |token: Option<&str>, method: Method, path: &str| {
+                let mut extensions = Extensions::new();
+                if let Some(token) = token {
+                    extensions.insert(User(token.to_string()));
+                }
+                router((), &extensions, method, path)
+            };
+            assert_eq!(
+                middleware(Some("alice"), Method::GET, "/profile"),
+                "profile(alice)"
+            );
+            assert_eq!(middleware(None, Method::GET, "/profile"), "401");
+            assert_eq!(
+                middleware(Some("bob"), Method::GET, "/posts/12"),
+                "post(12, Some(\"bob\"))"
+            );
+            assert_eq!(middleware(Some("bob"), Method::GET, "/posts"), "404");
+
+            let router = router!(matched; extensions; _ => fallback);
+            assert_eq!(
+                router((), &Extensions::new(), Method::GET, "/"),
+                (false, "404".to_string())
+            );
+        }
+    }
+
+    router_tests! {
+        fn test_hyphenated_segments() {
+            let acme = |_: &(), token: String| format!("acme({})", token);
+            let security = |_: &()| "security".to_string();
+            let user_posts = |_: &(), id: u32, slug: String| format!("posts({}, {})", id, slug);
+            let fallback = |_: &()| "404".to_string();
+            let router = router!(
+                GET /well-known/acme-challenge/{token: String} => acme,
+                GET /well-known/security-txt => security,
+                GET /users/{id: u32}/blog-posts/{slug: String} [normalize(ignore_case = true)] => user_posts,
+                _ => fallback,
+            );
+            assert_eq!(
+                router((), Method::GET, "/well-known/acme-challenge/abc-123"),
+                "acme(abc-123)"
+            );
+            assert_eq!(
+                router((), Method::GET, "/well-known/security-txt"),
+                "security"
+            );
+            assert_eq!(
+                router((), Method::GET, "/users/12/Blog-Posts/hello"),
+                "posts(12, hello)"
+            );
+            assert_eq!(router((), Method::GET, "/well/known/security-txt"), "404");
+            assert_eq!(router((), Method::GET, "/wellknown/security-txt"), "404");
+            assert_eq!(router((), Method::GET, "/well-known/security"), "404");
+        }
+    }
+
+    router_tests! {
+        fn test_many_routes() {
+            // more routes than the default `recursion_limit` of 128
+            let home = |_: &()| "home".to_string();
+            let security = |_: &()| "security".to_string();
+            let item = |_: &(), id: u32| format!("item({})", id);
+            let fallback = |_: &()| "404".to_string();
+            let router = router!(
+                GET / => home,
+                GET /well-known/security-txt => security,
+                GET /r0/{id: u32} => item, GET /r1/{id: u32} => item, GET /r2/{id: u32} => item, GET /r3/{id: u32} => item, GET /r4/{id: u32} => item,
+                GET /r5/{id: u32} => item, GET /r6/{id: u32} => item, GET /r7/{id: u32} => item, GET /r8/{id: u32} => item, GET /r9/{id: u32} => item,
+                GET /r10/{id: u32} => item, GET /r11/{id: u32} => item, GET /r12/{id: u32} => item, GET /r13/{id: u32} => item, GET /r14/{id: u32} => item,
+                GET /r15/{id: u32} => item, GET /r16/{id: u32} => item, GET /r17/{id: u32} => item, GET /r18/{id: u32} => item, GET /r19/{id: u32} => item,
+                GET /r20/{id: u32} => item, GET /r21/{id: u32} => item, GET /r22/{id: u32} => item, GET /r23/{id: u32} => item, GET /r24/{id: u32} => item,
+                GET /r25/{id: u32} => item, GET /r26/{id: u32} => item, GET /r27/{id: u32} => item, GET /r28/{id: u32} => item, GET /r29/{id: u32} => item,
+                GET /r30/{id: u32} => item, GET /r31/{id: u32} => item, GET /r32/{id: u32} => item, GET /r33/{id: u32} => item, GET /r34/{id: u32} => item,
+                GET /r35/{id: u32} => item, GET /r36/{id: u32} => item, GET /r37/{id: u32} => item, GET /r38/{id: u32} => item, GET /r39/{id: u32} => item,
+                GET /r40/{id: u32} => item, GET /r41/{id: u32} => item, GET /r42/{id: u32} => item, GET /r43/{id: u32} => item, GET /r44/{id: u32} => item,
+                GET /r45/{id: u32} => item, GET /r46/{id: u32} => item, GET /r47/{id: u32} => item, GET /r48/{id: u32} => item, GET /r49/{id: u32} => item,
+                GET /r50/{id: u32} => item, GET /r51/{id: u32} => item, GET /r52/{id: u32} => item, GET /r53/{id: u32} => item, GET /r54/{id: u32} => item,
+                GET /r55/{id: u32} => item, GET /r56/{id: u32} => item, GET /r57/{id: u32} => item, GET /r58/{id: u32} => item, GET /r59/{id: u32} => item,
+                GET /r60/{id: u32} => item, GET /r61/{id: u32} => item, GET /r62/{id: u32} => item, GET /r63/{id: u32} => item, GET /r64/{id: u32} => item,
+                GET /r65/{id: u32} => item, GET /r66/{id: u32} => item, GET /r67/{id: u32} => item, GET /r68/{id: u32} => item, GET /r69/{id: u32} => item,
+                GET /r70/{id: u32} => item, GET /r71/{id: u32} => item, GET /r72/{id: u32} => item, GET /r73/{id: u32} => item, GET /r74/{id: u32} => item,
+                GET /r75/{id: u32} => item, GET /r76/{id: u32} => item, GET /r77/{id: u32} => item, GET /r78/{id: u32} => item, GET /r79/{id: u32} => item,
+                GET /r80/{id: u32} => item, GET /r81/{id: u32} => item, GET /r82/{id: u32} => item, GET /r83/{id: u32} => item, GET /r84/{id: u32} => item,
+                GET /r85/{id: u32} => item, GET /r86/{id: u32} => item, GET /r87/{id: u32} => item, GET /r88/{id: u32} => item, GET /r89/{id: u32} => item,
+                GET /r90/{id: u32} => item, GET /r91/{id: u32} => item, GET /r92/{id: u32} => item, GET /r93/{id: u32} => item, GET /r94/{id: u32} => item,
+                GET /r95/{id: u32} => item, GET /r96/{id: u32} => item, GET /r97/{id: u32} => item, GET /r98/{id: u32} => item, GET /r99/{id: u32} => item,
+                GET /r100/{id: u32} => item, GET /r101/{id: u32} => item, GET /r102/{id: u32} => item, GET /r103/{id: u32} => item, GET /r104/{id: u32} => item,
+                GET /r105/{id: u32} => item, GET /r106/{id: u32} => item, GET /r107/{id: u32} => item, GET /r108/{id: u32} => item, GET /r109/{id: u32} => item,
+                GET /r110/{id: u32} => item, GET /r111/{id: u32} => item, GET /r112/{id: u32} => item, GET /r113/{id: u32} => item, GET /r114/{id: u32} => item,
+                GET /r115/{id: u32} => item, GET /r116/{id: u32} => item, GET /r117/{id: u32} => item, GET /r118/{id: u32} => item, GET /r119/{id: u32} => item,
+                GET /r120/{id: u32} => item, GET /r121/{id: u32} => item, GET /r122/{id: u32} => item, GET /r123/{id: u32} => item, GET /r124/{id: u32} => item,
+                GET /r125/{id: u32} => item, GET /r126/{id: u32} => item, GET /r127/{id: u32} => item, GET /r128/{id: u32} => item, GET /r129/{id: u32} => item,
+                GET /r130/{id: u32} => item, GET /r131/{id: u32} => item, GET /r132/{id: u32} => item, GET /r133/{id: u32} => item, GET /r134/{id: u32} => item,
+                GET /r135/{id: u32} => item, GET /r136/{id: u32} => item, GET /r137/{id: u32} => item, GET /r138/{id: u32} => item, GET /r139/{id: u32} => item,
+                GET /r140/{id: u32} => item, GET /r141/{id: u32} => item, GET /r142/{id: u32} => item, GET /r143/{id: u32} => item, GET /r144/{id: u32} => item,
+                GET /r145/{id: u32} => item, GET /r146/{id: u32} => item, GET /r147/{id: u32} => item, GET /r148/{id: u32} => item, GET /r149/{id: u32} => item,
+                _ => fallback,
+            );
+            assert_eq!(router((), Method::GET, "/"), "home");
+            assert_eq!(
+                router((), Method::GET, "/well-known/security-txt"),
+                "security"
+            );
+            assert_eq!(router((), Method::GET, "/r0/1"), "item(1)");
+            assert_eq!(router((), Method::GET, "/r149/2"), "item(2)");
+            assert_eq!(router((), Method::GET, "/r150/3"), "404");
+        }
+    }
+
+    router_tests! {
+        fn test_request() {
+            #[derive(Debug, PartialEq)]
+            struct Request {
+                body: String,
+            }
+
+            let get_user =
+                |_: &(), request: Request, id: u32| format!("get_user({}, {})", id, request.body);
+            let post_users = |_: &(), request: Request| format!("post_users({})", request.body);
+            let fallback = |_: &(), request: Request| format!("404({})", request.body);
+            let router = router!(
+                request;
+                GET /users/{id: u32} => get_user,
+                POST /users => post_users,
+                _ => fallback,
+            );
+            let request = |body: &str| Request {
+                body: body.to_string(),
+            };
+            assert_eq!(
+                router((), request("a"), Method::GET, "/users/12"),
+                "get_user(12, a)"
+            );
+            assert_eq!(
+                router((), request("b"), Method::POST, "/users"),
+                "post_users(b)"
+            );
+            assert_eq!(router((), request("c"), Method::GET, "/users"), "404(c)");
+
+            let only_fallback = router!(request; _ => fallback);
+            assert_eq!(only_fallback((), request("d"), Method::GET, "/"), "404(d)");
+
+            let get_profile = |_: &(), extensions: &Extensions, request: Request| {
+                format!("{}({})", extensions.get::<&str>().unwrap(), request.body)
+            };
+            let fallback = |_: &(), _: &Extensions, _: Request| "404".to_string();
+            let router = router!(extensions; request; GET /profile => get_profile, _ => fallback);
+            let mut extensions = Extensions::new();
+            extensions.insert("alice");
+            assert_eq!(
+                router((), &extensions, request("e"), Method::GET, "/profile"),
+                "alice(e)"
+            );
+        }
+    }
+
+    #[test]
+    fn test_no_body() {
+        struct Request {
+            body: String,
+        }
+
+        fn has_no_body(request: &Request) -> bool {
+            request.body.is_empty()
+        }
+
+        let get_user = |_: &(), _: Request, id: u32| format!("get_user({})", id);
+        let delete_user = |_: &(), _: Request| "delete_user".to_string();
+        let bad_request = |_: &(), request: Request| format!("400({})", request.body);
+        let fallback = |_: &(), _: Request| "404".to_string();
+        let request = |body: &str| Request {
+            body: body.to_string(),
         };
-        let five = |_: &(), p1: String, p2: String, p3: String, p4: String, p5: String| {
-            format!("{}{}{}{}{}", &p1, &p2, &p3, &p4, &p5)
+
+        let router = router!(
+            request;
+            GET /users/{id: u32} [no_body(has_no_body => bad_request)] => get_user,
+            DELETE /users [no_body(has_no_body => bad_request)] => delete_user,
+            _ => fallback,
+        );
+        assert_eq!(
+            router((), request(""), Method::GET, "/users/12"),
+            "get_user(12)"
+        );
+        assert_eq!(router((), request("x"), Method::GET, "/users/12"), "400(x)");
+        assert_eq!(
+            router((), request(""), Method::DELETE, "/users"),
+            "delete_user"
+        );
+        assert_eq!(router((), request("y"), Method::DELETE, "/users"), "400(y)");
+        // not checked when another route answers
+        assert_eq!(router((), request("z"), Method::GET, "/users"), "404");
+
+        let compact = router!(
+            compact;
+            request;
+            GET /users/{id: u32} [no_body(has_no_body => bad_request)] => get_user,
+            _ => fallback,
+        );
+        assert_eq!(
+            compact((), request(""), Method::GET, "/users/3"),
+            "get_user(3)"
+        );
+        assert_eq!(compact((), request("w"), Method::GET, "/users/3"), "400(w)");
+
+        async fn get_status(_: &(), _: Request) -> String {
+            "status".to_string()
+        }
+        async fn async_bad_request(_: &(), request: Request) -> String {
+            format!("400({})", request.body)
+        }
+        async fn async_fallback(_: &(), _: Request) -> String {
+            "404".to_string()
+        }
+        let router = router!(
+            async;
+            request;
+            GET /status [no_body(has_no_body => async_bad_request)] => get_status,
+            _ => async_fallback,
+        );
+        assert_eq!(
+            block_on(router(&(), request(""), Method::GET, "/status")),
+            "status"
+        );
+        assert_eq!(
+            block_on(router(&(), request("v"), Method::GET, "/status")),
+            "400(v)"
+        );
+    }
+
+    #[test]
+    fn test_pick() {
+        #[derive(Clone, Copy)]
+        struct Context {
+            seed: u64,
+        }
+
+        // same seed, same handler
+        fn variant(context: &Context) -> usize {
+            (context.seed.wrapping_mul(6364136223846793005) >> 62) as usize
+        }
+
+        let home_a = |_: &Context| "home_a".to_string();
+        let home_b = |_: &Context| "home_b".to_string();
+        let user_a = |_: &Context, id: u32| format!("user_a({})", id);
+        let user_b = |_: &Context, id: u32| format!("user_b({})", id);
+        let user_c = |_: &Context, id: u32| format!("user_c({})", id);
+        let fallback = |_: &Context| "404".to_string();
+        let router = router!(
+            GET / => pick(variant => home_a, home_b),
+            GET /users/{id: u32} => pick(variant => user_a, user_b, user_c),
+            GET /well-known => pick(variant => home_a, home_b) if |context: &Context| context.seed > 0,
+            _ => fallback,
+        );
+        let expected = |seed: u64, handlers: &[&str]| {
+            let index = variant(&Context { seed }).min(handlers.len() - 1);
+            handlers[index].to_string()
         };
-        let six =
-            |_: &(), p1: String, p2: String, p3: String, p4: String, p5: String, p6: String| {
-                format!("{}{}{}{}{}{}", &p1, &p2, &p3, &p4, &p5, &p6)
-            };
-        let seven =
-            |_: &(),
-             p1: String,
-             p2: String,
-             p3: String,
-             p4: String,
-             p5: String,
-             p6: String,
-             p7: String| format!("{}{}{}{}{}{}{}", &p1, &p2, &p3, &p4, &p5, &p6, &p7);
-        let unreachable = |_: &()| unreachable!();
+        for seed in 0..32 {
+            let context = Context { seed };
+            assert_eq!(
+                router(context, Method::GET, "/"),
+                expected(seed, &["home_a", "home_b"])
+            );
+            assert_eq!(
+                router(context, Method::GET, "/users/7"),
+                expected(seed, &["user_a(7)", "user_b(7)", "user_c(7)"])
+            );
+            assert_eq!(
+                router(context, Method::GET, "/users/7"),
+                router(context, Method::GET, "/users/7")
+            );
+        }
+        let seeds = |index: usize| (0..32).filter(move |&seed| variant(&Context { seed }) == index);
+        let seed = seeds(1).next().unwrap();
+        assert_eq!(router(Context { seed }, Method::GET, "/"), "home_b");
+        assert_eq!(
+            router(Context { seed }, Method::GET, "/users/3"),
+            "user_b(3)"
+        );
+        // past the end of the handlers
+        let seed = seeds(3).next().unwrap();
+        assert_eq!(
+            router(Context { seed }, Method::GET, "/users/3"),
+            "user_c(3)"
+        );
+        assert_eq!(router(Context { seed }, Method::GET, "/"), "home_b");
+        assert_eq!(
+            router(Context { seed: 0 }, Method::GET, "/well-known"),
+            "404"
+        );
+
+        let compact = router!(
+            compact;
+            GET /users/{id: u32} => pick(variant => user_a, user_b, user_c),
+            _ => fallback,
+        );
+        for seed in 0..8 {
+            let context = Context { seed };
+            assert_eq!(
+                compact(context, Method::GET, "/users/5"),
+                router(context, Method::GET, "/users/5")
+            );
+        }
+    }
+
+    router_tests! {
+        fn test_file_extensions() {
+            let json_report = |_: &(), id: u32| format!("json({})", id);
+            let xml_report = |_: &(), id: u32| format!("xml({})", id);
+            let json_status = |_: &()| "json".to_string();
+            let report = |_: &(), id: u32| format!("report({})", id);
+            let fallback = |_: &()| "404".to_string();
+            let router = router!(
+                GET /reports/{id: u32} => ext { "json" => json_report, "xml" => xml_report },
+                GET /reports/{id: u32} => report,
+                GET /status => ext { "json" => json_status } if |_| true,
+                _ => fallback,
+            );
+            assert_eq!(router((), Method::GET, "/reports/12.json"), "json(12)");
+            assert_eq!(router((), Method::GET, "/reports/12.xml"), "xml(12)");
+            assert_eq!(router((), Method::GET, "/reports/12"), "report(12)");
+            assert_eq!(router((), Method::GET, "/reports/12.csv"), "404");
+            assert_eq!(router((), Method::GET, "/reports/12xjson"), "404");
+            assert_eq!(router((), Method::GET, "/status.json"), "json");
+            assert_eq!(router((), Method::GET, "/status"), "404");
+        }
+    }
+
+    #[test]
+    fn test_param_validators() {
+        #[derive(Clone, Copy)]
+        struct Context {
+            key: u32,
+        }
+        // stands in for an HMAC: the token is `payload-checksum`
+        fn valid_signature(token: &str, context: &Context) -> bool {
+            match token.rsplit_once('-') {
+                Some((payload, checksum)) => {
+                    let sum = payload.bytes().map(u32::from).sum::<u32>() ^ context.key;
+                    checksum.parse() == Ok(sum)
+                }
+                None => false,
+            }
+        }
+        fn positive(id: &str, _: &Context) -> bool {
+            !id.starts_with('0')
+        }
+        let download = |_: &Context, token: String, id: u32| format!("download({}, {})", token, id);
+        let preview = |_: &Context, token: String, _: u32| format!("preview({})", token);
+        let file = |_: &Context, token: String| format!("file({})", token);
+        let not_found = |_: &Context| "404".to_string();
         let router = router!(
-            GET /users => zero,
-            GET /users/{p1: String} => one,
-            GET /users/{p1: String}/users2/{p2: String} => two,
-            GET /users/{p1: String}/users2/{p2: String}/users3/{p3: String} => three,
-            GET /users/{p1: String}/users2/{p2: String}/users3/{p3: String}/users4/{p4: String} => four,
-            GET /users/{p1: String}/users2/{p2: String}/users3/{p3: String}/users4/{p4: String}/users5/{p5: String} => five,
-            GET /users/{p1: String}/users2/{p2: String}/users3/{p3: String}/users4/{p4: String}/users5/{p5: String}/users6/{p6: String} => six,
-            GET /users/{p1: String}/users2/{p2: String}/users3/{p3: String}/users4/{p4: String}/users5/{p5: String}/users6/{p6: String}/users7/{p7: String} => seven,
-            _ => unreachable,
+            GET /download/{token: String if valid_signature}/{id: u32 if positive} => download,
+            GET /download/{token: String}/{id: u32} => preview,
+            GET /files/{token: String if valid_signature} => file,
+            _ => not_found,
+        );
+        let context = Context { key: 7 };
+        let sum = u32::from(b'a') + u32::from(b'b');
+        let token = format!("ab-{}", sum ^ 7);
+        assert_eq!(
+            router(context, Method::GET, &format!("/files/{}", token)),
+            format!("file({})", token)
+        );
+        assert_eq!(
+            router(context, Method::GET, &format!("/files/ab-{}", sum)),
+            "404"
+        );
+        assert_eq!(router(context, Method::GET, "/files/ab"), "404");
+        assert_eq!(
+            router(context, Method::GET, &format!("/download/{}/12", token)),
+            format!("download({}, 12)", token)
+        );
+        // a tampered token or a rejected id falls through to the next route
+        assert_eq!(
+            router(context, Method::GET, "/download/ac-1/12"),
+            "preview(ac-1)"
+        );
+        assert_eq!(
+            router(context, Method::GET, &format!("/download/{}/012", token)),
+            format!("preview({})", token)
         );
 
-        assert_eq!(router((), Method::GET, "/users"), "");
-        assert_eq!(router((), Method::GET, "/users/id1"), "id1");
-        assert_eq!(router((), Method::GET, "/users/id1/users2/id2"), "id1id2");
+        let router = router!(
+            compact;
+            GET /files/{token: String if valid_signature} => file,
+            _ => not_found,
+        );
         assert_eq!(
-            router((), Method::GET, "/users/id1/users2/id2/users3/id3"),
-            "id1id2id3"
+            router(context, Method::GET, &format!("/files/{}", token)),
+            format!("file({})", token)
+        );
+        assert_eq!(router(context, Method::GET, "/files/ab-1"), "404");
+    }
+
+    #[test]
+    fn test_no_extension() {
+        let page = |_: &(), name: String| format!("page({})", name);
+        let json_page = |_: &(), name: String| format!("json_page({})", name);
+        let file = |_: &(), path: String| format!("file({})", path);
+        let fallback = |_: &()| "404".to_string();
+        let router = router!(
+            GET /page/{name: String no_ext} => page,
+            GET /page/{name: String} => ext { "json" => json_page },
+            GET /page/{path: ..} => file,
+            _ => fallback,
         );
+        assert_eq!(router((), Method::GET, "/page/about"), "page(about)");
+        assert_eq!(router((), Method::GET, "/page/a%20b"), "page(a b)");
         assert_eq!(
-            router(
-                (),
-                Method::GET,
-                "/users/id1/users2/id2/users3/id3/users4/id4"
-            ),
-            "id1id2id3id4"
+            router((), Method::GET, "/page/about.json"),
+            "json_page(about)"
         );
         assert_eq!(
-            router(
-                (),
-                Method::GET,
-                "/users/id1/users2/id2/users3/id3/users4/id4/users5/id5"
-            ),
-            "id1id2id3id4id5"
+            router((), Method::GET, "/page/about%2Ejson"),
+            "file(about.json)"
         );
         assert_eq!(
-            router(
-                (),
-                Method::GET,
-                "/users/id1/users2/id2/users3/id3/users4/id4/users5/id5/users6/id6"
-            ),
-            "id1id2id3id4id5id6"
+            router((), Method::GET, "/page/about%2ejson"),
+            "file(about.json)"
+        );
+
+        let router = router!(
+            compact;
+            GET /page/{name: String no_ext} => page,
+            GET /page/{path: ..} => file,
+            _ => fallback,
         );
+        assert_eq!(router((), Method::GET, "/page/about"), "page(about)");
         assert_eq!(
-            router(
-                (),
-                Method::GET,
-                "/users/id1/users2/id2/users3/id3/users4/id4/users5/id5/users6/id6/users7/id7"
-            ),
-            "id1id2id3id4id5id6id7"
+            router((), Method::GET, "/page/about%2Ejson"),
+            "file(about.json)"
+        );
+    }
+
+    router_tests! {
+        #[cfg(debug_assertions)]
+        #[should_panic(expected = "of route `GET /posts/{slug: String len 100000}`")]
+        fn test_validate_regexes() {
+            let get_post = |_: &(), _: String| "get_post".to_string();
+            let fallback = |_: &()| "404".to_string();
+            // a valid range, but a regex over the size limit of `regex`
+            let _router = router!(
+                GET /posts/{slug: String len 100000} => get_post,
+                _ => fallback,
+            );
+        }
+    }
+
+    #[test]
+    fn test_memoize() {
+        use std::cell::Cell;
+        use std::sync::Arc;
+
+        let calls = Cell::new(0);
+        let build_report = |_: &(), year: u32| {
+            calls.set(calls.get() + 1);
+            format!("report({})", year)
+        };
+        let list_reports = |_: &()| {
+            calls.set(calls.get() + 1);
+            "reports".to_string()
+        };
+        let fallback = |_: &()| "404".to_string();
+        let reports = Arc::new(MemoryCache::new(8));
+        let cache = reports.clone();
+        let router = router!(
+            GET /reports/{year: u32} [memoize(cache)] => build_report,
+            GET /reports => list_reports,
+            _ => fallback,
+        );
+        assert_eq!(router((), Method::GET, "/reports/2020"), "report(2020)");
+        assert_eq!(router((), Method::GET, "/reports/2020"), "report(2020)");
+        assert_eq!(calls.get(), 1);
+        assert_eq!(
+            reports.get("GET /reports/2020"),
+            Some("report(2020)".to_string())
+        );
+        assert_eq!(router((), Method::GET, "/reports/2021"), "report(2021)");
+        assert_eq!(calls.get(), 2);
+        // unmarked routes never touch the cache
+        assert_eq!(router((), Method::GET, "/reports"), "reports");
+        assert_eq!(router((), Method::GET, "/reports"), "reports");
+        assert_eq!(calls.get(), 4);
+        assert_eq!(reports.len(), 2);
+
+        let calls = Cell::new(0);
+        let get_static = |_: &()| {
+            calls.set(calls.get() + 1);
+            "static".to_string()
+        };
+        let statics = MemoryCache::new(8);
+        let cache = &statics;
+        let router = router!(
+            compact;
+            GET /static [memoize(cache)] => get_static,
+            _ => fallback,
+        );
+        assert_eq!(router((), Method::GET, "/static"), "static");
+        assert_eq!(router((), Method::GET, "/static"), "static");
+        assert_eq!(calls.get(), 1);
+        assert_eq!(statics.get("GET /static"), Some("static".to_string()));
+    }
+
+    router_tests! {
+        fn test_route_attributes() {
+            use std::cell::Cell;
+
+            struct Request {
+                body: String,
+            }
+
+            fn has_no_body(request: &Request) -> bool {
+                request.body.is_empty()
+            }
+
+            let calls = Cell::new(0);
+            let build_report = |_: &(), _: Request, year: u32| {
+                calls.set(calls.get() + 1);
+                format!("report({})", year)
+            };
+            let bad_request = |_: &(), request: Request| format!("400({})", request.body);
+            let fallback = |_: &(), _: Request| "404".to_string();
+            let request = |body: &str| Request {
+                body: body.to_string(),
+            };
+            let reports = MemoryCache::new(8);
+            let cache = &reports;
+            let router = router!(
+                request;
+                GET /reports/{year: u32} [memoize(cache)] [no_body(has_no_body => bad_request)] [normalize(ignore_case = true)] => build_report,
+                _ => fallback,
+            );
+            assert_eq!(
+                router((), request(""), Method::GET, "/Reports/2020"),
+                "report(2020)"
+            );
+            assert_eq!(
+                router((), request(""), Method::GET, "/Reports/2020"),
+                "report(2020)"
+            );
+            assert_eq!(calls.get(), 1);
+            // rejected before the cache is looked up
+            assert_eq!(
+                router((), request("x"), Method::GET, "/Reports/2020"),
+                "400(x)"
+            );
+            assert_eq!(calls.get(), 1);
+            assert_eq!(reports.len(), 1);
+        }
+    }
+
+    router_tests! {
+        fn test_catch_panics() {
+            let recover = |context: &bool, message: &str| format!("500({}, {})", context, message);
+            let get_user = |_: &bool, id: u32| {
+                if id == 0 {
+                    panic!("no user {}", id);
+                }
+                format!("get_user({})", id)
+            };
+            let boom = |_: &bool| -> String { panic!("boom") };
+            let fallback = |fail: &bool| {
+                if *fail {
+                    std::panic::panic_any(7);
+                }
+                "404".to_string()
+            };
+            let router = router!(
+                catch_panics = recover;
+                GET /users/{id: u32} => get_user,
+                GET /boom => boom,
+                _ => fallback,
+            );
+            assert_eq!(
+                router(false, Method::GET, "/users/0"),
+                "500(false, no user 0)"
+            );
+            assert_eq!(router(false, Method::GET, "/users/7"), "get_user(7)");
+            assert_eq!(router(true, Method::GET, "/boom"), "500(true, boom)");
+            assert_eq!(router(false, Method::GET, "/boom"), "500(false, boom)");
+            assert_eq!(
+                router(true, Method::GET, "/posts"),
+                "500(true, Box<dyn Any>)"
+            );
+            assert_eq!(router(false, Method::GET, "/posts"), "404");
+            assert_eq!(router(false, Method::GET, "/users/8"), "get_user(8)");
+
+            let router = router!(matched; catch_panics = recover; GET /boom => boom, _ => fallback);
+            assert_eq!(
+                router(false, Method::GET, "/boom"),
+                (true, "500(false, boom)".to_string())
+            );
+            assert_eq!(
+                router(false, Method::GET, "/posts"),
+                (false, "404".to_string())
+            );
+
+            let router = router!(catch_panics = recover; _ => fallback);
+            assert_eq!(router(true, Method::GET, "/"), "500(true, Box<dyn Any>)");
+
+            let record = |template: &'static str, _: u64, _: u64| {
+                if template == "/users/{id: u32}" {
+                    panic!("hook failed");
+                }
+            };
+            let router = router!(
+                timing(record);
+                catch_panics = recover;
+                GET /users/{id: u32} => get_user,
+                _ => fallback,
+            );
+            assert_eq!(
+                router(false, Method::GET, "/users/3"),
+                "500(false, hook failed)"
+            );
+            assert_eq!(router(false, Method::GET, "/posts"), "404");
+        }
+    }
+
+    #[cfg(not(feature = "no_shared_cache"))]
+    #[test]
+    fn test_poisoned_regex_cache() {
+        let cache = REGEXES.get_or_init(Default::default);
+        let poisoned = thread::spawn(move || {
+            let _regexes = cache.lock().unwrap();
+            panic!("poisoning the regex cache");
+        })
+        .join();
+        assert!(poisoned.is_err());
+        assert!(cache.is_poisoned());
+
+        let get_page = |_: &(), page: u16| page;
+        let fallback = |_: &()| 0;
+        let router = router!(GET /poisoned/pages/{page: u16} => get_page, _ => fallback);
+        assert_eq!(router((), Method::GET, "/poisoned/pages/4"), 4);
+        assert!(__http_router_create_regex("^/poisoned$").is_match("/poisoned"));
+    }
+
+    #[test]
+    fn test_timing() {
+        use std::cell::RefCell;
+        use std::time::Duration;
+
+        let timings = RefCell::new(Vec::new());
+        let record = |template: &'static str, match_ns: u64, handler_ns: u64| {
+            timings.borrow_mut().push((template, match_ns, handler_ns));
+        };
+        let slow_user = |_: &(), id: u32| {
+            thread::sleep(Duration::from_millis(20));
+            id
+        };
+        let deny = |_: &()| Err::<(), _>(403);
+        let admin = |_: &()| 1;
+        let fallback = |_: &()| 0;
+        let router = router!(
+            timing(record);
+            GET /users/{id: u32} => slow_user,
+            GET /admin => admin guard deny,
+            _ => fallback,
+        );
+        // the first call compiles the regexes
+        router((), Method::GET, "/users/1");
+        timings.borrow_mut().clear();
+
+        assert_eq!(router((), Method::GET, "/users/12"), 12);
+        assert_eq!(router((), Method::GET, "/admin"), 403);
+        assert_eq!(router((), Method::GET, "/posts"), 0);
+        let timings = timings.into_inner();
+        let templates: Vec<_> = timings.iter().map(|&(template, _, _)| template).collect();
+        assert_eq!(templates, ["/users/{id: u32}", "/admin", "<fallback>"]);
+        let (_, match_ns, handler_ns) = timings[0];
+        assert!(handler_ns >= 20_000_000);
+        assert!(handler_ns < 10_000_000_000);
+        assert!(match_ns < handler_ns);
+        // the guard answered, no handler ran
+        assert_eq!(timings[1].2, 0);
+
+        let templates = RefCell::new(Vec::new());
+        let record = |template, _, _| templates.borrow_mut().push(template);
+        let router = router!(
+            timing(record);
+            compact;
+            GET /users/{id: u32} => slow_user,
+            _ => fallback,
+        );
+        assert_eq!(router((), Method::GET, "/users/12"), 12);
+        assert_eq!(router((), Method::GET, "/posts"), 0);
+        assert_eq!(templates.into_inner(), ["/users/{id: u32}", "<fallback>"]);
+    }
+
+    router_tests! {
+        fn test_router_call() {
+            let get_user = |_: &(), id: u32| format!("get_user({})", id);
+            let fallback = |_: &()| "404".to_string();
+            let router = router!(GET /users/{id: u32} => get_user, _ => fallback);
+            assert_eq!(router_call!(router, (), "GET", "/users/12"), "get_user(12)");
+            assert_eq!(
+                router_call!(router, (), Method::GET, "/users/12"),
+                "get_user(12)"
+            );
+            assert_eq!(router_call!(router, (), 1u8, "/users/12"), "404");
+
+            let get_profile =
+                |_: &(), extensions: &Extensions| format!("get_profile({:?})", extensions.get::<u32>());
+            let fallback = |_: &(), _: &Extensions| "404".to_string();
+            let router = router!(extensions; GET /profile => get_profile, _ => fallback);
+            let mut extensions = Extensions::new();
+            extensions.insert(7u32);
+            assert_eq!(
+                router_call!(router, (), &extensions, "GET", "/profile",),
+                "get_profile(Some(7))"
+            );
+        }
+    }
+
+    router_tests! {
+        #[should_panic(expected = "unsupported http method `FETCH`")]
+        fn test_router_call_invalid_method() {
+            let fallback = |_: &()| "404".to_string();
+            let router = router!(_ => fallback);
+            router_call!(router, (), "FETCH", "/users");
+        }
+    }
+
+    #[test]
+    fn test_reserved_paths() {
+        let cms_page = |_: &(), slug: String| format!("cms_page({})", slug);
+        let cms_file = |_: &(), slug: String, path: String| format!("cms_file({}, {})", slug, path);
+        let login = |_: &()| "login".to_string();
+        let reserved_path = |_: &()| "reserved".to_string();
+        let fallback = |_: &()| "404".to_string();
+        let router = router!(
+            reserved("admin", "login", "static") => reserved_path;
+            GET /{slug: String} => cms_page,
+            GET /{slug: String}/{path: ..} => cms_file,
+            GET /login => login,
+            _ => fallback,
         );
+        assert_eq!(router((), Method::GET, "/about"), "cms_page(about)");
+        assert_eq!(
+            router((), Method::GET, "/administration"),
+            "cms_page(administration)"
+        );
+        assert_eq!(router((), Method::GET, "/docs/a/b"), "cms_file(docs, a/b)");
+        assert_eq!(router((), Method::GET, "/admin"), "reserved");
+        assert_eq!(router((), Method::GET, "/static/app.js"), "reserved");
+        assert_eq!(router((), Method::GET, "/login"), "login");
+        assert_eq!(router((), Method::POST, "/about"), "404");
+
+        let router = router!(
+            reserved("admin");
+            compact;
+            GET /{slug: String} => cms_page,
+            _ => fallback,
+        );
+        assert_eq!(router((), Method::GET, "/about"), "cms_page(about)");
+        assert_eq!(router((), Method::GET, "/admin"), "404");
+
+        let router = router!(reserved("admin") => reserved_path; _ => fallback);
+        assert_eq!(router((), Method::GET, "/admin/users"), "reserved");
+        assert_eq!(router((), Method::GET, "/about"), "404");
+
+        async fn reserved_async(_: &()) -> &'static str {
+            "reserved"
+        }
+        async fn not_found(_: &()) -> &'static str {
+            "404"
+        }
+        let router = router!(async; reserved("admin") => reserved_async; _ => not_found);
+        assert_eq!(block_on(router(&(), Method::GET, "/admin")), "reserved");
+        assert_eq!(block_on(router(&(), Method::GET, "/about")), "404");
+    }
+
+    #[test]
+    fn test_valid_url_segment() {
+        const _: () = assert!(is_valid_url_segment("well-known"));
+        assert!(is_valid_url_segment("index.html"));
+        assert!(is_valid_url_segment("~user:1@host;v=2"));
+        assert!(is_valid_url_segment("caf%C3%A9"));
+        assert!(!is_valid_url_segment(""));
+        assert!(!is_valid_url_segment("users?format"));
+        assert!(!is_valid_url_segment("a#b"));
+        assert!(!is_valid_url_segment("two words"));
+        assert!(!is_valid_url_segment("\"quoted\""));
+        assert!(!is_valid_url_segment("100%"));
+        assert!(!is_valid_url_segment("%4"));
+        assert!(!is_valid_url_segment("%zz"));
+    }
+
+    router_tests! {
+        fn test_catch_all() {
+            let files = |_: &(), path: String| format!("files({})", path);
+            let index = |_: &()| "index".to_string();
+            let user_file = |_: &(), id: u32, path: String| format!("user_file({}, {})", id, path);
+            let fallback = |_: &()| "404".to_string();
+            let router = router!(
+                GET /files/{path: ..} => files,
+                GET /files/index.html => index,
+                GET /users/{id: u32}/files/{path: ..} => user_file,
+                _ => fallback,
+            );
+            assert_eq!(router((), Method::GET, "/files/index.html"), "index");
+            assert_eq!(
+                router((), Method::GET, "/files/indexxhtml"),
+                "files(indexxhtml)"
+            );
+            assert_eq!(
+                router((), Method::GET, "/files/other/path"),
+                "files(other/path)"
+            );
+            assert_eq!(router((), Method::GET, "/files/a.txt"), "files(a.txt)");
+            assert_eq!(
+                router((), Method::GET, "/users/12/files/a/b"),
+                "user_file(12, a/b)"
+            );
+            assert_eq!(router((), Method::GET, "/files"), "404");
+            assert_eq!(router((), Method::GET, "/files/"), "404");
+        }
+    }
+
+    #[test]
+    fn test_compact() {
+        fn to_admin(ctx: &&str) -> Option<String> {
+            if ctx.starts_with("admin") {
+                Some(ctx.to_string())
+            } else {
+                None
+            }
+        }
+        let admin_user = |ctx: &String, id: u32| format!("admin_user({}, {})", ctx, id);
+        let admin_fallback = |ctx: &String| format!("admin_404({})", ctx);
+        let admin_router = router!(GET /users/{id: u32} => admin_user, _ => admin_fallback);
+
+        let home = |_: &&str| "home".to_string();
+        let any_user = |_: &&str, id: u32| format!("any_user({})", id);
+        let get_user = |_: &&str, id: u32| format!("get_user({})", id);
+        let page = |_: &&str, slug: String| {
+            if slug == "about" {
+                Some(format!("page({})", slug))
+            } else {
+                None
+            }
+        };
+        let files = |_: &&str, path: String| format!("files({})", path);
+        let report = |_: &&str, id: u32| format!("report({})", id);
+        let token = |_: &&str, token: String| format!("token({})", token);
+        let beta = |_: &&str| "beta".to_string();
+        let auth_check = |ctx: &&str| {
+            if ctx.is_empty() {
+                Err("401".to_string())
+            } else {
+                Ok(())
+            }
+        };
+        let well_known = |_: &&str| "well_known".to_string();
+        let fallback = |_: &&str| "404".to_string();
+        let router = router!(
+            compact;
+            specificity_order;
+            normalize(ignore_case = true);
+            GET / => home,
+            ANY /users/{id: u32} => any_user,
+            GET /users/{id: u32} => get_user,
+            GET /files/{path: ..} => files,
+            GET /{slug: String} => page?,
+            GET /reports/{id: u32} => ext { "json" => report },
+            GET /Token/{token: String} [normalize(ignore_case = false)] => token,
+            GET /beta => beta guard auth_check,
+            GET /.well-known/config => well_known,
+            scope /admin (map_ctx = to_admin) => admin_router,
+            _ => fallback,
+        );
+        assert_eq!(router("", Method::GET, "/"), "home");
+        assert_eq!(router("", Method::GET, "/USERS/12"), "get_user(12)");
+        assert_eq!(router("", Method::DELETE, "/users/12"), "any_user(12)");
+        assert_eq!(router("", Method::GET, "/users/abc"), "404");
+        assert_eq!(router("", Method::GET, "/files/a/b"), "files(a/b)");
+        assert_eq!(router("", Method::GET, "/about"), "page(about)");
+        assert_eq!(router("", Method::GET, "/contact"), "404");
+        assert_eq!(router("", Method::GET, "/reports/3.json"), "report(3)");
+        assert_eq!(router("", Method::GET, "/reports/3.xml"), "404");
+        assert_eq!(router("", Method::GET, "/Token/AbC"), "token(AbC)");
+        assert_eq!(router("", Method::GET, "/token/AbC"), "404");
+        assert_eq!(router("", Method::GET, "/beta"), "401");
+        assert_eq!(router("user", Method::GET, "/beta"), "beta");
+        assert_eq!(router("", Method::GET, "/.well-known/config"), "well_known");
+        assert_eq!(
+            router("admin1", Method::GET, "/admin/users/7"),
+            "admin_user(admin1, 7)"
+        );
+        assert_eq!(
+            router("admin1", Method::GET, "/admin/posts"),
+            "admin_404(admin1)"
+        );
+        assert_eq!(router("guest", Method::GET, "/admin/users/7"), "404");
+    }
+
+    router_tests! {
+        fn test_param_len() {
+            let post = |_: &(), slug: String| format!("post({})", slug);
+            let short = |_: &(), code: String| format!("short({})", code);
+            let fallback = |_: &()| "404".to_string();
+            let router = router!(
+                GET /posts/{slug: String len 3..=40} => post,
+                GET /posts/{code: String len ..3} => short,
+                _ => fallback,
+            );
+            for &(len, expected) in &[(1, "short"), (2, "short"), (3, "post"), (40, "post")] {
+                let slug = "a".repeat(len);
+                assert_eq!(
+                    router((), Method::GET, &format!("/posts/{}", slug)),
+                    format!("{}({})", expected, slug)
+                );
+            }
+            assert_eq!(
+                router((), Method::GET, &format!("/posts/{}", "a".repeat(41))),
+                "404"
+            );
+
+            let exact = |_: &(), pin: u32| format!("pin({})", pin);
+            let router = router!(GET /pin/{pin: u32 len 4} => exact, _ => fallback);
+            assert_eq!(router((), Method::GET, "/pin/0042"), "pin(42)");
+            assert_eq!(router((), Method::GET, "/pin/042"), "404");
+            assert_eq!(router((), Method::GET, "/pin/00042"), "404");
+        }
+    }
+
+    router_tests! {
+        fn test_array_params() {
+            let point = |_: &(), coords: [f64; 2]| format!("point({}, {})", coords[0], coords[1]);
+            let fallback = |_: &()| "404".to_string();
+            let router = router!(
+                GET /point/{coords: [f64; 2]} => point,
+                _ => fallback,
+            );
+            assert_eq!(router((), Method::GET, "/point/1.5/2.5"), "point(1.5, 2.5)");
+            assert_eq!(router((), Method::GET, "/point/1.5"), "404");
+            assert_eq!(router((), Method::GET, "/point/1.5/2.5/3.5"), "404");
+            assert_eq!(router((), Method::GET, "/point/1.5/abc"), "404");
+        }
+    }
+
+    #[cfg(feature = "no_shared_cache")]
+    #[test]
+    fn test_no_shared_cache() {
+        let get_user = |_: &(), id: u32| format!("get_user({})", id);
+        let fallback = |_: &()| "404".to_string();
+        let router = router!(GET /users/{id: u32} => get_user, _ => fallback);
+        assert_eq!(router((), Method::GET, "/users/12"), "get_user(12)");
+        let router = router!(compact; GET /users/{id: u32} => get_user, _ => fallback);
+        assert_eq!(router((), Method::GET, "/users/12"), "get_user(12)");
+        assert!(REGEXES.get().is_none());
+    }
+
+    router_tests! {
+        #[cfg(feature = "with_uuid")]
+        fn test_uuid_params() {
+            use uuid::Uuid;
+
+            let item = |_: &(), id: Uuid| format!("item({})", id);
+            let slug = |_: &(), slug: String| format!("slug({})", slug);
+            let fallback = |_: &()| "404".to_string();
+            let router = router!(
+                GET /items/{item_id: Uuid} => item,
+                GET /items/{slug: String} => slug,
+                _ => fallback,
+            );
+            assert_eq!(
+                router(
+                    (),
+                    Method::GET,
+                    "/items/550e8400-e29b-41d4-a716-446655440000"
+                ),
+                "item(550e8400-e29b-41d4-a716-446655440000)"
+            );
+            assert_eq!(
+                router((), Method::GET, "/items/abc-def-ghij-klmn"),
+                "slug(abc-def-ghij-klmn)"
+            );
+            // uppercase hex parses too
+            assert_eq!(
+                router(
+                    (),
+                    Method::GET,
+                    "/items/550E8400-E29B-41D4-A716-446655440000"
+                ),
+                "item(550e8400-e29b-41d4-a716-446655440000)"
+            );
+            // the simple form parses as a `Uuid`, but isn't matched
+            assert_eq!(
+                router((), Method::GET, "/items/550e8400e29b41d4a716446655440000"),
+                "slug(550e8400e29b41d4a716446655440000)"
+            );
+            assert!(is_uuid_param(stringify!({ id: uuid::Uuid })));
+            assert!(!is_uuid_param(stringify!({ id: String })));
+        }
+    }
+
+    router_tests! {
+        fn test_params_number() {
+            let zero = |_: &()| String::new();
+            let one = |_: &(), p1: String| format!("{}", &p1);
+            let two = |_: &(), p1: String, p2: String| format!("{}{}", &p1, &p2);
+            let three = |_: &(), p1: String, p2: String, p3: String| format!("{}{}{}", &p1, &p2, &p3);
+            let four = |_: &(), p1: String, p2: String, p3: String, p4: String| {
+                format!("{}{}{}{}", &p1, &p2, &p3, &p4)
+            };
+            let five = |_: &(), p1: String, p2: String, p3: String, p4: String, p5: String| {
+                format!("{}{}{}{}{}", &p1, &p2, &p3, &p4, &p5)
+            };
+            let six =
+                |_: &(), p1: String, p2: String, p3: String, p4: String, p5: String, p6: String| {
+                    format!("{}{}{}{}{}{}", &p1, &p2, &p3, &p4, &p5, &p6)
+                };
+            let seven =
+                |_: &(),
+                 p1: String,
+                 p2: String,
+                 p3: String,
+                 p4: String,
+                 p5: String,
+                 p6: String,
+                 p7: String| format!("{}{}{}{}{}{}{}", &p1, &p2, &p3, &p4, &p5, &p6, &p7);
+            let unreachable = |_: &()| unreachable!();
+            let router = router!(
+                GET /users => zero,
+                GET /users/{p1: String} => one,
+                GET /users/{p1: String}/users2/{p2: String} => two,
+                GET /users/{p1: String}/users2/{p2: String}/users3/{p3: String} => three,
+                GET /users/{p1: String}/users2/{p2: String}/users3/{p3: String}/users4/{p4: String} => four,
+                GET /users/{p1: String}/users2/{p2: String}/users3/{p3: String}/users4/{p4: String}/users5/{p5: String} => five,
+                GET /users/{p1: String}/users2/{p2: String}/users3/{p3: String}/users4/{p4: String}/users5/{p5: String}/users6/{p6: String} => six,
+                GET /users/{p1: String}/users2/{p2: String}/users3/{p3: String}/users4/{p4: String}/users5/{p5: String}/users6/{p6: String}/users7/{p7: String} => seven,
+                _ => unreachable,
+            );
+
+            assert_eq!(router((), Method::GET, "/users"), "");
+            assert_eq!(router((), Method::GET, "/users/id1"), "id1");
+            assert_eq!(router((), Method::GET, "/users/id1/users2/id2"), "id1id2");
+            assert_eq!(
+                router((), Method::GET, "/users/id1/users2/id2/users3/id3"),
+                "id1id2id3"
+            );
+            assert_eq!(
+                router(
+                    (),
+                    Method::GET,
+                    "/users/id1/users2/id2/users3/id3/users4/id4"
+                ),
+                "id1id2id3id4"
+            );
+            assert_eq!(
+                router(
+                    (),
+                    Method::GET,
+                    "/users/id1/users2/id2/users3/id3/users4/id4/users5/id5"
+                ),
+                "id1id2id3id4id5"
+            );
+            assert_eq!(
+                router(
+                    (),
+                    Method::GET,
+                    "/users/id1/users2/id2/users3/id3/users4/id4/users5/id5/users6/id6"
+                ),
+                "id1id2id3id4id5id6"
+            );
+            assert_eq!(
+                router(
+                    (),
+                    Method::GET,
+                    "/users/id1/users2/id2/users3/id3/users4/id4/users5/id5/users6/id6/users7/id7"
+                ),
+                "id1id2id3id4id5id6id7"
+            );
+        }
+    }
+
+    router_tests! {
+        fn test_params_moved_to_handler() {
+            // handlers own their params, so they can return them without copying
+            let one = |_: &(), p1: String| p1;
+            let two = |_: &(), p1: String, mut p2: String| {
+                p2.insert_str(0, &p1);
+                p2
+            };
+            let fallback = |_: &()| String::new();
+            let router = router!(
+                GET /users/{p1: String} => one,
+                GET /users/{p1: String}/{p2: String} => two,
+                _ => fallback,
+            );
+            let test_cases = [
+                (Method::GET, "/users/id1", "id1"),
+                (Method::GET, "/users/id1/id2", "id1id2"),
+                (Method::POST, "/users/id1", ""),
+            ];
+            // `Method` is `Copy`, so a test case can be routed as it is
+            for &(method, path, expected) in test_cases.iter() {
+                assert_eq!(router((), method, path), expected);
+            }
+        }
     }
 }
 
@@ -0,0 +1,123 @@
+//! Route manifests, so that another process, e.g. an edge proxy, matches paths like the
+//! application's `router!` without its handlers
+
+use crate::error::ImportError;
+use crate::method::Method;
+use crate::pattern::RoutePattern;
+use crate::route_info::StaticRouteInfo;
+use serde_json::{json, Value};
+use std::str;
+
+/// Version of the manifest format written by `export_manifest`. `import_manifest` rejects
+/// other versions.
+pub const MANIFEST_VERSION: u64 = 1;
+
+/// Writes the `routes` of a `RouteTable` as JSON, e.g.
+/// `{"version":1,"routes":[{"method":"GET","pattern":"/users/{id: u32}"}]}`.
+/// `ANY` routes and scopes have a `null` method.
+pub fn export_manifest(routes: &[StaticRouteInfo]) -> String {
+    let routes: Vec<Value> = routes
+        .iter()
+        .map(|route| {
+            let method = route
+                .method
+                .map(|method| str::from_utf8(method.as_bytes()).expect("method names are ASCII"));
+            json!({ "method": method, "pattern": route.pattern })
+        })
+        .collect();
+    json!({ "version": MANIFEST_VERSION, "routes": routes }).to_string()
+}
+
+/// Reads a manifest written by `export_manifest`, returning the patterns of its routes in
+/// order, parsed with `RoutePattern::from_router` so that they match the same paths as the
+/// router. The router's normalization isn't part of the manifest.
+pub fn import_manifest(json: &str) -> Result<Vec<RoutePattern>, ImportError> {
+    let manifest: Value =
+        serde_json::from_str(json).map_err(|error| ImportError::Malformed(error.to_string()))?;
+    let malformed = |message: &str| ImportError::Malformed(message.to_string());
+    let version = manifest
+        .get("version")
+        .and_then(Value::as_u64)
+        .ok_or_else(|| malformed("missing `version`"))?;
+    if version != MANIFEST_VERSION {
+        return Err(ImportError::UnsupportedVersion(version));
+    }
+    let routes = manifest
+        .get("routes")
+        .and_then(Value::as_array)
+        .ok_or_else(|| malformed("missing `routes`"))?;
+    routes
+        .iter()
+        .map(|route| {
+            match route.get("method") {
+                Some(Value::Null) => {}
+                Some(Value::String(method)) => {
+                    method
+                        .parse::<Method>()
+                        .map_err(|error| ImportError::Malformed(error.to_string()))?;
+                }
+                _ => return Err(malformed("route without a `method`")),
+            }
+            let pattern = route
+                .get("pattern")
+                .and_then(Value::as_str)
+                .ok_or_else(|| malformed("route without a `pattern`"))?;
+            Ok(RoutePattern::from_router(pattern)?)
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::error::PatternParseError;
+
+    #[test]
+    fn test_export_manifest() {
+        let routes = [
+            StaticRouteInfo {
+                method: Some(Method::GET),
+                pattern: "/users/{id: u32}",
+                params: &["id"],
+            },
+            StaticRouteInfo {
+                method: None,
+                pattern: "/",
+                params: &[],
+            },
+        ];
+        assert_eq!(
+            export_manifest(&routes),
+            r#"{"routes":[{"method":"GET","pattern":"/users/{id: u32}"},{"method":null,"pattern":"/"}],"version":1}"#
+        );
+    }
+
+    #[test]
+    fn test_import_errors() {
+        assert!(matches!(
+            import_manifest("[1, 2"),
+            Err(ImportError::Malformed(_))
+        ));
+        assert_eq!(
+            import_manifest(r#"{"version":2,"routes":[]}"#).unwrap_err(),
+            ImportError::UnsupportedVersion(2)
+        );
+        assert_eq!(
+            import_manifest(r#"{"routes":[]}"#).unwrap_err(),
+            ImportError::Malformed("missing `version`".to_string())
+        );
+        assert!(matches!(
+            import_manifest(r#"{"version":1,"routes":[{"method":"BREW","pattern":"/"}]}"#),
+            Err(ImportError::Malformed(_))
+        ));
+        assert!(matches!(
+            import_manifest(r#"{"version":1,"routes":[{"method":"GET","pattern":"users"}]}"#),
+            Err(ImportError::Pattern(
+                PatternParseError::MissingLeadingSlash { .. }
+            ))
+        ));
+        assert!(import_manifest(r#"{"version":1,"routes":[]}"#)
+            .unwrap()
+            .is_empty());
+    }
+}
@@ -0,0 +1,302 @@
+use crate::method::Method;
+use std::collections::HashMap;
+use std::str;
+use std::sync::{Arc, Mutex};
+
+/// Cache of handler results for `[memoize(cache)]` routes of `router!`, keyed by method and
+/// path, e.g. `GET /reports/2020`
+pub trait RouteCache<R> {
+    fn get(&self, key: &str) -> Option<R>;
+    fn put(&self, key: &str, value: &R);
+}
+
+impl<R, C: RouteCache<R> + ?Sized> RouteCache<R> for &C {
+    fn get(&self, key: &str) -> Option<R> {
+        (**self).get(key)
+    }
+
+    fn put(&self, key: &str, value: &R) {
+        (**self).put(key, value)
+    }
+}
+
+impl<R, C: RouteCache<R> + ?Sized> RouteCache<R> for Arc<C> {
+    fn get(&self, key: &str) -> Option<R> {
+        (**self).get(key)
+    }
+
+    fn put(&self, key: &str, value: &R) {
+        (**self).put(key, value)
+    }
+}
+
+/// In-memory `RouteCache` keeping at most `capacity` results. When full, the least recently
+/// used result is dropped for the new one.
+#[derive(Debug)]
+pub struct MemoryCache<R> {
+    capacity: usize,
+    entries: Mutex<Entries<R>>,
+}
+
+// No entry, for the links of `Entries`
+const NONE: usize = usize::MAX;
+
+// Results in a list from the most to the least recently used, linked by their indices in
+// `nodes`, so that using or evicting one doesn't move the others
+#[derive(Debug)]
+struct Entries<R> {
+    indices: HashMap<String, usize>,
+    nodes: Vec<Node<R>>,
+    newest: usize,
+    oldest: usize,
+}
+
+#[derive(Debug)]
+struct Node<R> {
+    key: String,
+    value: R,
+    newer: usize,
+    older: usize,
+}
+
+impl<R> Entries<R> {
+    fn with_capacity(capacity: usize) -> Self {
+        Entries {
+            indices: HashMap::with_capacity(capacity),
+            nodes: Vec::with_capacity(capacity),
+            newest: NONE,
+            oldest: NONE,
+        }
+    }
+
+    fn unlink(&mut self, index: usize) {
+        let (newer, older) = (self.nodes[index].newer, self.nodes[index].older);
+        match newer {
+            NONE => self.newest = older,
+            newer => self.nodes[newer].older = older,
+        }
+        match older {
+            NONE => self.oldest = newer,
+            older => self.nodes[older].newer = newer,
+        }
+    }
+
+    fn push_newest(&mut self, index: usize) {
+        self.nodes[index].newer = NONE;
+        self.nodes[index].older = self.newest;
+        match self.newest {
+            NONE => self.oldest = index,
+            newest => self.nodes[newest].newer = index,
+        }
+        self.newest = index;
+    }
+
+    fn touch(&mut self, index: usize) {
+        if self.newest != index {
+            self.unlink(index);
+            self.push_newest(index);
+        }
+    }
+
+    fn clear(&mut self) {
+        self.indices.clear();
+        self.nodes.clear();
+        self.newest = NONE;
+        self.oldest = NONE;
+    }
+}
+
+impl<R> MemoryCache<R> {
+    pub fn new(capacity: usize) -> Self {
+        MemoryCache {
+            capacity,
+            entries: Mutex::new(Entries::with_capacity(capacity)),
+        }
+    }
+
+    pub fn capacity(&self) -> usize {
+        self.capacity
+    }
+
+    /// Number of cached results
+    pub fn len(&self) -> usize {
+        self.entries
+            .lock()
+            .expect("Failed to obtain mutex lock")
+            .indices
+            .len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    pub fn clear(&self) {
+        self.entries
+            .lock()
+            .expect("Failed to obtain mutex lock")
+            .clear()
+    }
+}
+
+impl<R: Clone> RouteCache<R> for MemoryCache<R> {
+    fn get(&self, key: &str) -> Option<R> {
+        let mut entries = self.entries.lock().expect("Failed to obtain mutex lock");
+        let index = *entries.indices.get(key)?;
+        entries.touch(index);
+        Some(entries.nodes[index].value.clone())
+    }
+
+    fn put(&self, key: &str, value: &R) {
+        if self.capacity == 0 {
+            return;
+        }
+        let mut entries = self.entries.lock().expect("Failed to obtain mutex lock");
+        if let Some(&index) = entries.indices.get(key) {
+            entries.nodes[index].value = value.clone();
+            entries.touch(index);
+        } else if entries.nodes.len() < self.capacity {
+            let index = entries.nodes.len();
+            entries.nodes.push(Node {
+                key: key.to_string(),
+                value: value.clone(),
+                newer: NONE,
+                older: NONE,
+            });
+            entries.indices.insert(key.to_string(), index);
+            entries.push_newest(index);
+        } else {
+            // the node of the evicted result is reused for the new one
+            let index = entries.oldest;
+            let evicted = std::mem::replace(&mut entries.nodes[index].key, key.to_string());
+            entries.indices.remove(&evicted);
+            entries.nodes[index].value = value.clone();
+            entries.indices.insert(key.to_string(), index);
+            entries.touch(index);
+        }
+    }
+}
+
+/// This is an implementation detail and *should not* be called directly!
+///
+/// Key of a request in a `RouteCache`, e.g. `GET /reports/2020`.
+#[doc(hidden)]
+pub fn __http_router_memo_key(method: Method, path: &str) -> String {
+    let method = str::from_utf8(method.as_bytes()).expect("method names are ASCII");
+    let mut key = String::with_capacity(method.len() + 1 + path.len());
+    key.push_str(method);
+    key.push(' ');
+    key.push_str(path);
+    key
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_memory_cache() {
+        let cache = MemoryCache::new(2);
+        assert_eq!(cache.get("GET /a"), None);
+        cache.put("GET /a", &1);
+        cache.put("GET /b", &2);
+        assert_eq!(cache.get("GET /a"), Some(1));
+        cache.put("GET /a", &3);
+        assert_eq!(cache.get("GET /a"), Some(3));
+        assert_eq!(cache.len(), 2);
+        // `GET /b` is the least recently used
+        cache.put("GET /c", &4);
+        assert_eq!(cache.len(), 2);
+        assert_eq!(cache.get("GET /b"), None);
+        assert_eq!(cache.get("GET /c"), Some(4));
+        assert_eq!(cache.get("GET /a"), Some(3));
+        cache.put("GET /d", &5);
+        assert_eq!(cache.get("GET /c"), None);
+        assert_eq!(cache.get("GET /a"), Some(3));
+        assert_eq!(cache.get("GET /d"), Some(5));
+        cache.clear();
+        assert!(cache.is_empty());
+        cache.put("GET /e", &6);
+        assert_eq!(cache.get("GET /e"), Some(6));
+
+        let cache = MemoryCache::new(1);
+        cache.put("GET /a", &1);
+        cache.put("GET /b", &2);
+        assert_eq!((cache.get("GET /a"), cache.get("GET /b")), (None, Some(2)));
+
+        let cache = MemoryCache::new(0);
+        cache.put("GET /a", &1);
+        assert!(cache.is_empty());
+    }
+
+    // Keys from the most to the least recently used, checking the links both ways
+    fn order(entries: &Entries<u32>) -> Vec<&str> {
+        let mut keys = Vec::new();
+        let mut newer = NONE;
+        let mut index = entries.newest;
+        while index != NONE {
+            assert_eq!(entries.nodes[index].newer, newer);
+            keys.push(entries.nodes[index].key.as_str());
+            newer = index;
+            index = entries.nodes[index].older;
+        }
+        assert_eq!(entries.oldest, newer);
+        keys
+    }
+
+    fn entries(keys: &[&str]) -> Entries<u32> {
+        let mut entries = Entries::with_capacity(keys.len());
+        for (index, key) in keys.iter().enumerate() {
+            entries.nodes.push(Node {
+                key: key.to_string(),
+                value: index as u32,
+                newer: NONE,
+                older: NONE,
+            });
+            entries.indices.insert(key.to_string(), index);
+            entries.push_newest(index);
+        }
+        entries
+    }
+
+    #[test]
+    fn test_unlink() {
+        // the newest entry
+        let mut head = entries(&["a", "b", "c"]);
+        assert_eq!(order(&head), ["c", "b", "a"]);
+        head.unlink(2);
+        assert_eq!(order(&head), ["b", "a"]);
+        head.push_newest(2);
+        assert_eq!(order(&head), ["c", "b", "a"]);
+
+        // the oldest entry
+        let mut tail = entries(&["a", "b", "c"]);
+        tail.unlink(0);
+        assert_eq!(order(&tail), ["c", "b"]);
+        tail.touch(1);
+        assert_eq!(order(&tail), ["b", "c"]);
+
+        // an entry in the middle
+        let mut middle = entries(&["a", "b", "c"]);
+        middle.touch(1);
+        assert_eq!(order(&middle), ["b", "c", "a"]);
+
+        // the only entry
+        let mut single = entries(&["a"]);
+        single.unlink(0);
+        assert_eq!((single.newest, single.oldest), (NONE, NONE));
+        assert!(order(&single).is_empty());
+        single.push_newest(0);
+        assert_eq!(order(&single), ["a"]);
+        single.touch(0);
+        assert_eq!(order(&single), ["a"]);
+    }
+
+    #[test]
+    fn test_memo_key() {
+        assert_eq!(
+            __http_router_memo_key(Method::GET, "/reports/2020"),
+            "GET /reports/2020"
+        );
+    }
+}
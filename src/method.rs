@@ -1,8 +1,16 @@
+use crate::error::MethodParseError;
+#[cfg(feature = "with_http_types")]
+use http_types::Method as HttpTypesMethod;
 #[cfg(feature = "with_hyper")]
 use hyper::Method as HyperMethod;
+use std::str::FromStr;
 
 /// Http verbs
-#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+///
+/// Methods may be added without a major release, as `LOCK` and `UNLOCK` were, so matches on
+/// a `Method` outside of this crate need a `_` arm.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[non_exhaustive]
 pub enum Method {
     GET,
     POST,
@@ -13,6 +21,10 @@ pub enum Method {
     HEAD,
     CONNECT,
     TRACE,
+    /// WebDAV lock
+    LOCK,
+    /// WebDAV unlock
+    UNLOCK,
 }
 
 #[cfg(feature = "with_hyper")]
@@ -28,7 +40,233 @@ impl From<HyperMethod> for Method {
             HyperMethod::TRACE => Method::TRACE,
             HyperMethod::CONNECT => Method::CONNECT,
             HyperMethod::PATCH => Method::PATCH,
+            ref other if other.as_str() == "LOCK" => Method::LOCK,
+            ref other if other.as_str() == "UNLOCK" => Method::UNLOCK,
             _ => panic!("Not implemented hyper method in http_router lib"),
         }
     }
 }
+
+impl Method {
+    /// Method name as sent in an HTTP/1.1 request line, e.g. `b"GET"`
+    pub fn as_bytes(self) -> &'static [u8] {
+        match self {
+            Method::GET => b"GET",
+            Method::POST => b"POST",
+            Method::PUT => b"PUT",
+            Method::PATCH => b"PATCH",
+            Method::DELETE => b"DELETE",
+            Method::OPTIONS => b"OPTIONS",
+            Method::HEAD => b"HEAD",
+            Method::CONNECT => b"CONNECT",
+            Method::TRACE => b"TRACE",
+            Method::LOCK => b"LOCK",
+            Method::UNLOCK => b"UNLOCK",
+        }
+    }
+}
+
+/// Parses an upper case method name like `GET`
+impl FromStr for Method {
+    type Err = MethodParseError;
+
+    fn from_str(s: &str) -> Result<Method, MethodParseError> {
+        match s {
+            "GET" => Ok(Method::GET),
+            "POST" => Ok(Method::POST),
+            "PUT" => Ok(Method::PUT),
+            "PATCH" => Ok(Method::PATCH),
+            "DELETE" => Ok(Method::DELETE),
+            "OPTIONS" => Ok(Method::OPTIONS),
+            "HEAD" => Ok(Method::HEAD),
+            "CONNECT" => Ok(Method::CONNECT),
+            "TRACE" => Ok(Method::TRACE),
+            "LOCK" => Ok(Method::LOCK),
+            "UNLOCK" => Ok(Method::UNLOCK),
+            _ => Err(MethodParseError {
+                method: s.to_string(),
+            }),
+        }
+    }
+}
+
+/// Values naming a method, converted by `router_call!` so that callers can write `"GET"`
+pub trait IntoMethod {
+    fn into_method(self) -> Result<Method, MethodParseError>;
+}
+
+impl IntoMethod for Method {
+    fn into_method(self) -> Result<Method, MethodParseError> {
+        Ok(self)
+    }
+}
+
+/// Parses the name like `Method::from_str`
+impl IntoMethod for &str {
+    fn into_method(self) -> Result<Method, MethodParseError> {
+        self.parse()
+    }
+}
+
+/// Number of the method in declaration order, i.e. `method as u8`, `GET` is 0
+impl IntoMethod for u8 {
+    fn into_method(self) -> Result<Method, MethodParseError> {
+        let method = match self {
+            0 => Method::GET,
+            1 => Method::POST,
+            2 => Method::PUT,
+            3 => Method::PATCH,
+            4 => Method::DELETE,
+            5 => Method::OPTIONS,
+            6 => Method::HEAD,
+            7 => Method::CONNECT,
+            8 => Method::TRACE,
+            9 => Method::LOCK,
+            10 => Method::UNLOCK,
+            _ => {
+                return Err(MethodParseError {
+                    method: self.to_string(),
+                })
+            }
+        };
+        Ok(method)
+    }
+}
+
+/// Methods of `http-types` beyond the ones of `Method` (`PROPFIND`, `PRI`, ...) are
+/// converted into `MethodParseError`, so that they can be answered with 405 or 501.
+#[cfg(feature = "with_http_types")]
+impl ::std::convert::TryFrom<HttpTypesMethod> for Method {
+    type Error = MethodParseError;
+
+    fn try_from(method: HttpTypesMethod) -> Result<Method, MethodParseError> {
+        match method {
+            HttpTypesMethod::Options => Ok(Method::OPTIONS),
+            HttpTypesMethod::Get => Ok(Method::GET),
+            HttpTypesMethod::Post => Ok(Method::POST),
+            HttpTypesMethod::Put => Ok(Method::PUT),
+            HttpTypesMethod::Delete => Ok(Method::DELETE),
+            HttpTypesMethod::Head => Ok(Method::HEAD),
+            HttpTypesMethod::Trace => Ok(Method::TRACE),
+            HttpTypesMethod::Connect => Ok(Method::CONNECT),
+            HttpTypesMethod::Patch => Ok(Method::PATCH),
+            HttpTypesMethod::Lock => Ok(Method::LOCK),
+            HttpTypesMethod::Unlock => Ok(Method::UNLOCK),
+            other => Err(MethodParseError {
+                method: other.to_string(),
+            }),
+        }
+    }
+}
+
+#[cfg(feature = "with_http_types")]
+impl From<Method> for HttpTypesMethod {
+    fn from(method: Method) -> HttpTypesMethod {
+        match method {
+            Method::OPTIONS => HttpTypesMethod::Options,
+            Method::GET => HttpTypesMethod::Get,
+            Method::POST => HttpTypesMethod::Post,
+            Method::PUT => HttpTypesMethod::Put,
+            Method::DELETE => HttpTypesMethod::Delete,
+            Method::HEAD => HttpTypesMethod::Head,
+            Method::TRACE => HttpTypesMethod::Trace,
+            Method::CONNECT => HttpTypesMethod::Connect,
+            Method::PATCH => HttpTypesMethod::Patch,
+            Method::LOCK => HttpTypesMethod::Lock,
+            Method::UNLOCK => HttpTypesMethod::Unlock,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    #[cfg(feature = "with_http_types")]
+    use crate::router;
+    #[cfg(feature = "with_http_types")]
+    use http_types::{Request, Url};
+    #[cfg(feature = "with_http_types")]
+    use std::convert::TryFrom;
+
+    const METHODS: [Method; 11] = [
+        Method::GET,
+        Method::POST,
+        Method::PUT,
+        Method::PATCH,
+        Method::DELETE,
+        Method::OPTIONS,
+        Method::HEAD,
+        Method::CONNECT,
+        Method::TRACE,
+        Method::LOCK,
+        Method::UNLOCK,
+    ];
+
+    #[test]
+    fn test_as_bytes() {
+        for &method in METHODS.iter() {
+            assert_eq!(method.as_bytes(), format!("{:?}", method).as_bytes());
+            assert_eq!(
+                std::str::from_utf8(method.as_bytes()).unwrap().parse(),
+                Ok(method)
+            );
+        }
+        assert_eq!(Method::DELETE.as_bytes(), b"DELETE");
+    }
+
+    #[test]
+    fn test_into_method() {
+        for &method in METHODS.iter() {
+            assert_eq!(method.into_method(), Ok(method));
+            assert_eq!(format!("{:?}", method).as_str().into_method(), Ok(method));
+            assert_eq!((method as u8).into_method(), Ok(method));
+        }
+        assert_eq!("get".into_method().unwrap_err().method, "get");
+        assert_eq!(11u8.into_method().unwrap_err().method, "11");
+    }
+
+    #[cfg(feature = "with_http_types")]
+    #[test]
+    fn test_http_types_conversions() {
+        for &method in METHODS.iter() {
+            let converted = HttpTypesMethod::from(method);
+            assert_eq!(converted.to_string(), format!("{:?}", method));
+            assert_eq!(Method::try_from(converted), Ok(method));
+        }
+        assert_eq!(
+            Method::try_from(HttpTypesMethod::PropFind)
+                .unwrap_err()
+                .method,
+            "PROPFIND"
+        );
+    }
+
+    #[cfg(feature = "with_http_types")]
+    #[test]
+    fn test_http_types_request() {
+        let get_user = |_: &(), id: u32| format!("get_user({})", id);
+        let fallback = |_: &()| "404".to_string();
+        let router = router!(
+            GET /users/{id: u32} => get_user,
+            _ => fallback,
+        );
+        let route = |request: &Request| match Method::try_from(request.method()) {
+            Ok(method) => router((), method, request.url().path()),
+            Err(_) => "501".to_string(),
+        };
+        let url = Url::parse("https://example.com/users/12?fields=name").unwrap();
+        assert_eq!(
+            route(&Request::new(HttpTypesMethod::Get, url.clone())),
+            "get_user(12)"
+        );
+        assert_eq!(
+            route(&Request::new(HttpTypesMethod::Post, url.clone())),
+            "404"
+        );
+        assert_eq!(
+            route(&Request::new(HttpTypesMethod::Lock, url.clone())),
+            "404"
+        );
+        assert_eq!(route(&Request::new(HttpTypesMethod::PropFind, url)), "501");
+    }
+}
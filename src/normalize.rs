@@ -0,0 +1,256 @@
+use std::borrow::Cow;
+use std::cell::OnceCell;
+
+/// Path normalization applied before matching.
+///
+/// Set for the whole router with a `normalize(...)` header and overridden per route
+/// with a `[normalize(...)]` attribute, e.g. `normalize(ignore_case = true);`
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Normalize {
+    /// Match literal segments ignoring ASCII case. Params are passed to handlers as is.
+    pub ignore_case: bool,
+    /// Treat `/users/` the same as `/users`
+    pub trailing_slash: bool,
+    /// Treat repeated slashes like `/users//12` as one
+    pub merge_slashes: bool,
+    /// Strip matrix params like `;jsessionid=ABC123` from segments, e.g. so that
+    /// `/users/42;jsessionid=ABC123` matches `/users/{id: u32}`
+    pub strip_matrix_params: MatrixParams,
+    /// Enum-like literal segments, in lowercase, that match in any ASCII case, e.g.
+    /// `&["csv", "json"]` so that `/exports/CSV` matches `/exports/csv`, without ignoring the
+    /// case of the other literals. The path isn't changed, so params keep their case.
+    pub lowercase_segments: &'static [&'static str],
+}
+
+/// Segments that `Normalize::strip_matrix_params` strips a `;...` suffix from. An escaped
+/// `%3B` isn't a separator, so it is kept and passed to handlers as `;`.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum MatrixParams {
+    /// Match segments as they are
+    #[default]
+    Keep,
+    /// Only the last segment, where clients like old Java servlet containers append them
+    LastSegment,
+    /// Every segment
+    AllSegments,
+}
+
+impl Normalize {
+    /// Applies slash normalization and strips matrix params from `path`. Case is handled by
+    /// the matcher.
+    pub fn path<'a>(&self, path: &'a str) -> Cow<'a, str> {
+        let mut path = self.strip_matrix(path);
+        if self.merge_slashes && path.contains("//") {
+            let mut merged = String::with_capacity(path.len());
+            for c in path.chars() {
+                if c != '/' || !merged.ends_with('/') {
+                    merged.push(c);
+                }
+            }
+            path = Cow::Owned(merged);
+        }
+        if self.trailing_slash && path.len() > 1 && path.ends_with('/') {
+            path = match path {
+                Cow::Borrowed(p) => Cow::Borrowed(&p[..p.len() - 1]),
+                Cow::Owned(mut p) => {
+                    p.pop();
+                    Cow::Owned(p)
+                }
+            };
+        }
+        path
+    }
+
+    /// Matrix params that `path` strips, without the `;`, e.g. `["jsessionid=ABC123"]`, for
+    /// handlers that need them after all
+    pub fn matrix_params<'a>(&self, path: &'a str) -> Vec<&'a str> {
+        let mut params: Vec<_> = self
+            .matrix_segments(path)
+            .filter_map(|segment| segment.find(';').map(|start| &segment[start + 1..]))
+            .collect();
+        params.reverse();
+        params
+    }
+
+    /// Segments of `path` to strip matrix params from
+    fn matrix_segments<'a>(&self, path: &'a str) -> impl Iterator<Item = &'a str> {
+        let segments = match self.strip_matrix_params {
+            MatrixParams::Keep => 0,
+            MatrixParams::LastSegment => 1,
+            MatrixParams::AllSegments => usize::MAX,
+        };
+        path.rsplit('/').take(segments)
+    }
+
+    /// Whether the literal segment `literal` of a route matches in any ASCII case
+    pub(crate) fn any_case(&self, literal: &str) -> bool {
+        self.ignore_case
+            || self
+                .lowercase_segments
+                .iter()
+                .any(|listed| listed.eq_ignore_ascii_case(literal))
+    }
+
+    fn strip_matrix<'a>(&self, path: &'a str) -> Cow<'a, str> {
+        if self.strip_matrix_params == MatrixParams::Keep || !path.contains(';') {
+            return Cow::Borrowed(path);
+        }
+        if self.strip_matrix_params == MatrixParams::LastSegment {
+            let last = path.rfind('/').map_or(0, |slash| slash + 1);
+            return match path[last..].find(';') {
+                Some(start) => Cow::Borrowed(&path[..last + start]),
+                None => Cow::Borrowed(path),
+            };
+        }
+        let mut stripped = String::with_capacity(path.len());
+        for (i, segment) in path.split('/').enumerate() {
+            if i > 0 {
+                stripped.push('/');
+            }
+            stripped.push_str(segment.split(';').next().unwrap_or(""));
+        }
+        Cow::Owned(stripped)
+    }
+}
+
+/// This is an implementation detail and *should not* be used directly!
+///
+/// Path of a request normalized for the routes of a `router!`, once for the router's
+/// `Normalize` and once per distinct `[normalize(...)]` override, when a route first needs it
+#[doc(hidden)]
+pub struct __HttpRouterNormalizedPaths<'a, const N: usize> {
+    path: &'a str,
+    normalize: Normalize,
+    // `[normalize(...)]` of each route, `None` for routes without one
+    overrides: [Option<Normalize>; N],
+    router_path: OnceCell<Cow<'a, str>>,
+    // path of each route whose override is the first of its kind
+    paths: [OnceCell<Cow<'a, str>>; N],
+}
+
+impl<'a, const N: usize> __HttpRouterNormalizedPaths<'a, N> {
+    pub fn new(path: &'a str, normalize: Normalize, overrides: [Option<Normalize>; N]) -> Self {
+        __HttpRouterNormalizedPaths {
+            path,
+            normalize,
+            overrides,
+            router_path: OnceCell::new(),
+            paths: std::array::from_fn(|_| OnceCell::new()),
+        }
+    }
+
+    /// Normalization of the route at `index`, and the path normalized with it
+    pub fn get(&self, index: usize) -> (Normalize, &str) {
+        let normalize = match self.overrides[index] {
+            Some(normalize) if normalize != self.normalize => normalize,
+            _ => {
+                let path = self
+                    .router_path
+                    .get_or_init(|| self.normalize.path(self.path));
+                return (self.normalize, path);
+            }
+        };
+        let first = self.overrides[..index]
+            .iter()
+            .position(|other| *other == Some(normalize))
+            .unwrap_or(index);
+        let path = self.paths[first].get_or_init(|| normalize.path(self.path));
+        (normalize, path)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_path() {
+        let none = Normalize::default();
+        assert_eq!(none.path("/users//12/"), "/users//12/");
+
+        let all = Normalize {
+            ignore_case: true,
+            trailing_slash: true,
+            merge_slashes: true,
+            strip_matrix_params: MatrixParams::AllSegments,
+            lowercase_segments: &["exports", "csv"],
+        };
+        assert_eq!(all.path("/users//12/"), "/users/12");
+        assert_eq!(all.path("/Exports/CSV;v=1/"), "/Exports/CSV");
+        assert_eq!(all.path("//"), "/");
+        assert_eq!(all.path("/"), "/");
+        assert_eq!(all.path("/Users"), "/Users");
+        assert_eq!(all.path("/users;v=1//12;jsessionid=A/"), "/users/12");
+    }
+
+    #[test]
+    fn test_strip_matrix_params() {
+        let path = "/shop;region=eu/users/42;jsessionid=ABC123";
+        let keep = Normalize::default();
+        assert_eq!(keep.path(path), path);
+        assert!(keep.matrix_params(path).is_empty());
+
+        let last = Normalize {
+            strip_matrix_params: MatrixParams::LastSegment,
+            ..Normalize::default()
+        };
+        assert_eq!(last.path(path), "/shop;region=eu/users/42");
+        assert_eq!(last.matrix_params(path), ["jsessionid=ABC123"]);
+        assert_eq!(last.path("/users/42%3Bx"), "/users/42%3Bx");
+        assert_eq!(last.path("/users/;a"), "/users/");
+
+        let all = Normalize {
+            strip_matrix_params: MatrixParams::AllSegments,
+            ..Normalize::default()
+        };
+        assert_eq!(all.path(path), "/shop/users/42");
+        assert_eq!(all.matrix_params(path), ["region=eu", "jsessionid=ABC123"]);
+        assert_eq!(all.path("/a;x;y/b"), "/a/b");
+        assert_eq!(all.matrix_params("/a;x;y/b"), ["x;y"]);
+    }
+
+    #[test]
+    fn test_lowercase_segments() {
+        let normalize = Normalize {
+            lowercase_segments: &["exports", "csv", "json"],
+            ..Normalize::default()
+        };
+        assert!(matches!(
+            normalize.path("/Exports/CSV/Report"),
+            Cow::Borrowed("/Exports/CSV/Report")
+        ));
+        assert!(normalize.any_case("csv"));
+        assert!(normalize.any_case("Json"));
+        assert!(!normalize.any_case("csvs"));
+        assert!(!normalize.any_case("users"));
+        assert!(Normalize {
+            ignore_case: true,
+            ..Normalize::default()
+        }
+        .any_case("users"));
+    }
+
+    #[test]
+    fn test_normalized_paths() {
+        let merge = Normalize {
+            merge_slashes: true,
+            ..Normalize::default()
+        };
+        let trailing = Normalize {
+            trailing_slash: true,
+            ..Normalize::default()
+        };
+        let paths = __HttpRouterNormalizedPaths::new(
+            "/users//12/",
+            merge,
+            [None, Some(trailing), Some(merge), Some(trailing)],
+        );
+        assert_eq!(paths.get(0), (merge, "/users/12/"));
+        assert_eq!(paths.get(1), (trailing, "/users//12"));
+        assert_eq!(paths.get(2), (merge, "/users/12/"));
+        assert_eq!(paths.get(3), (trailing, "/users//12"));
+        // the same normalization gives the same path rather than normalizing it again
+        assert!(std::ptr::eq(paths.get(0).1, paths.get(2).1));
+        assert!(std::ptr::eq(paths.get(1).1, paths.get(3).1));
+    }
+}
@@ -0,0 +1,58 @@
+use std::any::{Any, TypeId};
+use std::collections::HashMap;
+use std::sync::{OnceLock, RwLock};
+
+/// Parsers of param types without a `FromStr` impl, by type
+static PARSERS: OnceLock<RwLock<HashMap<TypeId, Box<dyn Any + Send + Sync>>>> = OnceLock::new();
+
+/// Registers the parser of `{name: T registered}` params in `router!` for a type that doesn't
+/// implement `FromStr`, e.g. one of another crate, which the orphan rules keep from
+/// implementing it. Params without the `registered` marker are always parsed with `FromStr`.
+///
+/// The parser gets the percent-decoded value, and returning `None` makes the route not
+/// match. Registering a type again replaces its parser. Until a parser is registered for the
+/// type, routes with such params don't match.
+pub fn register_param_parser<T: 'static>(parser: fn(&str) -> Option<T>) {
+    PARSERS
+        .get_or_init(Default::default)
+        .write()
+        .unwrap_or_else(|poisoned| poisoned.into_inner())
+        .insert(TypeId::of::<T>(), Box::new(parser));
+}
+
+/// Parser registered for `T` by `register_param_parser`
+pub(crate) fn registered_parser<T: 'static>() -> Option<fn(&str) -> Option<T>> {
+    PARSERS
+        .get()?
+        .read()
+        .unwrap_or_else(|poisoned| poisoned.into_inner())
+        .get(&TypeId::of::<T>())
+        .and_then(|parser| parser.downcast_ref::<fn(&str) -> Option<T>>())
+        .copied()
+}
+
+/// Parses `value` with the parser registered for `T`, `None` if there is none
+pub(crate) fn parse_registered<T: 'static>(value: &str) -> Option<T> {
+    registered_parser::<T>()?(value)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    struct Unregistered;
+
+    #[test]
+    fn test_register_param_parser() {
+        assert!(registered_parser::<Unregistered>().is_none());
+
+        register_param_parser(|value: &str| value.parse().ok().map(Duration::from_secs));
+        assert_eq!(
+            parse_registered::<Duration>("90"),
+            Some(Duration::from_secs(90))
+        );
+        assert_eq!(parse_registered::<Duration>("1m"), None);
+        assert_eq!(parse_registered::<Unregistered>("value").map(|_| ()), None);
+    }
+}
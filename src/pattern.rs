@@ -0,0 +1,1196 @@
+use crate::error::{PatternParseError, RenderError};
+use crate::escape::decode_segment;
+use regex::{self, bytes, Captures, Regex};
+use std::any;
+use std::collections::HashSet;
+use std::str::{self, FromStr};
+use std::sync::OnceLock;
+
+/// A route template like `/users/{user_id}/transactions`, parsed at runtime
+#[derive(Debug, Clone)]
+pub struct RoutePattern {
+    template: String,
+    segments: Vec<Segment>,
+    separator: char,
+    leading: bool,
+    regex: Regex,
+    prefix_regex: Regex,
+    // made by `from_router`, whose params are decoded before checking their type
+    router: bool,
+    // only compiled for `captures_bytes`
+    bytes_regex: OnceLock<bytes::Regex>,
+    bytes_prefix_regex: OnceLock<bytes::Regex>,
+}
+
+/// One `/`-separated part of a route template
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Segment {
+    Literal(String),
+    /// `{name}`
+    Param(String),
+    /// `{name: type}`, only matching values of the type
+    TypedParam(String, ParamType),
+    /// `{name: ..}`, matching the rest of the path, separators included
+    Wildcard(String),
+    /// Segment followed by `?`, e.g. `{page: u32}?`, matching with or without it
+    Optional(Box<Segment>),
+}
+
+impl Segment {
+    /// Name of the param, `None` for literals
+    pub fn param_name(&self) -> Option<&str> {
+        match *self {
+            Segment::Literal(_) => None,
+            Segment::Param(ref name)
+            | Segment::TypedParam(ref name, _)
+            | Segment::Wildcard(ref name) => Some(name),
+            Segment::Optional(ref segment) => segment.param_name(),
+        }
+    }
+
+    /// Type of a `{name: type}` param
+    pub fn param_type(&self) -> Option<ParamType> {
+        match *self {
+            Segment::TypedParam(_, ty) => Some(ty),
+            Segment::Optional(ref segment) => segment.param_type(),
+            _ => None,
+        }
+    }
+}
+
+/// Params captured by `RoutePattern::captures_inline`, in the order of the template, stored
+/// inline for up to `N` params
+#[derive(Debug, Clone, Copy)]
+pub struct InlineCaptures<'p, 'a, const N: usize> {
+    entries: [(&'p str, &'a str); N],
+    // may exceed `N` while matching, the params past `N` aren't stored
+    len: usize,
+}
+
+impl<'p, 'a, const N: usize> InlineCaptures<'p, 'a, N> {
+    fn new() -> Self {
+        InlineCaptures {
+            entries: [("", ""); N],
+            len: 0,
+        }
+    }
+
+    fn push(&mut self, name: &'p str, value: &'a str) {
+        if let Some(entry) = self.entries.get_mut(self.len) {
+            *entry = (name, value);
+        }
+        self.len += 1;
+    }
+
+    /// Names and values of the params
+    pub fn as_slice(&self) -> &[(&'p str, &'a str)] {
+        &self.entries[..self.len.min(N)]
+    }
+
+    /// Value of the param named `name`
+    pub fn get(&self, name: &str) -> Option<&'a str> {
+        self.as_slice()
+            .iter()
+            .find(|&&(param, _)| param == name)
+            .map(|&(_, value)| value)
+    }
+
+    pub fn len(&self) -> usize {
+        self.as_slice().len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+macro_rules! param_types {
+    ($($variant:ident => $ty:ident,)*) => {
+        /// Type of a `{name: type}` param of a runtime template
+        #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+        #[non_exhaustive]
+        pub enum ParamType {
+            $($variant,)*
+        }
+
+        impl ParamType {
+            /// Type named like in Rust, e.g. `u32`
+            pub fn from_name(name: &str) -> Option<ParamType> {
+                match name {
+                    $(stringify!($ty) => Some(ParamType::$variant),)*
+                    _ => None,
+                }
+            }
+
+            /// Name as given by `std::any::type_name`
+            pub fn type_name(self) -> &'static str {
+                match self {
+                    $(ParamType::$variant => any::type_name::<$ty>(),)*
+                }
+            }
+
+            /// Whether `value` parses into the type
+            pub fn accepts(self, value: &str) -> bool {
+                match self {
+                    $(ParamType::$variant => value.parse::<$ty>().is_ok(),)*
+                }
+            }
+        }
+    };
+}
+
+param_types! {
+    String => String,
+    Bool => bool,
+    Char => char,
+    U8 => u8,
+    U16 => u16,
+    U32 => u32,
+    U64 => u64,
+    U128 => u128,
+    Usize => usize,
+    I8 => i8,
+    I16 => i16,
+    I32 => i32,
+    I64 => i64,
+    I128 => i128,
+    Isize => isize,
+    F32 => f32,
+    F64 => f64,
+}
+
+impl RoutePattern {
+    /// Parses a template, see `FromStr`. Params are written as `{name}`, or `{name: type}`
+    /// to only match values of the type, e.g. `/users/{user_id: usize}/posts`.
+    ///
+    /// `{name: ..}` matches the rest of the path, and a segment followed by `?` is optional,
+    /// e.g. `/posts/page/{page: u32}?`.
+    pub fn parse(template: &str) -> Result<RoutePattern, PatternParseError> {
+        RoutePattern::parse_with(template, '/', Syntax::Typed)
+    }
+
+    /// Parses a template whose segments are separated by `separator` instead of `/`,
+    /// e.g. `{tenant}.example.com` with `.`.
+    ///
+    /// The leading separator is optional here. Write `\.` to match a literal separator
+    /// inside a segment, e.g. `users\.v2`.
+    pub fn parse_with_separator(
+        template: &str,
+        separator: char,
+    ) -> Result<RoutePattern, PatternParseError> {
+        RoutePattern::parse_with(template, separator, Syntax::Typed)
+    }
+
+    /// Parses an OpenAPI path template like `/stores/{store-id}/orders/{orderId}`.
+    ///
+    /// Param names may contain any characters except `/`, `{` and `}`, and are kept as is,
+    /// so `params.raw("store-id")` works. A param must take a whole segment.
+    pub fn from_openapi(template: &str) -> Result<RoutePattern, PatternParseError> {
+        RoutePattern::parse_with(template, '/', Syntax::OpenApi)
+    }
+
+    fn parse_with(
+        template: &str,
+        separator: char,
+        syntax: Syntax,
+    ) -> Result<RoutePattern, PatternParseError> {
+        let leading = template.starts_with(separator);
+        if separator == '/' && !leading {
+            return Err(PatternParseError::MissingLeadingSlash {
+                template: template.to_string(),
+            });
+        }
+        let offset = if leading { separator.len_utf8() } else { 0 };
+        let rest = &template[offset..];
+        let mut segments = Vec::new();
+        let mut names = HashSet::new();
+        if !rest.is_empty() {
+            for (position, raw) in split_unescaped(rest, separator) {
+                let position = offset + position;
+                let segment = parse_segment(template, position, &raw, syntax)?;
+                if let Some(name) = segment.param_name() {
+                    if !names.insert(name.to_string()) {
+                        return Err(PatternParseError::DuplicateParam {
+                            template: template.to_string(),
+                            name: name.to_string(),
+                            position,
+                        });
+                    }
+                }
+                segments.push(segment);
+            }
+        }
+        let regex = Regex::new(&to_regex(&segments, separator, leading, false, false))
+            .expect("Failed to compile route regex");
+        let prefix_regex = Regex::new(&to_regex(&segments, separator, leading, true, false))
+            .expect("Failed to compile route regex");
+        Ok(RoutePattern {
+            template: template.to_string(),
+            segments,
+            separator,
+            leading,
+            regex,
+            prefix_regex,
+            router: false,
+            bytes_regex: OnceLock::new(),
+            bytes_prefix_regex: OnceLock::new(),
+        })
+    }
+
+    /// Parses a route as written in `router!`, e.g. the `pattern` of a `StaticRouteInfo`,
+    /// matching the same paths as the macro: params only match word characters, hyphens and
+    /// `%XX` escapes, and are decoded before their type is checked. Captured values are
+    /// returned as they are in the path.
+    ///
+    /// Types that aren't a `ParamType`, like `Uuid` or `[f64; 2]`, aren't checked. File
+    /// extension routes like `/reports/{id: u32}.json` aren't supported.
+    pub fn from_router(template: &str) -> Result<RoutePattern, PatternParseError> {
+        let mut source = "^".to_string();
+        let segments = parse_router_segments(template, &mut source)?;
+        let (source, prefix_source) = if segments.is_empty() {
+            ("^/$".to_string(), "^(.*)$".to_string())
+        } else {
+            (format!("{}$", source), format!("{}(/.*)?$", source))
+        };
+        let compile = |source: &str| Regex::new(source).expect("Failed to compile route regex");
+        let compile_bytes =
+            |source: &str| bytes::Regex::new(source).expect("Failed to compile route regex");
+        Ok(RoutePattern {
+            template: template.to_string(),
+            regex: compile(&source),
+            prefix_regex: compile(&prefix_source),
+            router: true,
+            bytes_regex: OnceLock::from(compile_bytes(&source)),
+            bytes_prefix_regex: OnceLock::from(compile_bytes(&prefix_source)),
+            segments,
+            separator: '/',
+            leading: true,
+        })
+    }
+
+    /// The original template string
+    pub fn template(&self) -> &str {
+        &self.template
+    }
+
+    /// Parsed segments of the template
+    pub fn segments(&self) -> &[Segment] {
+        &self.segments
+    }
+
+    /// Score of how specific the pattern is, greater for patterns matching fewer paths:
+    /// the number of literal segments, then how tight the params are. Typed params other than
+    /// `String` score 2, other params 1, and `{name: ..}` and optional segments 0.
+    ///
+    /// E.g. `/users/me` scores `(2, 0)`, `/users/{id: u32}` `(1, 2)` and `/users/{name}` `(1, 1)`.
+    pub fn specificity(&self) -> (usize, usize) {
+        self.segments
+            .iter()
+            .fold((0, 0), |(literals, tightness), segment| match *segment {
+                Segment::Literal(_) => (literals + 1, tightness),
+                Segment::TypedParam(_, ty) if ty != ParamType::String => (literals, tightness + 2),
+                Segment::Param(_) | Segment::TypedParam(..) => (literals, tightness + 1),
+                Segment::Wildcard(_) | Segment::Optional(_) => (literals, tightness),
+            })
+    }
+
+    pub(crate) fn separator(&self) -> char {
+        self.separator
+    }
+
+    /// Builds a path matching the pattern from param values, e.g. `/users/12` from `/users/{id}`.
+    ///
+    /// Optional params without a value are left out, optional literals are kept.
+    pub fn render(&self, params: &[(&str, &str)]) -> Result<String, RenderError> {
+        let mut path = String::new();
+        for segment in &self.segments {
+            let (segment, optional) = match *segment {
+                Segment::Optional(ref segment) => (&**segment, true),
+                ref segment => (segment, false),
+            };
+            let name = match segment.param_name() {
+                Some(name) => name,
+                None => {
+                    if self.leading || !path.is_empty() {
+                        path.push(self.separator);
+                    }
+                    if let Segment::Literal(ref literal) = *segment {
+                        path.push_str(literal);
+                    }
+                    continue;
+                }
+            };
+            let value = match params.iter().find(|&&(param, _)| param == name) {
+                Some(&(_, value)) => value,
+                None if optional => continue,
+                None => {
+                    return Err(RenderError::MissingParam {
+                        template: self.template.clone(),
+                        name: name.to_string(),
+                    })
+                }
+            };
+            let valid = match *segment {
+                Segment::Wildcard(_) => !value.is_empty(),
+                _ => {
+                    !value.is_empty()
+                        && !value.contains(self.separator)
+                        && segment.param_type().map_or(true, |ty| ty.accepts(value))
+                }
+            };
+            if !valid {
+                return Err(RenderError::InvalidValue {
+                    template: self.template.clone(),
+                    name: name.to_string(),
+                    value: value.to_string(),
+                });
+            }
+            if self.leading || !path.is_empty() {
+                path.push(self.separator);
+            }
+            path.push_str(value);
+        }
+        if path.is_empty() && self.leading {
+            path.push(self.separator);
+        }
+        Ok(path)
+    }
+
+    /// Whether both patterns match the same paths, i.e. they differ at most in param names
+    pub fn is_equivalent(&self, other: &RoutePattern) -> bool {
+        // param names don't make it into the regex, types of typed params don't either
+        self.regex.as_str() == other.regex.as_str() && self.param_types().eq(other.param_types())
+    }
+
+    fn param_types(&self) -> impl Iterator<Item = Option<ParamType>> + '_ {
+        self.segments.iter().map(Segment::param_type)
+    }
+
+    /// Matches `path` against the pattern, returning the captured params in order
+    pub fn captures<'a>(&self, path: &'a str) -> Option<Vec<(&str, &'a str)>> {
+        let captures = self.regex.captures(path)?;
+        self.params(&captures)
+    }
+
+    /// Matches the start of `path` against the pattern, returning the captured params and
+    /// the rest of the path, e.g. `/a/b` for `/files` and `/files/a/b`.
+    ///
+    /// Only whole segments match, so `/files` doesn't match `/filesystem`. The rest is empty
+    /// if the whole path matched, and the home pattern `/` matches every path as the rest.
+    pub fn captures_prefix<'a>(&self, path: &'a str) -> Option<(Vec<(&str, &'a str)>, &'a str)> {
+        let captures = self.prefix_regex.captures(path)?;
+        let rest = captures
+            .get(captures.len() - 1)
+            .map_or("", |rest| rest.as_str());
+        Some((self.params(&captures)?, rest))
+    }
+
+    /// Same as `captures`, but stores the params inline instead of in a `Vec`, so matching
+    /// doesn't allocate. Returns `None` if the path doesn't match, or if it has more than `N`
+    /// params.
+    ///
+    /// Walks the segments instead of running the regex, same as the regex would match them.
+    /// Patterns made by `from_router` run their regex, so they do allocate.
+    pub fn captures_inline<'a, const N: usize>(
+        &self,
+        path: &'a str,
+    ) -> Option<InlineCaptures<'_, 'a, N>> {
+        let mut captures = InlineCaptures::new();
+        if self.router {
+            for (name, value) in self.captures(path)? {
+                captures.push(name, value);
+            }
+            return if captures.len > N {
+                None
+            } else {
+                Some(captures)
+            };
+        }
+        let matched = if self.segments.is_empty() {
+            // handle home case
+            path == if self.leading {
+                self.separator_str()
+            } else {
+                ""
+            }
+        } else {
+            self.match_from(&self.segments, path, 0, self.leading, &mut captures)
+                || (self.leading
+                    && path == self.separator_str()
+                    && self
+                        .segments
+                        .iter()
+                        .all(|segment| matches!(*segment, Segment::Optional(_))))
+        };
+        if !matched || captures.len > N {
+            return None;
+        }
+        let typed = captures.as_slice().iter().all(|&(name, value)| {
+            self.segments
+                .iter()
+                .find(|segment| segment.param_name() == Some(name))
+                .and_then(Segment::param_type)
+                .map_or(true, |ty| self.accepts(ty, value))
+        });
+        if typed {
+            Some(captures)
+        } else {
+            None
+        }
+    }
+
+    fn separator_str(&self) -> &str {
+        // the template starts with the separator if `leading`
+        &self.template[..self.separator.len_utf8()]
+    }
+
+    /// Whether `segments` match `path` from `pos` to the end, trying the alternatives in the
+    /// order the regex would
+    fn match_from<'p, 'a, const N: usize>(
+        &self,
+        segments: &'p [Segment],
+        path: &'a str,
+        pos: usize,
+        with_separator: bool,
+        captures: &mut InlineCaptures<'p, 'a, N>,
+    ) -> bool {
+        let (segment, rest) = match segments.split_first() {
+            Some(split) => split,
+            None => return pos == path.len(),
+        };
+        match *segment {
+            Segment::Optional(ref segment) => {
+                let len = captures.len;
+                if self.match_segment(segment, rest, path, pos, with_separator, captures) {
+                    return true;
+                }
+                captures.len = len;
+                self.match_from(rest, path, pos, true, captures)
+            }
+            ref segment => self.match_segment(segment, rest, path, pos, with_separator, captures),
+        }
+    }
+
+    fn match_segment<'p, 'a, const N: usize>(
+        &self,
+        segment: &'p Segment,
+        rest: &'p [Segment],
+        path: &'a str,
+        mut pos: usize,
+        with_separator: bool,
+        captures: &mut InlineCaptures<'p, 'a, N>,
+    ) -> bool {
+        if with_separator {
+            if !path[pos..].starts_with(self.separator) {
+                return false;
+            }
+            pos += self.separator.len_utf8();
+        }
+        let tail = &path[pos..];
+        // longest run of characters the param may match
+        let run = |matches: &dyn Fn(char) -> bool| tail.find(|c| !matches(c)).unwrap_or(tail.len());
+        let (name, run) = match *segment {
+            Segment::Literal(ref literal) => {
+                return tail.starts_with(literal.as_str())
+                    && self.match_from(rest, path, pos + literal.len(), true, captures);
+            }
+            Segment::Param(ref name) => (
+                name,
+                run(&|c| c == '-' || regex_syntax::is_word_character(c)),
+            ),
+            Segment::TypedParam(ref name, _) => (name, run(&|c| c != self.separator)),
+            Segment::Wildcard(ref name) => (name, run(&|c| c != '\n')),
+            Segment::Optional(_) => unreachable!(),
+        };
+        // greedy, like the regex
+        let len = captures.len;
+        for end in (1..=run).rev().filter(|&end| tail.is_char_boundary(end)) {
+            captures.push(name, &tail[..end]);
+            if self.match_from(rest, path, pos + end, true, captures) {
+                return true;
+            }
+            captures.len = len;
+        }
+        false
+    }
+
+    /// Same as `captures`, but for paths that may not be valid UTF-8.
+    ///
+    /// Params (but not typed ones) and wildcards also match bytes that aren't valid UTF-8.
+    pub fn captures_bytes<'a>(&self, path: &'a [u8]) -> Option<Vec<(&str, &'a [u8])>> {
+        let captures = self.bytes_regex(false).captures(path)?;
+        self.params_bytes(&captures)
+    }
+
+    /// Same as `captures_prefix`, but for paths that may not be valid UTF-8
+    #[allow(clippy::type_complexity)]
+    pub fn captures_prefix_bytes<'a>(
+        &self,
+        path: &'a [u8],
+    ) -> Option<(Vec<(&str, &'a [u8])>, &'a [u8])> {
+        let captures = self.bytes_regex(true).captures(path)?;
+        let rest = captures
+            .get(captures.len() - 1)
+            .map_or(&b""[..], |rest| rest.as_bytes());
+        Some((self.params_bytes(&captures)?, rest))
+    }
+
+    fn bytes_regex(&self, prefix: bool) -> &bytes::Regex {
+        let regex = if prefix {
+            &self.bytes_prefix_regex
+        } else {
+            &self.bytes_regex
+        };
+        regex.get_or_init(|| {
+            bytes::Regex::new(&to_regex(
+                &self.segments,
+                self.separator,
+                self.leading,
+                prefix,
+                true,
+            ))
+            .expect("Failed to compile route regex")
+        })
+    }
+
+    /// Name of the first typed param that doesn't parse into its type, if `path` matches the
+    /// pattern otherwise
+    #[cfg(feature = "trace")]
+    pub(crate) fn rejected_param(&self, path: &str, prefix: bool) -> Option<&str> {
+        let regex = if prefix {
+            &self.prefix_regex
+        } else {
+            &self.regex
+        };
+        let captures = regex.captures(path)?;
+        self.segments
+            .iter()
+            .filter(|segment| segment.param_name().is_some())
+            .zip(captures.iter().skip(1))
+            .find(|&(segment, value)| match (segment.param_type(), value) {
+                (Some(ty), Some(value)) => !self.accepts(ty, value.as_str()),
+                _ => false,
+            })
+            .and_then(|(segment, _)| segment.param_name())
+    }
+
+    /// Whether a captured value parses into the type of its param
+    fn accepts(&self, ty: ParamType, value: &str) -> bool {
+        if self.router {
+            decode_segment(value).is_ok_and(|value| ty.accepts(&value))
+        } else {
+            ty.accepts(value)
+        }
+    }
+
+    /// Captured params, `None` if a typed param doesn't parse into its type
+    fn params<'a>(&self, captures: &Captures<'a>) -> Option<Vec<(&str, &'a str)>> {
+        let params = self
+            .segments
+            .iter()
+            .filter(|segment| segment.param_name().is_some());
+        let mut values = Vec::new();
+        for (segment, value) in params.zip(captures.iter().skip(1)) {
+            let value = match value {
+                Some(value) => value.as_str(),
+                None => continue,
+            };
+            if !segment
+                .param_type()
+                .map_or(true, |ty| self.accepts(ty, value))
+            {
+                return None;
+            }
+            values.push((segment.param_name()?, value));
+        }
+        Some(values)
+    }
+
+    fn params_bytes<'a>(&self, captures: &bytes::Captures<'a>) -> Option<Vec<(&str, &'a [u8])>> {
+        let params = self
+            .segments
+            .iter()
+            .filter(|segment| segment.param_name().is_some());
+        let mut values = Vec::new();
+        for (segment, value) in params.zip(captures.iter().skip(1)) {
+            let value = match value {
+                Some(value) => value.as_bytes(),
+                None => continue,
+            };
+            if let Some(ty) = segment.param_type() {
+                if !str::from_utf8(value).is_ok_and(|value| self.accepts(ty, value)) {
+                    return None;
+                }
+            }
+            values.push((segment.param_name()?, value));
+        }
+        Some(values)
+    }
+}
+
+impl FromStr for RoutePattern {
+    type Err = PatternParseError;
+
+    fn from_str(template: &str) -> Result<RoutePattern, PatternParseError> {
+        RoutePattern::parse(template)
+    }
+}
+
+/// Names of the params of a template in order, e.g. `["user_id", "hash"]` for
+/// `/users/{user_id}/tx/{hash}`. Types like in `{id: u32}` are left out.
+///
+/// Doesn't validate the template, an unclosed `{` is ignored.
+pub fn param_names(pattern: &str) -> Vec<String> {
+    let mut names = Vec::new();
+    let mut rest = pattern;
+    while let Some(start) = rest.find('{') {
+        let end = match rest[start..].find('}') {
+            Some(end) => start + end,
+            None => break,
+        };
+        let param = &rest[start + 1..end];
+        names.push(param.split(':').next().unwrap_or("").trim().to_string());
+        rest = &rest[end + 1..];
+    }
+    names
+}
+
+/// Param syntax of a template
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Syntax {
+    /// `{name}`, `{name: type}`, `{name: ..}` and optional segments
+    Typed,
+    /// Only `{name}`, where the name may contain anything but braces
+    OpenApi,
+}
+
+/// Segments of a route as written in `router!`, appending their regex to `source`
+pub(crate) fn parse_router_segments(
+    template: &str,
+    source: &mut String,
+) -> Result<Vec<Segment>, PatternParseError> {
+    if !template.starts_with('/') {
+        return Err(PatternParseError::MissingLeadingSlash {
+            template: template.to_string(),
+        });
+    }
+    let mut segments = Vec::new();
+    let mut names = HashSet::new();
+    if template != "/" {
+        for (position, raw) in split_unescaped(&template[1..], '/') {
+            let position = position + 1;
+            source.push('/');
+            let segment = parse_router_segment(template, position, &raw, source)?;
+            if let Some(name) = segment.param_name() {
+                if !names.insert(name.to_string()) {
+                    return Err(PatternParseError::DuplicateParam {
+                        template: template.to_string(),
+                        name: name.to_string(),
+                        position,
+                    });
+                }
+            }
+            segments.push(segment);
+        }
+    }
+    Ok(segments)
+}
+
+fn parse_router_segment(
+    template: &str,
+    position: usize,
+    raw: &str,
+    source: &mut String,
+) -> Result<Segment, PatternParseError> {
+    if raw.is_empty() {
+        return Err(PatternParseError::EmptySegment {
+            template: template.to_string(),
+            position,
+        });
+    }
+    if !raw.starts_with('{') {
+        crate::__http_router_push_literal(source, raw);
+        return Ok(Segment::Literal(raw.to_string()));
+    }
+    if !raw.contains('}') {
+        return Err(PatternParseError::UnclosedParam {
+            template: template.to_string(),
+            position,
+        });
+    }
+    let param = raw[1..].trim_end_matches('}');
+    let (name, ty) = param
+        .split_once(':')
+        .map_or((param.trim(), ""), |(name, ty)| (name.trim(), ty.trim()));
+    if !raw.ends_with('}') || name.is_empty() || !is_param_name(name) {
+        return Err(PatternParseError::InvalidParam {
+            template: template.to_string(),
+            segment: raw.to_string(),
+            position,
+        });
+    }
+    if ty == ".." {
+        source.push_str("(.+)");
+        return Ok(Segment::Wildcard(name.to_string()));
+    }
+    crate::__http_router_push_param_regex(source, raw);
+    // e.g. `u32` of `{id: u32 len 1..=9}`
+    let ty = ty.split_whitespace().next().and_then(ParamType::from_name);
+    Ok(match ty {
+        Some(ty) => Segment::TypedParam(name.to_string(), ty),
+        None => Segment::Param(name.to_string()),
+    })
+}
+
+fn is_param_name(name: &str) -> bool {
+    name.chars().all(|c| c.is_alphanumeric() || c == '_')
+}
+
+/// Splits `s` on `separator`, turning `\<separator>` into a literal separator.
+/// Parts come with their byte offset in `s`.
+fn split_unescaped(s: &str, separator: char) -> Vec<(usize, String)> {
+    let mut parts = vec![(0, String::new())];
+    let mut chars = s.char_indices().peekable();
+    while let Some((i, c)) = chars.next() {
+        if c == '\\' && chars.peek().map(|&(_, next)| next) == Some(separator) {
+            parts.last_mut().unwrap().1.push(separator);
+            chars.next();
+        } else if c == separator {
+            parts.push((i + c.len_utf8(), String::new()));
+        } else {
+            parts.last_mut().unwrap().1.push(c);
+        }
+    }
+    parts
+}
+
+fn parse_segment(
+    template: &str,
+    position: usize,
+    raw: &str,
+    syntax: Syntax,
+) -> Result<Segment, PatternParseError> {
+    let invalid_param = || PatternParseError::InvalidParam {
+        template: template.to_string(),
+        segment: raw.to_string(),
+        position,
+    };
+    if raw.is_empty() {
+        return Err(PatternParseError::EmptySegment {
+            template: template.to_string(),
+            position,
+        });
+    }
+    if syntax == Syntax::Typed && raw.len() > 1 && raw.ends_with('?') {
+        let segment = parse_segment(template, position, &raw[..raw.len() - 1], syntax)?;
+        return Ok(Segment::Optional(Box::new(segment)));
+    }
+    if raw.starts_with('{') && !raw.contains('}') {
+        return Err(PatternParseError::UnclosedParam {
+            template: template.to_string(),
+            position,
+        });
+    }
+    if !raw.starts_with('{') && !raw.ends_with('}') {
+        return Ok(Segment::Literal(raw.to_string()));
+    }
+    if !raw.starts_with('{') || !raw.ends_with('}') {
+        return Err(invalid_param());
+    }
+    let param = &raw[1..raw.len() - 1];
+    let (name, ty) = match param.find(':') {
+        Some(colon) if syntax == Syntax::Typed => {
+            (param[..colon].trim(), Some(param[colon + 1..].trim()))
+        }
+        _ => (param.trim(), None),
+    };
+    let valid_name = match syntax {
+        Syntax::Typed => is_param_name(name),
+        Syntax::OpenApi => !name.contains(['{', '}']),
+    };
+    if name.is_empty() || !valid_name {
+        return Err(invalid_param());
+    }
+    let name = name.to_string();
+    match ty {
+        None => Ok(Segment::Param(name)),
+        Some("..") => Ok(Segment::Wildcard(name)),
+        Some(ty) => match ParamType::from_name(ty) {
+            Some(ty) => Ok(Segment::TypedParam(name, ty)),
+            None => Err(PatternParseError::UnknownParamType {
+                template: template.to_string(),
+                type_name: ty.to_string(),
+                position,
+            }),
+        },
+    }
+}
+
+/// Regex of the pattern. With `prefix`, it also captures the rest of the path as the last group.
+/// With `bytes`, params also match bytes that aren't valid UTF-8, for `regex::bytes`.
+fn to_regex(
+    segments: &[Segment],
+    separator: char,
+    leading: bool,
+    prefix: bool,
+    bytes: bool,
+) -> String {
+    let escaped = regex::escape(&separator.to_string());
+    let mut s = "^".to_string();
+    if prefix && segments.is_empty() {
+        s.push_str("(.*)$");
+        return s;
+    }
+    for (i, segment) in segments.iter().enumerate() {
+        push_segment(&mut s, segment, separator, leading || i > 0, bytes);
+    }
+    // handle home case
+    if s.len() == 1 && leading {
+        s.push_str(&escaped)
+    } else if leading
+        && segments
+            .iter()
+            .all(|segment| matches!(*segment, Segment::Optional(_)))
+    {
+        // all segments left out, e.g. `/` for `/{page}?`
+        s = format!("^(?:{}|{})", &s[1..], escaped);
+    }
+    if prefix {
+        s.push_str(&format!("({}.*)?", escaped));
+    }
+    s.push('$');
+    s
+}
+
+fn push_segment(
+    s: &mut String,
+    segment: &Segment,
+    separator: char,
+    with_separator: bool,
+    bytes: bool,
+) {
+    let escaped = regex::escape(&separator.to_string());
+    if let Segment::Optional(ref segment) = *segment {
+        s.push_str("(?:");
+        push_segment(s, segment, separator, with_separator, bytes);
+        s.push_str(")?");
+        return;
+    }
+    if with_separator {
+        s.push_str(&escaped);
+    }
+    match *segment {
+        Segment::Literal(ref literal) => s.push_str(&regex::escape(literal)),
+        Segment::Param(_) if bytes => s.push_str(r#"((?:[\w-]|(?-u:[\x80-\xFF]))+)"#),
+        Segment::Param(_) => s.push_str(r#"([\w-]+)"#),
+        // values are checked against the type after matching
+        Segment::TypedParam(..) => s.push_str(&format!("([^{}]+)", escaped)),
+        Segment::Wildcard(_) if bytes => s.push_str("((?-u:.)+)"),
+        Segment::Wildcard(_) => s.push_str("(.+)"),
+        Segment::Optional(_) => unreachable!(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_separator_escape() {
+        let pattern = RoutePattern::parse_with_separator(r"users\.v2/info.{id}", '.').unwrap();
+        assert_eq!(
+            pattern.segments(),
+            &[
+                Segment::Literal("users.v2/info".to_string()),
+                Segment::Param("id".to_string()),
+            ]
+        );
+        assert_eq!(
+            pattern.captures("users.v2/info.12"),
+            Some(vec![("id", "12")])
+        );
+        assert_eq!(pattern.captures("users.v2/info"), None);
+        assert_eq!(pattern.captures("usersXv2/info.12"), None);
+
+        let pattern = RoutePattern::parse_with_separator(r"users\.v2/info", '.').unwrap();
+        assert_eq!(pattern.captures("users.v2/info"), Some(vec![]));
+        assert_eq!(pattern.captures(".users.v2/info"), None);
+
+        let pattern = RoutePattern::parse(r"/files/a\/b/{id}").unwrap();
+        assert_eq!(pattern.captures("/files/a/b/7"), Some(vec![("id", "7")]));
+    }
+
+    #[test]
+    fn test_captures_prefix() {
+        let pattern = RoutePattern::parse("/users/{id}/files").unwrap();
+        assert_eq!(
+            pattern.captures_prefix("/users/12/files/a/b.txt"),
+            Some((vec![("id", "12")], "/a/b.txt"))
+        );
+        assert_eq!(
+            pattern.captures_prefix("/users/12/files"),
+            Some((vec![("id", "12")], ""))
+        );
+        assert_eq!(pattern.captures_prefix("/users/12/filesystem"), None);
+        assert_eq!(pattern.captures("/users/12/files/a"), None);
+
+        let home = RoutePattern::parse("/").unwrap();
+        assert_eq!(home.captures_prefix("/a/b"), Some((vec![], "/a/b")));
+        assert_eq!(home.captures_prefix("/"), Some((vec![], "/")));
+    }
+
+    #[test]
+    fn test_from_str() {
+        let pattern: RoutePattern = "/users/{user_id: usize}/posts".parse().unwrap();
+        assert_eq!(
+            pattern.segments(),
+            &[
+                Segment::Literal("users".to_string()),
+                Segment::TypedParam("user_id".to_string(), ParamType::Usize),
+                Segment::Literal("posts".to_string()),
+            ]
+        );
+        assert_eq!(
+            pattern.captures("/users/12/posts"),
+            Some(vec![("user_id", "12")])
+        );
+        assert_eq!(pattern.captures("/users/-1/posts"), None);
+        assert_eq!(pattern.captures("/users/alice/posts"), None);
+
+        let pattern: RoutePattern = "/files/{path: ..}".parse().unwrap();
+        assert_eq!(
+            pattern.captures("/files/a/b.txt"),
+            Some(vec![("path", "a/b.txt")])
+        );
+        assert_eq!(pattern.captures("/files"), None);
+
+        let pattern: RoutePattern = "/posts/page?/{page: u32}?".parse().unwrap();
+        assert_eq!(pattern.captures("/posts"), Some(vec![]));
+        assert_eq!(pattern.captures("/posts/page/2"), Some(vec![("page", "2")]));
+        assert_eq!(pattern.captures("/posts/2"), Some(vec![("page", "2")]));
+        assert_eq!(pattern.captures("/posts/page/x"), None);
+        assert_eq!(pattern.render(&[]), Ok("/posts/page".to_string()));
+
+        let pattern: RoutePattern = "/{lang}?".parse().unwrap();
+        assert_eq!(pattern.captures("/"), Some(vec![]));
+        assert_eq!(pattern.captures("/en"), Some(vec![("lang", "en")]));
+
+        let typed: RoutePattern = "/users/{id: u32}".parse().unwrap();
+        let other: RoutePattern = "/users/{user_id: u32}".parse().unwrap();
+        let float: RoutePattern = "/users/{id: f64}".parse().unwrap();
+        assert!(typed.is_equivalent(&other));
+        assert!(!typed.is_equivalent(&float));
+    }
+
+    #[test]
+    fn test_from_str_errors() {
+        assert_eq!(
+            "/users/{id: u32".parse::<RoutePattern>().unwrap_err(),
+            PatternParseError::UnclosedParam {
+                template: "/users/{id: u32".to_string(),
+                position: 7,
+            }
+        );
+        assert_eq!(
+            "/users/{id: uint}".parse::<RoutePattern>().unwrap_err(),
+            PatternParseError::UnknownParamType {
+                template: "/users/{id: uint}".to_string(),
+                type_name: "uint".to_string(),
+                position: 7,
+            }
+        );
+        assert_eq!(
+            "/users/{id}/posts/{id: u32}"
+                .parse::<RoutePattern>()
+                .unwrap_err(),
+            PatternParseError::DuplicateParam {
+                template: "/users/{id}/posts/{id: u32}".to_string(),
+                name: "id".to_string(),
+                position: 18,
+            }
+        );
+    }
+
+    #[test]
+    fn test_captures_bytes() {
+        let pattern = RoutePattern::parse("/files/{name}/{id: u32}").unwrap();
+        assert_eq!(
+            pattern.captures_bytes(b"/files/caf\xE9/12"),
+            Some(vec![("name", &b"caf\xE9"[..]), ("id", &b"12"[..])])
+        );
+        assert_eq!(
+            pattern.captures_bytes("/files/café/12".as_bytes()),
+            Some(vec![("name", "café".as_bytes()), ("id", &b"12"[..])])
+        );
+        assert_eq!(pattern.captures_bytes(b"/files/caf\xE9/\xFF"), None);
+        assert_eq!(pattern.captures_bytes(b"/files/a.b/12"), None);
+
+        let pattern = RoutePattern::parse("/static/{path: ..}").unwrap();
+        assert_eq!(
+            pattern.captures_prefix_bytes(b"/static/a\xFF/b"),
+            Some((vec![("path", &b"a\xFF/b"[..])], &b""[..]))
+        );
+    }
+
+    #[test]
+    fn test_captures_inline() {
+        let templates = [
+            "/",
+            "/users",
+            "/users/{id: u32}/posts/{slug}",
+            "/files/{path: ..}",
+            "/files/{path: ..}/edit",
+            "/posts/page/{page: u32}?",
+            "/{lang}?/{page}?",
+            "/café/{name}",
+        ];
+        let paths = [
+            "",
+            "/",
+            "/users",
+            "/users/",
+            "/users/12/posts/hello-world",
+            "/users/x/posts/hello-world",
+            "/users/12/posts/a.b",
+            "/files/a/b/edit",
+            "/files/a/b",
+            "/files/a\nb",
+            "/posts/page",
+            "/posts/page/3",
+            "/en/about",
+            "/about",
+            "/café/zoë",
+        ];
+        for template in templates.iter() {
+            let pattern = RoutePattern::parse(template).unwrap();
+            for path in paths.iter() {
+                assert_eq!(
+                    pattern
+                        .captures_inline::<4>(path)
+                        .map(|captures| captures.as_slice().to_vec()),
+                    pattern.captures(path),
+                    "{} for {}",
+                    template,
+                    path
+                );
+            }
+        }
+
+        let pattern = RoutePattern::parse_with_separator(r"{tenant}.example\.com", '.').unwrap();
+        let captures = pattern.captures_inline::<1>("acme.example.com").unwrap();
+        assert_eq!(captures.get("tenant"), Some("acme"));
+        assert_eq!(captures.len(), 1);
+
+        let pattern = RoutePattern::parse("/users/{id}/posts/{slug}").unwrap();
+        assert!(pattern.captures_inline::<1>("/users/12/posts/a").is_none());
+        assert!(pattern.captures_inline::<2>("/users/12/posts/a").is_some());
+    }
+
+    #[test]
+    fn test_param_names() {
+        assert_eq!(
+            param_names("/users/{user_id}/tx/{hash}"),
+            vec!["user_id", "hash"]
+        );
+        assert_eq!(
+            param_names("/users/{id: u32}/files/{path: ..}"),
+            vec!["id", "path"]
+        );
+        assert_eq!(param_names("/stores/{store-id}"), vec!["store-id"]);
+        assert_eq!(param_names("/users"), Vec::<String>::new());
+        assert_eq!(param_names("/users/{id"), Vec::<String>::new());
+    }
+
+    #[test]
+    fn test_render() {
+        let pattern = RoutePattern::parse("/users/{id}/posts/{slug}").unwrap();
+        assert_eq!(
+            pattern.render(&[("slug", "hello"), ("id", "12")]),
+            Ok("/users/12/posts/hello".to_string())
+        );
+        assert_eq!(
+            pattern.render(&[("id", "12"), ("slug", "a/b")]),
+            Err(RenderError::InvalidValue {
+                template: "/users/{id}/posts/{slug}".to_string(),
+                name: "slug".to_string(),
+                value: "a/b".to_string(),
+            })
+        );
+        assert_eq!(
+            RoutePattern::parse("/").unwrap().render(&[]),
+            Ok("/".to_string())
+        );
+        let host = RoutePattern::parse_with_separator("{tenant}.example.com", '.').unwrap();
+        assert_eq!(
+            host.render(&[("tenant", "acme")]),
+            Ok("acme.example.com".to_string())
+        );
+    }
+
+    #[test]
+    fn test_from_router() {
+        let pattern = RoutePattern::from_router("/users/{id: u32}/files/{path: ..}").unwrap();
+        assert_eq!(
+            pattern.segments(),
+            &[
+                Segment::Literal("users".to_string()),
+                Segment::TypedParam("id".to_string(), ParamType::U32),
+                Segment::Literal("files".to_string()),
+                Segment::Wildcard("path".to_string()),
+            ]
+        );
+        assert_eq!(
+            pattern.captures("/users/1%32/files/a/b"),
+            Some(vec![("id", "1%32"), ("path", "a/b")])
+        );
+        assert_eq!(pattern.captures("/users/+1/files/a"), None);
+        assert_eq!(pattern.captures("/users/x/files/a"), None);
+        assert_eq!(
+            pattern
+                .captures_inline::<2>("/users/12/files/a")
+                .map(|captures| captures.len()),
+            Some(2)
+        );
+        assert_eq!(
+            pattern.captures_bytes(b"/users/12/files/a"),
+            Some(vec![("id", &b"12"[..]), ("path", &b"a"[..])])
+        );
+        assert_eq!(
+            pattern.captures_prefix("/users/12/files/a"),
+            Some((vec![("id", "12"), ("path", "a")], ""))
+        );
+
+        let name = RoutePattern::from_router("/tags/{name: String len 3..=8}").unwrap();
+        assert!(name.captures("/tags/abc").is_some());
+        assert!(name.captures("/tags/ab").is_none());
+        assert!(name.captures("/tags/a.b").is_none());
+        let home = RoutePattern::from_router("/").unwrap();
+        assert_eq!(home.captures("/"), Some(vec![]));
+        assert_eq!(home.captures_prefix("/users"), Some((vec![], "/users")));
+
+        assert!(matches!(
+            RoutePattern::from_router("/reports/{id: u32}.json"),
+            Err(PatternParseError::InvalidParam { .. })
+        ));
+        assert!(matches!(
+            RoutePattern::from_router("/users/{id"),
+            Err(PatternParseError::UnclosedParam { .. })
+        ));
+        assert!(matches!(
+            RoutePattern::from_router("/{id: u32}/{id: u32}"),
+            Err(PatternParseError::DuplicateParam { .. })
+        ));
+    }
+
+    #[test]
+    fn test_specificity() {
+        let specificity = |template| RoutePattern::parse(template).unwrap().specificity();
+        assert_eq!(specificity("/users/me"), (2, 0));
+        assert_eq!(specificity("/users/{id: u32}"), (1, 2));
+        assert_eq!(specificity("/users/{name: String}"), (1, 1));
+        assert_eq!(specificity("/users/{name}"), (1, 1));
+        assert_eq!(specificity("/users/{path: ..}"), (1, 0));
+        assert_eq!(specificity("/users/{id: u32}/posts/{page: u32}?"), (2, 2));
+        assert!(specificity("/users/me") > specificity("/users/{id: u32}"));
+    }
+}
@@ -0,0 +1,28 @@
+use crate::pattern::RoutePattern;
+use std::hint::black_box;
+use std::time::{Duration, Instant};
+
+/// Times matching `path` against `pattern` `iterations` times, e.g. to find slow regexes.
+///
+/// Returns the total time, divide by `iterations` for the cost of one match.
+pub fn bench_route(pattern: &RoutePattern, path: &str, iterations: u32) -> Duration {
+    let start = Instant::now();
+    for _ in 0..iterations {
+        black_box(pattern.captures(black_box(path)));
+    }
+    start.elapsed()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_bench_route() {
+        let pattern =
+            RoutePattern::parse("/users/{user_id}/transactions/{hash}/items/{item_id}").unwrap();
+        let elapsed = bench_route(&pattern, "/users/12/transactions/0x234/items/7", 1000);
+        assert!(elapsed > Duration::from_nanos(0));
+        assert!(elapsed < Duration::from_secs(10));
+    }
+}
@@ -0,0 +1,92 @@
+use crate::error::QueryError;
+use crate::escape::decode_segment;
+use std::borrow::Cow;
+use std::str::FromStr;
+
+/// Typed access to the query string of a request, which the router doesn't match, so
+/// handlers can answer 400 Bad Request when a required param is missing instead of routing on
+/// to the fallback.
+///
+/// Values are percent-decoded with `+` as a space, and the first of repeated params wins.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Query<'a> {
+    query: &'a str,
+}
+
+impl<'a> Query<'a> {
+    /// Query string with or without its leading `?`, e.g. `q=rust&page=2`
+    pub fn new(query: &'a str) -> Self {
+        Query {
+            query: query.strip_prefix('?').unwrap_or(query),
+        }
+    }
+
+    /// Decoded value of the param named `name`, `None` if it is missing or doesn't decode to
+    /// UTF-8. A param without `=` has an empty value.
+    pub fn raw(&self, name: &str) -> Option<Cow<'a, str>> {
+        self.query
+            .split('&')
+            .map(|pair| pair.split_once('=').unwrap_or((pair, "")))
+            .find(|&(key, _)| decode(key).as_deref() == Some(name))
+            .and_then(|(_, value)| decode(value))
+    }
+
+    /// Parses the required param named `name` into `T`, failing with
+    /// `QueryError::BadParam` if it is missing or can't be parsed
+    pub fn required<T: FromStr>(&self, name: &str) -> Result<T, QueryError> {
+        self.optional(name)?.ok_or_else(|| bad_param(name))
+    }
+
+    /// Parses the param named `name` into `T` if it is present, failing with
+    /// `QueryError::BadParam` if it can't be parsed
+    pub fn optional<T: FromStr>(&self, name: &str) -> Result<Option<T>, QueryError> {
+        match self.raw(name) {
+            Some(value) => value.parse().map(Some).map_err(|_| bad_param(name)),
+            None => Ok(None),
+        }
+    }
+}
+
+fn decode(value: &str) -> Option<Cow<'_, str>> {
+    if value.contains('+') {
+        let spaced = value.replace('+', " ");
+        return decode_segment(&spaced)
+            .ok()
+            .map(|decoded| Cow::Owned(decoded.into_owned()));
+    }
+    decode_segment(value).ok()
+}
+
+fn bad_param(name: &str) -> QueryError {
+    QueryError::BadParam {
+        name: name.to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_query() {
+        let query = Query::new("?q=rust+macros&page=2&page=3&debug&name=j%C3%B6rg");
+        assert_eq!(query.raw("q").as_deref(), Some("rust macros"));
+        assert_eq!(query.raw("debug").as_deref(), Some(""));
+        assert_eq!(query.raw("name").as_deref(), Some("jörg"));
+        assert_eq!(query.raw("missing"), None);
+        assert_eq!(query.required::<u32>("page"), Ok(2));
+        assert_eq!(query.optional::<u32>("limit"), Ok(None));
+        assert_eq!(
+            query.required::<u32>("q"),
+            Err(QueryError::BadParam {
+                name: "q".to_string()
+            })
+        );
+        assert_eq!(
+            Query::new("").required::<String>("q"),
+            Err(QueryError::BadParam {
+                name: "q".to_string()
+            })
+        );
+    }
+}
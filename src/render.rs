@@ -0,0 +1,113 @@
+use crate::error::RenderError;
+use crate::escape::decode_segment;
+use crate::pattern::{self, Segment};
+
+/// Canonical template of a route of a `RouteTable`, parsed once for its `render`
+#[doc(hidden)]
+#[derive(Debug)]
+pub struct __HttpRouterTemplate {
+    template: &'static str,
+    // `None` for templates `RoutePattern::from_router` doesn't parse, like those of `ext`
+    segments: Option<Vec<Segment>>,
+}
+
+impl __HttpRouterTemplate {
+    pub fn new(template: &'static str) -> Self {
+        __HttpRouterTemplate {
+            template,
+            segments: pattern::parse_router_segments(template, &mut String::new()).ok(),
+        }
+    }
+
+    /// Builds a path matching the template from param values, e.g. `/users/12` from
+    /// `/users/{id: u32}`. Values are put in the path as they are, so they must be escaped
+    /// already, and are decoded before their type is checked like when matching.
+    pub fn render(&self, params: &[(&str, &str)]) -> Result<String, RenderError> {
+        let segments = self
+            .segments
+            .as_ref()
+            .ok_or_else(|| RenderError::Unsupported {
+                template: self.template.to_string(),
+            })?;
+        let mut path = String::new();
+        for segment in segments {
+            path.push('/');
+            let name = match *segment {
+                Segment::Literal(ref literal) => {
+                    path.push_str(literal);
+                    continue;
+                }
+                ref segment => segment.param_name().unwrap_or_default(),
+            };
+            let value = params
+                .iter()
+                .find(|&&(param, _)| param == name)
+                .map(|&(_, value)| value)
+                .ok_or_else(|| RenderError::MissingParam {
+                    template: self.template.to_string(),
+                    name: name.to_string(),
+                })?;
+            let valid = match *segment {
+                Segment::Wildcard(_) => !value.is_empty(),
+                _ => {
+                    !value.is_empty()
+                        && !value.contains('/')
+                        && segment.param_type().map_or(true, |ty| {
+                            decode_segment(value).is_ok_and(|value| ty.accepts(&value))
+                        })
+                }
+            };
+            if !valid {
+                return Err(RenderError::InvalidValue {
+                    template: self.template.to_string(),
+                    name: name.to_string(),
+                    value: value.to_string(),
+                });
+            }
+            path.push_str(value);
+        }
+        if path.is_empty() {
+            path.push('/');
+        }
+        Ok(path)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_render() {
+        let template = __HttpRouterTemplate::new("/users/{id: u32}/files/{path: ..}");
+        assert_eq!(
+            template.render(&[("path", "a/b.txt"), ("id", "12")]),
+            Ok("/users/12/files/a/b.txt".to_string())
+        );
+        assert_eq!(
+            template.render(&[("id", "twelve"), ("path", "a")]),
+            Err(RenderError::InvalidValue {
+                template: "/users/{id: u32}/files/{path: ..}".to_string(),
+                name: "id".to_string(),
+                value: "twelve".to_string(),
+            })
+        );
+        assert_eq!(
+            template.render(&[("id", "12")]),
+            Err(RenderError::MissingParam {
+                template: "/users/{id: u32}/files/{path: ..}".to_string(),
+                name: "path".to_string(),
+            })
+        );
+        assert_eq!(
+            __HttpRouterTemplate::new("/").render(&[]),
+            Ok("/".to_string())
+        );
+        assert_eq!(
+            __HttpRouterTemplate::new("/reports/{id: u32}.json").render(&[("id", "1")]),
+            Err(RenderError::Unsupported {
+                template: "/reports/{id: u32}.json".to_string(),
+            })
+        );
+    }
+}
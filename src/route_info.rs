@@ -0,0 +1,94 @@
+use crate::method::Method;
+use crate::pattern::RoutePattern;
+use std::any;
+
+/// Description of a registered route
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct RouteInfo {
+    pub method: Method,
+    /// Route template, e.g. `/users/{id}`
+    pub pattern: String,
+    /// Params of the route in template order
+    pub params: Vec<ParamInfo>,
+}
+
+/// Name and type of a route param
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct ParamInfo {
+    pub name: String,
+    /// Type the param is parsed into, as given by `std::any::type_name`
+    pub type_name: &'static str,
+}
+
+/// Description of a route of a `router!`, listed in the `routes` of its `RouteTable`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct StaticRouteInfo {
+    /// `None` for `ANY` routes and scopes
+    pub method: Option<Method>,
+    /// Route as written, e.g. `/users/{id: u32}`
+    pub pattern: &'static str,
+    /// Names of the params in route order
+    pub params: &'static [&'static str],
+}
+
+/// Route of a `router!` with the declared types of its params, listed in the `meta` of its
+/// `RouteTable`, e.g. to generate client SDKs
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct RouteMeta {
+    /// `None` for `ANY` routes and scopes
+    pub method: Option<Method>,
+    /// Route as written, e.g. `/users/{id: u32}`
+    pub pattern: &'static str,
+    /// `(name, type)` of the params in route order with types as written, e.g. `("id", "u32")`.
+    /// Catch-alls are `String`s.
+    pub params: &'static [(&'static str, &'static str)],
+    /// `(key, value)` pairs of a `[meta(key = "value", ...)]` attribute of the route
+    pub meta: &'static [(&'static str, &'static str)],
+}
+
+/// Canonical template and aliases of a route of a `router!`, listed in the `aliases` of its
+/// `RouteTable`, e.g. for `GET /organizations | /organisations`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct RouteAliases {
+    /// First template of the route, e.g. `/organizations`
+    pub canonical: &'static str,
+    /// The other templates of the route, empty if it has none
+    pub aliases: &'static [&'static str],
+    /// Whether the route is one of the `aliases` rather than the canonical one
+    pub alias: bool,
+}
+
+impl ParamInfo {
+    /// Param named `name` of type `T`
+    pub fn of<T: ?Sized>(name: &str) -> ParamInfo {
+        ParamInfo {
+            name: name.to_string(),
+            type_name: any::type_name::<T>(),
+        }
+    }
+}
+
+impl RouteInfo {
+    /// Info of a runtime route. Its params are raw strings, so the type of untyped ones is `str`.
+    pub(crate) fn from_pattern(method: Method, pattern: &RoutePattern) -> RouteInfo {
+        let params = pattern
+            .segments()
+            .iter()
+            .filter_map(|segment| {
+                let name = segment.param_name()?;
+                Some(match segment.param_type() {
+                    Some(ty) => ParamInfo {
+                        name: name.to_string(),
+                        type_name: ty.type_name(),
+                    },
+                    None => ParamInfo::of::<str>(name),
+                })
+            })
+            .collect();
+        RouteInfo {
+            method,
+            pattern: pattern.template().to_string(),
+            params,
+        }
+    }
+}
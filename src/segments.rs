@@ -0,0 +1,346 @@
+use crate::pattern::{RoutePattern, Segment};
+use memchr::memchr_iter;
+
+/// Offsets of the first slashes kept without allocating, enough for most paths
+const INLINE: usize = 16;
+
+/// Slash offsets of a path, found in one pass over its bytes, so that the segment count
+/// and the segments themselves don't need another `split('/')` per route
+pub(crate) struct SegmentOffsets<'a> {
+    path: &'a [u8],
+    inline: [usize; INLINE],
+    // offsets after the first `INLINE`, empty (and not allocated) for most paths
+    spilled: Vec<usize>,
+    count: usize,
+}
+
+impl<'a> SegmentOffsets<'a> {
+    pub(crate) fn scan(path: &'a [u8]) -> Self {
+        let mut offsets = SegmentOffsets {
+            path,
+            inline: [0; INLINE],
+            spilled: Vec::new(),
+            count: 0,
+        };
+        for offset in memchr_iter(b'/', path) {
+            if offsets.count < INLINE {
+                offsets.inline[offsets.count] = offset;
+            } else {
+                offsets.spilled.push(offset);
+            }
+            offsets.count += 1;
+        }
+        offsets
+    }
+
+    /// Number of slashes, which is the number of segments of a path starting with one
+    pub(crate) fn count(&self) -> usize {
+        self.count
+    }
+
+    /// Segment after the `index`th slash, up to the next one. Empty for `//` and a trailing `/`.
+    ///
+    /// Slashes never occur inside multi-byte UTF-8 characters, so segments of a `&str`
+    /// path are valid UTF-8 too.
+    pub(crate) fn segment(&self, index: usize) -> Option<&'a [u8]> {
+        if index >= self.count {
+            return None;
+        }
+        let start = self.offset(index) + 1;
+        let end = if index + 1 < self.count {
+            self.offset(index + 1)
+        } else {
+            self.path.len()
+        };
+        Some(&self.path[start..end])
+    }
+
+    /// Byte length of the first `segments` segments, slashes included
+    pub(crate) fn prefix_len(&self, segments: usize) -> usize {
+        if segments < self.count {
+            self.offset(segments)
+        } else {
+            self.path.len()
+        }
+    }
+
+    fn offset(&self, index: usize) -> usize {
+        if index < INLINE {
+            self.inline[index]
+        } else {
+            self.spilled[index - INLINE]
+        }
+    }
+}
+
+/// Segment count and literal segments a `/`-separated pattern needs, checked on the
+/// `SegmentOffsets` of a path before running the pattern's regex
+#[derive(Clone)]
+pub(crate) struct SegmentFilter {
+    // segments that aren't optional
+    min: usize,
+    // `None` with a catch-all
+    max: Option<usize>,
+    // literals before the first optional segment or catch-all, by index
+    literals: Vec<(usize, String)>,
+}
+
+impl SegmentFilter {
+    /// `None` if the pattern isn't `/`-separated or has a literal with an escaped `/`
+    pub(crate) fn new(pattern: &RoutePattern) -> Option<Self> {
+        if pattern.separator() != '/' {
+            return None;
+        }
+        let segments = pattern.segments();
+        let mut literals = Vec::new();
+        let mut fixed = true;
+        for (index, segment) in segments.iter().enumerate() {
+            match *segment {
+                Segment::Literal(ref literal) if literal.contains('/') => return None,
+                Segment::Literal(ref literal) if fixed => {
+                    literals.push((index, literal.clone()));
+                }
+                Segment::Optional(ref segment) => {
+                    if let Segment::Literal(ref literal) = **segment {
+                        if literal.contains('/') {
+                            return None;
+                        }
+                    }
+                    fixed = false;
+                }
+                Segment::Wildcard(_) => fixed = false,
+                _ => {}
+            }
+        }
+        let required = segments
+            .iter()
+            .filter(|segment| !matches!(**segment, Segment::Optional(_)))
+            .count();
+        let catch_all = segments.iter().any(|segment| match *segment {
+            Segment::Wildcard(_) => true,
+            Segment::Optional(ref segment) => matches!(**segment, Segment::Wildcard(_)),
+            _ => false,
+        });
+        Some(SegmentFilter {
+            min: required,
+            max: if catch_all {
+                None
+            } else {
+                Some(segments.len().max(1))
+            },
+            literals,
+        })
+    }
+
+    /// Whether a path with these offsets may match, `prefix` for prefix match mode
+    pub(crate) fn accepts(&self, offsets: &SegmentOffsets, prefix: bool) -> bool {
+        let count = offsets.count();
+        if !prefix && self.max.is_some_and(|max| count > max) {
+            return false;
+        }
+        count >= self.min
+            && self
+                .literals
+                .iter()
+                .all(|(index, literal)| offsets.segment(*index) == Some(literal.as_bytes()))
+    }
+}
+
+/// Number of leading segments of the path matching the segments of a `/`-separated pattern,
+/// e.g. 1 for `/users/abc` and `/users/{id: u32}`. A catch-all matches all the rest.
+pub(crate) fn matched_segments(pattern: &RoutePattern, offsets: &SegmentOffsets) -> usize {
+    if pattern.separator() != '/' {
+        return 0;
+    }
+    let mut matched = 0;
+    for segment in pattern.segments() {
+        let segment = match *segment {
+            Segment::Optional(ref segment) => &**segment,
+            ref segment => segment,
+        };
+        if let Segment::Wildcard(_) = *segment {
+            return offsets.count();
+        }
+        let value = match offsets.segment(matched) {
+            Some(value) => value,
+            None => break,
+        };
+        if !matches_segment(segment, value) {
+            break;
+        }
+        matched += 1;
+    }
+    matched
+}
+
+fn matches_segment(segment: &Segment, value: &[u8]) -> bool {
+    let value = match ::std::str::from_utf8(value) {
+        Ok(value) => value,
+        Err(_) => return false,
+    };
+    match *segment {
+        Segment::Literal(ref literal) => value == literal,
+        Segment::Param(_) => {
+            !value.is_empty()
+                && value
+                    .chars()
+                    .all(|c| c.is_alphanumeric() || c == '_' || c == '-')
+        }
+        Segment::TypedParam(_, ty) => !value.is_empty() && ty.accepts(value),
+        Segment::Wildcard(_) => true,
+        Segment::Optional(ref segment) => matches_segment(segment, value.as_bytes()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn segments(path: &str) -> Vec<&str> {
+        let offsets = SegmentOffsets::scan(path.as_bytes());
+        (0..offsets.count())
+            .map(|index| std::str::from_utf8(offsets.segment(index).unwrap()).unwrap())
+            .collect()
+    }
+
+    #[test]
+    fn test_segments() {
+        assert_eq!(segments("/"), vec![""]);
+        assert_eq!(segments("/users/12"), vec!["users", "12"]);
+        assert_eq!(segments("/users/"), vec!["users", ""]);
+        assert_eq!(segments("/users//12"), vec!["users", "", "12"]);
+        assert_eq!(segments("//"), vec!["", ""]);
+        assert_eq!(segments("/users/jörg/日本"), vec!["users", "jörg", "日本"]);
+        assert_eq!(segments(""), Vec::<&str>::new());
+        assert_eq!(segments("users"), Vec::<&str>::new());
+    }
+
+    #[test]
+    fn test_matches_split() {
+        let long = "/a".repeat(INLINE + 5) + "/";
+        for path in ["/", "/users/12/", "//a//", "/jörg/ünïcode/", long.as_str()] {
+            let expected: Vec<&str> = path.split('/').skip(1).collect();
+            assert_eq!(segments(path), expected);
+            assert_eq!(
+                SegmentOffsets::scan(path.as_bytes()).count(),
+                expected.len()
+            );
+        }
+        let offsets = SegmentOffsets::scan(long.as_bytes());
+        assert_eq!(offsets.segment(INLINE + 2), Some(&b"a"[..]));
+        assert_eq!(offsets.segment(INLINE + 5), Some(&b""[..]));
+        assert_eq!(offsets.segment(INLINE + 6), None);
+    }
+
+    #[test]
+    fn test_filter_agrees_with_regex() {
+        let templates = [
+            "/",
+            "/users",
+            "/users/{id}",
+            "/users/{id: u32}/posts",
+            "/posts/page/{page: u32}?",
+            "/{page}?",
+            "/files/{path: ..}",
+            "/files/{path: ..}?",
+            "/docs/index.html",
+        ];
+        let paths = [
+            "",
+            "/",
+            "//",
+            "/users",
+            "/users/",
+            "/users/12",
+            "/users//12",
+            "/users/12/posts",
+            "/users/jörg/posts",
+            "/posts/page",
+            "/posts/page/2",
+            "/files",
+            "/files/a/b/",
+            "/docs/index.html",
+            "/docs/index.html/x",
+            "/userss/12",
+        ];
+        for template in templates.iter() {
+            let pattern = RoutePattern::parse(template).unwrap();
+            let filter = SegmentFilter::new(&pattern).unwrap();
+            for path in paths.iter() {
+                let offsets = SegmentOffsets::scan(path.as_bytes());
+                // the filter may let paths through, but must never reject a match
+                if pattern.captures(path).is_some() {
+                    assert!(filter.accepts(&offsets, false), "{} {}", template, path);
+                }
+                if pattern.captures_prefix(path).is_some() {
+                    assert!(filter.accepts(&offsets, true), "{} {}", template, path);
+                }
+            }
+        }
+        let pattern = RoutePattern::parse("/users/{id}").unwrap();
+        let filter = SegmentFilter::new(&pattern).unwrap();
+        let accepts =
+            |path: &str, prefix| filter.accepts(&SegmentOffsets::scan(path.as_bytes()), prefix);
+        assert!(!accepts("/users", false));
+        assert!(!accepts("/users/12/posts", false));
+        assert!(accepts("/users/12/posts", true));
+        assert!(!accepts("/posts/12", false));
+    }
+
+    #[test]
+    fn test_matched_segments() {
+        let matched = |template: &str, path: &str| {
+            let pattern = RoutePattern::parse(template).unwrap();
+            matched_segments(&pattern, &SegmentOffsets::scan(path.as_bytes()))
+        };
+        assert_eq!(matched("/users/{id: u32}", "/users/abc"), 1);
+        assert_eq!(matched("/users/{id: u32}", "/users/12/posts"), 2);
+        assert_eq!(matched("/users/{id}", "/users//posts"), 1);
+        assert_eq!(matched("/posts", "/users"), 0);
+        assert_eq!(matched("/files/{path: ..}", "/files/a/b"), 3);
+    }
+
+    #[test]
+    fn test_filter_escaped_slash() {
+        let pattern = RoutePattern::parse_with_separator("/a\\/b", '.').unwrap();
+        assert!(SegmentFilter::new(&pattern).is_none());
+        let pattern = RoutePattern::parse("/a\\/b/{id}").unwrap();
+        assert!(SegmentFilter::new(&pattern).is_none());
+    }
+}
+
+#[cfg(all(test, feature = "nightly"))]
+mod benches {
+    extern crate test;
+
+    use self::test::{black_box, Bencher};
+    use super::*;
+
+    const PATHS: [&str; 4] = [
+        "/users/534/transactions/0x234",
+        "/users/534/transactions",
+        "/files/a/b/c/d/e/f/index.html",
+        "/",
+    ];
+
+    #[bench]
+    fn bench_split_segments(b: &mut Bencher) {
+        b.iter(|| {
+            for path in PATHS.iter() {
+                let count = black_box(path).split('/').count() - 1;
+                let first = path.split('/').nth(1);
+                black_box((count, first));
+            }
+        });
+    }
+
+    #[bench]
+    fn bench_scan_segments(b: &mut Bencher) {
+        b.iter(|| {
+            for path in PATHS.iter() {
+                let offsets = SegmentOffsets::scan(black_box(path).as_bytes());
+                black_box((offsets.count(), offsets.segment(0)));
+            }
+        });
+    }
+}
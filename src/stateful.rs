@@ -0,0 +1,146 @@
+use crate::dynamic::{DynHandler, DynamicRouter, Params};
+use crate::error::RouteError;
+use crate::method::Method;
+use std::sync::Arc;
+
+/// `DynamicRouter` with a shared state, e.g. a database pool, that handlers added with
+/// `add_with_state` get as `&S` next to the context, like axum's `State`.
+///
+/// Made by `DynamicRouter::with_state`. Handlers added with `add` don't get the state.
+pub struct StatefulRouter<S, C, R> {
+    router: DynamicRouter<C, R>,
+    state: Arc<S>,
+}
+
+/// Handler of `add_with_state`, holding its own reference to the state
+struct WithState<S, H> {
+    state: Arc<S>,
+    handler: H,
+}
+
+impl<C, R, S, H> DynHandler<C, R> for WithState<S, H>
+where
+    S: Send + Sync,
+    H: Fn(&C, &S, &Params) -> R + Send + Sync,
+{
+    fn call(&self, context: &C, params: &Params) -> R {
+        (self.handler)(context, &self.state, params)
+    }
+}
+
+impl<S, C, R> StatefulRouter<S, C, R> {
+    pub(crate) fn new(router: DynamicRouter<C, R>, state: Arc<S>) -> Self {
+        StatefulRouter { router, state }
+    }
+
+    pub fn state(&self) -> &Arc<S> {
+        &self.state
+    }
+
+    /// Registers a handler that doesn't need the state, see `DynamicRouter::add`
+    pub fn add<H>(
+        &mut self,
+        method: Method,
+        pattern: &str,
+        handler: H,
+    ) -> Result<&mut Self, RouteError>
+    where
+        H: DynHandler<C, R> + 'static,
+    {
+        self.router.add(method, pattern, handler)?;
+        Ok(self)
+    }
+
+    /// Registers a handler that gets the state, e.g.
+    /// `|context: &Context, db: &Db, params: &Params| ...`
+    pub fn add_with_state<H>(
+        &mut self,
+        method: Method,
+        pattern: &str,
+        handler: H,
+    ) -> Result<&mut Self, RouteError>
+    where
+        S: Send + Sync + 'static,
+        H: Fn(&C, &S, &Params) -> R + Send + Sync + 'static,
+    {
+        let handler = WithState {
+            state: self.state.clone(),
+            handler,
+        };
+        self.router.add(method, pattern, handler)?;
+        Ok(self)
+    }
+
+    /// Calls the first matching handler, see `DynamicRouter::route`
+    pub fn route(&self, context: &C, method: Method, path: &str) -> Option<R> {
+        self.router.route(context, method, path)
+    }
+
+    /// The underlying router, e.g. to set the fallback
+    pub fn router(&self) -> &DynamicRouter<C, R> {
+        &self.router
+    }
+
+    pub fn router_mut(&mut self) -> &mut DynamicRouter<C, R> {
+        &mut self.router
+    }
+
+    /// The underlying router. Handlers added with `add_with_state` keep the state.
+    pub fn into_router(self) -> DynamicRouter<C, R> {
+        self.router
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    struct Db {
+        queries: AtomicUsize,
+    }
+
+    #[test]
+    fn test_with_state() {
+        let db = Arc::new(Db {
+            queries: AtomicUsize::new(0),
+        });
+        let mut router = DynamicRouter::new().with_state(db.clone());
+        router
+            .add_with_state(
+                Method::GET,
+                "/users/{id}",
+                |prefix: &&str, db: &Db, params: &Params| {
+                    db.queries.fetch_add(1, Ordering::SeqCst);
+                    format!("{}{}", prefix, params.raw("id").unwrap())
+                },
+            )
+            .unwrap()
+            .add(Method::GET, "/health", |_: &&str, _: &Params| {
+                "ok".to_string()
+            })
+            .unwrap();
+        router
+            .router()
+            .swap_fallback(Box::new(|_: &&str, _: &Params| "404".to_string()));
+
+        assert_eq!(
+            router.route(&"user-", Method::GET, "/users/12"),
+            Some("user-12".to_string())
+        );
+        assert_eq!(
+            router.route(&"", Method::GET, "/health"),
+            Some("ok".to_string())
+        );
+        assert_eq!(
+            router.route(&"", Method::GET, "/posts"),
+            Some("404".to_string())
+        );
+        assert_eq!(db.queries.load(Ordering::SeqCst), 1);
+        assert!(Arc::ptr_eq(router.state(), &db));
+
+        let router = router.into_router();
+        router.route(&"", Method::GET, "/users/1");
+        assert_eq!(db.queries.load(Ordering::SeqCst), 2);
+    }
+}
@@ -0,0 +1,101 @@
+use crate::compact::RouteCaptures;
+use crate::error::RenderError;
+use crate::method::Method;
+use crate::render::__HttpRouterTemplate;
+use crate::route_info::{RouteAliases, RouteMeta, StaticRouteInfo};
+use std::ops::Deref;
+use std::sync::OnceLock;
+
+/// `match_pattern` of the routes of a table, generated by `router!`
+type MatchPattern = fn(Method, &str) -> Option<(usize, RouteCaptures<'_>)>;
+
+/// Router made by `router!` with a `table;` header, along with its routes in declaration
+/// order. It derefs to the router, so that `api(context, method, path)` calls it.
+///
+/// With `table(predicates);`, `is_route` has a method per handler telling whether
+/// `match_pattern` finds one of its routes, e.g. `api.is_route.get_user(Method::GET,
+/// "/users/12")`. It is `()` otherwise.
+pub struct RouteTable<F, P = ()> {
+    router: F,
+    /// Routes of the router
+    pub routes: &'static [StaticRouteInfo],
+    /// Routes of the router with the types of their params
+    pub meta: &'static [RouteMeta],
+    /// `[timeout_ms = ...]` of each route
+    pub timeouts_ms: &'static [Option<u64>],
+    /// Canonical template and aliases of each route
+    pub aliases: &'static [RouteAliases],
+    pub is_route: P,
+    match_pattern: MatchPattern,
+    templates: OnceLock<Vec<__HttpRouterTemplate>>,
+}
+
+impl<F, P> RouteTable<F, P> {
+    #[doc(hidden)]
+    pub fn new(
+        router: F,
+        routes: &'static [StaticRouteInfo],
+        meta: &'static [RouteMeta],
+        timeouts_ms: &'static [Option<u64>],
+        aliases: &'static [RouteAliases],
+        match_pattern: MatchPattern,
+        is_route: P,
+    ) -> Self {
+        RouteTable {
+            router,
+            routes,
+            meta,
+            timeouts_ms,
+            aliases,
+            is_route,
+            match_pattern,
+            templates: OnceLock::new(),
+        }
+    }
+
+    /// Number of routes, the fallback aside
+    pub fn route_count(&self) -> usize {
+        self.routes.len()
+    }
+
+    /// Index in `routes` of the first route whose method and pattern match `method` and `path`,
+    /// with its params as matched. Indices follow declaration order, each extension of an
+    /// `ext { ... }` route and each alias taking one.
+    ///
+    /// Only patterns are matched: conditions, guards, `reserved(...)` and param types aren't
+    /// checked, so the route may be one the router declines for the same request, and a caller
+    /// dispatching on the index checks them itself. Call the router to dispatch with them.
+    pub fn match_pattern<'a>(
+        &self,
+        method: Method,
+        path: &'a str,
+    ) -> Option<(usize, RouteCaptures<'a>)> {
+        (self.match_pattern)(method, path)
+    }
+
+    /// Renders the canonical template of the route at `index` with `params`, so that links to
+    /// a route matched by one of its aliases use the canonical spelling. Templates of
+    /// `ext { ... }` give `RenderError::Unsupported`.
+    pub fn render(&self, index: usize, params: &[(&str, &str)]) -> Result<String, RenderError> {
+        self.templates.get_or_init(|| {
+            self.aliases
+                .iter()
+                .map(|route| __HttpRouterTemplate::new(route.canonical))
+                .collect()
+        })[index]
+            .render(params)
+    }
+
+    /// The router without its table
+    pub fn into_router(self) -> F {
+        self.router
+    }
+}
+
+impl<F, P> Deref for RouteTable<F, P> {
+    type Target = F;
+
+    fn deref(&self) -> &F {
+        &self.router
+    }
+}
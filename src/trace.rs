@@ -0,0 +1,22 @@
+/// How far a route got with a path in `DynamicRouter::route_traced`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum TraceOutcome {
+    /// The route is for another method
+    MethodRejected,
+    /// The path doesn't match the route template
+    PatternRejected,
+    /// The path matches the template, but the named typed param doesn't parse into its type
+    ParamRejected(String),
+    /// The route's handler was called
+    Accepted,
+    /// The path matches, but in best match mode a more specific route was called instead
+    Outranked,
+}
+
+/// A route tried by `DynamicRouter::route_traced`, in the order routes are tried
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CandidateTrace {
+    /// Template of the route, e.g. `/users/{id: u32}`
+    pub template: String,
+    pub outcome: TraceOutcome,
+}
@@ -0,0 +1,161 @@
+use std::collections::{BTreeMap, HashMap};
+use std::sync::Mutex;
+
+/// Counts paths that reached the fallback, keeping at most `capacity` of them.
+///
+/// When full, the least counted path is replaced and the new one takes over its count
+/// (the "space-saving" algorithm), so frequent paths are never lost, but counts of paths
+/// that came in late may be overestimated by up to the evicted count.
+/// Use it with `record_unmatched`.
+#[derive(Debug)]
+pub struct UnmatchedPaths {
+    capacity: usize,
+    counts: Mutex<Counts>,
+}
+
+// Counts by path, and paths ordered by count so that the least counted one is found in
+// O(log n). `seq` tells apart paths with the same count, the oldest first.
+#[derive(Debug, Default)]
+struct Counts {
+    by_path: HashMap<String, (u64, u64)>,
+    by_count: BTreeMap<(u64, u64), String>,
+    next_seq: u64,
+}
+
+impl UnmatchedPaths {
+    pub fn new(capacity: usize) -> Self {
+        UnmatchedPaths {
+            capacity,
+            counts: Mutex::new(Counts {
+                by_path: HashMap::with_capacity(capacity),
+                ..Counts::default()
+            }),
+        }
+    }
+
+    pub fn capacity(&self) -> usize {
+        self.capacity
+    }
+
+    /// Counts one more hit of `path` in O(log n). Only copies `path` when it isn't tracked yet.
+    pub fn record(&self, path: &str) {
+        if self.capacity == 0 {
+            return;
+        }
+        let mut guard = self.counts.lock().expect("Failed to obtain mutex lock");
+        let counts = &mut *guard;
+        if let Some(&mut (ref mut count, seq)) = counts.by_path.get_mut(path) {
+            let tracked = counts
+                .by_count
+                .remove(&(*count, seq))
+                .expect("paths are tracked in both maps");
+            *count += 1;
+            counts.by_count.insert((*count, seq), tracked);
+            return;
+        }
+        let mut count = 1;
+        if counts.by_path.len() >= self.capacity {
+            if let Some(((evicted_count, _), evicted)) = counts.by_count.pop_first() {
+                counts.by_path.remove(&evicted);
+                count += evicted_count;
+            }
+        }
+        let seq = counts.next_seq;
+        counts.next_seq += 1;
+        counts.by_path.insert(path.to_string(), (count, seq));
+        counts.by_count.insert((count, seq), path.to_string());
+    }
+
+    /// The `n` most counted paths, most counted first
+    pub fn snapshot_top_n(&self, n: usize) -> Vec<(String, u64)> {
+        let counts = self.counts.lock().expect("Failed to obtain mutex lock");
+        let mut top: Vec<(String, u64)> = counts
+            .by_path
+            .iter()
+            .map(|(path, &(count, _))| (path.clone(), count))
+            .collect();
+        top.sort_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.cmp(&b.0)));
+        top.truncate(n);
+        top
+    }
+
+    /// Number of tracked paths
+    pub fn len(&self) -> usize {
+        self.counts
+            .lock()
+            .expect("Failed to obtain mutex lock")
+            .by_path
+            .len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    pub fn reset(&self) {
+        let mut counts = self.counts.lock().expect("Failed to obtain mutex lock");
+        counts.by_path.clear();
+        counts.by_count.clear();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_top_n() {
+        let unmatched = UnmatchedPaths::new(8);
+        for i in 0..100 {
+            unmatched.record("/usres");
+            if i % 2 == 0 {
+                unmatched.record("/transactoins");
+            }
+            if i % 10 == 0 {
+                unmatched.record("/favicon.ico");
+            }
+            unmatched.record(&format!("/random/{}", i));
+        }
+        assert_eq!(unmatched.len(), 8);
+        let top = unmatched.snapshot_top_n(2);
+        assert_eq!(
+            top,
+            vec![
+                ("/usres".to_string(), 100),
+                ("/transactoins".to_string(), 50)
+            ]
+        );
+        assert_eq!(unmatched.snapshot_top_n(10).len(), 8);
+
+        unmatched.reset();
+        assert!(unmatched.is_empty());
+        assert_eq!(unmatched.snapshot_top_n(2), vec![]);
+    }
+
+    #[test]
+    fn test_eviction() {
+        let unmatched = UnmatchedPaths::new(2);
+        unmatched.record("/a");
+        unmatched.record("/a");
+        unmatched.record("/b");
+        // `/b` is the least counted and `/c` takes over its count
+        unmatched.record("/c");
+        assert_eq!(
+            unmatched.snapshot_top_n(2),
+            vec![("/a".to_string(), 2), ("/c".to_string(), 2)]
+        );
+        // on a tie, the oldest path is evicted
+        unmatched.record("/d");
+        assert_eq!(
+            unmatched.snapshot_top_n(2),
+            vec![("/d".to_string(), 3), ("/c".to_string(), 2)]
+        );
+    }
+
+    #[test]
+    fn test_zero_capacity() {
+        let unmatched = UnmatchedPaths::new(0);
+        unmatched.record("/usres");
+        assert!(unmatched.is_empty());
+    }
+}
@@ -0,0 +1,261 @@
+use crate::method::Method;
+use crate::unmatched::UnmatchedPaths;
+use std::fmt::Display;
+use std::str::FromStr;
+use std::sync::Arc;
+
+/// Wraps a router whose routes start with a `{locale: L}` segment, so that paths
+/// without a locale prefix are routed with `default`.
+///
+/// The first segment is kept if it parses as `L`, otherwise `default` is prepended,
+/// e.g. `/about` is routed as `/en/about`. Unknown prefixes like `/xx/about` become
+/// `/en/xx/about` and reach the fallback instead of matching a slug route.
+pub fn with_locale<L, C, R, F>(default: L, router: F) -> impl Fn(C, Method, &str) -> R
+where
+    L: FromStr + Display,
+    F: Fn(C, Method, &str) -> R,
+{
+    move |context, method, path| {
+        let first_segment = path.trim_start_matches('/').split('/').next().unwrap_or("");
+        if first_segment.parse::<L>().is_ok() {
+            router(context, method, path)
+        } else if path == "/" {
+            router(context, method, &format!("/{}", default))
+        } else {
+            router(context, method, &format!("/{}{}", default, path))
+        }
+    }
+}
+
+/// Wraps a router so that the first of `prefixes` found at the start of the path is removed,
+/// e.g. for running behind a proxy that only sometimes strips `/service-a`.
+///
+/// Prefixes only match whole segments, so `/service-ab/foo` is left alone, and a path equal
+/// to a prefix is routed as `/`.
+pub fn strip_prefixes<C, R, F>(prefixes: &[&str], router: F) -> impl Fn(C, Method, &str) -> R
+where
+    F: Fn(C, Method, &str) -> R,
+{
+    let prefixes: Vec<String> = prefixes
+        .iter()
+        .map(|prefix| prefix.trim_end_matches('/').to_string())
+        .filter(|prefix| !prefix.is_empty())
+        .collect();
+    move |context, method, path| {
+        for prefix in &prefixes {
+            if let Some(rest) = path.strip_prefix(prefix.as_str()) {
+                if rest.is_empty() {
+                    return router(context, method, "/");
+                }
+                if rest.starts_with('/') {
+                    return router(context, method, rest);
+                }
+            }
+        }
+        router(context, method, path)
+    }
+}
+
+/// Wraps a `matched;` router, counting paths that reached the fallback in `unmatched`
+pub fn record_unmatched<C, R, F>(
+    unmatched: Arc<UnmatchedPaths>,
+    router: F,
+) -> impl Fn(C, Method, &str) -> R
+where
+    F: Fn(C, Method, &str) -> (bool, R),
+{
+    move |context, method, path| {
+        let (matched, value) = router(context, method, path);
+        if !matched {
+            unmatched.record(path);
+        }
+        value
+    }
+}
+
+/// Wraps a router so that each call builds its context with `factory`, leaving adapters
+/// (e.g. a hyper `Service`) with just the method and the path to pass
+pub fn with_context_factory<C, R, G, F>(factory: G, router: F) -> impl Fn(Method, &str) -> R
+where
+    G: Fn() -> C,
+    F: Fn(C, Method, &str) -> R,
+{
+    move |method, path| router(factory(), method, path)
+}
+
+/// Router behind an `Arc`, which is `Clone`, `Send` and `Sync` whatever its handlers are
+pub type SharedRouter<C, R> = Arc<dyn Fn(C, Method, &str) -> R + Send + Sync>;
+
+/// Boxes a router into a `SharedRouter`, e.g. to hand a copy to each spawned task when the
+/// handlers it captures aren't `Clone`
+pub fn shared_router<C, R, F>(router: F) -> SharedRouter<C, R>
+where
+    F: Fn(C, Method, &str) -> R + Send + Sync + 'static,
+{
+    Arc::new(router)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::router;
+    use std::fmt;
+
+    #[derive(Debug, Clone, Copy, PartialEq)]
+    enum Locale {
+        En,
+        De,
+        Fr,
+    }
+
+    impl FromStr for Locale {
+        type Err = ();
+
+        fn from_str(s: &str) -> Result<Locale, ()> {
+            match s {
+                "en" => Ok(Locale::En),
+                "de" => Ok(Locale::De),
+                "fr" => Ok(Locale::Fr),
+                _ => Err(()),
+            }
+        }
+    }
+
+    impl fmt::Display for Locale {
+        fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+            let s = match *self {
+                Locale::En => "en",
+                Locale::De => "de",
+                Locale::Fr => "fr",
+            };
+            f.write_str(s)
+        }
+    }
+
+    #[test]
+    fn test_with_locale() {
+        let home = |_: &(), locale: Locale| format!("home({})", locale);
+        let page = |_: &(), locale: Locale, slug: String| format!("page({}, {})", locale, slug);
+        let fallback = |_: &()| "404".to_string();
+        let router = with_locale(
+            Locale::En,
+            router!(
+                GET /{locale: Locale} => home,
+                GET /{locale: Locale}/{slug: String} => page,
+                _ => fallback,
+            ),
+        );
+        assert_eq!(router((), Method::GET, "/en/about"), "page(en, about)");
+        assert_eq!(router((), Method::GET, "/de/about"), "page(de, about)");
+        assert_eq!(router((), Method::GET, "/fr/about"), "page(fr, about)");
+        assert_eq!(router((), Method::GET, "/about"), "page(en, about)");
+        assert_eq!(router((), Method::GET, "/"), "home(en)");
+        assert_eq!(router((), Method::GET, "/de"), "home(de)");
+        assert_eq!(router((), Method::GET, "/xx/about"), "404");
+    }
+
+    #[test]
+    fn test_strip_prefixes() {
+        let home = |_: &()| "home".to_string();
+        let foo = |_: &()| "foo".to_string();
+        let fallback = |_: &()| "404".to_string();
+        let router = strip_prefixes(
+            &["/service-a", "/service-b/"],
+            router!(
+                GET / => home,
+                GET /foo => foo,
+                _ => fallback,
+            ),
+        );
+        assert_eq!(router((), Method::GET, "/service-a/foo"), "foo");
+        assert_eq!(router((), Method::GET, "/service-b/foo"), "foo");
+        assert_eq!(router((), Method::GET, "/foo"), "foo");
+        assert_eq!(router((), Method::GET, "/service-a"), "home");
+        assert_eq!(router((), Method::GET, "/service-a/"), "home");
+        assert_eq!(router((), Method::GET, "/service-ab/foo"), "404");
+        assert_eq!(router((), Method::GET, "/service-a/service-a/foo"), "404");
+    }
+
+    #[test]
+    fn test_record_unmatched() {
+        let users = |_: &()| "users".to_string();
+        let fallback = |_: &()| "404".to_string();
+        let unmatched = Arc::new(UnmatchedPaths::new(16));
+        let router = record_unmatched(
+            unmatched.clone(),
+            router!(
+                matched;
+                GET /users => users,
+                _ => fallback,
+            ),
+        );
+        for path in &["/users", "/usres", "/users", "/usres", "/favicon.ico"] {
+            router((), Method::GET, path);
+        }
+        assert_eq!(router((), Method::POST, "/users"), "404");
+        assert_eq!(
+            unmatched.snapshot_top_n(3),
+            vec![
+                ("/usres".to_string(), 2),
+                ("/favicon.ico".to_string(), 1),
+                ("/users".to_string(), 1)
+            ]
+        );
+    }
+
+    #[test]
+    fn test_with_context_factory() {
+        use std::sync::atomic::{AtomicUsize, Ordering};
+
+        struct Context {
+            requests: Arc<AtomicUsize>,
+            request_id: usize,
+        }
+
+        let get_users = |context: &Context| {
+            format!(
+                "users(request {} of {})",
+                context.request_id,
+                context.requests.load(Ordering::SeqCst)
+            )
+        };
+        let fallback = |context: &Context| format!("404(request {})", context.request_id);
+        let requests = Arc::new(AtomicUsize::new(0));
+        let shared = requests.clone();
+        let router = with_context_factory(
+            move || Context {
+                requests: shared.clone(),
+                request_id: shared.fetch_add(1, Ordering::SeqCst) + 1,
+            },
+            router!(GET /users => get_users, _ => fallback),
+        );
+        assert_eq!(router(Method::GET, "/users"), "users(request 1 of 1)");
+        assert_eq!(router(Method::GET, "/posts"), "404(request 2)");
+        assert_eq!(router(Method::GET, "/users"), "users(request 3 of 3)");
+        assert_eq!(requests.load(Ordering::SeqCst), 3);
+    }
+
+    #[test]
+    fn test_shared_router() {
+        use std::thread;
+
+        // not `Clone`, so neither are the handler and the router capturing it
+        struct Greeting(String);
+
+        let greeting = Greeting("hello".to_string());
+        let get_hello = move |_: &()| greeting.0.clone();
+        let fallback = |_: &()| "404".to_string();
+        let router = shared_router(router!(GET /hello => get_hello, _ => fallback));
+
+        let handles: Vec<_> = (0..4)
+            .map(|_| {
+                let router = router.clone();
+                thread::spawn(move || router((), Method::GET, "/hello"))
+            })
+            .collect();
+        for handle in handles {
+            assert_eq!(handle.join().unwrap(), "hello");
+        }
+        assert_eq!(router((), Method::GET, "/other"), "404");
+    }
+}
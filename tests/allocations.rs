@@ -0,0 +1,107 @@
+//! Dispatching to a static route must not allocate, with or without a timing hook, and
+//! neither must `RoutePattern::captures_inline`. This is its own test binary, so that the
+//! counting allocator only sees these tests, which take turns.
+
+#[macro_use]
+extern crate http_router;
+
+use http_router::{Method, RoutePattern};
+use std::alloc::{GlobalAlloc, Layout, System};
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+use std::sync::Mutex;
+
+struct CountingAllocator;
+
+static ALLOCATIONS: AtomicUsize = AtomicUsize::new(0);
+
+unsafe impl GlobalAlloc for CountingAllocator {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        ALLOCATIONS.fetch_add(1, Ordering::SeqCst);
+        System.alloc(layout)
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        System.dealloc(ptr, layout)
+    }
+
+    unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+        ALLOCATIONS.fetch_add(1, Ordering::SeqCst);
+        System.realloc(ptr, layout, new_size)
+    }
+}
+
+#[global_allocator]
+static ALLOCATOR: CountingAllocator = CountingAllocator;
+
+static SERIAL: Mutex<()> = Mutex::new(());
+
+#[test]
+fn test_static_dispatch_does_not_allocate() {
+    let _serial = SERIAL.lock().unwrap();
+    let get_users = |_: &()| 1;
+    let get_user = |_: &(), _: u32| 2;
+    let get_posts = |_: &(), _: u32| 3;
+    let get_health = |_: &()| 4;
+    let not_found = |_: &()| 0;
+    let router = router!(
+        GET /users => get_users,
+        GET /users/{id: u32} => get_user,
+        GET /users/{id: u32}/posts => get_posts,
+        GET /api/health => get_health,
+        _ => not_found,
+    );
+    // the first requests compile and cache the regexes of the routes tried before
+    for _ in 0..10 {
+        assert_eq!(router((), Method::GET, "/api/health"), 4);
+        assert_eq!(router((), Method::GET, "/users"), 1);
+    }
+
+    let before = ALLOCATIONS.load(Ordering::SeqCst);
+    for _ in 0..1000 {
+        assert_eq!(router((), Method::GET, "/api/health"), 4);
+        assert_eq!(router((), Method::GET, "/users"), 1);
+    }
+    assert_eq!(ALLOCATIONS.load(Ordering::SeqCst) - before, 0);
+
+    static CALLS: AtomicU64 = AtomicU64::new(0);
+    fn record(_: &'static str, _: u64, _: u64) {
+        CALLS.fetch_add(1, Ordering::SeqCst);
+    }
+    let timed_router = router!(
+        timing(record);
+        GET /users => get_users,
+        GET /api/health => get_health,
+        _ => not_found,
+    );
+    assert_eq!(timed_router((), Method::GET, "/api/health"), 4);
+
+    let before = ALLOCATIONS.load(Ordering::SeqCst);
+    for _ in 0..1000 {
+        assert_eq!(timed_router((), Method::GET, "/api/health"), 4);
+        assert_eq!(timed_router((), Method::GET, "/posts"), 0);
+    }
+    assert_eq!(ALLOCATIONS.load(Ordering::SeqCst) - before, 0);
+    assert_eq!(CALLS.load(Ordering::SeqCst), 2001);
+}
+
+#[test]
+fn test_inline_captures_do_not_allocate() {
+    let _serial = SERIAL.lock().unwrap();
+    let pattern = RoutePattern::parse("/users/{id: u32}/posts/{slug}").unwrap();
+    let path = "/users/12/posts/hello-world";
+
+    let before = ALLOCATIONS.load(Ordering::SeqCst);
+    for _ in 0..1000 {
+        let captures = pattern.captures(path).unwrap();
+        assert_eq!(captures[1], ("slug", "hello-world"));
+    }
+    // at least the `Vec` of every call
+    assert!(ALLOCATIONS.load(Ordering::SeqCst) - before >= 1000);
+
+    let before = ALLOCATIONS.load(Ordering::SeqCst);
+    for _ in 0..1000 {
+        let captures = pattern.captures_inline::<4>(path).unwrap();
+        assert_eq!(captures.get("slug"), Some("hello-world"));
+    }
+    assert_eq!(ALLOCATIONS.load(Ordering::SeqCst) - before, 0);
+}
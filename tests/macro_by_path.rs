@@ -0,0 +1,111 @@
+//! The macros imported by path, without `#[macro_use]`. Each module imports only the macro
+//! it uses, so that expansions must reach everything else through `$crate`.
+
+mod plain {
+    use http_router::router;
+
+    #[test]
+    fn test_router() {
+        fn to_admin(ctx: &&str) -> Option<String> {
+            if ctx.starts_with("admin") {
+                Some(ctx.to_string())
+            } else {
+                None
+            }
+        }
+        let admin_user = |ctx: &String, id: u32| format!("admin_user({}, {})", ctx, id);
+        let admin_fallback = |ctx: &String| format!("admin_404({})", ctx);
+        let admin_router = router!(GET /users/{id: u32} => admin_user, _ => admin_fallback);
+
+        let get_user = |_: &&str, id: u32| format!("get_user({})", id);
+        let files = |_: &&str, path: String| format!("files({})", path);
+        let report = |_: &&str, id: u32| format!("report({})", id);
+        let beta = |_: &&str| "beta".to_string();
+        let auth_check = |ctx: &&str| {
+            if ctx.is_empty() {
+                Err("401".to_string())
+            } else {
+                Ok(())
+            }
+        };
+        let fallback = |_: &&str| "404".to_string();
+        let router = router!(
+            normalize(ignore_case = true);
+            GET /users/{id: u32} => get_user,
+            GET /files/{path: ..} => files,
+            GET /reports/{id: u32} => ext { "json" => report },
+            GET /beta => beta guard auth_check,
+            scope /admin (map_ctx = to_admin) => admin_router,
+            _ => fallback,
+        );
+        let get = |ctx, path| router(ctx, http_router::Method::GET, path);
+        assert_eq!(get("", "/Users/12"), "get_user(12)");
+        assert_eq!(get("", "/files/a/b"), "files(a/b)");
+        assert_eq!(get("", "/reports/3.json"), "report(3)");
+        assert_eq!(get("", "/beta"), "401");
+        assert_eq!(get("admin1", "/admin/users/7"), "admin_user(admin1, 7)");
+        assert_eq!(get("", "/posts"), "404");
+    }
+
+    #[test]
+    fn test_compact_matched() {
+        let get_user = |_: &(), id: u32| id;
+        let fallback = |_: &()| 0;
+        let router = router!(
+            matched;
+            compact;
+            GET /users/{id: u32} => get_user,
+            _ => fallback,
+        );
+        assert_eq!(
+            router((), http_router::Method::GET, "/users/12"),
+            (true, 12)
+        );
+        assert_eq!(router((), http_router::Method::GET, "/posts"), (false, 0));
+    }
+
+    #[test]
+    fn test_table() {
+        let get_user = |_: &(), id: u32| id;
+        let fallback = |_: &()| 0;
+        let api = router!(table; GET /users/{id: u32} => get_user, _ => fallback);
+        assert_eq!(api.route_count(), 1);
+        assert_eq!(api.meta[0].params, &[("id", "u32")]);
+        assert_eq!(api((), http_router::Method::GET, "/users/12"), 12);
+    }
+}
+
+mod boxed {
+    use http_router::async_router;
+    use std::sync::Arc;
+    use std::task::{Context, Poll, Wake, Waker};
+
+    struct NoopWaker;
+
+    impl Wake for NoopWaker {
+        fn wake(self: Arc<Self>) {}
+    }
+
+    #[test]
+    fn test_async_router() {
+        let get_user = |_: &(), id: u32| std::future::ready(format!("get_user({})", id));
+        let fallback = |_: &()| "404".to_string();
+        let router = async_router!(GET /users/{id: u32} => get_user, _ => fallback);
+        let mut future = router(&(), http_router::Method::GET, "/users/12");
+        let waker = Waker::from(Arc::new(NoopWaker));
+        let mut cx = Context::from_waker(&waker);
+        assert_eq!(
+            future.as_mut().poll(&mut cx),
+            Poll::Ready("get_user(12)".to_string())
+        );
+    }
+}
+
+mod route_count {
+    use http_router::const_assert_route_count;
+
+    const_assert_route_count!({
+        GET /users => get_users,
+        _ => fallback,
+    }, 1);
+}
@@ -0,0 +1,42 @@
+//! Routers created at the same time on several threads race for the first use of the shared
+//! regex cache. This is its own test binary, so that no other test initializes the cache first.
+
+use http_router::{router, Method};
+use std::sync::{Arc, Barrier};
+use std::thread;
+
+const THREADS: usize = 16;
+
+#[test]
+fn test_first_use_race() {
+    let barrier = Arc::new(Barrier::new(THREADS));
+    let threads: Vec<_> = (0..THREADS)
+        .map(|thread| {
+            let barrier = barrier.clone();
+            thread::spawn(move || {
+                let get_user = |_: &(), id: u32| format!("get_user({})", id);
+                let get_post =
+                    |_: &(), id: u32, slug: String| format!("get_post({}, {})", id, slug);
+                let fallback = |_: &()| "404".to_string();
+                barrier.wait();
+                let router = router!(
+                    GET /users/{id: u32} => get_user,
+                    GET /posts/{id: u32}/{slug: String} => get_post,
+                    _ => fallback,
+                );
+                assert_eq!(
+                    router((), Method::GET, &format!("/users/{}", thread)),
+                    format!("get_user({})", thread)
+                );
+                assert_eq!(
+                    router((), Method::GET, "/posts/1/hello"),
+                    "get_post(1, hello)"
+                );
+                assert_eq!(router((), Method::GET, "/posts/1"), "404");
+            })
+        })
+        .collect();
+    for thread in threads {
+        thread.join().unwrap();
+    }
+}